@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS orphaned_blocks_number;
+DROP TABLE IF EXISTS orphaned_blocks;
@@ -0,0 +1,10 @@
+-- Blocks removed from the canonical chain by a reorganization.
+CREATE TABLE IF NOT EXISTS orphaned_blocks (
+    hash BLOB PRIMARY KEY,
+    number BIGINT NOT NULL,
+    parent_hash BLOB NOT NULL,
+    timestamp BIGINT NOT NULL,
+    orphaned_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS orphaned_blocks_number ON orphaned_blocks (number);
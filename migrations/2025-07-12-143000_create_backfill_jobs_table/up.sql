@@ -0,0 +1,8 @@
+-- Historical ranges requested for indexing. `next_block` is the first block not processed yet,
+-- so a job is finished once it is greater than `to_block`.
+CREATE TABLE IF NOT EXISTS backfill_jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    from_block BIGINT NOT NULL,
+    to_block BIGINT NOT NULL,
+    next_block BIGINT NOT NULL
+);
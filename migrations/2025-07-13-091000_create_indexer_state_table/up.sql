@@ -0,0 +1,5 @@
+-- Numeric markers the indexer needs to keep across restarts.
+CREATE TABLE IF NOT EXISTS indexer_state (
+    key TEXT PRIMARY KEY NOT NULL,
+    value BIGINT NOT NULL
+);
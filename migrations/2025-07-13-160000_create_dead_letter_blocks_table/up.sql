@@ -0,0 +1,8 @@
+-- Blocks that could not be fetched, even after retrying, waiting to be attempted again.
+CREATE TABLE IF NOT EXISTS dead_letter_blocks (
+    number BIGINT PRIMARY KEY,
+    hash BLOB NOT NULL,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    last_attempt_at BIGINT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- How settled each block is: 'latest', 'safe' or 'finalized'.
+ALTER TABLE blocks ADD COLUMN finality TEXT NOT NULL DEFAULT 'latest';
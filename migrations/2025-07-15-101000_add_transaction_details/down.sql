@@ -0,0 +1,14 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS access_list_items;
+ALTER TABLE transactions DROP COLUMN max_priority_fee_per_gas;
+ALTER TABLE transactions DROP COLUMN max_fee_per_gas;
+ALTER TABLE transactions DROP COLUMN gas_price;
+ALTER TABLE transactions DROP COLUMN gas_limit;
+ALTER TABLE transactions DROP COLUMN input;
+ALTER TABLE transactions DROP COLUMN nonce;
+ALTER TABLE transactions DROP COLUMN value;
+ALTER TABLE transactions DROP COLUMN to_address;
+ALTER TABLE transactions DROP COLUMN from_address;
+ALTER TABLE transactions DROP COLUMN chain_id;
+ALTER TABLE transactions DROP COLUMN tx_type;
+ALTER TABLE transactions DROP COLUMN transaction_index;
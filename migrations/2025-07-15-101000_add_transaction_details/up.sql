@@ -0,0 +1,24 @@
+-- Transactions indexed before these columns existed keep zeroed values.
+ALTER TABLE transactions ADD COLUMN transaction_index BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE transactions ADD COLUMN tx_type INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE transactions ADD COLUMN chain_id BIGINT;
+ALTER TABLE transactions ADD COLUMN from_address BLOB NOT NULL DEFAULT x'0000000000000000000000000000000000000000';
+ALTER TABLE transactions ADD COLUMN to_address BLOB;
+ALTER TABLE transactions ADD COLUMN value BLOB NOT NULL DEFAULT x'0000000000000000000000000000000000000000000000000000000000000000';
+ALTER TABLE transactions ADD COLUMN nonce BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE transactions ADD COLUMN input BLOB NOT NULL DEFAULT x'';
+ALTER TABLE transactions ADD COLUMN gas_limit BIGINT NOT NULL DEFAULT 0;
+-- Fee fields are 16 byte big-endian integers.
+ALTER TABLE transactions ADD COLUMN gas_price BLOB;
+ALTER TABLE transactions ADD COLUMN max_fee_per_gas BLOB;
+ALTER TABLE transactions ADD COLUMN max_priority_fee_per_gas BLOB;
+
+CREATE TABLE IF NOT EXISTS access_list_items (
+    transaction_hash BLOB NOT NULL,
+    item_index INTEGER NOT NULL,
+    address BLOB NOT NULL,
+    -- The 32 byte storage keys, concatenated.
+    storage_keys BLOB NOT NULL,
+    PRIMARY KEY (transaction_hash, item_index),
+    FOREIGN KEY (transaction_hash) REFERENCES transactions (hash) ON DELETE CASCADE
+);
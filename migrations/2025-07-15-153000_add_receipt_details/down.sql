@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS receipts_success;
+ALTER TABLE receipts DROP COLUMN logs_bloom;
+ALTER TABLE receipts DROP COLUMN contract_address;
+ALTER TABLE receipts DROP COLUMN effective_gas_price;
+ALTER TABLE receipts DROP COLUMN cumulative_gas_used;
+ALTER TABLE receipts DROP COLUMN success;
+ALTER TABLE receipts DROP COLUMN transaction_index;
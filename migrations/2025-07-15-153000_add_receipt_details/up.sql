@@ -0,0 +1,10 @@
+-- Receipts indexed before these columns existed keep placeholder values.
+ALTER TABLE receipts ADD COLUMN transaction_index BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE receipts ADD COLUMN success BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE receipts ADD COLUMN cumulative_gas_used BIGINT NOT NULL DEFAULT 0;
+-- 16 byte big-endian integer.
+ALTER TABLE receipts ADD COLUMN effective_gas_price BLOB NOT NULL DEFAULT x'00000000000000000000000000000000';
+ALTER TABLE receipts ADD COLUMN contract_address BLOB;
+ALTER TABLE receipts ADD COLUMN logs_bloom BLOB NOT NULL DEFAULT x'';
+
+CREATE INDEX IF NOT EXISTS receipts_success ON receipts (success);
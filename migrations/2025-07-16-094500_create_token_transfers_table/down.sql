@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS token_transfers_block_number;
+DROP INDEX IF EXISTS token_transfers_to_address;
+DROP INDEX IF EXISTS token_transfers_from_address;
+DROP INDEX IF EXISTS token_transfers_token;
+DROP TABLE IF EXISTS token_transfers;
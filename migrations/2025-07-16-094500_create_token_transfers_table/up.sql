@@ -0,0 +1,18 @@
+-- Decoded ERC-20 `Transfer` events.
+CREATE TABLE IF NOT EXISTS token_transfers (
+    transaction_hash BLOB NOT NULL,
+    log_index BIGINT NOT NULL,
+    token BLOB NOT NULL,
+    from_address BLOB NOT NULL,
+    to_address BLOB NOT NULL,
+    -- 32 byte big-endian integer.
+    value BLOB NOT NULL,
+    block_number BIGINT NOT NULL,
+    PRIMARY KEY (transaction_hash, log_index),
+    FOREIGN KEY (block_number) REFERENCES blocks (number) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS token_transfers_token ON token_transfers (token, block_number);
+CREATE INDEX IF NOT EXISTS token_transfers_from_address ON token_transfers (from_address, block_number);
+CREATE INDEX IF NOT EXISTS token_transfers_to_address ON token_transfers (to_address, block_number);
+CREATE INDEX IF NOT EXISTS token_transfers_block_number ON token_transfers (block_number);
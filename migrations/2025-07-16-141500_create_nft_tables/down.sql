@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS nft_owners_last_transfer_block;
+DROP INDEX IF EXISTS nft_owners_owner;
+DROP TABLE IF EXISTS nft_owners;
+DROP INDEX IF EXISTS nft_transfers_block_number;
+DROP INDEX IF EXISTS nft_transfers_to_address;
+DROP INDEX IF EXISTS nft_transfers_from_address;
+DROP INDEX IF EXISTS nft_transfers_token;
+DROP TABLE IF EXISTS nft_transfers;
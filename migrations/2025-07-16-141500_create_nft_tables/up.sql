@@ -0,0 +1,31 @@
+-- Decoded ERC-721 `Transfer` events.
+CREATE TABLE IF NOT EXISTS nft_transfers (
+    transaction_hash BLOB NOT NULL,
+    log_index BIGINT NOT NULL,
+    collection BLOB NOT NULL,
+    -- 32 byte big-endian integer.
+    token_id BLOB NOT NULL,
+    from_address BLOB NOT NULL,
+    to_address BLOB NOT NULL,
+    block_number BIGINT NOT NULL,
+    PRIMARY KEY (transaction_hash, log_index),
+    FOREIGN KEY (block_number) REFERENCES blocks (number) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS nft_transfers_token ON nft_transfers (collection, token_id, block_number);
+CREATE INDEX IF NOT EXISTS nft_transfers_from_address ON nft_transfers (from_address, block_number);
+CREATE INDEX IF NOT EXISTS nft_transfers_to_address ON nft_transfers (to_address, block_number);
+CREATE INDEX IF NOT EXISTS nft_transfers_block_number ON nft_transfers (block_number);
+
+-- Current owner of each ERC-721 token, as of its last indexed transfer. Burned tokens are owned
+-- by the zero address.
+CREATE TABLE IF NOT EXISTS nft_owners (
+    collection BLOB NOT NULL,
+    token_id BLOB NOT NULL,
+    owner BLOB NOT NULL,
+    last_transfer_block BIGINT NOT NULL,
+    PRIMARY KEY (collection, token_id)
+);
+
+CREATE INDEX IF NOT EXISTS nft_owners_owner ON nft_owners (owner);
+CREATE INDEX IF NOT EXISTS nft_owners_last_transfer_block ON nft_owners (last_transfer_block);
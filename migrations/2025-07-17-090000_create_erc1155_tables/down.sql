@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS erc1155_balances_holder;
+DROP TABLE IF EXISTS erc1155_balances;
+DROP INDEX IF EXISTS erc1155_transfers_block_number;
+DROP INDEX IF EXISTS erc1155_transfers_token;
+DROP TABLE IF EXISTS erc1155_transfers;
@@ -0,0 +1,35 @@
+-- Decoded ERC-1155 `TransferSingle` and `TransferBatch` events, one row per id moved.
+CREATE TABLE IF NOT EXISTS erc1155_transfers (
+    transaction_hash BLOB NOT NULL,
+    log_index BIGINT NOT NULL,
+    -- Position of the id in a `TransferBatch`, 0 for a `TransferSingle`.
+    batch_index BIGINT NOT NULL,
+    contract BLOB NOT NULL,
+    operator BLOB NOT NULL,
+    from_address BLOB NOT NULL,
+    to_address BLOB NOT NULL,
+    -- 32 byte big-endian integers.
+    token_id BLOB NOT NULL,
+    value BLOB NOT NULL,
+    block_number BIGINT NOT NULL,
+    PRIMARY KEY (transaction_hash, log_index, batch_index),
+    FOREIGN KEY (block_number) REFERENCES blocks (number) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS erc1155_transfers_token ON erc1155_transfers (contract, token_id, block_number);
+CREATE INDEX IF NOT EXISTS erc1155_transfers_block_number ON erc1155_transfers (block_number);
+
+-- Amounts of each ERC-1155 id received and sent by a holder, summed from the indexed transfers.
+-- The balance is their difference, unknown when the holder sent more than it received since the
+-- first indexed block.
+CREATE TABLE IF NOT EXISTS erc1155_balances (
+    contract BLOB NOT NULL,
+    token_id BLOB NOT NULL,
+    holder BLOB NOT NULL,
+    -- 32 byte big-endian integers.
+    received BLOB NOT NULL,
+    sent BLOB NOT NULL,
+    PRIMARY KEY (contract, token_id, holder)
+);
+
+CREATE INDEX IF NOT EXISTS erc1155_balances_holder ON erc1155_balances (holder);
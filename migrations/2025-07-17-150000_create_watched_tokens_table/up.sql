@@ -0,0 +1,14 @@
+-- ERC-20 tokens whose balances are tracked, replacing the list built into the indexer.
+CREATE TABLE IF NOT EXISTS watched_tokens (
+    address BLOB PRIMARY KEY,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- Whether the balances of the token's past holders are still to be fetched.
+    backfill_pending BOOLEAN NOT NULL DEFAULT FALSE,
+    added_at BIGINT NOT NULL
+);
+
+-- The tokens tracked until now: USDC, WETH and WBTC.
+INSERT OR IGNORE INTO watched_tokens (address, added_at) VALUES
+    (x'a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48', 0),
+    (x'c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2', 0),
+    (x'2260fac5e5542a773aa44fbcfedf7c193bc2c599', 0);
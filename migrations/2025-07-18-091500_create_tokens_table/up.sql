@@ -0,0 +1,9 @@
+-- Metadata of the ERC-20 tokens seen in transfers, fetched once from the token contract.
+-- Fields a token does not implement are left NULL.
+CREATE TABLE IF NOT EXISTS tokens (
+    address BLOB PRIMARY KEY,
+    name TEXT,
+    symbol TEXT,
+    decimals INTEGER,
+    discovered_at BIGINT NOT NULL
+);
@@ -0,0 +1,10 @@
+-- Balances the node could not serve at their block, waiting to be fetched again.
+-- The zero token stands for the native balance.
+CREATE TABLE IF NOT EXISTS missing_balances (
+    account BLOB NOT NULL,
+    token BLOB NOT NULL,
+    block_id BIGINT NOT NULL REFERENCES blocks(number) ON DELETE CASCADE,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    PRIMARY KEY (account, token, block_id)
+);
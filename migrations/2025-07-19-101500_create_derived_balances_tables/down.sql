@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS balance_drifts;
+DROP TABLE IF EXISTS derived_balances;
+DROP TABLE IF EXISTS balance_deltas;
@@ -0,0 +1,39 @@
+-- Changes each block made to the balances of watched tokens, when they are derived from
+-- transfers. Only the applied ones were added to `derived_balances`: a change is not applied
+-- before its balance is seeded, nor to a balance seeded at a later block.
+CREATE TABLE IF NOT EXISTS balance_deltas (
+    account BLOB NOT NULL,
+    token BLOB NOT NULL,
+    block_id BIGINT NOT NULL REFERENCES blocks(number) ON DELETE CASCADE,
+    -- 32 byte big-endian two's complement integer.
+    delta BLOB NOT NULL,
+    applied BOOLEAN NOT NULL,
+    PRIMARY KEY (account, token, block_id)
+);
+
+CREATE INDEX IF NOT EXISTS balance_deltas_block_id ON balance_deltas (block_id);
+
+-- Balance of each account in each watched token, fetched once at `seeded_block` and updated
+-- with the changes of the following blocks.
+CREATE TABLE IF NOT EXISTS derived_balances (
+    account BLOB NOT NULL,
+    token BLOB NOT NULL,
+    -- 32 byte big-endian two's complement integer.
+    balance BLOB NOT NULL,
+    seeded_block BIGINT NOT NULL,
+    updated_block BIGINT NOT NULL,
+    -- Unix timestamp of the last comparison with the token contract, 0 if never compared.
+    checked_at BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (account, token)
+);
+
+-- Derived balances that did not match the token contract when last compared.
+CREATE TABLE IF NOT EXISTS balance_drifts (
+    account BLOB NOT NULL,
+    token BLOB NOT NULL,
+    block_id BIGINT NOT NULL REFERENCES blocks(number) ON DELETE CASCADE,
+    derived BLOB NOT NULL,
+    onchain BLOB NOT NULL,
+    detected_at BIGINT NOT NULL,
+    PRIMARY KEY (account, token)
+);
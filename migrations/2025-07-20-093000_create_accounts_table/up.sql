@@ -0,0 +1,15 @@
+-- Latest balance of each account in each token, the zero token standing for the native balance,
+-- so it can be read without going through every snapshot in `balances`.
+CREATE TABLE IF NOT EXISTS accounts (
+    account BLOB NOT NULL,
+    token BLOB NOT NULL,
+    -- 32 byte big-endian integer.
+    balance BLOB NOT NULL,
+    -- Block the balance was fetched at.
+    block_id BIGINT NOT NULL REFERENCES blocks(number) ON DELETE CASCADE,
+    PRIMARY KEY (account, token)
+);
+
+-- With a single MAX, SQLite takes the other columns from the row holding the maximum.
+INSERT OR IGNORE INTO accounts (account, token, balance, block_id)
+SELECT account, token, balance, MAX(block_id) FROM balances GROUP BY account, token;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS token_stats;
+DROP INDEX IF EXISTS accounts_token_balance;
@@ -0,0 +1,16 @@
+-- Balances are 32 byte big-endian integers, so they sort by value.
+CREATE INDEX IF NOT EXISTS accounts_token_balance ON accounts (token, balance);
+
+-- Statistics of each token, kept up to date as balances are stored.
+CREATE TABLE IF NOT EXISTS token_stats (
+    token BLOB PRIMARY KEY,
+    -- Accounts whose latest balance is not zero.
+    holders BIGINT NOT NULL DEFAULT 0,
+    -- 32 byte big-endian integer, as returned by `totalSupply`.
+    total_supply BLOB,
+    -- Unix timestamp of when `total_supply` was fetched.
+    supply_updated_at BIGINT
+);
+
+INSERT OR IGNORE INTO token_stats (token, holders)
+SELECT token, COUNT(*) FROM accounts WHERE balance != zeroblob(32) GROUP BY token;
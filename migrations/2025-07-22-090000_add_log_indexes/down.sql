@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS logs_address;
+DROP INDEX IF EXISTS logs_block_number;
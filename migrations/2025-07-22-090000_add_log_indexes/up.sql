@@ -0,0 +1,3 @@
+-- Logs are filtered by block range and by contract, and returned in chain order.
+CREATE INDEX IF NOT EXISTS logs_block_number ON logs (block_number, log_index);
+CREATE INDEX IF NOT EXISTS logs_address ON logs (address, block_number);
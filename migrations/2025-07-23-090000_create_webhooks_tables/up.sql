@@ -0,0 +1,34 @@
+-- Webhooks notified of the stored blocks matching their filter. Unset filter fields match
+-- anything; with `topic`, logs are matched instead of transfers.
+CREATE TABLE IF NOT EXISTS webhooks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    -- Key of the HMAC-SHA256 signature of each payload.
+    secret TEXT NOT NULL,
+    address BLOB,
+    token BLOB,
+    topic BLOB,
+    -- 32 byte big-endian integer.
+    min_value BLOB,
+    created_at BIGINT NOT NULL
+);
+
+-- Every payload sent, or to be sent, to a webhook, with the outcome of its last attempt.
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    webhook_id INTEGER NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    block_number BIGINT NOT NULL,
+    block_hash BLOB NOT NULL,
+    payload TEXT NOT NULL,
+    -- `pending`, `delivered`, `failed` or `cancelled`.
+    status TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at BIGINT NOT NULL,
+    last_status_code INTEGER,
+    last_error TEXT,
+    created_at BIGINT NOT NULL,
+    delivered_at BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_due ON webhook_deliveries(status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS webhook_deliveries_webhook ON webhook_deliveries(webhook_id, id);
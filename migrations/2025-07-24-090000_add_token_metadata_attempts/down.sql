@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE tokens DROP COLUMN retry_at;
+ALTER TABLE tokens DROP COLUMN metadata_attempts;
@@ -0,0 +1,4 @@
+-- Tokens whose metadata calls all failed are stored without metadata, and looked up again at
+-- `retry_at`, which is NULL once the metadata is fetched.
+ALTER TABLE tokens ADD COLUMN metadata_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE tokens ADD COLUMN retry_at BIGINT;
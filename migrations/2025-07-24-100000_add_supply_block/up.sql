@@ -0,0 +1,2 @@
+-- Stored block `total_supply` was read at, so that it matches the balances of that block.
+ALTER TABLE token_stats ADD COLUMN supply_block BIGINT;
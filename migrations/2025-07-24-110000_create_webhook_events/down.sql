@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS webhook_deliveries_block;
+DROP TABLE IF EXISTS webhook_events;
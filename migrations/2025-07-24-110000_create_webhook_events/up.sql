@@ -0,0 +1,11 @@
+-- Blocks stored or rolled back whose webhook deliveries are still to be queued. Rows are written
+-- along with the blocks, while webhooks exist, and removed once the deliveries are queued.
+CREATE TABLE IF NOT EXISTS webhook_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    block_number BIGINT NOT NULL,
+    block_hash BLOB NOT NULL,
+    -- Whether the block was rolled back, rather than stored.
+    removed BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_block ON webhook_deliveries(block_hash);
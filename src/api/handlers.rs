@@ -1,87 +1,970 @@
+use alloy::primitives::U256;
 use axum::{
-    extract::{Path, State},
     Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
 };
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tokio::sync::{Mutex, Notify, watch};
 
-use crate::{api::models::InternalErrors, db::Database};
 use crate::{
-    api::models::{ApiResponse, Transaction},
-    types::Info,
+    api::models::{
+        AccountBalance, ApiResponse, BalanceDrift, BalanceSnapshot, Block, BlockDetail,
+        BlockListParams, BlockParams, CreateWebhookRequest, Cursor, CursorKey, DeadLetterBlock,
+        Erc1155Balance, FinalityParams, Log, LogParams, MissingBalance, NftOwnership, NftTransfer,
+        OrphanedBlock, Page, PageParams, Receipt, Status, Token, TokenHolder, TokenStats,
+        TokenTransfer, Transaction, TransferParams, WatchTokenRequest, WatchedToken, Webhook,
+        WebhookDelivery,
+    },
+    eth_client::metrics::RpcMetrics,
+    types::{ConnectionStatus, Finality, RpcStats, WebhookFilter},
+};
+use crate::{
+    api::{TokenRegistry, models::InternalErrors},
+    db::{BalanceHistoryFilter, Database, LogFilter, TransferFilter, TransferParty},
 };
 
+const DEFAULT_LIMIT: u32 = 100;
+
+/// Most items returned in one page.
+const MAX_LIMIT: u32 = 1000;
+
+/// Most blocks a log filter may span.
+pub(crate) const MAX_LOG_BLOCK_RANGE: u64 = 10_000;
+
+/// Most addresses, or topics at one position, a log filter may list.
+const MAX_LOG_FILTER_VALUES: usize = 100;
+
+/// Decimals of the native currency, whose balances are stored under the zero address.
+const NATIVE_DECIMALS: u8 = 18;
+
+pub(crate) fn parse_address(address: &str) -> Result<[u8; 20], InternalErrors> {
+    hex::decode(address)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| InternalErrors::InvalidAddress(address.to_string()))
+}
+
+pub(crate) fn parse_hash(hash: &str) -> Result<[u8; 32], InternalErrors> {
+    hex::decode(hash)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| InternalErrors::InvalidHash(hash.to_string()))
+}
+
+fn page_limit(limit: Option<u32>) -> usize {
+    limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize
+}
+
+/// The sort key of the last item of the previous page, from its `next_cursor`.
+fn after<K: CursorKey>(cursor: Option<&str>) -> Result<Option<K>, InternalErrors> {
+    cursor.map(Cursor::decode).transpose()
+}
+
+/// Where a page of the contents of a block starts, counted from the start of the block.
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    limit: usize,
+    offset: usize,
+}
+
+impl Position {
+    /// How many items to fetch: one more than a page, to tell whether there is a next one.
+    fn fetch(&self) -> i64 {
+        self.limit as i64 + 1
+    }
+
+    fn offset(&self) -> i64 {
+        self.offset as i64
+    }
+
+    /// Starts at `cursor` within the contents of the block with `hash`. A cursor given for another
+    /// block, such as the one stored at the same number before a reorg, is refused.
+    fn within(
+        hash: [u8; 32],
+        limit: Option<u32>,
+        cursor: Option<&str>,
+    ) -> Result<Self, InternalErrors> {
+        let offset = match cursor {
+            Some(cursor) => {
+                let (block, offset): ([u8; 32], u64) = Cursor::decode(cursor)?;
+                if block != hash {
+                    return Err(InternalErrors::InvalidCursor(cursor.to_string()));
+                }
+                offset as usize
+            }
+            None => 0,
+        };
+        Ok(Position {
+            limit: page_limit(limit),
+            offset,
+        })
+    }
+
+    fn page_within<T>(&self, hash: [u8; 32], items: Vec<T>) -> Page<T> {
+        let next = (self.offset + self.limit) as u64;
+        Page::truncate(items, self.limit, |_| Cursor::encode((hash, next)))
+    }
+}
+
+#[tracing::instrument(skip(db, connection))]
+pub async fn get_status(
+    State(db): State<Arc<Mutex<Database>>>,
+    State(connection): State<watch::Receiver<ConnectionStatus>>,
+) -> ApiResponse<Status> {
+    let connection = connection.borrow().clone();
+    let mut db = db.lock().await;
+    let mut heads = [None; 3];
+    for (head, finality) in heads.iter_mut().zip(Finality::ALL) {
+        *head = db
+            .query_highest_block_number(finality)
+            .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    }
+    let [latest_block, safe_block, finalized_block] = heads;
+    Ok(Json(Status {
+        state: connection.state,
+        endpoint: connection.endpoint,
+        since: connection.since,
+        reconnects: connection.reconnects,
+        latest_block,
+        safe_block,
+        finalized_block,
+    }))
+}
+
+/// Returns the counts of the calls made to the node, overall and per fetched block.
+#[tracing::instrument(skip(metrics))]
+pub async fn get_metrics(State(metrics): State<Arc<RpcMetrics>>) -> Json<RpcStats> {
+    Json(metrics.stats())
+}
+
+/// Lists the headers of the stored blocks, lowest number first.
+#[tracing::instrument(skip(db))]
+pub async fn get_blocks(
+    Query(params): Query<BlockListParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<Block>> {
+    let after = after(params.cursor.as_deref())?;
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    let blocks = db
+        .query_blocks(
+            params.from,
+            params.to,
+            after,
+            limit as i64 + 1,
+            params.finality,
+        )
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    Ok(Json(
+        Page::truncate(blocks, limit, |block| Cursor::encode(block.number)).map(Block::from),
+    ))
+}
+
+/// Returns `block` with the pages of its transactions and logs `params` asks for. The contents
+/// stored under a block hash never change, so pages of them are counted from the start, their
+/// cursors holding the hash: a reorg can replace the block stored at a number.
+fn block_detail(
+    db: &mut Database,
+    block: crate::types::Block,
+    params: &BlockParams,
+) -> ApiResponse<BlockDetail> {
+    let hash = block.hash;
+    let transactions = Position::within(
+        hash,
+        params.transaction_limit,
+        params.transaction_cursor.as_deref(),
+    )?;
+    let logs = Position::within(hash, params.log_limit, params.log_cursor.as_deref())?;
+    let block_transactions = db
+        .query_block_transactions(block.number, transactions.fetch(), transactions.offset())
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    let block_logs = db
+        .query_block_logs(block.number, logs.fetch(), logs.offset())
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    Ok(Json(BlockDetail {
+        block: block.into(),
+        transactions: transactions
+            .page_within(hash, block_transactions)
+            .map(Transaction::from),
+        logs: logs.page_within(hash, block_logs).map(Log::from),
+    }))
+}
+
 #[tracing::instrument(skip(db))]
 pub async fn get_block_by_number(
     Path(number): Path<u64>,
+    Query(params): Query<BlockParams>,
     State(db): State<Arc<Mutex<Database>>>,
-) -> ApiResponse<Info> {
+) -> ApiResponse<BlockDetail> {
     let mut db = db.lock().await;
-    match db.query_block_by_number(number) {
-        Ok(block) => Ok(Json(block)),
-        Err(_) => Err(InternalErrors::BlockNotFound(number.to_string())),
-    }
+    let block = db
+        .query_block_header_by_number(number, params.finality)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?
+        .ok_or_else(|| InternalErrors::BlockNotFound(number.to_string()))?;
+    block_detail(&mut db, block, &params)
 }
 
 #[tracing::instrument(skip(db))]
 pub async fn get_block_by_hash(
     Path(hash): Path<String>,
+    Query(params): Query<BlockParams>,
     State(db): State<Arc<Mutex<Database>>>,
-) -> ApiResponse<Info> {
+) -> ApiResponse<BlockDetail> {
     let Ok(hash_parsed) = hex::decode(hash.clone()) else {
         return Err(InternalErrors::InvalidHash(hash));
     };
     let mut db = db.lock().await;
-    match db.query_block_by_hash(hash_parsed.as_slice()) {
-        Ok(block) => Ok(Json(block)),
-        Err(_) => Err(InternalErrors::BlockNotFound(hash)),
-    }
+    let block = db
+        .query_block_header_by_hash(hash_parsed.as_slice(), params.finality)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?
+        .ok_or(InternalErrors::BlockNotFound(hash))?;
+    block_detail(&mut db, block, &params)
+}
+
+/// Returns the highest stored block that is at least as settled as asked.
+#[tracing::instrument(skip(db))]
+pub async fn get_latest_block(
+    Query(params): Query<BlockParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<BlockDetail> {
+    let mut db = db.lock().await;
+    let not_found = || InternalErrors::BlockNotFound(params.finality.as_str().to_string());
+    let number = db
+        .query_highest_block_number(params.finality)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?
+        .ok_or_else(not_found)?;
+    let block = db
+        .query_block_header_by_number(number, params.finality)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?
+        .ok_or_else(not_found)?;
+    block_detail(&mut db, block, &params)
 }
 
 #[tracing::instrument(skip(db))]
 pub async fn get_transaction_by_hash(
     Path(hash): Path<String>,
+    Query(params): Query<FinalityParams>,
     State(db): State<Arc<Mutex<Database>>>,
 ) -> ApiResponse<Transaction> {
     let Ok(hash_parsed) = hex::decode(hash.clone()) else {
         return Err(InternalErrors::InvalidHash(hash));
     };
     let mut db = db.lock().await;
-    match db.query_transaction_by_hash(hash_parsed.as_slice()) {
+    match db.query_transaction_by_hash(hash_parsed.as_slice(), params.finality) {
         Ok(transaction) => Ok(Json(transaction.into())),
         Err(_) => Err(InternalErrors::TransactionNotFound(hash)),
     }
 }
 
-// pub async fn get_logs_filtered(
-//     Query(params): Query<HashMap<String, String>>,
-//     State(db): State<Arc<Database>>,
-// ) -> impl IntoResponse {
-//     let block_hash = params.get("block_hash").map(|s| decode(s).ok()).flatten();
-//     let transaction_hash = params
-//         .get("transaction_hash")
-//         .map(|s| decode(s).ok())
-//         .flatten();
-//     let topics = params.get("topics").map(|s| {
-//         s.split(',')
-//             .map(|t| decode(t).ok())
-//             .collect::<Vec<Option<Vec<u8>>>>()
-//             .into_iter()
-//             .filter_map(|x| x)
-//             .collect::<Vec<Vec<u8>>>()
-//     });
-
-//     let topics_refs: Vec<&[u8]> = topics
-//         .as_ref()
-//         .map_or(vec![], |v| v.iter().map(|x| x.as_slice()).collect());
-
-//     let mut db = db.as_ref().clone();
-//     match db.get_logs_filtered(
-//         block_hash.as_deref(),
-//         transaction_hash.as_deref(),
-//         topics_refs,
-//     ) {
-//         Ok(logs) => Json(json!(logs.into_iter().map(Log::from).collect::<Vec<Log>>())),
-//         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-//     }
-// }
+#[tracing::instrument(skip(db))]
+pub async fn get_transaction_receipt(
+    Path(hash): Path<String>,
+    Query(params): Query<FinalityParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Receipt> {
+    let Ok(hash_parsed) = hex::decode(hash.clone()) else {
+        return Err(InternalErrors::InvalidHash(hash));
+    };
+    let mut db = db.lock().await;
+    match db.query_receipt_by_transaction_hash(hash_parsed.as_slice(), params.finality) {
+        Ok(receipt) => Ok(Json(receipt.into())),
+        Err(_) => Err(InternalErrors::ReceiptNotFound(hash)),
+    }
+}
+
+/// Lists the transactions of a block that were reverted.
+#[tracing::instrument(skip(db))]
+pub async fn get_failed_transactions(
+    Path(number): Path<u64>,
+    Query(params): Query<FinalityParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<Transaction>> {
+    let mut db = db.lock().await;
+    match db.query_failed_transactions(number, params.finality) {
+        Ok(transactions) => Ok(Json(Page::complete(transactions).map(Transaction::from))),
+        Err(_) => Err(InternalErrors::BlockNotFound(number.to_string())),
+    }
+}
+
+async fn get_transfers(
+    db: &Mutex<Database>,
+    party: TransferParty,
+    params: TransferParams,
+) -> ApiResponse<Page<TokenTransfer>> {
+    let filter = TransferFilter {
+        party,
+        from_block: params.from_block,
+        to_block: params.to_block,
+        finality: params.finality,
+    };
+    let after = after(params.cursor.as_deref())?;
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    let transfers = db
+        .query_token_transfers(filter, after, limit as i64 + 1)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+
+    let tokens: Vec<[u8; 20]> = transfers.iter().map(|t| t.token).collect();
+    let decimals = token_decimals(&mut db, &tokens)?;
+    let page = Page::truncate(transfers, limit, |t| {
+        Cursor::encode((t.block_number, t.log_index))
+    });
+    Ok(Json(page.map(|t| {
+        let decimals = decimals.get(&t.token).copied();
+        TokenTransfer::new(t, decimals)
+    })))
+}
+
+/// Returns the decimals of the `tokens` whose metadata is known, the zero address standing for the
+/// native currency.
+fn token_decimals(
+    db: &mut Database,
+    tokens: &[[u8; 20]],
+) -> Result<HashMap<[u8; 20], u8>, InternalErrors> {
+    let mut decimals: HashMap<[u8; 20], u8> = db
+        .query_token_metadata(tokens)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?
+        .into_iter()
+        .filter_map(|token| Some((token.address, token.decimals?)))
+        .collect();
+    decimals.insert([0; 20], NATIVE_DECIMALS);
+    Ok(decimals)
+}
+
+/// Returns the name, symbol and decimals of a token, once discovered in a transfer.
+#[tracing::instrument(skip(db))]
+pub async fn get_token(
+    Path(address): Path<String>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Token> {
+    let token = parse_address(&address)?;
+    let mut db = db.lock().await;
+    match db.query_token_metadata(&[token]) {
+        Ok(tokens) => match tokens.into_iter().next() {
+            Some(token) => Ok(Json(Token::from(token))),
+            None => Err(InternalErrors::TokenNotFound(address)),
+        },
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+/// Lists the accounts holding a token, largest balance first, from the latest balance fetched for
+/// each of them.
+#[tracing::instrument(skip(db))]
+pub async fn get_token_holders(
+    Path(address): Path<String>,
+    Query(params): Query<PageParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<TokenHolder>> {
+    let token = parse_address(&address)?;
+    // The rank of the last holder of the previous page goes along with its key.
+    let (mut rank, after) = match after(params.cursor.as_deref())? {
+        Some((rank, balance, account)) => (rank, Some((balance, account))),
+        None => (0, None),
+    };
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    let holders = db
+        .query_top_holders(&token, after, limit as i64 + 1)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    let decimals = token_decimals(&mut db, &[token])?.get(&token).copied();
+    let last_rank = rank + holders.len().min(limit) as u64;
+    let page = Page::truncate(holders, limit, |holder| {
+        Cursor::encode((last_rank, holder.balance, holder.account))
+    });
+    Ok(Json(page.map(|holder| {
+        rank += 1;
+        TokenHolder::new(rank, holder, decimals)
+    })))
+}
+
+/// Returns the holder count, total supply and share of the ten largest holders of a token, which
+/// must be watched or have had balances fetched.
+#[tracing::instrument(skip(db))]
+pub async fn get_token_stats(
+    Path(address): Path<String>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<TokenStats> {
+    let token = parse_address(&address)?;
+    let mut db = db.lock().await;
+    let stats = db
+        .query_token_stats(&token)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    let stats = match stats {
+        Some(stats) => stats,
+        None => {
+            let watched = db
+                .query_watched_tokens()
+                .map_err(|e| InternalErrors::Database(e.to_string()))?;
+            if !watched.iter().any(|w| w.address == token) {
+                return Err(InternalErrors::TokenNotFound(address));
+            }
+            crate::types::TokenStats {
+                token,
+                ..Default::default()
+            }
+        }
+    };
+    let top_10 = db
+        .query_top_holders(&token, None, 10)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    let decimals = token_decimals(&mut db, &[token])?.get(&token).copied();
+    Ok(Json(TokenStats::new(stats, &top_10, decimals)))
+}
+
+/// Lists the transfers of a token, most recent first.
+#[tracing::instrument(skip(db))]
+pub async fn get_token_transfers(
+    Path(address): Path<String>,
+    Query(params): Query<TransferParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<TokenTransfer>> {
+    let token = parse_address(&address)?;
+    get_transfers(&db, TransferParty::Token(token), params).await
+}
+
+/// Lists the token transfers sent or received by an account, most recent first.
+#[tracing::instrument(skip(db))]
+pub async fn get_account_transfers(
+    Path(address): Path<String>,
+    Query(params): Query<TransferParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<TokenTransfer>> {
+    let account = parse_address(&address)?;
+    get_transfers(&db, TransferParty::Account(account), params).await
+}
+
+/// Lists the ERC-721 tokens an account currently owns.
+#[tracing::instrument(skip(db))]
+pub async fn get_account_nfts(
+    Path(address): Path<String>,
+    Query(params): Query<PageParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<NftOwnership>> {
+    let owner = parse_address(&address)?;
+    let after = after(params.cursor.as_deref())?;
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    match db.query_nfts_owned(&owner, after, limit as i64 + 1) {
+        Ok(owned) => Ok(Json(
+            Page::truncate(owned, limit, |nft| {
+                Cursor::encode((nft.collection, nft.token_id))
+            })
+            .map(NftOwnership::from),
+        )),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+/// Lists the latest balance of an account in each token, the native balance under the zero
+/// address. A balance derived from transfers takes the place of the last one fetched for the same
+/// token.
+#[tracing::instrument(skip(db))]
+pub async fn get_account_balances(
+    Path(address): Path<String>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<AccountBalance>> {
+    let account = parse_address(&address)?;
+    let mut db = db.lock().await;
+    let fetched = db
+        .query_account_balances(&account)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    let derived = db
+        .query_derived_balances(&account)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+
+    let tokens: Vec<[u8; 20]> = fetched
+        .iter()
+        .map(|b| b.token)
+        .chain(derived.iter().map(|b| b.token))
+        .collect();
+    let decimals = token_decimals(&mut db, &tokens)?;
+    let derived_tokens: HashSet<[u8; 20]> = derived.iter().map(|b| b.token).collect();
+    let mut balances: Vec<AccountBalance> = fetched
+        .into_iter()
+        .filter(|b| !derived_tokens.contains(&b.token))
+        .map(|b| {
+            let decimals = decimals.get(&b.token).copied();
+            AccountBalance::new(b, decimals)
+        })
+        .chain(derived.into_iter().map(|b| {
+            let decimals = decimals.get(&b.token).copied();
+            AccountBalance::derived(b, decimals)
+        }))
+        .collect();
+    balances.sort_by(|a, b| a.token.cmp(&b.token));
+    Ok(Json(Page::complete(balances)))
+}
+
+/// Lists the balances stored for an account in a token, oldest first. The native balance is
+/// under the zero address.
+#[tracing::instrument(skip(db))]
+pub async fn get_balance_history(
+    Path((address, token)): Path<(String, String)>,
+    Query(params): Query<TransferParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<BalanceSnapshot>> {
+    let filter = BalanceHistoryFilter {
+        account: parse_address(&address)?,
+        token: parse_address(&token)?,
+        from_block: params.from_block,
+        to_block: params.to_block,
+        finality: params.finality,
+    };
+    let after = after(params.cursor.as_deref())?;
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    let history = db
+        .query_balance_history(filter, after, limit as i64 + 1)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    let decimals = token_decimals(&mut db, &[filter.token])?
+        .get(&filter.token)
+        .copied();
+    Ok(Json(
+        Page::truncate(history, limit, |snapshot| Cursor::encode(snapshot.block_id))
+            .map(|snapshot| BalanceSnapshot::new(snapshot, decimals)),
+    ))
+}
+
+/// Lists the ERC-1155 balances of an account.
+#[tracing::instrument(skip(db))]
+pub async fn get_account_erc1155_balances(
+    Path(address): Path<String>,
+    Query(params): Query<PageParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<Erc1155Balance>> {
+    let holder = parse_address(&address)?;
+    let after = after(params.cursor.as_deref())?;
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    match db.query_erc1155_balances(&holder, after, limit as i64 + 1) {
+        Ok(balances) => Ok(Json(
+            Page::truncate(balances, limit, |balance| {
+                Cursor::encode((balance.contract, balance.token_id))
+            })
+            .map(Erc1155Balance::from),
+        )),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+/// Lists the transfers of an ERC-721 token, most recent first. The token id is either decimal or
+/// `0x`-prefixed hexadecimal.
+#[tracing::instrument(skip(db))]
+pub async fn get_nft_history(
+    Path((collection, token_id)): Path<(String, String)>,
+    Query(params): Query<TransferParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<NftTransfer>> {
+    let collection = parse_address(&collection)?;
+    let Ok(token_id_parsed) = token_id.parse::<U256>() else {
+        return Err(InternalErrors::InvalidTokenId(token_id));
+    };
+    let filter = TransferFilter {
+        party: TransferParty::Token(collection),
+        from_block: params.from_block,
+        to_block: params.to_block,
+        finality: params.finality,
+    };
+    let after = after(params.cursor.as_deref())?;
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    match db.query_nft_transfers(
+        filter,
+        Some(token_id_parsed.to_be_bytes()),
+        after,
+        limit as i64 + 1,
+    ) {
+        Ok(transfers) => Ok(Json(
+            Page::truncate(transfers, limit, |t| {
+                Cursor::encode((t.block_number, t.log_index))
+            })
+            .map(NftTransfer::from),
+        )),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn get_orphaned_blocks(
+    Query(params): Query<PageParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<OrphanedBlock>> {
+    let after = after(params.cursor.as_deref())?;
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    match db.query_orphaned_blocks(after, limit as i64 + 1) {
+        Ok(blocks) => Ok(Json(
+            Page::truncate(blocks, limit, |block| {
+                Cursor::encode((block.orphaned_at, block.number, block.hash))
+            })
+            .map(OrphanedBlock::from),
+        )),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn get_dead_letters(
+    Query(params): Query<PageParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<DeadLetterBlock>> {
+    let after = after(params.cursor.as_deref())?;
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    match db.query_dead_letters(after, limit as i64 + 1) {
+        Ok(blocks) => Ok(Json(
+            Page::truncate(blocks, limit, |block| Cursor::encode(block.number))
+                .map(DeadLetterBlock::from),
+        )),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+/// Lists the balances the node could not serve at their block, most recent first. Those
+/// attempted 5 times are no longer fetched again.
+#[tracing::instrument(skip(db))]
+pub async fn get_missing_balances(
+    Query(params): Query<PageParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<MissingBalance>> {
+    let after = after(params.cursor.as_deref())?;
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    match db.query_missing_balances(after, limit as i64 + 1) {
+        Ok(balances) => Ok(Json(
+            Page::truncate(balances, limit, |balance| {
+                Cursor::encode((balance.block_id, balance.account, balance.token))
+            })
+            .map(MissingBalance::from),
+        )),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+/// Lists the derived balances that differed from their token contract when last compared, most
+/// recently detected first.
+#[tracing::instrument(skip(db))]
+pub async fn get_balance_drifts(
+    Query(params): Query<PageParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<BalanceDrift>> {
+    let after = after(params.cursor.as_deref())?;
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    match db.query_balance_drifts(after, limit as i64 + 1) {
+        Ok(drifts) => Ok(Json(
+            Page::truncate(drifts, limit, |drift| {
+                Cursor::encode((drift.detected_at, drift.token, drift.account))
+            })
+            .map(BalanceDrift::from),
+        )),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+/// Queues a dead-lettered block to be fetched again right away, whatever its attempt count.
+#[tracing::instrument(skip(db, worker))]
+pub async fn retry_dead_letter(
+    Path(number): Path<u64>,
+    State(db): State<Arc<Mutex<Database>>>,
+    State(worker): State<Arc<Notify>>,
+) -> Result<StatusCode, InternalErrors> {
+    let mut db = db.lock().await;
+    match db.reset_dead_letter(number) {
+        Ok(true) => {
+            worker.notify_one();
+            Ok(StatusCode::ACCEPTED)
+        }
+        Ok(false) => Err(InternalErrors::DeadLetterNotFound(number.to_string())),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn get_watched_tokens(
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<WatchedToken>> {
+    let mut db = db.lock().await;
+    match db.query_watched_tokens() {
+        Ok(tokens) => Ok(Json(Page::complete(tokens).map(WatchedToken::from))),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+/// Starts watching a token, or enables it again if it was already registered.
+#[tracing::instrument(skip(db, registry))]
+pub async fn add_watched_token(
+    State(db): State<Arc<Mutex<Database>>>,
+    State(registry): State<TokenRegistry>,
+    Json(request): Json<WatchTokenRequest>,
+) -> Result<StatusCode, InternalErrors> {
+    let address = parse_address(&request.address)?;
+    let mut db = db.lock().await;
+    let created = db
+        .add_watched_token(&address, request.backfill)
+        .and_then(|created| registry.reload(&mut db).map(|()| created))
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    if request.backfill {
+        registry.backfills.notify_one();
+    }
+    Ok(if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    })
+}
+
+/// Stops watching a token. The balances already stored for it are kept.
+#[tracing::instrument(skip(db, registry))]
+pub async fn remove_watched_token(
+    Path(address): Path<String>,
+    State(db): State<Arc<Mutex<Database>>>,
+    State(registry): State<TokenRegistry>,
+) -> Result<StatusCode, InternalErrors> {
+    let token = parse_address(&address)?;
+    let mut db = db.lock().await;
+    match db.remove_watched_token(&token) {
+        Ok(true) => registry
+            .reload(&mut db)
+            .map(|()| StatusCode::NO_CONTENT)
+            .map_err(|e| InternalErrors::Database(e.to_string())),
+        Ok(false) => Err(InternalErrors::TokenNotFound(address)),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+async fn set_token_enabled(
+    db: &Mutex<Database>,
+    registry: &TokenRegistry,
+    address: String,
+    enabled: bool,
+) -> Result<StatusCode, InternalErrors> {
+    let token = parse_address(&address)?;
+    let mut db = db.lock().await;
+    match db.set_token_enabled(&token, enabled) {
+        Ok(true) => registry
+            .reload(&mut db)
+            .map(|()| StatusCode::NO_CONTENT)
+            .map_err(|e| InternalErrors::Database(e.to_string())),
+        Ok(false) => Err(InternalErrors::TokenNotFound(address)),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+#[tracing::instrument(skip(db, registry))]
+pub async fn enable_watched_token(
+    Path(address): Path<String>,
+    State(db): State<Arc<Mutex<Database>>>,
+    State(registry): State<TokenRegistry>,
+) -> Result<StatusCode, InternalErrors> {
+    set_token_enabled(&db, &registry, address, true).await
+}
+
+/// Pauses a token: its balances are no longer fetched, but it stays registered.
+#[tracing::instrument(skip(db, registry))]
+pub async fn disable_watched_token(
+    Path(address): Path<String>,
+    State(db): State<Arc<Mutex<Database>>>,
+    State(registry): State<TokenRegistry>,
+) -> Result<StatusCode, InternalErrors> {
+    set_token_enabled(&db, &registry, address, false).await
+}
+
+/// Parses a comma-separated list of values, any of which may match. Missing or empty, it matches
+/// anything.
+pub(crate) fn parse_any_of<T>(
+    list: Option<&str>,
+    parse: fn(&str) -> Result<T, InternalErrors>,
+) -> Result<Vec<T>, InternalErrors> {
+    let values = match list {
+        Some(list) if !list.is_empty() => {
+            list.split(',').map(parse).collect::<Result<Vec<_>, _>>()?
+        }
+        _ => Vec::new(),
+    };
+    if values.len() > MAX_LOG_FILTER_VALUES {
+        return Err(InternalErrors::InvalidLogFilter(format!(
+            "at most {MAX_LOG_FILTER_VALUES} values can be listed"
+        )));
+    }
+    Ok(values)
+}
+
+/// Lists the logs matching a filter in chain order, a page at a time.
+///
+/// Without `block_hash`, the range of blocks defaults to the highest stored block, like
+/// `eth_getLogs` defaults to `latest`.
+#[tracing::instrument(skip(db))]
+pub async fn get_logs(
+    Query(params): Query<LogParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<Log>> {
+    let addresses = parse_any_of(params.address.as_deref(), parse_address)?;
+    let topics = [
+        &params.topic0,
+        &params.topic1,
+        &params.topic2,
+        &params.topic3,
+    ]
+    .into_iter()
+    .map(|topics| parse_any_of(topics.as_deref(), parse_hash))
+    .collect::<Result<Vec<_>, _>>()?;
+    let after = after(params.cursor.as_deref())?;
+    let limit = page_limit(params.limit);
+
+    let mut db = db.lock().await;
+    let (from_block, to_block) = match &params.block_hash {
+        Some(hash) => {
+            if params.from_block.is_some() || params.to_block.is_some() {
+                return Err(InternalErrors::InvalidLogFilter(
+                    "block_hash cannot be combined with from_block or to_block".to_string(),
+                ));
+            }
+            let number = db
+                .query_block_number_by_hash(&parse_hash(hash)?, params.finality)
+                .map_err(|e| InternalErrors::Database(e.to_string()))?
+                .ok_or_else(|| InternalErrors::BlockNotFound(hash.clone()))?;
+            (number, number)
+        }
+        None => {
+            let highest = db
+                .query_highest_block_number(params.finality)
+                .map_err(|e| InternalErrors::Database(e.to_string()))?;
+            let Some(to_block) = params.to_block.or(highest) else {
+                return Ok(Json(Page::complete(Vec::new())));
+            };
+            (params.from_block.unwrap_or(to_block), to_block)
+        }
+    };
+    if from_block > to_block {
+        return Err(InternalErrors::InvalidLogFilter(
+            "from_block is above to_block".to_string(),
+        ));
+    }
+    if to_block - from_block >= MAX_LOG_BLOCK_RANGE {
+        return Err(InternalErrors::InvalidLogFilter(format!(
+            "at most {MAX_LOG_BLOCK_RANGE} blocks can be searched at once"
+        )));
+    }
+
+    let filter = LogFilter {
+        from_block: Some(from_block),
+        to_block: Some(to_block),
+        addresses,
+        topics,
+        finality: params.finality,
+    };
+    // One more log tells whether there is a next page.
+    let logs = db
+        .query_logs(&filter, after, limit as i64 + 1)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    Ok(Json(
+        Page::truncate(logs, limit, |(id, log)| {
+            Cursor::encode((log.block_number, *id))
+        })
+        .map(|(_, log)| Log::from(log)),
+    ))
+}
+
+/// Registers a webhook, returning it with its secret.
+#[tracing::instrument(skip(db, request), fields(url = %request.url))]
+pub async fn create_webhook(
+    State(db): State<Arc<Mutex<Database>>>,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<Webhook>), InternalErrors> {
+    let url = reqwest::Url::parse(&request.url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .ok_or_else(|| InternalErrors::InvalidWebhook(format!("invalid URL {}", request.url)))?;
+    let filter = WebhookFilter {
+        address: request.address.as_deref().map(parse_address).transpose()?,
+        token: request.token.as_deref().map(parse_address).transpose()?,
+        topic: request.topic.as_deref().map(parse_hash).transpose()?,
+        min_value: request
+            .min_value
+            .as_deref()
+            .map(|value| {
+                value
+                    .parse::<U256>()
+                    .map(|value| value.to_be_bytes())
+                    .map_err(|_| InternalErrors::InvalidWebhook(format!("invalid value {value}")))
+            })
+            .transpose()?,
+    };
+    if filter.address.is_none() && filter.token.is_none() && filter.topic.is_none() {
+        return Err(InternalErrors::InvalidWebhook(
+            "an address, token or topic is required".to_string(),
+        ));
+    }
+    let secret = request
+        .secret
+        .unwrap_or_else(|| hex::encode(rand::random::<[u8; 32]>()));
+
+    let mut db = db.lock().await;
+    let webhook = db
+        .create_webhook(url.as_str(), &secret, &filter)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    let secret = Some(webhook.secret.clone());
+    Ok((
+        StatusCode::CREATED,
+        Json(Webhook {
+            secret,
+            ..webhook.into()
+        }),
+    ))
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn get_webhooks(State(db): State<Arc<Mutex<Database>>>) -> ApiResponse<Page<Webhook>> {
+    let mut db = db.lock().await;
+    match db.query_webhooks() {
+        Ok(webhooks) => Ok(Json(Page::complete(webhooks).map(Webhook::from))),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+/// Removes a webhook, along with its pending deliveries and delivery log.
+#[tracing::instrument(skip(db))]
+pub async fn remove_webhook(
+    Path(id): Path<i32>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> Result<StatusCode, InternalErrors> {
+    let mut db = db.lock().await;
+    match db.remove_webhook(id) {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(InternalErrors::WebhookNotFound(id.to_string())),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
+
+/// Lists the deliveries made to a webhook, most recent first.
+#[tracing::instrument(skip(db))]
+pub async fn get_webhook_deliveries(
+    Path(id): Path<i32>,
+    Query(params): Query<PageParams>,
+    State(db): State<Arc<Mutex<Database>>>,
+) -> ApiResponse<Page<WebhookDelivery>> {
+    let before = after::<u64>(params.cursor.as_deref())?.map(|id| id as i32);
+    let limit = page_limit(params.limit);
+    let mut db = db.lock().await;
+    let webhook = db
+        .query_webhook(id)
+        .map_err(|e| InternalErrors::Database(e.to_string()))?;
+    if webhook.is_none() {
+        return Err(InternalErrors::WebhookNotFound(id.to_string()));
+    }
+    match db.query_webhook_deliveries(id, before, limit as i64 + 1) {
+        Ok(deliveries) => Ok(Json(
+            Page::truncate(deliveries, limit, |delivery| {
+                Cursor::encode(delivery.id as u64)
+            })
+            .map(WebhookDelivery::from),
+        )),
+        Err(e) => Err(InternalErrors::Database(e.to_string())),
+    }
+}
@@ -1,23 +1,230 @@
 pub mod handlers;
 pub mod models;
+pub mod rpc;
+pub mod stream;
 
-use crate::db::Database;
-use axum::{Router, routing::get};
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use crate::{
+    api::{models::InternalErrors, stream::ChainEvents},
+    db::Database,
+    eth_client::metrics::RpcMetrics,
+    types::ConnectionStatus,
+};
+use alloy::primitives::Address;
+use axum::{
+    Router,
+    extract::{FromRef, Request, State},
+    http::header::AUTHORIZATION,
+    middleware::{self, Next},
+    response::Response,
+    routing::{delete, get, post},
+};
+use sha2::{Digest, Sha256};
+use std::{collections::HashSet, sync::Arc};
+use subtle::ConstantTimeEq;
+use tokio::sync::{Mutex, Notify, watch};
 
-#[tracing::instrument(skip(db))]
-pub async fn run_api(db: Arc<Mutex<Database>>) {
-    let app = Router::new()
+/// Everything the handlers can extract with `State`.
+#[derive(Clone)]
+pub struct AppState {
+    pub db: Arc<Mutex<Database>>,
+    pub connection: watch::Receiver<ConnectionStatus>,
+    /// Wakes the dead-letter worker up.
+    pub dead_letters: Arc<Notify>,
+    pub tokens: TokenRegistry,
+    pub metrics: Arc<RpcMetrics>,
+    /// Blocks stored and rolled back by the indexer, pushed to `/ws` and `/events`.
+    pub events: ChainEvents,
+    /// Bearer token required by the admin routes, which refuse every request without one.
+    pub admin_token: Option<Arc<str>>,
+}
+
+/// Lets the admin API change which tokens the indexer watches.
+#[derive(Clone, Default)]
+pub struct TokenRegistry {
+    /// Enabled tokens, read by the indexer for each block it fetches.
+    pub watchlist: Arc<watch::Sender<HashSet<Address>>>,
+    /// Wakes the token backfill worker up.
+    pub backfills: Arc<Notify>,
+}
+
+impl TokenRegistry {
+    /// Publishes the tokens currently enabled in `db` to the indexer.
+    pub fn reload(&self, db: &mut Database) -> anyhow::Result<()> {
+        let tokens = db.query_enabled_tokens()?;
+        self.watchlist
+            .send_replace(tokens.into_iter().map(Address::from).collect());
+        Ok(())
+    }
+}
+
+impl FromRef<AppState> for Arc<Mutex<Database>> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.db)
+    }
+}
+
+impl FromRef<AppState> for watch::Receiver<ConnectionStatus> {
+    fn from_ref(state: &AppState) -> Self {
+        state.connection.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Notify> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.dead_letters)
+    }
+}
+
+impl FromRef<AppState> for Arc<RpcMetrics> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.metrics)
+    }
+}
+
+impl FromRef<AppState> for ChainEvents {
+    fn from_ref(state: &AppState) -> Self {
+        state.events.clone()
+    }
+}
+
+impl FromRef<AppState> for TokenRegistry {
+    fn from_ref(state: &AppState) -> Self {
+        state.tokens.clone()
+    }
+}
+
+/// Lets a request through only if it carries the admin token as `Authorization: Bearer`.
+async fn require_admin(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, InternalErrors> {
+    let Some(token) = state.admin_token.as_deref() else {
+        return Err(InternalErrors::Unauthorized(
+            "the admin API is disabled, set ADMIN_TOKEN".to_string(),
+        ));
+    };
+    let bearer = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !bearer.is_some_and(|bearer| same_token(bearer, token)) {
+        return Err(InternalErrors::Unauthorized(
+            "missing or invalid admin token".to_string(),
+        ));
+    }
+    Ok(next.run(request).await)
+}
+
+/// Compares the digests of the tokens in constant time, so that neither the time taken nor the
+/// length of the tokens tells how much of the admin token was guessed right.
+fn same_token(given: &str, token: &str) -> bool {
+    Sha256::digest(given).ct_eq(&Sha256::digest(token)).into()
+}
+
+/// Every route of the API.
+pub fn router(state: AppState) -> Router {
+    let admin = Router::new()
+        .route("/admin/dead_letters", get(handlers::get_dead_letters))
+        .route(
+            "/admin/dead_letters/{number}/retry",
+            post(handlers::retry_dead_letter),
+        )
+        .route(
+            "/admin/missing_balances",
+            get(handlers::get_missing_balances),
+        )
+        .route("/admin/balance_drifts", get(handlers::get_balance_drifts))
+        .route(
+            "/admin/tokens",
+            get(handlers::get_watched_tokens).post(handlers::add_watched_token),
+        )
+        .route(
+            "/admin/tokens/{address}",
+            delete(handlers::remove_watched_token),
+        )
+        .route(
+            "/admin/tokens/{address}/enable",
+            post(handlers::enable_watched_token),
+        )
+        .route(
+            "/admin/tokens/{address}/disable",
+            post(handlers::disable_watched_token),
+        )
+        .route(
+            "/webhooks",
+            get(handlers::get_webhooks).post(handlers::create_webhook),
+        )
+        .route("/webhooks/{id}", delete(handlers::remove_webhook))
+        .route(
+            "/webhooks/{id}/deliveries",
+            get(handlers::get_webhook_deliveries),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_admin));
+
+    Router::new()
+        .route("/status", get(handlers::get_status))
+        .route("/metrics", get(handlers::get_metrics))
+        .route("/blocks", get(handlers::get_blocks))
+        .route("/blocks/latest", get(handlers::get_latest_block))
         .route("/blocks/{number}", get(handlers::get_block_by_number))
+        .route("/blocks/orphaned", get(handlers::get_orphaned_blocks))
+        .route(
+            "/blocks/{number}/failed_transactions",
+            get(handlers::get_failed_transactions),
+        )
         .route("/blocks/hash/{hash}", get(handlers::get_block_by_hash))
         .route(
             "/transactions/{hash}",
             get(handlers::get_transaction_by_hash),
         )
-        // .route("/logs/filter", get(handlers::get_logs_filtered))
-        .with_state(db);
+        .route(
+            "/transactions/{hash}/receipt",
+            get(handlers::get_transaction_receipt),
+        )
+        .route("/tokens/{address}", get(handlers::get_token))
+        .route(
+            "/tokens/{address}/transfers",
+            get(handlers::get_token_transfers),
+        )
+        .route(
+            "/tokens/{address}/holders",
+            get(handlers::get_token_holders),
+        )
+        .route("/tokens/{address}/stats", get(handlers::get_token_stats))
+        .route(
+            "/accounts/{address}/transfers",
+            get(handlers::get_account_transfers),
+        )
+        .route(
+            "/accounts/{address}/balances",
+            get(handlers::get_account_balances),
+        )
+        .route(
+            "/accounts/{address}/balances/{token}/history",
+            get(handlers::get_balance_history),
+        )
+        .route("/accounts/{address}/nfts", get(handlers::get_account_nfts))
+        .route(
+            "/accounts/{address}/erc1155_balances",
+            get(handlers::get_account_erc1155_balances),
+        )
+        .route(
+            "/nfts/{collection}/{token_id}/history",
+            get(handlers::get_nft_history),
+        )
+        .route("/logs", get(handlers::get_logs))
+        .route("/rpc", post(rpc::handle))
+        .route("/ws", get(stream::subscribe_ws))
+        .route("/events", get(stream::subscribe_sse))
+        .merge(admin)
+        .with_state(state)
+}
 
+#[tracing::instrument(skip(state))]
+pub async fn run_api(state: AppState) {
+    let app = router(state);
     let listener = tokio::net::TcpListener::bind("127.0.0.1:8383")
         .await
         .unwrap();
@@ -29,26 +236,80 @@ mod tests {
     use std::time::Duration;
 
     use super::*;
+    use crate::types::{Balance, BalanceDrift};
+    use alloy::primitives::U256;
     use axum::http::StatusCode;
     use tokio::sync::OnceCell;
 
     static ONCE: OnceCell<Arc<Mutex<Database>>> = OnceCell::const_new();
+    const ADMIN_TOKEN: &str = "test-token";
+
+    /// A client sending the admin token along with every request.
+    fn admin_client() -> reqwest::Client {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            format!("Bearer {ADMIN_TOKEN}").parse().unwrap(),
+        );
+        reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap()
+    }
 
     // This helper function will spawn the server in the background, only once.
+    // The server gets a runtime of its own, since each test's runtime is dropped when it ends.
     async fn setup_app() -> Arc<Mutex<Database>> {
         let database = ONCE
             .get_or_init(|| async {
-                let database = Arc::new(Mutex::new(Database::connect_test()));
-                let db = Arc::clone(&database);
-                tokio::spawn(async {
-                    run_api(db).await;
+                let mut db = Database::connect_test();
+                db.insert_block(&Database::data_setup())
+                    .expect("Insertion failed.");
+                db.record_balance_checks(
+                    &[],
+                    &[BalanceDrift {
+                        account: [7; 20],
+                        token: [6; 20],
+                        block_id: 1,
+                        derived: U256::from(100).to_be_bytes(),
+                        onchain: U256::from(90).to_be_bytes(),
+                        detected_at: 1,
+                    }],
+                    1,
+                )
+                .expect("Insertion failed.");
+                db.insert_balances(&[Balance {
+                    account: [8; 20],
+                    token: [0; 20],
+                    balance: U256::from(10).pow(U256::from(18)).to_be_bytes(),
+                    block_id: 1,
+                }])
+                .expect("Insertion failed.");
+                let supply = U256::from_be_bytes([3; 32]) * U256::from(4);
+                db.update_total_supply(&[2; 20], &supply.to_be_bytes(), 1, 1)
+                    .expect("Insertion failed.");
+                let database = Arc::new(Mutex::new(db));
+                let (_, connection) = watch::channel(ConnectionStatus::default());
+                let state = AppState {
+                    db: Arc::clone(&database),
+                    connection,
+                    dead_letters: Arc::new(Notify::new()),
+                    tokens: TokenRegistry::default(),
+                    metrics: Arc::default(),
+                    events: ChainEvents::default(),
+                    admin_token: Some(Arc::from(ADMIN_TOKEN)),
+                };
+                std::thread::spawn(move || {
+                    tokio::runtime::Runtime::new()
+                        .expect("Failed to build the API runtime")
+                        .block_on(run_api(state));
                 });
                 // Give the server a moment to start up.
                 tokio::time::sleep(Duration::from_millis(100)).await;
                 database
             })
             .await;
-        
+
         database.clone()
     }
 
@@ -63,6 +324,114 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_get_block_by_finality() {
+        setup_app().await;
+
+        let response = reqwest::get("http://127.0.0.1:8383/blocks/1?finality=latest")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let block: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(block["block"]["finality"], "latest");
+
+        let response = reqwest::get("http://127.0.0.1:8383/blocks/1?finality=finalized")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = reqwest::get("http://127.0.0.1:8383/blocks/1?finality=unknown")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_blocks() {
+        setup_app().await;
+
+        let response = reqwest::get("http://127.0.0.1:8383/blocks?from=1&limit=1")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let page: serde_json::Value = response.json().await.unwrap();
+        let blocks = page["items"].as_array().unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(
+            blocks[0]["hash"],
+            "0101010101010101010101010101010101010101010101010101010101010101"
+        );
+        assert!(page["next_cursor"].is_null());
+
+        // A page starts after the block its cursor points at.
+        let response = reqwest::get("http://127.0.0.1:8383/blocks?cursor=0000000000000001")
+            .await
+            .unwrap();
+        let page: serde_json::Value = response.json().await.unwrap();
+        assert!(page["items"].as_array().unwrap().is_empty());
+
+        let response = reqwest::get("http://127.0.0.1:8383/blocks?cursor=01")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = reqwest::get("http://127.0.0.1:8383/blocks/latest")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let block: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(block["block"]["number"], 1);
+
+        let response = reqwest::get("http://127.0.0.1:8383/blocks/latest?finality=finalized")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_block_pages() {
+        setup_app().await;
+
+        let url = "http://127.0.0.1:8383/blocks/1";
+        let response = reqwest::get(format!("{url}?transaction_limit=1&log_limit=1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let block: serde_json::Value = response.json().await.unwrap();
+        let transactions = block["transactions"]["items"].as_array().unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0]["transaction_index"], 0);
+        assert_eq!(block["logs"]["items"].as_array().unwrap().len(), 1);
+        assert!(block["logs"]["next_cursor"].is_string());
+
+        // The transactions and logs are paged through separately.
+        let cursor = block["transactions"]["next_cursor"].as_str().unwrap();
+        let response = reqwest::get(format!(
+            "{url}?transaction_limit=1&transaction_cursor={cursor}&log_limit=1"
+        ))
+        .await
+        .unwrap();
+        let block: serde_json::Value = response.json().await.unwrap();
+        let transactions = block["transactions"]["items"].as_array().unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            transactions[0]["hash"],
+            "0303030303030303030303030303030303030303030303030303030303030303"
+        );
+        assert!(block["transactions"]["next_cursor"].is_null());
+        assert_eq!(block["logs"]["items"].as_array().unwrap().len(), 1);
+
+        let response = reqwest::get(format!("{url}?log_cursor=zz")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // A cursor of another block is refused, even for the same number.
+        let cursor = models::Cursor::encode(([9u8; 32], 1u64));
+        let response = reqwest::get(format!("{url}?log_cursor={cursor}"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn test_get_block_by_hash_not_found() {
         setup_app().await;
@@ -87,18 +456,971 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_transaction_by_hash() {
+        setup_app().await;
+
+        let response = reqwest::get("http://127.0.0.1:8383/transactions/0202020202020202020202020202020202020202020202020202020202020202")
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let transaction: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            transaction["from"],
+            "0707070707070707070707070707070707070707"
+        );
+        assert_eq!(transaction["max_fee_per_gas"], "30000000000");
+        assert_eq!(transaction["gas_price"], serde_json::Value::Null);
+        assert_eq!(transaction["input"], "a9059cbb");
+        assert_eq!(
+            transaction["access_list"][0]["storage_keys"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_transaction_receipt() {
+        setup_app().await;
+
+        let response = reqwest::get("http://127.0.0.1:8383/transactions/0303030303030303030303030303030303030303030303030303030303030303/receipt")
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let receipt: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(receipt["status"], "reverted");
+        assert_eq!(receipt["transaction_index"], 1);
+        assert_eq!(receipt["cumulative_gas_used"], 51000);
+        assert_eq!(receipt["effective_gas_price"], "20000000000");
+        assert_eq!(
+            receipt["contract_address"],
+            "0909090909090909090909090909090909090909"
+        );
+
+        let response = reqwest::get("http://127.0.0.1:8383/transactions/0404040404040404040404040404040404040404040404040404040404040404/receipt")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_failed_transactions() {
+        setup_app().await;
+
+        let response = reqwest::get("http://127.0.0.1:8383/blocks/1/failed_transactions")
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let transactions: serde_json::Value = response.json().await.unwrap();
+        let transactions = transactions["items"].as_array().unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            transactions[0]["hash"],
+            "0303030303030303030303030303030303030303030303030303030303030303"
+        );
+
+        let response = reqwest::get("http://127.0.0.1:8383/blocks/2/failed_transactions")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_token_transfers() {
+        setup_app().await;
+
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/tokens/0808080808080808080808080808080808080808/transfers",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let transfers: serde_json::Value = response.json().await.unwrap();
+        let transfers = transfers["items"].as_array().unwrap();
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(
+            transfers[0]["from"],
+            "0707070707070707070707070707070707070707"
+        );
+        assert_eq!(transfers[0]["log_index"], 0);
+
+        let response = reqwest::get("http://127.0.0.1:8383/tokens/0x08/transfers")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_account_transfers() {
+        setup_app().await;
+
+        let url =
+            "http://127.0.0.1:8383/accounts/0404040404040404040404040404040404040404/transfers";
+        let response = reqwest::get(format!("{url}?limit=1")).await.unwrap();
+        let page: serde_json::Value = response.json().await.unwrap();
+        let cursor = page["next_cursor"].as_str().unwrap();
+        let response = reqwest::get(format!("{url}?limit=1&cursor={cursor}"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let transfers: serde_json::Value = response.json().await.unwrap();
+        let transfers = transfers["items"].as_array().unwrap();
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(
+            transfers[0]["transaction_hash"],
+            "0202020202020202020202020202020202020202020202020202020202020202"
+        );
+
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/accounts/0404040404040404040404040404040404040404/transfers?from_block=2",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let transfers: serde_json::Value = response.json().await.unwrap();
+        assert!(transfers["items"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_account_nfts() {
+        setup_app().await;
+
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/accounts/0707070707070707070707070707070707070707/nfts",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let owned: serde_json::Value = response.json().await.unwrap();
+        let owned = owned["items"].as_array().unwrap();
+        assert_eq!(owned.len(), 1);
+        assert_eq!(
+            owned[0]["collection"],
+            "0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a"
+        );
+        assert_eq!(owned[0]["token_id"], "42");
+        assert_eq!(owned[0]["last_transfer_block"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_account_erc1155_balances() {
+        setup_app().await;
+
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/accounts/0404040404040404040404040404040404040404/erc1155_balances",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let balances: serde_json::Value = response.json().await.unwrap();
+        let balances = balances["items"].as_array().unwrap();
+        assert_eq!(balances.len(), 1);
+        assert_eq!(
+            balances[0]["contract"],
+            "0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b"
+        );
+        assert_eq!(balances[0]["token_id"], "42");
+        assert_eq!(balances[0]["balance"], "5");
+        assert_eq!(balances[0]["complete"], true);
+    }
+
+    #[tokio::test]
+    async fn test_get_nft_history() {
+        setup_app().await;
+
+        for token_id in ["42", "0x2a"] {
+            let response = reqwest::get(format!(
+                "http://127.0.0.1:8383/nfts/0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a/{token_id}/history"
+            ))
+            .await
+            .unwrap();
+
+            assert_eq!(response.status(), StatusCode::OK);
+            let history: serde_json::Value = response.json().await.unwrap();
+            let history = history["items"].as_array().unwrap();
+            assert_eq!(history.len(), 1);
+            assert_eq!(
+                history[0]["from"],
+                "0000000000000000000000000000000000000000"
+            );
+            assert_eq!(history[0]["to"], "0707070707070707070707070707070707070707");
+        }
+
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/nfts/0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a/forty-two/history",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_orphaned_blocks() {
+        setup_app().await;
+
+        let response = reqwest::get("http://127.0.0.1:8383/blocks/orphaned?limit=10")
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let blocks: serde_json::Value = response.json().await.unwrap();
+        assert!(blocks["items"].as_array().unwrap().is_empty());
+        assert!(blocks["next_cursor"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_get_status() {
+        setup_app().await;
+
+        let response = reqwest::get("http://127.0.0.1:8383/status").await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let status: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(status["state"], "connecting");
+        assert_eq!(status["latest_block"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters() {
+        let db = setup_app().await;
+        db.lock()
+            .await
+            .record_dead_letter(42, &[42; 32], "timeout")
+            .expect("Record failed.");
+
+        let client = admin_client();
+        let response = client
+            .get("http://127.0.0.1:8383/admin/dead_letters")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let dead_letters: serde_json::Value = response.json().await.unwrap();
+        assert!(
+            dead_letters["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|d| d["number"] == 42 && d["error"] == "timeout")
+        );
+
+        let response = client
+            .post("http://127.0.0.1:8383/admin/dead_letters/42/retry")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = client
+            .post("http://127.0.0.1:8383/admin/dead_letters/43/retry")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_admin_requires_token() {
+        setup_app().await;
+
+        let response = reqwest::get("http://127.0.0.1:8383/admin/dead_letters")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = reqwest::Client::new()
+            .post("http://127.0.0.1:8383/admin/tokens")
+            .bearer_auth("wrong-token")
+            .json(&serde_json::json!({ "address": "1212121212121212121212121212121212121212" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // Without a configured token, the admin routes stay closed.
+        let (address, _) = serve_events(TokenRegistry::default()).await;
+        let response = admin_client()
+            .get(format!("http://{address}/admin/dead_letters"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_token_watchlist() {
         let db = setup_app().await;
+        let token = "1111111111111111111111111111111111111111";
+        let client = admin_client();
 
-        let info = Database::data_setup();
+        let response = client
+            .post("http://127.0.0.1:8383/admin/tokens")
+            .json(&serde_json::json!({ "address": token }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = client
+            .post("http://127.0.0.1:8383/admin/tokens")
+            .json(&serde_json::json!({ "address": token, "backfill": true }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = client
+            .get("http://127.0.0.1:8383/admin/tokens")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let tokens: serde_json::Value = response.json().await.unwrap();
+        assert!(
+            tokens["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|t| t["address"] == token
+                    && t["enabled"] == true
+                    && t["backfill_pending"] == true)
+        );
+
+        let response = client
+            .post(format!(
+                "http://127.0.0.1:8383/admin/tokens/{token}/disable"
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let enabled = db.lock().await.query_enabled_tokens().unwrap();
+        assert!(!enabled.contains(&[0x11; 20]));
+
+        let response = client
+            .delete(format!("http://127.0.0.1:8383/admin/tokens/{token}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = client
+            .post(format!("http://127.0.0.1:8383/admin/tokens/{token}/enable"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_token() {
+        let db = setup_app().await;
         db.lock()
             .await
-            .insert_block(&info)
+            .insert_token_metadata(&[crate::types::TokenMetadata {
+                address: [8; 20],
+                name: Some("Eight".to_string()),
+                symbol: Some("EIGHT".to_string()),
+                decimals: Some(2),
+            }])
             .expect("Insertion failed.");
 
-        let response = reqwest::get("http://127.0.0.1:8383/transactions/0202020202020202020202020202020202020202020202020202020202020202")
+        let response =
+            reqwest::get("http://127.0.0.1:8383/tokens/0808080808080808080808080808080808080808")
+                .await
+                .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let token: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(token["symbol"], "EIGHT");
+        assert_eq!(token["decimals"], 2);
+
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/tokens/0808080808080808080808080808080808080808/transfers",
+        )
+        .await
+        .unwrap();
+        let transfers: serde_json::Value = response.json().await.unwrap();
+        let transfer = &transfers["items"].as_array().unwrap()[0];
+        assert_eq!(
+            transfer["formatted_value"],
+            "4540866244600635114649842549360310111892940575123159374096375843447573711.37"
+        );
+
+        let response =
+            reqwest::get("http://127.0.0.1:8383/tokens/0909090909090909090909090909090909090909")
+                .await
+                .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_missing_balances() {
+        setup_app().await;
+
+        let response = admin_client()
+            .get("http://127.0.0.1:8383/admin/missing_balances")
+            .send()
             .await
             .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let missing: serde_json::Value = response.json().await.unwrap();
+        let missing = &missing["items"].as_array().unwrap()[0];
+        assert_eq!(
+            missing["account"],
+            "0909090909090909090909090909090909090909"
+        );
+        assert_eq!(missing["token"], "0000000000000000000000000000000000000000");
+        assert_eq!(missing["block_number"], 1);
+        assert_eq!(missing["error"], "missing trie node");
+    }
+
+    #[tokio::test]
+    async fn test_get_account_balances() {
+        setup_app().await;
+
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/accounts/0101010101010101010101010101010101010101/balances",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let balances: serde_json::Value = response.json().await.unwrap();
+        let balances = balances["items"].as_array().unwrap();
+        assert_eq!(balances.len(), 1);
+        assert_eq!(
+            balances[0]["token"],
+            "0202020202020202020202020202020202020202"
+        );
+        assert_eq!(
+            balances[0]["balance"],
+            U256::from_be_bytes([3; 32]).to_string()
+        );
+        assert_eq!(balances[0]["block_number"], 1);
+        assert_eq!(balances[0]["derived"], false);
+    }
+
+    #[tokio::test]
+    async fn test_get_logs() {
+        setup_app().await;
 
+        let url = "http://127.0.0.1:8383/logs?from_block=1&to_block=1";
+        let response = reqwest::get(format!(
+            "{url}&topic0=0505050505050505050505050505050505050505050505050505050505050505"
+        ))
+        .await
+        .unwrap();
         assert_eq!(response.status(), StatusCode::OK);
+        let page: serde_json::Value = response.json().await.unwrap();
+        let logs = page["items"].as_array().unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(
+            logs[0]["address"],
+            "0404040404040404040404040404040404040404"
+        );
+        assert_eq!(logs[0]["topics"].as_array().unwrap().len(), 2);
+        assert!(page["next_cursor"].is_null());
+
+        // Pages are chained through their cursor.
+        let addresses = "address=0404040404040404040404040404040404040404,1010101010101010101010101010101010101010";
+        let response = reqwest::get(format!("{url}&{addresses}&limit=1"))
+            .await
+            .unwrap();
+        let page: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            page["items"][0]["address"],
+            "0404040404040404040404040404040404040404"
+        );
+        let cursor = page["next_cursor"].as_str().unwrap();
+        let response = reqwest::get(format!("{url}&{addresses}&limit=1&cursor={cursor}"))
+            .await
+            .unwrap();
+        let page: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            page["items"][0]["address"],
+            "1010101010101010101010101010101010101010"
+        );
+        assert!(page["next_cursor"].is_null());
+
+        for invalid in [
+            "from_block=2&to_block=1",
+            "from_block=0&to_block=10000",
+            "cursor=01",
+            "topic1=05",
+            "from_block=1&block_hash=0101010101010101010101010101010101010101010101010101010101010101",
+        ] {
+            let response = reqwest::get(format!("http://127.0.0.1:8383/logs?{invalid}"))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{invalid}");
+        }
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/logs?block_hash=abababababababababababababababababababababababababababababababab",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_with_alloy_provider() {
+        use alloy::{
+            consensus::Transaction as _,
+            primitives::{Address, B256},
+            providers::{Provider, ProviderBuilder},
+            rpc::types::Filter,
+        };
+
+        setup_app().await;
+
+        let provider =
+            ProviderBuilder::new().connect_http("http://127.0.0.1:8383/rpc".parse().unwrap());
+        assert_eq!(provider.get_block_number().await.unwrap(), 1);
+
+        let block = provider
+            .get_block_by_number(1.into())
+            .full()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(block.header.hash, B256::from([1; 32]));
+        assert_eq!(block.header.base_fee_per_gas, Some(100));
+        let transactions: Vec<_> = block.transactions.into_transactions().collect();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].max_fee_per_gas(), 30_000_000_000);
+        assert_eq!(transactions[1].gas_price(), Some(20_000_000_000));
+        assert!(
+            provider
+                .get_block_by_number(2.into())
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let transaction = provider
+            .get_transaction_by_hash(B256::from([2; 32]))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(transaction.inner.signer(), Address::from([7; 20]));
+        assert_eq!(transaction.access_list().unwrap().len(), 1);
+
+        let receipt = provider
+            .get_transaction_receipt(B256::from([2; 32]))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(receipt.status());
+        assert_eq!(receipt.effective_gas_price, 25_000_000_000);
+        assert_eq!(receipt.inner.logs().len(), 3);
+
+        let filter = Filter::new()
+            .from_block(1)
+            .to_block(1)
+            .event_signature(B256::from([5; 32]));
+        let logs = provider.get_logs(&filter).await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].address(), Address::from([4; 20]));
+        assert_eq!(logs[0].block_hash, Some(B256::from([1; 32])));
+
+        let balance = provider
+            .get_balance(Address::from([8; 20]))
+            .number(1)
+            .await
+            .unwrap();
+        assert_eq!(balance, U256::from(10).pow(U256::from(18)));
+    }
+
+    #[tokio::test]
+    async fn test_rpc_batch_and_errors() {
+        setup_app().await;
+
+        let client = reqwest::Client::new();
+        let post = |body: &'static str| {
+            client
+                .post("http://127.0.0.1:8383/rpc")
+                .header("content-type", "application/json")
+                .body(body)
+                .send()
+        };
+
+        let response = post(
+            r#"[
+                {"jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber"},
+                {"jsonrpc": "2.0", "id": 2, "method": "eth_sendRawTransaction", "params": ["0x00"]},
+                {"jsonrpc": "2.0", "method": "eth_blockNumber"},
+                {"jsonrpc": "2.0", "id": 3, "method": "eth_getBlockByNumber", "params": ["soon", false]},
+                {"jsonrpc": "2.0", "id": 4, "method": "eth_getBalance", "params": ["0x0909090909090909090909090909090909090909", "latest"]},
+                {"jsonrpc": "2.0", "id": null, "method": "eth_blockNumber"}
+            ]"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let responses: serde_json::Value = response.json().await.unwrap();
+        // The notification gets no response, unlike the request whose id is null.
+        let responses = responses.as_array().unwrap();
+        assert_eq!(responses.len(), 5);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["result"], "0x1");
+        assert_eq!(responses[1]["error"]["code"], -32601);
+        assert_eq!(responses[2]["error"]["code"], -32602);
+        assert_eq!(responses[3]["error"]["code"], -32001);
+        assert_eq!(responses[4]["id"], serde_json::Value::Null);
+        assert_eq!(responses[4]["result"], "0x1");
+
+        let response: serde_json::Value = post("{").await.unwrap().json().await.unwrap();
+        assert_eq!(response["error"]["code"], -32700);
+        let response: serde_json::Value = post(r#"{"id": 1, "method": "eth_blockNumber"}"#)
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(response["error"]["code"], -32600);
+    }
+
+    #[tokio::test]
+    async fn test_get_token_holders() {
+        setup_app().await;
+
+        let url = "http://127.0.0.1:8383/tokens/0202020202020202020202020202020202020202/holders";
+        let response = reqwest::get(url).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let holders: serde_json::Value = response.json().await.unwrap();
+        let holders = holders["items"].as_array().unwrap();
+        assert_eq!(holders.len(), 1);
+        assert_eq!(holders[0]["rank"], 1);
+        assert_eq!(
+            holders[0]["account"],
+            "0101010101010101010101010101010101010101"
+        );
+        assert_eq!(
+            holders[0]["balance"],
+            U256::from_be_bytes([3; 32]).to_string()
+        );
+
+        // The next page starts after the key of the last holder, whatever came before it.
+        let cursor = models::Cursor::encode((1u64, [3u8; 32], [1u8; 20]));
+        let response = reqwest::get(format!("{url}?cursor={cursor}"))
+            .await
+            .unwrap();
+        let holders: serde_json::Value = response.json().await.unwrap();
+        assert!(holders["items"].as_array().unwrap().is_empty());
+        let cursor = models::Cursor::encode((1u64, [3u8; 32], [0u8; 20]));
+        let response = reqwest::get(format!("{url}?cursor={cursor}"))
+            .await
+            .unwrap();
+        let holders: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(holders["items"][0]["rank"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_get_token_stats() {
+        setup_app().await;
+
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/tokens/0202020202020202020202020202020202020202/stats",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let stats: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(stats["holders"], 1);
+        assert_eq!(
+            stats["total_supply"],
+            (U256::from_be_bytes([3; 32]) * U256::from(4)).to_string()
+        );
+        assert_eq!(stats["top_10_share"], 0.25);
+
+        // Without a total supply, the share of the largest holders is unknown.
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/tokens/0505050505050505050505050505050505050505/stats",
+        )
+        .await
+        .unwrap();
+        let stats: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(stats["holders"], 1);
+        assert!(stats["top_10_share"].is_null());
+
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/tokens/0909090909090909090909090909090909090909/stats",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_balance_history() {
+        setup_app().await;
+
+        let url = "http://127.0.0.1:8383/accounts/0101010101010101010101010101010101010101/balances/0202020202020202020202020202020202020202/history";
+        let response = reqwest::get(url).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let history: serde_json::Value = response.json().await.unwrap();
+        let history = history["items"].as_array().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0]["block_number"], 1);
+        assert_eq!(history[0]["timestamp"], 1234567890);
+
+        let response = reqwest::get(format!("{url}?from_block=2")).await.unwrap();
+        let history: serde_json::Value = response.json().await.unwrap();
+        assert!(history["items"].as_array().unwrap().is_empty());
+
+        let response = reqwest::get(
+            "http://127.0.0.1:8383/accounts/0101010101010101010101010101010101010101/balances/nope/history",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_balance_drifts() {
+        setup_app().await;
+
+        let response = admin_client()
+            .get("http://127.0.0.1:8383/admin/balance_drifts")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let drifts: serde_json::Value = response.json().await.unwrap();
+        let drift = &drifts["items"].as_array().unwrap()[0];
+        assert_eq!(drift["account"], "0707070707070707070707070707070707070707");
+        assert_eq!(drift["block_number"], 1);
+        assert_eq!(drift["derived"], "100");
+        assert_eq!(drift["onchain"], "90");
+        assert_eq!(drift["difference"], "-10");
+    }
+
+    #[tokio::test]
+    async fn test_get_metrics() {
+        setup_app().await;
+
+        let response = reqwest::get("http://127.0.0.1:8383/metrics").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let stats: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(stats["calls"], 0);
+        assert_eq!(stats["blocks"], 0);
+        assert_eq!(stats["last_block"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_webhooks() {
+        setup_app().await;
+        let response = reqwest::get("http://127.0.0.1:8383/webhooks")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let client = admin_client();
+
+        let response = client
+            .post("http://127.0.0.1:8383/webhooks")
+            .json(&serde_json::json!({ "url": "http://127.0.0.1:9000/hook" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = client
+            .post("http://127.0.0.1:8383/webhooks")
+            .json(&serde_json::json!({ "url": "ftp://example.com", "address": "07" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = client
+            .post("http://127.0.0.1:8383/webhooks")
+            .json(&serde_json::json!({
+                "url": "http://127.0.0.1:9000/hook",
+                "token": "0505050505050505050505050505050505050505",
+                "min_value": "0x10",
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let webhook: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(webhook["secret"].as_str().unwrap().len(), 64);
+        assert_eq!(webhook["min_value"], "16");
+        let id = webhook["id"].as_i64().unwrap();
+
+        let response = client
+            .get("http://127.0.0.1:8383/webhooks")
+            .send()
+            .await
+            .unwrap();
+        let webhooks: serde_json::Value = response.json().await.unwrap();
+        let listed = webhooks["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|w| w["id"] == id)
+            .unwrap();
+        assert_eq!(listed["token"], "0505050505050505050505050505050505050505");
+        assert_eq!(listed["secret"], serde_json::Value::Null);
+
+        let response = client
+            .get(format!("http://127.0.0.1:8383/webhooks/{id}/deliveries"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let deliveries: serde_json::Value = response.json().await.unwrap();
+        assert!(deliveries["items"].as_array().unwrap().is_empty());
+
+        let response = client
+            .delete(format!("http://127.0.0.1:8383/webhooks/{id}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = client
+            .get(format!("http://127.0.0.1:8383/webhooks/{id}/deliveries"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    /// Serves the API on a port of its own, so that the test alone publishes to its subscribers.
+    async fn serve_events(tokens: TokenRegistry) -> (std::net::SocketAddr, ChainEvents) {
+        let events = ChainEvents::default();
+        let (_, connection) = watch::channel(ConnectionStatus::default());
+        let state = AppState {
+            db: Arc::new(Mutex::new(Database::connect_test())),
+            connection,
+            dead_letters: Arc::new(Notify::new()),
+            tokens,
+            metrics: Arc::default(),
+            events: events.clone(),
+            admin_token: None,
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(state)).await });
+        (address, events)
+    }
+
+    #[tokio::test]
+    async fn test_stream_events_over_sse() {
+        use crate::types::{Block, BlockSummary};
+
+        let (address, events) = serve_events(TokenRegistry::default()).await;
+        let response = reqwest::get(format!(
+            "http://{address}/events?account=0707070707070707070707070707070707070707"
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let unrelated = BlockSummary {
+            block: Block {
+                number: 2,
+                hash: [2; 32],
+                parent_hash: [1; 32],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let block = Database::data_setup();
+        let orphaned = crate::types::OrphanedBlock {
+            number: 1,
+            hash: [1; 32],
+            ..Default::default()
+        };
+        events.publish_followed(unrelated);
+        events.publish_followed(block);
+        events.publish_removed(vec![orphaned]);
+
+        let mut response = response;
+        let mut received = String::new();
+        let mut messages = Vec::new();
+        while messages.len() < 2 {
+            let chunk = response.chunk().await.unwrap().unwrap();
+            received.push_str(std::str::from_utf8(&chunk).unwrap());
+            while let Some(end) = received.find("\n\n") {
+                let event: String = received.drain(..end + 2).collect();
+                if let Some(data) = event.lines().find_map(|line| line.strip_prefix("data: ")) {
+                    messages.push(serde_json::from_str::<serde_json::Value>(data).unwrap());
+                }
+            }
+        }
+
+        // Block 2 involves nobody, so it is not sent.
+        let account = "0707070707070707070707070707070707070707";
+        assert_eq!(messages[0]["type"], "block");
+        assert_eq!(messages[0]["block"]["number"], 1);
+        let transactions = messages[0]["transactions"].as_array().unwrap();
+        assert!(!transactions.is_empty());
+        assert!(
+            transactions
+                .iter()
+                .all(|tx| tx["from"] == account || tx["to"] == account)
+        );
+        assert_eq!(messages[0]["token_transfers"].as_array().unwrap().len(), 2);
+        assert!(messages[0]["logs"].as_array().unwrap().is_empty());
+
+        assert_eq!(messages[1]["type"], "removed");
+        assert_eq!(
+            messages[1]["block"]["hash"],
+            "0101010101010101010101010101010101010101010101010101010101010101"
+        );
+        assert_eq!(messages[1]["transactions"], messages[0]["transactions"]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_events_over_ws() {
+        use futures::StreamExt;
+        use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+        let tokens = TokenRegistry::default();
+        tokens
+            .watchlist
+            .send_replace([Address::from([8; 20])].into_iter().collect());
+        let (address, events) = serve_events(tokens).await;
+
+        let (mut logs, _) = connect_async(format!(
+            "ws://{address}/ws?address=0404040404040404040404040404040404040404&topic0=0505050505050505050505050505050505050505050505050505050505050505"
+        ))
+        .await
+        .unwrap();
+        let (mut transfers, _) = connect_async(format!("ws://{address}/ws?watched=true"))
+            .await
+            .unwrap();
+        let response = reqwest::get(format!("http://{address}/ws?address=zz"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        events.publish_stored(Database::data_setup());
+
+        let Some(Ok(Message::Text(text))) = logs.next().await else {
+            panic!("No block received");
+        };
+        let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(message["type"], "block");
+        let sent = message["logs"].as_array().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0]["address"],
+            "0404040404040404040404040404040404040404"
+        );
+        assert!(message["transactions"].as_array().unwrap().is_empty());
+        assert!(message["token_transfers"].as_array().unwrap().is_empty());
+
+        let Some(Ok(Message::Text(text))) = transfers.next().await else {
+            panic!("No block received");
+        };
+        let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let sent = message["token_transfers"].as_array().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["token"], "0808080808080808080808080808080808080808");
+        assert!(message["logs"].as_array().unwrap().is_empty());
     }
 }
@@ -1,7 +1,10 @@
-use axum::{http::StatusCode, response::IntoResponse, Json};
+use alloy::primitives::{I256, U256};
+use axum::{Json, http::StatusCode, response::IntoResponse};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::types::{ConnectionState, DeliveryStatus, Finality};
+
 pub(crate) type ApiResponse<T> = Result<Json<T>, InternalErrors>;
 
 #[derive(Deserialize, Serialize)]
@@ -23,8 +26,30 @@ pub enum InternalErrors {
     BlockNotFound(String),
     #[error("Invalid hash {0}")]
     InvalidHash(String),
+    #[error("Invalid address {0}")]
+    InvalidAddress(String),
+    #[error("Invalid token id {0}")]
+    InvalidTokenId(String),
     #[error("Transaction not found {0}")]
     TransactionNotFound(String),
+    #[error("Receipt not found {0}")]
+    ReceiptNotFound(String),
+    #[error("Dead-lettered block not found {0}")]
+    DeadLetterNotFound(String),
+    #[error("Token not found {0}")]
+    TokenNotFound(String),
+    #[error("Invalid log filter: {0}")]
+    InvalidLogFilter(String),
+    #[error("Invalid cursor {0}")]
+    InvalidCursor(String),
+    #[error("Invalid webhook: {0}")]
+    InvalidWebhook(String),
+    #[error("Webhook not found {0}")]
+    WebhookNotFound(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Database error {0}")]
+    Database(String),
 }
 
 impl IntoResponse for InternalErrors {
@@ -32,7 +57,18 @@ impl IntoResponse for InternalErrors {
         let status_code = match self {
             InternalErrors::BlockNotFound(_) => StatusCode::NOT_FOUND,
             InternalErrors::InvalidHash(_) => StatusCode::BAD_REQUEST,
+            InternalErrors::InvalidAddress(_) => StatusCode::BAD_REQUEST,
+            InternalErrors::InvalidTokenId(_) => StatusCode::BAD_REQUEST,
             InternalErrors::TransactionNotFound(_) => StatusCode::NOT_FOUND,
+            InternalErrors::ReceiptNotFound(_) => StatusCode::NOT_FOUND,
+            InternalErrors::DeadLetterNotFound(_) => StatusCode::NOT_FOUND,
+            InternalErrors::TokenNotFound(_) => StatusCode::NOT_FOUND,
+            InternalErrors::InvalidLogFilter(_) => StatusCode::BAD_REQUEST,
+            InternalErrors::InvalidCursor(_) => StatusCode::BAD_REQUEST,
+            InternalErrors::InvalidWebhook(_) => StatusCode::BAD_REQUEST,
+            InternalErrors::WebhookNotFound(_) => StatusCode::NOT_FOUND,
+            InternalErrors::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            InternalErrors::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
         };
         (status_code, Json(ErrorResponse::from(self))).into_response()
     }
@@ -47,6 +83,7 @@ pub struct Block {
     pub gas_limit: u64,
     pub gas_used: u64,
     pub base_fee_per_gas: Option<u64>,
+    pub finality: Finality,
 }
 
 impl From<crate::types::Block> for Block {
@@ -59,19 +96,707 @@ impl From<crate::types::Block> for Block {
             gas_limit: block.gas_limit,
             gas_used: block.gas_used,
             base_fee_per_gas: block.base_fee_per_gas,
+            finality: block.finality,
+        }
+    }
+}
+
+/// A block with a page of its transactions, in block order, and a page of its logs.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlockDetail {
+    pub block: Block,
+    pub transactions: Page<Transaction>,
+    pub logs: Page<Log>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Status {
+    pub state: ConnectionState,
+    pub endpoint: usize,
+    pub since: u64,
+    pub reconnects: u64,
+    pub latest_block: Option<u64>,
+    pub safe_block: Option<u64>,
+    pub finalized_block: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OrphanedBlock {
+    pub number: u64,
+    pub hash: String,
+    pub parent_hash: String,
+    pub timestamp: u64,
+    pub orphaned_at: u64,
+}
+
+impl From<crate::types::OrphanedBlock> for OrphanedBlock {
+    fn from(block: crate::types::OrphanedBlock) -> Self {
+        OrphanedBlock {
+            number: block.number,
+            hash: hex::encode(block.hash),
+            parent_hash: hex::encode(block.parent_hash),
+            timestamp: block.timestamp,
+            orphaned_at: block.orphaned_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeadLetterBlock {
+    pub number: u64,
+    pub hash: String,
+    pub error: String,
+    pub attempts: u32,
+    pub last_attempt_at: u64,
+}
+
+impl From<crate::types::DeadLetterBlock> for DeadLetterBlock {
+    fn from(block: crate::types::DeadLetterBlock) -> Self {
+        DeadLetterBlock {
+            number: block.number,
+            hash: hex::encode(block.hash),
+            error: block.error,
+            attempts: block.attempts,
+            last_attempt_at: block.last_attempt_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WatchedToken {
+    pub address: String,
+    pub enabled: bool,
+    pub backfill_pending: bool,
+    pub added_at: u64,
+}
+
+impl From<crate::types::WatchedToken> for WatchedToken {
+    fn from(token: crate::types::WatchedToken) -> Self {
+        WatchedToken {
+            address: hex::encode(token.address),
+            enabled: token.enabled,
+            backfill_pending: token.backfill_pending,
+            added_at: token.added_at,
+        }
+    }
+}
+
+/// Body of a request adding a token to the watchlist. With `backfill`, the balances of the
+/// accounts that already moved the token are fetched at each block where they did.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WatchTokenRequest {
+    pub address: String,
+    #[serde(default)]
+    pub backfill: bool,
+}
+
+/// Body of a request registering a webhook. Addresses and the topic are hex, and the minimum
+/// value a decimal or `0x`-prefixed integer. Without `secret`, a random one is generated.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    pub secret: Option<String>,
+    pub address: Option<String>,
+    pub token: Option<String>,
+    pub topic: Option<String>,
+    pub min_value: Option<String>,
+}
+
+/// A registered webhook. Its `secret` is only returned when it is registered.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    pub secret: Option<String>,
+    pub address: Option<String>,
+    pub token: Option<String>,
+    pub topic: Option<String>,
+    /// As a decimal string.
+    pub min_value: Option<String>,
+    pub created_at: u64,
+}
+
+impl From<crate::types::Webhook> for Webhook {
+    fn from(webhook: crate::types::Webhook) -> Self {
+        Webhook {
+            id: webhook.id,
+            url: webhook.url,
+            secret: None,
+            address: webhook.filter.address.map(hex::encode),
+            token: webhook.filter.token.map(hex::encode),
+            topic: webhook.filter.topic.map(hex::encode),
+            min_value: webhook
+                .filter
+                .min_value
+                .map(|value| U256::from_be_bytes(value).to_string()),
+            created_at: webhook.created_at,
+        }
+    }
+}
+
+/// An entry of a webhook's delivery log.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub block_number: u64,
+    pub block_hash: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub next_attempt_at: u64,
+    pub last_status_code: Option<u16>,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    pub delivered_at: Option<u64>,
+}
+
+impl From<crate::types::WebhookDelivery> for WebhookDelivery {
+    fn from(delivery: crate::types::WebhookDelivery) -> Self {
+        WebhookDelivery {
+            id: delivery.id,
+            block_number: delivery.block_number,
+            block_hash: hex::encode(delivery.block_hash),
+            status: delivery.status,
+            attempts: delivery.attempts,
+            next_attempt_at: delivery.next_attempt_at,
+            last_status_code: delivery.last_status_code,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at,
+            delivered_at: delivery.delivered_at,
+        }
+    }
+}
+
+/// Body posted to a webhook: a stored block, and what in it matched the webhook's filter. Once the
+/// block is rolled back, the same body is posted again as `removed`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WebhookPayload {
+    pub webhook_id: i32,
+    pub event: WebhookEventKind,
+    pub block: Block,
+    pub transactions: Vec<Transaction>,
+    pub token_transfers: Vec<TokenTransfer>,
+    pub logs: Vec<Log>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    Stored,
+    Removed,
+}
+
+/// A balance the node could not serve at its block. The zero token stands for the native
+/// balance.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MissingBalance {
+    pub account: String,
+    pub token: String,
+    pub block_number: u64,
+    pub error: String,
+    pub attempts: u32,
+}
+
+impl From<crate::types::MissingBalance> for MissingBalance {
+    fn from(balance: crate::types::MissingBalance) -> Self {
+        MissingBalance {
+            account: hex::encode(balance.account),
+            token: hex::encode(balance.token),
+            block_number: balance.block_id,
+            error: balance.error,
+            attempts: balance.attempts,
         }
     }
 }
 
+/// A derived balance that differed from its token contract when last compared.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BalanceDrift {
+    pub account: String,
+    pub token: String,
+    /// Block both balances were read at.
+    pub block_number: u64,
+    /// As decimal strings, the derived one being negative only if the derivation went wrong.
+    pub derived: String,
+    pub onchain: String,
+    /// `onchain - derived`, as a decimal string.
+    pub difference: String,
+    pub detected_at: u64,
+}
+
+impl From<crate::types::BalanceDrift> for BalanceDrift {
+    fn from(drift: crate::types::BalanceDrift) -> Self {
+        let derived = I256::from_raw(U256::from_be_bytes(drift.derived));
+        let onchain = I256::from_raw(U256::from_be_bytes(drift.onchain));
+        BalanceDrift {
+            account: hex::encode(drift.account),
+            token: hex::encode(drift.token),
+            block_number: drift.block_id,
+            derived: derived.to_string(),
+            onchain: U256::from_be_bytes(drift.onchain).to_string(),
+            difference: onchain.wrapping_sub(derived).to_string(),
+            detected_at: drift.detected_at,
+        }
+    }
+}
+
+/// Pages through a list, from the `next_cursor` of the previous page.
+#[derive(Deserialize, Debug, Default)]
+pub struct PageParams {
+    pub limit: Option<u32>,
+    pub cursor: Option<String>,
+}
+
+/// Lists block headers in ascending order, optionally within a range of blocks.
+#[derive(Deserialize, Debug)]
+pub struct BlockListParams {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub limit: Option<u32>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub finality: Finality,
+}
+
+/// Pages through the transactions and logs of a block, separately.
+#[derive(Deserialize, Debug)]
+pub struct BlockParams {
+    #[serde(default)]
+    pub finality: Finality,
+    pub transaction_limit: Option<u32>,
+    pub transaction_cursor: Option<String>,
+    pub log_limit: Option<u32>,
+    pub log_cursor: Option<String>,
+}
+
+/// Pages through token transfers or balances, optionally within a range of blocks.
+#[derive(Deserialize, Debug)]
+pub struct TransferParams {
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub limit: Option<u32>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub finality: Finality,
+}
+
+/// Selects logs the way `eth_getLogs` does. Addresses and the topics at each position are
+/// comma-separated lists, any of which may match.
+#[derive(Deserialize, Debug)]
+pub struct LogParams {
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    /// The only block searched, instead of a range.
+    pub block_hash: Option<String>,
+    pub address: Option<String>,
+    pub topic0: Option<String>,
+    pub topic1: Option<String>,
+    pub topic2: Option<String>,
+    pub topic3: Option<String>,
+    pub limit: Option<u32>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub finality: Finality,
+}
+
+/// Selects what a `/ws` or `/events` subscriber is sent. Lists are comma-separated, any of which
+/// may match. Without any parameter, every block is sent in full.
+#[derive(Deserialize, Debug, Default)]
+pub struct StreamParams {
+    /// Contracts whose logs are sent.
+    pub address: Option<String>,
+    /// First topics of the logs sent.
+    pub topic0: Option<String>,
+    /// Accounts whose transactions and token transfers are sent.
+    pub account: Option<String>,
+    /// Tokens whose transfers are sent.
+    pub token: Option<String>,
+    /// Sends the transfers of the enabled watched tokens.
+    #[serde(default)]
+    pub watched: bool,
+}
+
+/// Restricts results to blocks at least as settled as `finality`. Defaults to `latest`, which
+/// accepts every block.
+#[derive(Deserialize, Debug)]
+pub struct FinalityParams {
+    #[serde(default)]
+    pub finality: Finality,
+}
+
+/// Amounts of wei are decimal strings, since they do not always fit in a JSON number.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Transaction {
     pub hash: String,
+    pub block_number: u64,
+    pub transaction_index: u64,
+    pub tx_type: u8,
+    pub chain_id: Option<u64>,
+    pub from: String,
+    pub to: Option<String>,
+    pub value: String,
+    pub nonce: u64,
+    pub input: String,
+    pub gas_limit: u64,
+    pub gas_price: Option<String>,
+    pub max_fee_per_gas: Option<String>,
+    pub max_priority_fee_per_gas: Option<String>,
+    pub access_list: Vec<AccessListItem>,
 }
 
 impl From<crate::types::Transaction> for Transaction {
     fn from(tx: crate::types::Transaction) -> Self {
         Transaction {
             hash: hex::encode(tx.hash),
+            block_number: tx.block_number,
+            transaction_index: tx.transaction_index,
+            tx_type: tx.tx_type,
+            chain_id: tx.chain_id,
+            from: hex::encode(tx.from),
+            to: tx.to.map(hex::encode),
+            value: U256::from_be_bytes(tx.value).to_string(),
+            nonce: tx.nonce,
+            input: hex::encode(tx.input),
+            gas_limit: tx.gas_limit,
+            gas_price: tx.gas_price.map(|fee| fee.to_string()),
+            max_fee_per_gas: tx.max_fee_per_gas.map(|fee| fee.to_string()),
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas.map(|fee| fee.to_string()),
+            access_list: tx
+                .access_list
+                .into_iter()
+                .map(AccessListItem::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptStatus {
+    Success,
+    Reverted,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Receipt {
+    pub transaction_hash: String,
+    pub transaction_index: u64,
+    pub status: ReceiptStatus,
+    pub gas_used: u64,
+    pub cumulative_gas_used: u64,
+    /// In wei, as a decimal string.
+    pub effective_gas_price: String,
+    pub contract_address: Option<String>,
+    pub logs_bloom: String,
+}
+
+impl From<crate::types::Receipt> for Receipt {
+    fn from(receipt: crate::types::Receipt) -> Self {
+        Receipt {
+            transaction_hash: hex::encode(receipt.transaction_hash),
+            transaction_index: receipt.transaction_index,
+            status: if receipt.success {
+                ReceiptStatus::Success
+            } else {
+                ReceiptStatus::Reverted
+            },
+            gas_used: receipt.gas_used,
+            cumulative_gas_used: receipt.cumulative_gas_used,
+            effective_gas_price: receipt.effective_gas_price.to_string(),
+            contract_address: receipt.contract_address.map(hex::encode),
+            logs_bloom: hex::encode(receipt.logs_bloom),
+        }
+    }
+}
+
+/// Writes an amount given in a token's smallest unit in whole tokens, without trailing zeros.
+/// Returns `None` for decimals too large for any amount to be formatted.
+pub fn format_units(value: U256, decimals: u8) -> Option<String> {
+    let unit = U256::from(10).checked_pow(U256::from(decimals))?;
+    let (whole, fraction) = value.div_rem(unit);
+    if fraction.is_zero() {
+        return Some(whole.to_string());
+    }
+    let fraction = format!(
+        "{:0>width$}",
+        fraction.to_string(),
+        width = decimals as usize
+    );
+    Some(format!("{whole}.{}", fraction.trim_end_matches('0')))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Token {
+    pub address: String,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+}
+
+impl From<crate::types::TokenMetadata> for Token {
+    fn from(token: crate::types::TokenMetadata) -> Self {
+        Token {
+            address: hex::encode(token.address),
+            name: token.name,
+            symbol: token.symbol,
+            decimals: token.decimals,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenTransfer {
+    pub token: String,
+    pub from: String,
+    pub to: String,
+    /// In the token's smallest unit, as a decimal string.
+    pub value: String,
+    /// `value` in whole tokens, once the token's decimals are known.
+    pub formatted_value: Option<String>,
+    pub transaction_hash: String,
+    pub log_index: u64,
+    pub block_number: u64,
+}
+
+impl TokenTransfer {
+    pub fn new(transfer: crate::types::TokenTransfer, decimals: Option<u8>) -> Self {
+        let value = U256::from_be_bytes(transfer.value);
+        TokenTransfer {
+            token: hex::encode(transfer.token),
+            from: hex::encode(transfer.from),
+            to: hex::encode(transfer.to),
+            value: value.to_string(),
+            formatted_value: decimals.and_then(|decimals| format_units(value, decimals)),
+            transaction_hash: hex::encode(transfer.transaction_hash),
+            log_index: transfer.log_index,
+            block_number: transfer.block_number,
+        }
+    }
+}
+
+/// Latest known balance of an account in a token.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccountBalance {
+    /// The zero address for the native balance.
+    pub token: String,
+    /// In the token's smallest unit, as a decimal string.
+    pub balance: String,
+    /// `balance` in whole tokens, once the token's decimals are known.
+    pub formatted_balance: Option<String>,
+    /// Block the balance was fetched at or, for a derived balance, the last block that moved it.
+    pub block_number: u64,
+    /// Whether the balance is derived from transfers rather than fetched.
+    pub derived: bool,
+}
+
+impl AccountBalance {
+    pub fn new(balance: crate::types::Balance, decimals: Option<u8>) -> Self {
+        let value = U256::from_be_bytes(balance.balance);
+        AccountBalance {
+            token: hex::encode(balance.token),
+            balance: value.to_string(),
+            formatted_balance: decimals.and_then(|decimals| format_units(value, decimals)),
+            block_number: balance.block_id,
+            derived: false,
+        }
+    }
+
+    pub fn derived(balance: crate::types::DerivedBalance, decimals: Option<u8>) -> Self {
+        let value = I256::from_raw(U256::from_be_bytes(balance.balance));
+        AccountBalance {
+            token: hex::encode(balance.token),
+            balance: value.to_string(),
+            // A negative balance is a derivation gone wrong, left as is.
+            formatted_balance: decimals
+                .filter(|_| !value.is_negative())
+                .and_then(|decimals| format_units(value.into_raw(), decimals)),
+            block_number: balance.updated_block,
+            derived: true,
+        }
+    }
+}
+
+/// A balance stored for an account and token, as of one block.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BalanceSnapshot {
+    pub block_number: u64,
+    pub timestamp: u64,
+    /// In the token's smallest unit, as a decimal string.
+    pub balance: String,
+    /// `balance` in whole tokens, once the token's decimals are known.
+    pub formatted_balance: Option<String>,
+}
+
+impl BalanceSnapshot {
+    pub fn new(snapshot: crate::types::BalanceSnapshot, decimals: Option<u8>) -> Self {
+        let value = U256::from_be_bytes(snapshot.balance);
+        BalanceSnapshot {
+            block_number: snapshot.block_id,
+            timestamp: snapshot.timestamp,
+            balance: value.to_string(),
+            formatted_balance: decimals.and_then(|decimals| format_units(value, decimals)),
+        }
+    }
+}
+
+/// An account holding a token, ranked by balance.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenHolder {
+    /// 1 for the largest holder.
+    pub rank: u64,
+    pub account: String,
+    /// In the token's smallest unit, as a decimal string.
+    pub balance: String,
+    /// `balance` in whole tokens, once the token's decimals are known.
+    pub formatted_balance: Option<String>,
+    /// Block the balance was fetched at.
+    pub block_number: u64,
+}
+
+impl TokenHolder {
+    pub fn new(rank: u64, balance: crate::types::Balance, decimals: Option<u8>) -> Self {
+        let value = U256::from_be_bytes(balance.balance);
+        TokenHolder {
+            rank,
+            account: hex::encode(balance.account),
+            balance: value.to_string(),
+            formatted_balance: decimals.and_then(|decimals| format_units(value, decimals)),
+            block_number: balance.block_id,
+        }
+    }
+}
+
+/// Holder count and concentration of a token.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenStats {
+    pub token: String,
+    /// Accounts whose latest balance is not zero.
+    pub holders: u64,
+    /// In the token's smallest unit, as a decimal string, once fetched.
+    pub total_supply: Option<String>,
+    /// `total_supply` in whole tokens, once the token's decimals are known.
+    pub formatted_total_supply: Option<String>,
+    /// Unix timestamp of when `total_supply` was fetched.
+    pub supply_updated_at: Option<u64>,
+    /// Block `total_supply` was read at.
+    pub supply_block: Option<u64>,
+    /// Sum of the ten largest balances, as a decimal string.
+    pub top_10_balance: String,
+    /// `top_10_balance` as a fraction of `total_supply`, once fetched.
+    pub top_10_share: Option<f64>,
+}
+
+impl TokenStats {
+    pub fn new(
+        stats: crate::types::TokenStats,
+        top_10: &[crate::types::Balance],
+        decimals: Option<u8>,
+    ) -> Self {
+        let supply = stats.total_supply.map(U256::from_be_bytes);
+        let top_10_balance = top_10.iter().fold(U256::ZERO, |sum, b| {
+            sum.saturating_add(U256::from_be_bytes(b.balance))
+        });
+        TokenStats {
+            token: hex::encode(stats.token),
+            holders: stats.holders,
+            total_supply: supply.map(|supply| supply.to_string()),
+            formatted_total_supply: supply
+                .zip(decimals)
+                .and_then(|(supply, decimals)| format_units(supply, decimals)),
+            supply_updated_at: stats.supply_updated_at,
+            supply_block: stats.supply_block,
+            top_10_balance: top_10_balance.to_string(),
+            top_10_share: supply
+                .filter(|supply| !supply.is_zero())
+                .map(|supply| f64::from(top_10_balance) / f64::from(supply)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NftTransfer {
+    pub collection: String,
+    /// As a decimal string.
+    pub token_id: String,
+    pub from: String,
+    pub to: String,
+    pub transaction_hash: String,
+    pub log_index: u64,
+    pub block_number: u64,
+}
+
+impl From<crate::types::NftTransfer> for NftTransfer {
+    fn from(transfer: crate::types::NftTransfer) -> Self {
+        NftTransfer {
+            collection: hex::encode(transfer.collection),
+            token_id: U256::from_be_bytes(transfer.token_id).to_string(),
+            from: hex::encode(transfer.from),
+            to: hex::encode(transfer.to),
+            transaction_hash: hex::encode(transfer.transaction_hash),
+            log_index: transfer.log_index,
+            block_number: transfer.block_number,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NftOwnership {
+    pub collection: String,
+    /// As a decimal string.
+    pub token_id: String,
+    pub owner: String,
+    pub last_transfer_block: u64,
+}
+
+impl From<crate::types::NftOwnership> for NftOwnership {
+    fn from(ownership: crate::types::NftOwnership) -> Self {
+        NftOwnership {
+            collection: hex::encode(ownership.collection),
+            token_id: U256::from_be_bytes(ownership.token_id).to_string(),
+            owner: hex::encode(ownership.owner),
+            last_transfer_block: ownership.last_transfer_block,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Erc1155Balance {
+    pub contract: String,
+    /// As a decimal string.
+    pub token_id: String,
+    pub holder: String,
+    /// As a decimal string, 0 when incomplete.
+    pub balance: String,
+    /// False when tokens received before the first indexed block were sent since, which leaves
+    /// the balance unknown.
+    pub complete: bool,
+}
+
+impl From<crate::types::Erc1155Balance> for Erc1155Balance {
+    fn from(balance: crate::types::Erc1155Balance) -> Self {
+        Erc1155Balance {
+            contract: hex::encode(balance.contract),
+            token_id: U256::from_be_bytes(balance.token_id).to_string(),
+            holder: hex::encode(balance.holder),
+            balance: U256::from_be_bytes(balance.balance).to_string(),
+            complete: balance.complete,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccessListItem {
+    pub address: String,
+    pub storage_keys: Vec<String>,
+}
+
+impl From<crate::types::AccessListItem> for AccessListItem {
+    fn from(item: crate::types::AccessListItem) -> Self {
+        AccessListItem {
+            address: hex::encode(item.address),
+            storage_keys: item.storage_keys.iter().map(hex::encode).collect(),
         }
     }
 }
@@ -81,6 +806,7 @@ pub struct Log {
     pub transaction_hash: Option<String>,
     pub log_index: Option<u64>,
     pub address: String,
+    pub topics: Vec<String>,
     pub data: String,
     pub block_number: u64,
 }
@@ -91,8 +817,171 @@ impl From<crate::types::Log> for Log {
             transaction_hash: log.transaction_hash.map(hex::encode),
             log_index: log.log_index,
             address: hex::encode(log.address),
+            topics: log.topics.iter().map(hex::encode).collect(),
             data: hex::encode(log.data),
             block_number: log.block_number,
         }
     }
 }
+
+/// A page of a list, and where the next one starts if there are more. Every list endpoint
+/// returns one.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Passed as `cursor` to get the next page.
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// A list that is never split into pages.
+    pub fn complete(items: Vec<T>) -> Self {
+        Page {
+            items,
+            next_cursor: None,
+        }
+    }
+
+    /// Makes a page of at most `limit` out of `items`, which were fetched with one more to tell
+    /// whether there is a next page. That page starts after the item `position` returns the
+    /// cursor of.
+    pub fn truncate(mut items: Vec<T>, limit: usize, position: impl FnOnce(&T) -> String) -> Self {
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(position)
+        } else {
+            None
+        };
+        Page { items, next_cursor }
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+/// Position in a list, encoded as an opaque string: the hexadecimal of the key the list is sorted
+/// by, in big-endian bytes.
+pub struct Cursor;
+
+impl Cursor {
+    pub fn encode<K: CursorKey>(position: K) -> String {
+        let mut bytes = Vec::new();
+        position.write(&mut bytes);
+        hex::encode(bytes)
+    }
+
+    pub fn decode<K: CursorKey>(cursor: &str) -> Result<K, InternalErrors> {
+        let invalid = || InternalErrors::InvalidCursor(cursor.to_string());
+        let bytes = hex::decode(cursor).map_err(|_| invalid())?;
+        let mut rest = bytes.as_slice();
+        let position = K::read(&mut rest).ok_or_else(invalid)?;
+        if !rest.is_empty() {
+            return Err(invalid());
+        }
+        Ok(position)
+    }
+}
+
+/// A value, or tuple of values, making up a [`Cursor`], each written in a fixed number of bytes.
+pub trait CursorKey: Sized {
+    fn write(&self, bytes: &mut Vec<u8>);
+
+    /// Reads the value off the start of `bytes`.
+    fn read(bytes: &mut &[u8]) -> Option<Self>;
+}
+
+impl CursorKey for u8 {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.push(*self);
+    }
+
+    fn read(bytes: &mut &[u8]) -> Option<Self> {
+        let (value, rest) = bytes.split_first()?;
+        *bytes = rest;
+        Some(*value)
+    }
+}
+
+impl CursorKey for u64 {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn read(bytes: &mut &[u8]) -> Option<Self> {
+        let (value, rest) = bytes.split_first_chunk()?;
+        *bytes = rest;
+        Some(u64::from_be_bytes(*value))
+    }
+}
+
+impl<T: CursorKey, const N: usize> CursorKey for [T; N] {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        for value in self {
+            value.write(bytes);
+        }
+    }
+
+    fn read(bytes: &mut &[u8]) -> Option<Self> {
+        let values = (0..N).map(|_| T::read(bytes)).collect::<Option<Vec<_>>>()?;
+        values.try_into().ok()
+    }
+}
+
+macro_rules! tuple_cursor_key {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<$($name: CursorKey),+> CursorKey for ($($name,)+) {
+            fn write(&self, bytes: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.write(bytes);)+
+            }
+
+            fn read(bytes: &mut &[u8]) -> Option<Self> {
+                Some(($($name::read(bytes)?,)+))
+            }
+        }
+    };
+}
+
+tuple_cursor_key!(A, B);
+tuple_cursor_key!(A, B, C);
+
+/// A notification pushed to `/ws` and `/events` subscribers, tagged by its `type`.
+///
+/// The transactions, logs and token transfers of a block are only the ones matching the
+/// subscriber's filter.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamMessage {
+    /// A block was stored.
+    Block {
+        block: Block,
+        transactions: Vec<Transaction>,
+        logs: Vec<Log>,
+        token_transfers: Vec<TokenTransfer>,
+    },
+    /// A block was rolled back by a reorganization, along with its contents when they were
+    /// pushed before.
+    Removed {
+        block: OrphanedBlock,
+        transactions: Vec<Transaction>,
+        logs: Vec<Log>,
+        token_transfers: Vec<TokenTransfer>,
+    },
+    /// The subscriber fell behind and missed `skipped` notifications.
+    Lagged { skipped: u64 },
+}
+
+impl StreamMessage {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StreamMessage::Block { .. } => "block",
+            StreamMessage::Removed { .. } => "removed",
+            StreamMessage::Lagged { .. } => "lagged",
+        }
+    }
+}
@@ -0,0 +1,604 @@
+//! A read-only subset of the Ethereum JSON-RPC API, answered from the database, so that Ethereum
+//! clients can query the indexed chain directly.
+//!
+//! Responses are built from the RPC types of `alloy`, and the fields the indexer does not store,
+//! such as state roots and transaction signatures, are left zero.
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    sync::Arc,
+};
+
+use alloy::{
+    consensus::{
+        Eip658Value, Header, Receipt as ConsensusReceipt, ReceiptEnvelope, ReceiptWithBloom,
+        Signed, TxEip1559, TxEip2930, TxEip4844, TxEip4844Variant, TxEip7702, TxEnvelope, TxLegacy,
+        transaction::Recovered,
+    },
+    eips::eip2930::{AccessList, AccessListItem},
+    primitives::{Address, B256, Bloom, Bytes, LogData, Signature, TxKind, U64, U256},
+};
+use alloy_rpc_types_eth::{
+    Block as RpcBlock, BlockId, BlockNumberOrTag, BlockTransactions, Filter, FilterBlockOption,
+    Header as RpcHeader, Log as RpcLog, Transaction as RpcTransaction, TransactionReceipt,
+};
+use axum::{
+    Json,
+    body::Bytes as Body,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Deserializer, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+use crate::{
+    api::handlers::MAX_LOG_BLOCK_RANGE,
+    db::{Database, LogFilter},
+    types::{Block, Finality, Info, Log, Receipt, Transaction},
+};
+
+/// Most requests answered in one batch.
+const MAX_BATCH_SIZE: usize = 100;
+
+/// Most logs returned by `eth_getLogs`, which cannot be paged through.
+const MAX_RPC_LOGS: usize = 10_000;
+
+/// Errors with their code from the JSON-RPC specification and EIP-1474.
+#[derive(Debug, Error)]
+pub enum RpcError {
+    #[error("Parse error")]
+    Parse,
+    #[error("Invalid request")]
+    InvalidRequest,
+    #[error("Method not found: {0}")]
+    MethodNotFound(String),
+    #[error("Invalid params: {0}")]
+    InvalidParams(String),
+    #[error("Internal error: {0}")]
+    Internal(String),
+    #[error("Resource not found: {0}")]
+    ResourceNotFound(String),
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(String),
+}
+
+impl RpcError {
+    fn code(&self) -> i64 {
+        match self {
+            RpcError::Parse => -32700,
+            RpcError::InvalidRequest => -32600,
+            RpcError::MethodNotFound(_) => -32601,
+            RpcError::InvalidParams(_) => -32602,
+            RpcError::Internal(_) => -32603,
+            RpcError::ResourceNotFound(_) => -32001,
+            RpcError::LimitExceeded(_) => -32005,
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(error: anyhow::Error) -> Self {
+        RpcError::Internal(error.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// Missing on notifications, which get no response. An explicit `null` is an id like any
+    /// other, and gets one.
+    #[serde(default, deserialize_with = "present")]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Deserializes a field that is present, even as `null`, to `Some`. Absent, it takes its default.
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+#[derive(Serialize, Debug)]
+struct RpcResponse {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorObject>,
+}
+
+#[derive(Serialize, Debug)]
+struct ErrorObject {
+    code: i64,
+    message: String,
+}
+
+impl RpcResponse {
+    fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(e) => (
+                None,
+                Some(ErrorObject {
+                    code: e.code(),
+                    message: e.to_string(),
+                }),
+            ),
+        };
+        RpcResponse {
+            jsonrpc: "2.0",
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+/// Answers a JSON-RPC request, or a batch of them.
+#[tracing::instrument(skip(db, body))]
+pub async fn handle(State(db): State<Arc<Mutex<Database>>>, body: Body) -> Response {
+    let request = match serde_json::from_slice::<Value>(&body) {
+        Ok(request) => request,
+        Err(_) => return Json(RpcResponse::new(Value::Null, Err(RpcError::Parse))).into_response(),
+    };
+    match request {
+        Value::Array(batch) if batch.is_empty() => {
+            Json(RpcResponse::new(Value::Null, Err(RpcError::InvalidRequest))).into_response()
+        }
+        Value::Array(batch) if batch.len() > MAX_BATCH_SIZE => Json(RpcResponse::new(
+            Value::Null,
+            Err(RpcError::LimitExceeded(format!(
+                "at most {MAX_BATCH_SIZE} requests per batch"
+            ))),
+        ))
+        .into_response(),
+        Value::Array(batch) => {
+            let mut responses = Vec::new();
+            for request in batch {
+                responses.extend(answer(&db, request).await);
+            }
+            if responses.is_empty() {
+                StatusCode::NO_CONTENT.into_response()
+            } else {
+                Json(responses).into_response()
+            }
+        }
+        request => match answer(&db, request).await {
+            Some(response) => Json(response).into_response(),
+            None => StatusCode::NO_CONTENT.into_response(),
+        },
+    }
+}
+
+/// Answers a single request, unless it is a notification.
+async fn answer(db: &Mutex<Database>, request: Value) -> Option<RpcResponse> {
+    let request = match serde_json::from_value::<Request>(request) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        _ => return Some(RpcResponse::new(Value::Null, Err(RpcError::InvalidRequest))),
+    };
+    let result = call(&mut *db.lock().await, &request.method, request.params);
+    request.id.map(|id| RpcResponse::new(id, result))
+}
+
+fn call(db: &mut Database, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "eth_blockNumber" => {
+            let number = highest_block(db, Finality::Latest)?.unwrap_or_default();
+            to_value(U64::from(number))
+        }
+        "eth_getBlockByNumber" => {
+            let (number, full): (BlockNumberOrTag, bool) = parse_params(params)?;
+            let block = match resolve_block_number(db, number)? {
+                Some(number) => found(db.query_block_by_number(number, Finality::Latest))?,
+                None => None,
+            };
+            to_value(block.map(|info| rpc_block(info, full)))
+        }
+        "eth_getBlockByHash" => {
+            let (hash, full): (B256, bool) = parse_params(params)?;
+            let block = found(db.query_block_by_hash(hash.as_slice(), Finality::Latest))?;
+            to_value(block.map(|info| rpc_block(info, full)))
+        }
+        "eth_getTransactionByHash" => {
+            let (hash,): (B256,) = parse_params(params)?;
+            let transaction =
+                found(db.query_transaction_by_hash(hash.as_slice(), Finality::Latest))?;
+            let transaction = match transaction {
+                Some(transaction) => {
+                    let block = block_header(db, transaction.block_number)?;
+                    Some(rpc_transaction(transaction, block.hash))
+                }
+                None => None,
+            };
+            to_value(transaction)
+        }
+        "eth_getTransactionReceipt" => {
+            let (hash,): (B256,) = parse_params(params)?;
+            to_value(transaction_receipt(db, hash)?)
+        }
+        "eth_getLogs" => {
+            let (filter,): (Filter,) = parse_params(params)?;
+            to_value(logs(db, filter)?)
+        }
+        "eth_getBalance" => {
+            let (address, block): (Address, BlockId) = parse_params(params)?;
+            to_value(balance(db, address, block)?)
+        }
+        _ => Err(RpcError::MethodNotFound(method.to_string())),
+    }
+}
+
+/// Parses positional parameters into a tuple.
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::InvalidParams(e.to_string()))
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::Internal(e.to_string()))
+}
+
+/// Turns a lookup that failed because nothing was found into `None`.
+fn found<T>(result: anyhow::Result<T>) -> Result<Option<T>, RpcError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if matches!(e.downcast_ref(), Some(diesel::result::Error::NotFound)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn highest_block(db: &mut Database, finality: Finality) -> Result<Option<u64>, RpcError> {
+    Ok(db.query_highest_block_number(finality)?)
+}
+
+/// Returns the number a block tag stands for, if any block is stored under that tag.
+fn resolve_block_number(
+    db: &mut Database,
+    number: BlockNumberOrTag,
+) -> Result<Option<u64>, RpcError> {
+    match number {
+        BlockNumberOrTag::Number(number) => Ok(Some(number)),
+        BlockNumberOrTag::Earliest => Ok(Some(0)),
+        BlockNumberOrTag::Latest | BlockNumberOrTag::Pending => highest_block(db, Finality::Latest),
+        BlockNumberOrTag::Safe => highest_block(db, Finality::Safe),
+        BlockNumberOrTag::Finalized => highest_block(db, Finality::Finalized),
+    }
+}
+
+fn block_header(db: &mut Database, number: u64) -> Result<Block, RpcError> {
+    db.query_block_header(number)?
+        .ok_or_else(|| RpcError::ResourceNotFound(format!("block {number} is not indexed")))
+}
+
+fn rpc_header(block: &Block) -> RpcHeader {
+    RpcHeader {
+        hash: block.hash.into(),
+        inner: Header {
+            parent_hash: block.parent_hash.into(),
+            number: block.number,
+            gas_limit: block.gas_limit,
+            gas_used: block.gas_used,
+            timestamp: block.timestamp,
+            base_fee_per_gas: block.base_fee_per_gas,
+            ..Default::default()
+        },
+        total_difficulty: None,
+        size: None,
+    }
+}
+
+fn rpc_block(info: Info, full: bool) -> RpcBlock {
+    let header = rpc_header(&info.block);
+    let transactions = if full {
+        BlockTransactions::Full(
+            info.transactions
+                .into_iter()
+                .map(|transaction| rpc_transaction(transaction, info.block.hash))
+                .collect(),
+        )
+    } else {
+        BlockTransactions::Hashes(info.transactions.iter().map(|t| t.hash.into()).collect())
+    };
+    RpcBlock {
+        header,
+        uncles: Vec::new(),
+        transactions,
+        withdrawals: None,
+    }
+}
+
+fn rpc_transaction(transaction: Transaction, block_hash: [u8; 32]) -> RpcTransaction {
+    let hash = B256::from(transaction.hash);
+    let to = transaction
+        .to
+        .map_or(TxKind::Create, |to| TxKind::Call(to.into()));
+    let value = U256::from_be_bytes(transaction.value);
+    let input = Bytes::from(transaction.input);
+    let access_list = AccessList(
+        transaction
+            .access_list
+            .into_iter()
+            .map(|item| AccessListItem {
+                address: item.address.into(),
+                storage_keys: item.storage_keys.into_iter().map(B256::from).collect(),
+            })
+            .collect(),
+    );
+    let chain_id = transaction.chain_id.unwrap_or_default();
+    let max_fee_per_gas = transaction.max_fee_per_gas.unwrap_or_default();
+    let max_priority_fee_per_gas = transaction.max_priority_fee_per_gas.unwrap_or_default();
+    // Signatures are not stored.
+    let signature = Signature::new(U256::ZERO, U256::ZERO, false);
+
+    let envelope = match transaction.tx_type {
+        0 => TxEnvelope::Legacy(Signed::new_unchecked(
+            TxLegacy {
+                chain_id: transaction.chain_id,
+                nonce: transaction.nonce,
+                gas_price: transaction.gas_price.unwrap_or_default(),
+                gas_limit: transaction.gas_limit,
+                to,
+                value,
+                input,
+            },
+            signature,
+            hash,
+        )),
+        1 => TxEnvelope::Eip2930(Signed::new_unchecked(
+            TxEip2930 {
+                chain_id,
+                nonce: transaction.nonce,
+                gas_price: transaction.gas_price.unwrap_or_default(),
+                gas_limit: transaction.gas_limit,
+                to,
+                value,
+                access_list,
+                input,
+            },
+            signature,
+            hash,
+        )),
+        3 => TxEnvelope::Eip4844(Signed::new_unchecked(
+            TxEip4844Variant::TxEip4844(TxEip4844 {
+                chain_id,
+                nonce: transaction.nonce,
+                gas_limit: transaction.gas_limit,
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+                to: to.to().copied().unwrap_or_default(),
+                value,
+                access_list,
+                input,
+                ..Default::default()
+            }),
+            signature,
+            hash,
+        )),
+        4 => TxEnvelope::Eip7702(Signed::new_unchecked(
+            TxEip7702 {
+                chain_id,
+                nonce: transaction.nonce,
+                gas_limit: transaction.gas_limit,
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+                to: to.to().copied().unwrap_or_default(),
+                value,
+                access_list,
+                input,
+                ..Default::default()
+            },
+            signature,
+            hash,
+        )),
+        _ => TxEnvelope::Eip1559(Signed::new_unchecked(
+            TxEip1559 {
+                chain_id,
+                nonce: transaction.nonce,
+                gas_limit: transaction.gas_limit,
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+                to,
+                value,
+                access_list,
+                input,
+            },
+            signature,
+            hash,
+        )),
+    };
+    RpcTransaction {
+        inner: Recovered::new_unchecked(envelope, transaction.from.into()),
+        block_hash: Some(block_hash.into()),
+        block_number: Some(transaction.block_number),
+        transaction_index: Some(transaction.transaction_index),
+        effective_gas_price: None,
+    }
+}
+
+fn rpc_log(log: Log, block_hash: [u8; 32], transaction_index: Option<u64>) -> RpcLog {
+    RpcLog {
+        inner: alloy::primitives::Log {
+            address: log.address.into(),
+            data: LogData::new_unchecked(
+                log.topics.into_iter().map(B256::from).collect(),
+                Bytes::from(log.data),
+            ),
+        },
+        block_hash: Some(block_hash.into()),
+        block_number: Some(log.block_number),
+        block_timestamp: None,
+        transaction_hash: log.transaction_hash.map(B256::from),
+        transaction_index,
+        log_index: log.log_index,
+        removed: false,
+    }
+}
+
+fn transaction_receipt(
+    db: &mut Database,
+    hash: B256,
+) -> Result<Option<TransactionReceipt>, RpcError> {
+    let Some(receipt) =
+        found(db.query_receipt_by_transaction_hash(hash.as_slice(), Finality::Latest))?
+    else {
+        return Ok(None);
+    };
+    let transaction = db.query_transaction_by_hash(hash.as_slice(), Finality::Latest)?;
+    let info = db.query_block_by_number(transaction.block_number, Finality::Latest)?;
+    let logs = info
+        .logs
+        .into_iter()
+        .filter(|log| log.transaction_hash == Some(transaction.hash))
+        .map(|log| rpc_log(log, info.block.hash, Some(transaction.transaction_index)))
+        .collect();
+    Ok(Some(rpc_receipt(
+        receipt,
+        &transaction,
+        info.block.hash,
+        logs,
+    )))
+}
+
+fn rpc_receipt(
+    receipt: Receipt,
+    transaction: &Transaction,
+    block_hash: [u8; 32],
+    logs: Vec<RpcLog>,
+) -> TransactionReceipt {
+    let receipt_with_bloom = ReceiptWithBloom {
+        receipt: ConsensusReceipt {
+            status: Eip658Value::Eip658(receipt.success),
+            cumulative_gas_used: receipt.cumulative_gas_used,
+            logs,
+        },
+        logs_bloom: <[u8; 256]>::try_from(receipt.logs_bloom.as_slice())
+            .map(Bloom::from)
+            .unwrap_or_default(),
+    };
+    let inner = match transaction.tx_type {
+        0 => ReceiptEnvelope::Legacy(receipt_with_bloom),
+        1 => ReceiptEnvelope::Eip2930(receipt_with_bloom),
+        3 => ReceiptEnvelope::Eip4844(receipt_with_bloom),
+        4 => ReceiptEnvelope::Eip7702(receipt_with_bloom),
+        _ => ReceiptEnvelope::Eip1559(receipt_with_bloom),
+    };
+    TransactionReceipt {
+        inner,
+        transaction_hash: receipt.transaction_hash.into(),
+        transaction_index: Some(receipt.transaction_index),
+        block_hash: Some(block_hash.into()),
+        block_number: Some(transaction.block_number),
+        gas_used: receipt.gas_used,
+        effective_gas_price: receipt.effective_gas_price,
+        blob_gas_used: None,
+        blob_gas_price: None,
+        from: transaction.from.into(),
+        to: transaction.to.map(Into::into),
+        contract_address: receipt.contract_address.map(Into::into),
+    }
+}
+
+/// Answers `eth_getLogs`, whose results cannot be paged through: a search matching more than
+/// [`MAX_RPC_LOGS`] logs fails.
+fn logs(db: &mut Database, filter: Filter) -> Result<Vec<RpcLog>, RpcError> {
+    let (from_block, to_block) = match filter.block_option {
+        FilterBlockOption::AtBlockHash(hash) => {
+            let number = db
+                .query_block_number_by_hash(&hash.0, Finality::Latest)?
+                .ok_or_else(|| {
+                    RpcError::ResourceNotFound(format!("block {hash} is not indexed"))
+                })?;
+            (number, number)
+        }
+        FilterBlockOption::Range {
+            from_block,
+            to_block,
+        } => {
+            let latest = BlockNumberOrTag::Latest;
+            let to_block = resolve_block_number(db, to_block.unwrap_or(latest))?;
+            let from_block = resolve_block_number(db, from_block.unwrap_or(latest))?;
+            let (Some(from_block), Some(to_block)) = (from_block, to_block) else {
+                return Ok(Vec::new());
+            };
+            (from_block, to_block)
+        }
+    };
+    if from_block > to_block {
+        return Err(RpcError::InvalidParams(
+            "fromBlock is above toBlock".to_string(),
+        ));
+    }
+    if to_block - from_block >= MAX_LOG_BLOCK_RANGE {
+        return Err(RpcError::LimitExceeded(format!(
+            "at most {MAX_LOG_BLOCK_RANGE} blocks can be searched at once"
+        )));
+    }
+
+    let log_filter = LogFilter {
+        from_block: Some(from_block),
+        to_block: Some(to_block),
+        addresses: filter.address.iter().map(|address| address.0.0).collect(),
+        topics: filter
+            .topics
+            .iter()
+            .map(|topics| topics.iter().map(|topic| topic.0).collect())
+            .collect(),
+        finality: Finality::Latest,
+    };
+    let logs: Vec<_> = db
+        .query_logs(&log_filter, None, MAX_RPC_LOGS as i64 + 1)?
+        .into_iter()
+        .map(|(_, log)| log)
+        .collect();
+    if logs.len() > MAX_RPC_LOGS {
+        return Err(RpcError::LimitExceeded(format!(
+            "query returned more than {MAX_RPC_LOGS} results"
+        )));
+    }
+
+    let mut block_hashes = HashMap::new();
+    for log in &logs {
+        if let Entry::Vacant(entry) = block_hashes.entry(log.block_number) {
+            entry.insert(block_header(db, log.block_number)?.hash);
+        }
+    }
+    let transactions: Vec<[u8; 32]> = logs.iter().filter_map(|log| log.transaction_hash).collect();
+    let transaction_indexes = db.query_transaction_indexes(&transactions)?;
+    Ok(logs
+        .into_iter()
+        .map(|log| {
+            let block_hash = block_hashes[&log.block_number];
+            let transaction_index = log
+                .transaction_hash
+                .and_then(|hash| transaction_indexes.get(&hash).copied());
+            rpc_log(log, block_hash, transaction_index)
+        })
+        .collect())
+}
+
+/// Answers `eth_getBalance` with the last native balance stored for `address` as of the block,
+/// which must be indexed. Accounts whose balance was never fetched have none.
+fn balance(db: &mut Database, address: Address, block: BlockId) -> Result<U256, RpcError> {
+    let number = match block {
+        BlockId::Number(number) => resolve_block_number(db, number)?
+            .ok_or_else(|| RpcError::ResourceNotFound("no block is indexed".to_string()))?,
+        BlockId::Hash(hash) => db
+            .query_block_number_by_hash(&hash.block_hash.0, Finality::Latest)?
+            .ok_or_else(|| {
+                RpcError::ResourceNotFound(format!("block {} is not indexed", hash.block_hash))
+            })?,
+    };
+    block_header(db, number)?;
+    let balance = db
+        .query_balance_at(&address.0.0, &[0; 20], number)?
+        .ok_or_else(|| {
+            RpcError::ResourceNotFound(format!(
+                "no balance of {address} is indexed at or before block {number}"
+            ))
+        })?;
+    Ok(U256::from_be_bytes(balance.balance))
+}
@@ -0,0 +1,381 @@
+//! Pushes every stored block, and every block rolled back by a reorganization, to subscribers over
+//! WebSocket (`/ws`) and server-sent events (`/events`).
+//!
+//! The indexer publishes to a bounded broadcast channel, which never waits for subscribers: one
+//! that falls too far behind misses the oldest notifications and is told how many with a `lagged`
+//! message, while the indexer carries on.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    convert::Infallible,
+    sync::{self, Arc},
+};
+
+use alloy::primitives::Address;
+use axum::{
+    extract::{
+        Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures::{Stream, stream};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    api::{
+        TokenRegistry,
+        handlers::{parse_address, parse_any_of, parse_hash},
+        models::{InternalErrors, StreamMessage, StreamParams, TokenTransfer},
+    },
+    indexer::MAX_REORG_DEPTH,
+    types::{BlockSummary, OrphanedBlock},
+};
+
+/// Notifications kept for subscribers that have not read them yet.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Something that happened to the stored chain.
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    Stored(Arc<BlockSummary>),
+    /// `summary` is missing when the block was not stored by the live follower since the indexer
+    /// started.
+    Removed {
+        block: OrphanedBlock,
+        summary: Option<Arc<BlockSummary>>,
+    },
+}
+
+/// Lets the indexer publish what it stores and rolls back to the API subscribers.
+#[derive(Clone)]
+pub struct ChainEvents {
+    sender: broadcast::Sender<ChainEvent>,
+    /// The last blocks stored by the live follower, the only ones a reorganization rolls back,
+    /// so that rolling one back can tell subscribers what it contained.
+    recent: Arc<sync::Mutex<VecDeque<Arc<BlockSummary>>>>,
+}
+
+impl Default for ChainEvents {
+    fn default() -> Self {
+        ChainEvents {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            recent: Arc::default(),
+        }
+    }
+}
+
+impl ChainEvents {
+    /// Publishes a block once it is committed to the database.
+    pub fn publish_stored(&self, block: BlockSummary) {
+        // Sending only fails when nobody is subscribed.
+        let _ = self.sender.send(ChainEvent::Stored(Arc::new(block)));
+    }
+
+    /// Publishes a block stored by the live follower, keeping it until it is too deep to be
+    /// rolled back. Blocks stored behind the head, by backfills or retries, go through
+    /// `publish_stored` instead, so that they do not push the head out.
+    pub fn publish_followed(&self, block: BlockSummary) {
+        let block = Arc::new(block);
+        {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == MAX_REORG_DEPTH {
+                recent.pop_front();
+            }
+            recent.push_back(Arc::clone(&block));
+        }
+        let _ = self.sender.send(ChainEvent::Stored(block));
+    }
+
+    /// Publishes the blocks of a rollback, as returned by the database, newest first.
+    pub fn publish_removed(&self, orphaned: Vec<OrphanedBlock>) {
+        for block in orphaned.into_iter().rev() {
+            let summary = {
+                let mut recent = self.recent.lock().unwrap();
+                let position = recent.iter().position(|b| b.block.hash == block.hash);
+                position.and_then(|position| recent.remove(position))
+            };
+            let _ = self.sender.send(ChainEvent::Removed { block, summary });
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// What a subscriber asked to be sent, parsed from [`StreamParams`].
+///
+/// Logs match on `addresses` and `topic0`, transactions on `accounts`, and token transfers on
+/// `tokens` (or the watchlist) and `accounts`. Within a list any value matches, and all the
+/// criteria that apply to an item must match. Items no criterion applies to are left out, unless
+/// the filter is empty.
+struct StreamFilter {
+    addresses: Vec<[u8; 20]>,
+    topic0: Vec<[u8; 32]>,
+    accounts: Vec<[u8; 20]>,
+    tokens: Vec<[u8; 20]>,
+    /// The registry whose watchlist is matched, if asked for.
+    watched: Option<TokenRegistry>,
+}
+
+impl StreamFilter {
+    fn parse(params: &StreamParams, tokens: TokenRegistry) -> Result<Self, InternalErrors> {
+        Ok(StreamFilter {
+            addresses: parse_any_of(params.address.as_deref(), parse_address)?,
+            topic0: parse_any_of(params.topic0.as_deref(), parse_hash)?,
+            accounts: parse_any_of(params.account.as_deref(), parse_address)?,
+            tokens: parse_any_of(params.token.as_deref(), parse_address)?,
+            watched: params.watched.then_some(tokens),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+            && self.topic0.is_empty()
+            && self.accounts.is_empty()
+            && self.tokens.is_empty()
+            && self.watched.is_none()
+    }
+
+    /// The notification to send for `event`, if any of it matches.
+    fn message(&self, event: &ChainEvent) -> Option<StreamMessage> {
+        let summary = match event {
+            ChainEvent::Stored(summary) => summary,
+            ChainEvent::Removed {
+                block,
+                summary: None,
+            } => {
+                // Whether it matched is unknown, so every subscriber hears about it.
+                return Some(StreamMessage::Removed {
+                    block: block.clone().into(),
+                    transactions: Vec::new(),
+                    logs: Vec::new(),
+                    token_transfers: Vec::new(),
+                });
+            }
+            ChainEvent::Removed {
+                summary: Some(summary),
+                ..
+            } => summary,
+        };
+
+        let empty = self.is_empty();
+        let transactions: Vec<_> = summary
+            .transactions
+            .iter()
+            .filter(|tx| {
+                empty
+                    || (!self.accounts.is_empty()
+                        && (self.accounts.contains(&tx.from)
+                            || tx.to.is_some_and(|to| self.accounts.contains(&to))))
+            })
+            .cloned()
+            .map(Into::into)
+            .collect();
+
+        let logs: Vec<_> = summary
+            .logs
+            .iter()
+            .filter(|log| {
+                empty
+                    || ((!self.addresses.is_empty() || !self.topic0.is_empty())
+                        && (self.addresses.is_empty() || self.addresses.contains(&log.address))
+                        && (self.topic0.is_empty()
+                            || log.topics.first().is_some_and(|t| self.topic0.contains(t))))
+            })
+            .cloned()
+            .map(Into::into)
+            .collect();
+
+        let watchlist = self
+            .watched
+            .as_ref()
+            .map(|registry| registry.watchlist.borrow().clone())
+            .unwrap_or_default();
+        let token_transfers: Vec<_> = summary
+            .token_transfers
+            .iter()
+            .filter(|transfer| self.matches_transfer(transfer, &watchlist))
+            .cloned()
+            .map(|transfer| TokenTransfer::new(transfer, None))
+            .collect();
+
+        if !empty && transactions.is_empty() && logs.is_empty() && token_transfers.is_empty() {
+            return None;
+        }
+        Some(match event {
+            ChainEvent::Stored(summary) => StreamMessage::Block {
+                block: summary.block.clone().into(),
+                transactions,
+                logs,
+                token_transfers,
+            },
+            ChainEvent::Removed { block, .. } => StreamMessage::Removed {
+                block: block.clone().into(),
+                transactions,
+                logs,
+                token_transfers,
+            },
+        })
+    }
+
+    fn matches_transfer(
+        &self,
+        transfer: &crate::types::TokenTransfer,
+        watchlist: &HashSet<Address>,
+    ) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let by_token = !self.tokens.is_empty() || self.watched.is_some();
+        if !by_token && self.accounts.is_empty() {
+            return false;
+        }
+        let token_matches = !by_token
+            || self.tokens.contains(&transfer.token)
+            || watchlist.contains(&Address::from(transfer.token));
+        let account_matches = self.accounts.is_empty()
+            || self.accounts.contains(&transfer.from)
+            || self.accounts.contains(&transfer.to);
+        token_matches && account_matches
+    }
+}
+
+/// The next notification for a subscriber, or `None` once the indexer is gone.
+async fn next_message(
+    receiver: &mut broadcast::Receiver<ChainEvent>,
+    filter: &StreamFilter,
+) -> Option<StreamMessage> {
+    loop {
+        match receiver.recv().await {
+            Ok(event) => {
+                if let Some(message) = filter.message(&event) {
+                    return Some(message);
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => return Some(StreamMessage::Lagged { skipped }),
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+#[tracing::instrument(skip(upgrade, events, tokens))]
+pub async fn subscribe_ws(
+    upgrade: WebSocketUpgrade,
+    Query(params): Query<StreamParams>,
+    State(events): State<ChainEvents>,
+    State(tokens): State<TokenRegistry>,
+) -> Result<Response, InternalErrors> {
+    let filter = StreamFilter::parse(&params, tokens)?;
+    // Subscribing before the upgrade completes, so that nothing stored meanwhile is missed.
+    let receiver = events.subscribe();
+    Ok(upgrade.on_upgrade(move |socket| forward_ws(socket, receiver, filter)))
+}
+
+/// Sends the notifications to the socket as JSON text messages, until either side goes away.
+async fn forward_ws(
+    mut socket: WebSocket,
+    mut receiver: broadcast::Receiver<ChainEvent>,
+    filter: StreamFilter,
+) {
+    loop {
+        tokio::select! {
+            message = next_message(&mut receiver, &filter) => {
+                let Some(message) = message else { break };
+                let Ok(text) = serde_json::to_string(&message) else { break };
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            // Incoming messages are ignored, but reading them answers pings and notices closes.
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+#[tracing::instrument(skip(events, tokens))]
+pub async fn subscribe_sse(
+    Query(params): Query<StreamParams>,
+    State(events): State<ChainEvents>,
+    State(tokens): State<TokenRegistry>,
+) -> Result<impl IntoResponse, InternalErrors> {
+    let filter = StreamFilter::parse(&params, tokens)?;
+    let receiver = events.subscribe();
+    Ok(Sse::new(sse_events(receiver, filter)).keep_alive(KeepAlive::default()))
+}
+
+/// Each notification as an event named after its type, with the JSON message as data.
+fn sse_events(
+    receiver: broadcast::Receiver<ChainEvent>,
+    filter: StreamFilter,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold((receiver, filter), |(mut receiver, filter)| async move {
+        let message = next_message(&mut receiver, &filter).await?;
+        let event = Event::default()
+            .event(message.kind())
+            .data(serde_json::to_string(&message).unwrap_or_default());
+        Some((Ok(event), (receiver, filter)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_slow_subscriber_lags() {
+        let events = ChainEvents::default();
+        let mut receiver = events.subscribe();
+        let filter =
+            StreamFilter::parse(&StreamParams::default(), TokenRegistry::default()).unwrap();
+
+        // Publishing never waits for the subscriber, which only misses the oldest blocks.
+        for number in 0..CHANNEL_CAPACITY as u64 + 2 {
+            let mut block = BlockSummary::default();
+            block.block.number = number;
+            events.publish_stored(block);
+        }
+
+        let message = next_message(&mut receiver, &filter).await.unwrap();
+        assert!(matches!(message, StreamMessage::Lagged { skipped: 2 }));
+        let Some(StreamMessage::Block { block, .. }) = next_message(&mut receiver, &filter).await
+        else {
+            panic!("Expected a block");
+        };
+        assert_eq!(block.number, 2);
+    }
+
+    #[test]
+    fn test_backfilled_blocks_keep_the_head() {
+        let events = ChainEvents::default();
+        let mut receiver = events.subscribe();
+        let mut head = BlockSummary::default();
+        head.block.hash = [1; 32];
+        events.publish_followed(head);
+        // Blocks stored behind the head, by a backfill, do not push it out.
+        for number in 0..=MAX_REORG_DEPTH as u64 {
+            let mut block = BlockSummary::default();
+            block.block.number = number;
+            events.publish_stored(block);
+        }
+        events.publish_removed(vec![OrphanedBlock {
+            hash: [1; 32],
+            ..Default::default()
+        }]);
+
+        let mut removed = None;
+        while let Ok(event) = receiver.try_recv() {
+            if let ChainEvent::Removed { summary, .. } = event {
+                removed = summary;
+            }
+        }
+        assert_eq!(removed.expect("Contents lost").block.hash, [1; 32]);
+    }
+}
@@ -0,0 +1,254 @@
+use std::{env, ops::RangeInclusive, path::PathBuf, str::FromStr, time::Duration};
+
+use alloy::primitives::Address;
+use anyhow::Context;
+
+use crate::eth_client::{
+    connection::IngestionMode,
+    retry::RetryPolicy,
+    update_balances::{BalanceMode, BatchPolicy},
+};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(4);
+
+/// Runtime settings, read from the environment (or `.env`) and the command line.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// RPC endpoints, in order of preference. WebSocket endpoints are subscribed to and HTTP
+    /// ones are polled.
+    pub rpc_urls: Vec<String>,
+    pub database_url: String,
+    /// Historical range to index, given with `--from` and `--to`.
+    pub backfill: Option<RangeInclusive<u64>>,
+    /// Retries of the RPC calls made to fetch a block, from `RPC_MAX_ATTEMPTS` and
+    /// `RPC_RETRY_BACKOFF_MS`.
+    pub retry: RetryPolicy,
+    /// Grouping of balance lookups, from `BALANCE_BATCH_SIZE` and `BALANCE_CONCURRENCY`.
+    pub batch: BatchPolicy,
+    /// `BALANCE_MODE`, either `rpc` or `derived`, how the balances of watched tokens are kept.
+    pub balance_mode: BalanceMode,
+    /// `INGESTION_MODE`, either `subscribe` or `poll`, forcing how every endpoint is followed.
+    pub ingestion: Option<IngestionMode>,
+    /// `POLL_INTERVAL_MS`, the wait between two checks for new blocks when polling.
+    pub poll_interval: Duration,
+    /// `RECORD_DIR`, where every response received from the node is saved to be replayed later.
+    pub record_dir: Option<PathBuf>,
+    /// `REPLAY_DIR`, a recording to index instead of following a node.
+    pub replay_dir: Option<PathBuf>,
+    /// `CONFIRMATIONS`, how many blocks must be built on top of a block before it is stored.
+    /// Defaults to 0, storing blocks as soon as they are received.
+    pub confirmations: u64,
+    /// Tokens listed in `TOKENS_FILE`, added to the watchlist at startup if not registered yet.
+    pub tokens: Vec<[u8; 20]>,
+    /// `ADMIN_TOKEN`, the bearer token the admin routes require. Without it, they refuse every
+    /// request.
+    pub admin_token: Option<String>,
+}
+
+impl Config {
+    pub fn load() -> anyhow::Result<Self> {
+        let replay_dir: Option<PathBuf> = env_var("REPLAY_DIR")?;
+        // `JSON_RPC_URLS` lists fallback endpoints; a single one can still be given the old way.
+        let rpc_urls = match env::var("JSON_RPC_URLS") {
+            Ok(urls) => parse_rpc_urls(&urls),
+            // A replay never reaches the network.
+            Err(_) if replay_dir.is_some() => Vec::new(),
+            Err(_) => vec![
+                env::var("JSON_RPC_API_KEY")
+                    .context("JSON_RPC_API_KEY must be set. You can set it in .env file")?,
+            ],
+        };
+        anyhow::ensure!(
+            !rpc_urls.is_empty() || replay_dir.is_some(),
+            "JSON_RPC_URLS has no endpoint"
+        );
+        let database_url = env::var("DATABASE_URL")
+            .context("DATABASE_URL must be set. You can set it in .env file")?;
+        let backfill = parse_backfill_args(env::args().skip(1))?;
+
+        let default_retry = RetryPolicy::default();
+        let retry = RetryPolicy {
+            max_attempts: env_var("RPC_MAX_ATTEMPTS")?.unwrap_or(default_retry.max_attempts),
+            initial_backoff: env_var("RPC_RETRY_BACKOFF_MS")?
+                .map(Duration::from_millis)
+                .unwrap_or(default_retry.initial_backoff),
+        };
+        anyhow::ensure!(
+            retry.max_attempts > 0,
+            "RPC_MAX_ATTEMPTS must be at least 1"
+        );
+
+        let default_batch = BatchPolicy::default();
+        let batch = BatchPolicy {
+            batch_size: env_var("BALANCE_BATCH_SIZE")?.unwrap_or(default_batch.batch_size),
+            concurrency: env_var("BALANCE_CONCURRENCY")?.unwrap_or(default_batch.concurrency),
+        };
+        anyhow::ensure!(
+            batch.batch_size > 0 && batch.concurrency > 0,
+            "BALANCE_BATCH_SIZE and BALANCE_CONCURRENCY must be at least 1"
+        );
+
+        let balance_mode = match env::var("BALANCE_MODE").ok().as_deref() {
+            None | Some("rpc") => BalanceMode::Rpc,
+            Some("derived") => BalanceMode::Derived,
+            Some(mode) => anyhow::bail!("Invalid BALANCE_MODE: {mode}"),
+        };
+
+        let ingestion = match env::var("INGESTION_MODE").ok().as_deref() {
+            None => None,
+            Some("subscribe") => Some(IngestionMode::Subscribe),
+            Some("poll") => Some(IngestionMode::Poll),
+            Some(mode) => anyhow::bail!("Invalid INGESTION_MODE: {mode}"),
+        };
+        anyhow::ensure!(
+            ingestion != Some(IngestionMode::Subscribe)
+                || rpc_urls
+                    .iter()
+                    .all(|url| IngestionMode::for_url(url) == IngestionMode::Subscribe),
+            "INGESTION_MODE=subscribe needs WebSocket endpoints"
+        );
+        let poll_interval = env_var("POLL_INTERVAL_MS")?
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+
+        let tokens = match env::var("TOKENS_FILE") {
+            Ok(path) => {
+                let contents = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Cannot read TOKENS_FILE {path}"))?;
+                parse_token_list(&contents)?
+            }
+            Err(_) => Vec::new(),
+        };
+
+        Ok(Config {
+            rpc_urls,
+            database_url,
+            backfill,
+            retry,
+            batch,
+            balance_mode,
+            ingestion,
+            poll_interval,
+            record_dir: env_var("RECORD_DIR")?,
+            replay_dir,
+            confirmations: env_var("CONFIRMATIONS")?.unwrap_or_default(),
+            tokens,
+            admin_token: env::var("ADMIN_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+        })
+    }
+}
+
+/// Reads and parses an optional environment variable.
+fn env_var<T>(name: &str) -> anyhow::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    env::var(name)
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .with_context(|| format!("Invalid {name}: {value}"))
+        })
+        .transpose()
+}
+
+/// Splits a comma separated list of endpoints.
+fn parse_rpc_urls(urls: &str) -> Vec<String> {
+    urls.split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Reads one token address per line, ignoring blank lines and `#` comments.
+fn parse_token_list(contents: &str) -> anyhow::Result<Vec<[u8; 20]>> {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            Address::from_str(line)
+                .map(<[u8; 20]>::from)
+                .with_context(|| format!("Invalid token address {line}"))
+        })
+        .collect()
+}
+
+/// Reads `--from <block>` and `--to <block>` from the command line arguments.
+///
+/// Both bounds are inclusive and must be given together.
+fn parse_backfill_args(
+    args: impl IntoIterator<Item = String>,
+) -> anyhow::Result<Option<RangeInclusive<u64>>> {
+    let (mut from, mut to) = (None, None);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let bound = match arg.as_str() {
+            "--from" => &mut from,
+            "--to" => &mut to,
+            _ => anyhow::bail!("Unknown argument {arg}"),
+        };
+        let value = args
+            .next()
+            .with_context(|| format!("Missing block number after {arg}"))?;
+        *bound = Some(
+            value
+                .parse::<u64>()
+                .with_context(|| format!("Invalid block number {value}"))?,
+        );
+    }
+
+    match (from, to) {
+        (None, None) => Ok(None),
+        (Some(from), Some(to)) if from <= to => Ok(Some(from..=to)),
+        (Some(from), Some(to)) => anyhow::bail!("--from {from} is greater than --to {to}"),
+        _ => anyhow::bail!("--from and --to must be used together"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_backfill_args() {
+        assert_eq!(parse_backfill_args(args(&[])).unwrap(), None);
+        assert_eq!(
+            parse_backfill_args(args(&["--to", "20", "--from", "10"])).unwrap(),
+            Some(10..=20)
+        );
+        assert!(parse_backfill_args(args(&["--from", "10"])).is_err());
+        assert!(parse_backfill_args(args(&["--from", "20", "--to", "10"])).is_err());
+        assert!(parse_backfill_args(args(&["--from", "ten", "--to", "20"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_rpc_urls() {
+        assert_eq!(
+            parse_rpc_urls("wss://a.example, wss://b.example,"),
+            vec!["wss://a.example", "wss://b.example"]
+        );
+        assert!(parse_rpc_urls(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_parse_token_list() {
+        let tokens = parse_token_list(
+            "# Stablecoins\n0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48\n\n\
+             c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2 # WETH\n",
+        )
+        .unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1][0], 0xc0);
+        assert!(parse_token_list("0x1234").is_err());
+    }
+}
@@ -1,11 +1,28 @@
 pub mod models;
 pub mod schema;
 
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use self::models::{
-    DbBlock, DbTransaction, NewBalance, NewBlock, NewLog, NewLogTopic, NewReceipt, NewTransaction,
+    BlockGap, DbAccessListItem, DbBackfillJob, DbBalance, DbBalanceDelta, DbBalanceDrift, DbBlock,
+    DbDeadLetterBlock, DbDerivedBalance, DbErc1155Balance, DbErc1155Transfer, DbMissingBalance,
+    DbNftOwner, DbNftTransfer, DbOrphanedBlock, DbReceipt, DbToken, DbTokenStats, DbTokenTransfer,
+    DbTransaction, DbWatchedToken, DbWebhook, DbWebhookDelivery, DbWebhookEvent, NewAccessListItem,
+    NewBackfillJob, NewBalance, NewBlock, NewDeadLetterBlock, NewErc1155Transfer, NewLog,
+    NewLogTopic, NewMissingBalance, NewNftOwner, NewNftTransfer, NewOrphanedBlock, NewReceipt,
+    NewToken, NewTokenTransfer, NewTransaction, NewWatchedToken, NewWebhook, NewWebhookDelivery,
+    UnfetchedBalance, UnseededBalance,
+};
+use crate::types::{
+    self, BackfillJob, BalanceDelta, BalanceDrift, BalanceSnapshot, BlockSummary, DeadLetterBlock,
+    DeliveryStatus, DerivedBalance, Erc1155Balance, Erc1155Transfer, Finality, MissingBalance,
+    NftOwnership, NftTransfer, Receipt, TokenMetadata, TokenStats, TokenTransfer, WatchedToken,
+    Webhook, WebhookDelivery, WebhookEvent, WebhookFilter,
 };
-use crate::types::{self, BlockSummary};
-use crate::types::{Block, Info, Log, Transaction};
+use crate::types::{Block, Info, Log, OrphanedBlock, Transaction};
+use diesel::connection::SimpleConnection;
 use diesel::define_sql_function;
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
@@ -17,6 +34,73 @@ pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 
 define_sql_function!(fn last_insert_rowid() -> BigInt);
 
+pub(crate) fn unix_now() -> anyhow::Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// Whether a big-endian integer is not zero.
+fn is_nonzero(value: &[u8]) -> bool {
+    value.iter().any(|byte| *byte != 0)
+}
+
+/// Values of `blocks.finality` that are at least as settled as `finality`.
+fn settled_as(finality: Finality) -> Vec<&'static str> {
+    Finality::ALL
+        .into_iter()
+        .filter(|f| *f >= finality)
+        .map(|f| f.as_str())
+        .collect()
+}
+
+/// Whose token transfers to look up.
+#[derive(Debug, Clone, Copy)]
+pub enum TransferParty {
+    /// Transfers of this token, or of the tokens of this NFT collection.
+    Token([u8; 20]),
+    /// Transfers sent or received by this account, in any token.
+    Account([u8; 20]),
+}
+
+/// Selects the transfers returned by [`Database::query_token_transfers`] and
+/// [`Database::query_nft_transfers`].
+#[derive(Debug, Clone, Copy)]
+pub struct TransferFilter {
+    pub party: TransferParty,
+    /// Lowest block number included.
+    pub from_block: Option<u64>,
+    /// Highest block number included.
+    pub to_block: Option<u64>,
+    pub finality: Finality,
+}
+
+/// Selects the balances returned by [`Database::query_balance_history`].
+#[derive(Debug, Clone, Copy)]
+pub struct BalanceHistoryFilter {
+    pub account: [u8; 20],
+    /// The zero token stands for the native balance.
+    pub token: [u8; 20],
+    /// Lowest block number included.
+    pub from_block: Option<u64>,
+    /// Highest block number included.
+    pub to_block: Option<u64>,
+    pub finality: Finality,
+}
+
+/// Selects the logs returned by [`Database::query_logs`], the way `eth_getLogs` does.
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    /// Lowest block number included.
+    pub from_block: Option<u64>,
+    /// Highest block number included.
+    pub to_block: Option<u64>,
+    /// Contracts any of which may have emitted the log, or any contract if empty.
+    pub addresses: Vec<[u8; 20]>,
+    /// Topics the log may have at each position, the first one being the event signature. An
+    /// empty position matches any topic, and positions past the end are not checked.
+    pub topics: Vec<Vec<[u8; 32]>>,
+    pub finality: Finality,
+}
+
 pub struct Database {
     pub conn: SqliteConnection,
 }
@@ -24,7 +108,9 @@ pub struct Database {
 impl Database {
     #[tracing::instrument(skip(database_url))]
     pub fn connect(database_url: &str) -> anyhow::Result<Self> {
-        let conn = SqliteConnection::establish(database_url)?;
+        let mut conn = SqliteConnection::establish(database_url)?;
+        // SQLite only enforces foreign keys (and their `ON DELETE CASCADE`) when asked to.
+        conn.batch_execute("PRAGMA foreign_keys = ON;")?;
         Ok(Self { conn })
     }
 
@@ -39,6 +125,7 @@ impl Database {
                     .number
                     .ok_or_else(|| anyhow::anyhow!("Missing block number"))?),
             )
+            .order(schema::transactions::transaction_index)
             .select(DbTransaction::as_select())
             .load::<DbTransaction>(conn)?;
 
@@ -49,53 +136,87 @@ impl Database {
                     .ok_or_else(|| anyhow::anyhow!("Missing block number"))?),
             )
             .load::<models::Log>(conn)?;
+        let logs = Self::load_log_topics(conn, db_logs)?;
 
+        let block = Block::try_from(db_block)?;
+
+        let mut transactions = db_transactions
+            .into_iter()
+            .map(|v| v.try_into())
+            .collect::<Result<Vec<Transaction>, _>>()?;
+        Self::load_access_lists(conn, &mut transactions)?;
+
+        Ok(Info {
+            block,
+            transactions,
+            logs,
+        })
+    }
+
+    /// Attaches their topics to `db_logs`, in order.
+    fn load_log_topics(
+        conn: &mut SqliteConnection,
+        db_logs: Vec<models::Log>,
+    ) -> anyhow::Result<Vec<Log>> {
         let log_ids: Vec<i32> = db_logs.iter().filter_map(|log| log.id).collect();
 
         let db_log_topics: Vec<models::LogTopic> = schema::log_topics::table
             .filter(schema::log_topics::log_id.eq_any(log_ids))
+            .order((schema::log_topics::log_id, schema::log_topics::topic_index))
             .load::<models::LogTopic>(conn)?;
 
-        let logs = db_logs
+        db_logs
             .into_iter()
             .map(|db_log| {
                 let topics: Vec<[u8; 32]> = db_log_topics
                     .iter()
-                    .filter(|topic| {
-                        db_log
-                            .id
-                            .and_then(|i| (i == topic.log_id).then_some(i))
-                            .is_some()
-                    })
-                    .map(|topic| topic.topic.clone().try_into().unwrap())
-                    .collect();
-                let mut log: Result<types::Log, _> = db_log.try_into();
-                if let Ok(log) = &mut log {
-                    log.topics = topics;
-                }
-                log
+                    .filter(|topic| db_log.id == Some(topic.log_id))
+                    .map(|topic| topic.topic.clone().try_into())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| anyhow::anyhow!("Invalid topic"))?;
+                let mut log = types::Log::try_from(db_log)?;
+                log.topics = topics;
+                Ok(log)
             })
-            .collect::<Result<Vec<Log>, _>>()?;
-
-        let block = Block::try_from(db_block)?;
+            .collect()
+    }
 
-        let transactions = db_transactions
-            .into_iter()
-            .map(|v| v.try_into())
-            .collect::<Result<Vec<Transaction>, _>>()?;
+    /// Fills in the access lists of `transactions` from `access_list_items`.
+    fn load_access_lists(
+        conn: &mut SqliteConnection,
+        transactions: &mut [Transaction],
+    ) -> anyhow::Result<()> {
+        let hashes: Vec<&[u8]> = transactions.iter().map(|tx| tx.hash.as_slice()).collect();
+        let items: Vec<DbAccessListItem> = schema::access_list_items::table
+            .filter(schema::access_list_items::transaction_hash.eq_any(hashes))
+            .order((
+                schema::access_list_items::transaction_hash,
+                schema::access_list_items::item_index,
+            ))
+            .select(DbAccessListItem::as_select())
+            .load(conn)?;
 
-        Ok(Info {
-            block,
-            transactions,
-            logs,
-        })
+        for item in items {
+            if let Some(tx) = transactions
+                .iter_mut()
+                .find(|tx| tx.hash.as_slice() == item.transaction_hash)
+            {
+                tx.access_list.push(item.try_into()?);
+            }
+        }
+        Ok(())
     }
 
     #[tracing::instrument(skip(self))]
-    pub fn query_block_by_number(&mut self, number: u64) -> anyhow::Result<Info> {
+    pub fn query_block_by_number(
+        &mut self,
+        number: u64,
+        finality: Finality,
+    ) -> anyhow::Result<Info> {
         let conn = &mut self.conn;
         let db_block: DbBlock = schema::blocks::table
             .filter(schema::blocks::number.eq(number as i64))
+            .filter(schema::blocks::finality.eq_any(settled_as(finality)))
             .select(DbBlock::as_select())
             .first(conn)?;
 
@@ -103,231 +224,2501 @@ impl Database {
     }
 
     #[tracing::instrument(skip(self))]
-    pub fn query_block_by_hash(&mut self, hash: &[u8]) -> anyhow::Result<Info> {
+    pub fn query_block_by_hash(&mut self, hash: &[u8], finality: Finality) -> anyhow::Result<Info> {
         let conn = &mut self.conn;
         let db_block: DbBlock = schema::blocks::table
             .filter(schema::blocks::hash.eq(hash))
+            .filter(schema::blocks::finality.eq_any(settled_as(finality)))
             .select(DbBlock::as_select())
             .first(conn)?;
 
         Self::get_block_info(conn, db_block)
     }
 
+    /// Returns the headers of the stored blocks numbered `from..=to` and above `after`, lowest
+    /// number first, that are at least as settled as `finality`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_blocks(
+        &mut self,
+        from: Option<u64>,
+        to: Option<u64>,
+        after: Option<u64>,
+        limit: i64,
+        finality: Finality,
+    ) -> anyhow::Result<Vec<Block>> {
+        use schema::blocks::dsl;
+
+        let conn = &mut self.conn;
+        let mut query = dsl::blocks
+            .filter(dsl::finality.eq_any(settled_as(finality)))
+            .into_boxed();
+        if let Some(from) = from {
+            query = query.filter(dsl::number.ge(from as i64));
+        }
+        if let Some(to) = to {
+            query = query.filter(dsl::number.le(to as i64));
+        }
+        if let Some(after) = after {
+            query = query.filter(dsl::number.gt(after as i64));
+        }
+        query
+            .order(dsl::number.asc())
+            .limit(limit)
+            .select(DbBlock::as_select())
+            .load::<DbBlock>(conn)?
+            .into_iter()
+            .map(Block::try_from)
+            .collect()
+    }
+
+    /// Returns the header of the block stored at `number`, if it is at least as settled as
+    /// `finality`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_block_header_by_number(
+        &mut self,
+        number: u64,
+        finality: Finality,
+    ) -> anyhow::Result<Option<Block>> {
+        let conn = &mut self.conn;
+        schema::blocks::table
+            .filter(schema::blocks::number.eq(number as i64))
+            .filter(schema::blocks::finality.eq_any(settled_as(finality)))
+            .select(DbBlock::as_select())
+            .first(conn)
+            .optional()?
+            .map(Block::try_from)
+            .transpose()
+    }
+
+    /// Returns the header of the block stored with `hash`, if it is at least as settled as
+    /// `finality`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_block_header_by_hash(
+        &mut self,
+        hash: &[u8],
+        finality: Finality,
+    ) -> anyhow::Result<Option<Block>> {
+        let conn = &mut self.conn;
+        schema::blocks::table
+            .filter(schema::blocks::hash.eq(hash))
+            .filter(schema::blocks::finality.eq_any(settled_as(finality)))
+            .select(DbBlock::as_select())
+            .first(conn)
+            .optional()?
+            .map(Block::try_from)
+            .transpose()
+    }
+
+    /// Returns the transactions of the block stored at `number`, in block order, skipping the
+    /// first `offset` of them.
+    #[tracing::instrument(skip(self))]
+    pub fn query_block_transactions(
+        &mut self,
+        number: u64,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<Transaction>> {
+        let conn = &mut self.conn;
+        let db_transactions: Vec<DbTransaction> = schema::transactions::table
+            .filter(schema::transactions::block_number.eq(number as i64))
+            .order(schema::transactions::transaction_index)
+            .limit(limit)
+            .offset(offset)
+            .select(DbTransaction::as_select())
+            .load(conn)?;
+        let mut transactions = db_transactions
+            .into_iter()
+            .map(Transaction::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::load_access_lists(conn, &mut transactions)?;
+        Ok(transactions)
+    }
+
+    /// Returns the logs of the block stored at `number`, by log index, skipping the first `offset`
+    /// of them.
+    #[tracing::instrument(skip(self))]
+    pub fn query_block_logs(
+        &mut self,
+        number: u64,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<Log>> {
+        let conn = &mut self.conn;
+        let db_logs: Vec<models::Log> = schema::logs::table
+            .filter(schema::logs::block_number.eq(number as i64))
+            .order((schema::logs::log_index, schema::logs::id))
+            .limit(limit)
+            .offset(offset)
+            .load(conn)?;
+        Self::load_log_topics(conn, db_logs)
+    }
+
+    /// Returns the block stored with `hash`, with its transactions, logs and token transfers, the
+    /// rest of its summary being left empty.
+    #[tracing::instrument(skip(self))]
+    pub fn query_stored_block(&mut self, hash: &[u8; 32]) -> anyhow::Result<Option<BlockSummary>> {
+        let conn = &mut self.conn;
+        let Some(db_block) = schema::blocks::table
+            .filter(schema::blocks::hash.eq(hash.as_slice()))
+            .select(DbBlock::as_select())
+            .first(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let info = Self::get_block_info(conn, db_block)?;
+        let token_transfers = schema::token_transfers::table
+            .filter(schema::token_transfers::block_number.eq(info.block.number as i64))
+            .order(schema::token_transfers::log_index.asc())
+            .select(DbTokenTransfer::as_select())
+            .load::<DbTokenTransfer>(conn)?
+            .into_iter()
+            .map(TokenTransfer::try_from)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Some(BlockSummary {
+            block: info.block,
+            transactions: info.transactions,
+            logs: info.logs,
+            token_transfers,
+            ..Default::default()
+        }))
+    }
+
     #[tracing::instrument(skip(self))]
-    pub fn query_transaction_by_hash(&mut self, hash: &[u8]) -> anyhow::Result<types::Transaction> {
+    pub fn query_transaction_by_hash(
+        &mut self,
+        hash: &[u8],
+        finality: Finality,
+    ) -> anyhow::Result<types::Transaction> {
         let conn = &mut self.conn;
+        let settled_blocks = schema::blocks::table
+            .filter(schema::blocks::finality.eq_any(settled_as(finality)))
+            .select(schema::blocks::number.assume_not_null());
         let db_tx: DbTransaction = schema::transactions::table
             .filter(schema::transactions::hash.eq(hash))
+            .filter(schema::transactions::block_number.eq_any(settled_blocks))
             .select(DbTransaction::as_select())
             .first(conn)?;
-        db_tx.try_into()
-    }
-
-    // pub fn get_logs_filtered(
-    //     &mut self,
-    //     block_hash: Option<&[u8]>,
-    //     transaction_hash: Option<&[u8]>,
-    //     topics: Vec<&[u8]>,
-    // ) -> anyhow::Result<Vec<types::Log>> {
-    //     let conn = &mut self.conn;
-    //     let mut query = schema::logs::table.into_boxed::<diesel::sqlite::Sqlite>();
-
-    //     if let Some(b_hash) = block_hash {
-    //         let db_block: DbBlock = schema::blocks::table
-    //             .filter(schema::blocks::hash.eq(b_hash))
-    //             .select(DbBlock::as_select())
-    //             .first(conn)?;
-    //         query = query
-    //             .filter(schema::logs::block_number.eq(db_block.number.unwrap_or_default() as i64));
-    //     }
-
-    //     if let Some(tx_hash) = transaction_hash {
-    //         query = query.filter(schema::logs::transaction_hash.eq(tx_hash));
-    //     }
-
-    //     let db_logs: Vec<models::Log> = query.load::<models::Log>(conn)?;
-
-    //     let log_ids: Vec<i32> = db_logs.iter().filter_map(|log| log.id).collect();
-
-    //     let db_log_topics: Vec<models::LogTopic> = schema::log_topics::table
-    //         .filter(schema::log_topics::log_id.eq_any(log_ids))
-    //         .load::<models::LogTopic>(conn)?;
-
-    //     let mut logs: Vec<types::Log> = db_logs
-    //         .into_iter()
-    //         .map(|db_log| {
-    //             let topics: Vec<[u8; 32]> = db_log_topics
-    //                 .iter()
-    //                 .filter(|topic| {
-    //                     db_log
-    //                         .id
-    //                         .and_then(|i| (i == topic.log_id).then_some(i))
-    //                         .is_some()
-    //                 })
-    //                 .map(|topic| topic.topic.clone().try_into().unwrap())
-    //                 .collect();
-    //             let mut log: Result<types::Log, _> = db_log.try_into();
-    //             if let Ok(log) = &mut log {
-    //                 log.topics = topics;
-    //             }
-    //             log
-    //         })
-    //         .collect::<Result<Vec<types::Log>, _>>()?;
-
-    //     if !topics.is_empty() {
-    //         logs.retain(|log| {
-    //             topics.iter().all(|filter_topic| {
-    //                 log.topics
-    //                     .iter()
-    //                     .any(|log_topic| log_topic.as_slice() == *filter_topic)
-    //             })
-    //         });
-    //     }
-
-    //     Ok(logs)
-    // }
+        let mut transactions = [db_tx.try_into()?];
+        Self::load_access_lists(conn, &mut transactions)?;
+        let [transaction] = transactions;
+        Ok(transaction)
+    }
 
-    #[tracing::instrument(skip(self, info))]
-    pub fn insert_block(&mut self, info: &BlockSummary) -> anyhow::Result<()> {
+    /// Returns the receipt of the transaction with the given hash.
+    #[tracing::instrument(skip(self))]
+    pub fn query_receipt_by_transaction_hash(
+        &mut self,
+        hash: &[u8],
+        finality: Finality,
+    ) -> anyhow::Result<Receipt> {
         let conn = &mut self.conn;
-        conn.transaction(|conn| -> diesel::result::QueryResult<()> {
-            let new_block = NewBlock::from(&info.block);
-            diesel::insert_into(schema::blocks::table)
-                .values(&new_block)
-                .execute(conn)?;
+        let settled_blocks = schema::blocks::table
+            .filter(schema::blocks::finality.eq_any(settled_as(finality)))
+            .select(schema::blocks::number.assume_not_null());
+        let db_receipt: DbReceipt = schema::receipts::table
+            .inner_join(schema::transactions::table)
+            .filter(schema::receipts::transaction_hash.eq(hash))
+            .filter(schema::transactions::block_number.eq_any(settled_blocks))
+            .select(DbReceipt::as_select())
+            .first(conn)?;
+        db_receipt.try_into()
+    }
 
-            if !info.transactions.is_empty() {
-                let new_txs: Vec<NewTransaction> = info
-                    .transactions
-                    .iter()
-                    .map(|tx| NewTransaction {
-                        hash: &tx.hash,
-                        block_number: info.block.number as i64,
-                    })
-                    .collect();
+    /// Returns the reverted transactions of the block stored at `number`, in block order. Fails
+    /// if the block is not stored, or is less settled than `finality`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_failed_transactions(
+        &mut self,
+        number: u64,
+        finality: Finality,
+    ) -> anyhow::Result<Vec<Transaction>> {
+        let conn = &mut self.conn;
+        schema::blocks::table
+            .filter(schema::blocks::number.eq(number as i64))
+            .filter(schema::blocks::finality.eq_any(settled_as(finality)))
+            .select(schema::blocks::number)
+            .first::<Option<i64>>(conn)?;
 
-                diesel::insert_into(schema::transactions::table)
-                    .values(&new_txs)
-                    .execute(conn)?;
+        let db_transactions: Vec<DbTransaction> = schema::transactions::table
+            .inner_join(schema::receipts::table)
+            .filter(schema::transactions::block_number.eq(number as i64))
+            .filter(schema::receipts::success.eq(false))
+            .order(schema::transactions::transaction_index)
+            .select(DbTransaction::as_select())
+            .load(conn)?;
+        let mut transactions = db_transactions
+            .into_iter()
+            .map(Transaction::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::load_access_lists(conn, &mut transactions)?;
+        Ok(transactions)
+    }
+
+    /// Returns the token transfers matching `filter`, most recent first, starting after the one at
+    /// `after`, given as a block number and log index.
+    #[tracing::instrument(skip(self))]
+    pub fn query_token_transfers(
+        &mut self,
+        filter: TransferFilter,
+        after: Option<(u64, u64)>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<TokenTransfer>> {
+        use schema::token_transfers::dsl;
+
+        let conn = &mut self.conn;
+        let settled_blocks = schema::blocks::table
+            .filter(schema::blocks::finality.eq_any(settled_as(filter.finality)))
+            .select(schema::blocks::number.assume_not_null());
+        let mut query = dsl::token_transfers
+            .filter(dsl::block_number.eq_any(settled_blocks))
+            .into_boxed();
+        query = match filter.party {
+            TransferParty::Token(token) => query.filter(dsl::token.eq(token.to_vec())),
+            TransferParty::Account(account) => query.filter(
+                dsl::from_address
+                    .eq(account.to_vec())
+                    .or(dsl::to_address.eq(account.to_vec())),
+            ),
+        };
+        if let Some(from_block) = filter.from_block {
+            query = query.filter(dsl::block_number.ge(from_block as i64));
+        }
+        if let Some(to_block) = filter.to_block {
+            query = query.filter(dsl::block_number.le(to_block as i64));
+        }
+        if let Some((block_number, log_index)) = after {
+            let (block_number, log_index) = (block_number as i64, log_index as i64);
+            query = query.filter(
+                dsl::block_number.lt(block_number).or(dsl::block_number
+                    .eq(block_number)
+                    .and(dsl::log_index.lt(log_index))),
+            );
+        }
+
+        query
+            .order((dsl::block_number.desc(), dsl::log_index.desc()))
+            .limit(limit)
+            .select(DbTokenTransfer::as_select())
+            .load::<DbTokenTransfer>(conn)?
+            .into_iter()
+            .map(TokenTransfer::try_from)
+            .collect()
+    }
+
+    /// Returns the ERC-721 transfers matching `filter`, most recent first, starting after the one
+    /// at `after`, given as a block number and log index. With `token_id`, only the transfers of
+    /// that token of the collection are returned.
+    #[tracing::instrument(skip(self))]
+    pub fn query_nft_transfers(
+        &mut self,
+        filter: TransferFilter,
+        token_id: Option<[u8; 32]>,
+        after: Option<(u64, u64)>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<NftTransfer>> {
+        use schema::nft_transfers::dsl;
+
+        let conn = &mut self.conn;
+        let settled_blocks = schema::blocks::table
+            .filter(schema::blocks::finality.eq_any(settled_as(filter.finality)))
+            .select(schema::blocks::number.assume_not_null());
+        let mut query = dsl::nft_transfers
+            .filter(dsl::block_number.eq_any(settled_blocks))
+            .into_boxed();
+        query = match filter.party {
+            TransferParty::Token(collection) => {
+                query.filter(dsl::collection.eq(collection.to_vec()))
             }
+            TransferParty::Account(account) => query.filter(
+                dsl::from_address
+                    .eq(account.to_vec())
+                    .or(dsl::to_address.eq(account.to_vec())),
+            ),
+        };
+        if let Some(token_id) = token_id {
+            query = query.filter(dsl::token_id.eq(token_id.to_vec()));
+        }
+        if let Some(from_block) = filter.from_block {
+            query = query.filter(dsl::block_number.ge(from_block as i64));
+        }
+        if let Some(to_block) = filter.to_block {
+            query = query.filter(dsl::block_number.le(to_block as i64));
+        }
+        if let Some((block_number, log_index)) = after {
+            let (block_number, log_index) = (block_number as i64, log_index as i64);
+            query = query.filter(
+                dsl::block_number.lt(block_number).or(dsl::block_number
+                    .eq(block_number)
+                    .and(dsl::log_index.lt(log_index))),
+            );
+        }
 
-            if !info.logs.is_empty() {
-                let mut new_log_topics: Vec<NewLogTopic> = Vec::new();
+        query
+            .order((dsl::block_number.desc(), dsl::log_index.desc()))
+            .limit(limit)
+            .select(DbNftTransfer::as_select())
+            .load::<DbNftTransfer>(conn)?
+            .into_iter()
+            .map(NftTransfer::try_from)
+            .collect()
+    }
 
-                for log in &info.logs {
-                    let new_log = NewLog {
-                        transaction_hash: log.transaction_hash.as_ref().map(|h| h.as_slice()),
-                        log_index: log.log_index.map(|i| i as i64),
-                        address: &log.address,
-                        data: &log.data,
-                        block_number: info.block.number as i64,
-                    };
+    /// Returns the ERC-721 tokens currently owned by `owner`, ordered by collection and token id,
+    /// starting after the token `after`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_nfts_owned(
+        &mut self,
+        owner: &[u8; 20],
+        after: Option<([u8; 20], [u8; 32])>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<NftOwnership>> {
+        use schema::nft_owners::dsl;
 
-                    diesel::insert_into(schema::logs::table)
-                        .values(&new_log)
-                        .execute(conn)?;
+        let conn = &mut self.conn;
+        let mut query = dsl::nft_owners
+            .filter(dsl::owner.eq(owner.as_slice()))
+            .into_boxed();
+        if let Some((collection, token_id)) = after {
+            query = query.filter(
+                dsl::collection.gt(collection.to_vec()).or(dsl::collection
+                    .eq(collection.to_vec())
+                    .and(dsl::token_id.gt(token_id.to_vec()))),
+            );
+        }
+        query
+            .order((dsl::collection, dsl::token_id))
+            .limit(limit)
+            .select(DbNftOwner::as_select())
+            .load::<DbNftOwner>(conn)?
+            .into_iter()
+            .map(NftOwnership::try_from)
+            .collect()
+    }
 
-                    let last_id: i64 = diesel::select(last_insert_rowid()).get_result(conn)?;
+    /// Records the new owner of each token moved by `transfers`, given in log order. Blocks can
+    /// be indexed out of order, so an owner is only replaced by a transfer from the same block or
+    /// a later one.
+    fn update_nft_owners(
+        conn: &mut SqliteConnection,
+        transfers: &[NftTransfer],
+    ) -> QueryResult<()> {
+        use diesel::sql_types::{BigInt, Binary};
 
-                    let topics: Vec<NewLogTopic> = log
-                        .topics
-                        .iter()
-                        .enumerate()
-                        .map(|(i, topic)| NewLogTopic {
-                            log_id: last_id as i32,
-                            topic_index: i as i32,
-                            topic,
-                        })
-                        .collect();
+        // Diesel cannot add a `WHERE` clause to an upsert on SQLite.
+        for transfer in transfers {
+            diesel::sql_query(
+                "INSERT INTO nft_owners (collection, token_id, owner, last_transfer_block) \
+                 VALUES (?, ?, ?, ?) \
+                 ON CONFLICT (collection, token_id) DO UPDATE \
+                 SET owner = excluded.owner, last_transfer_block = excluded.last_transfer_block \
+                 WHERE excluded.last_transfer_block >= nft_owners.last_transfer_block",
+            )
+            .bind::<Binary, _>(transfer.collection.as_slice())
+            .bind::<Binary, _>(transfer.token_id.as_slice())
+            .bind::<Binary, _>(transfer.to.as_slice())
+            .bind::<BigInt, _>(transfer.block_number as i64)
+            .execute(conn)?;
+        }
+        Ok(())
+    }
 
-                    new_log_topics.extend(topics);
-                }
+    /// Gives each of the `moved` tokens back to the recipient of its last stored transfer, once
+    /// the blocks rolled back are removed. Tokens without any transfer left are forgotten.
+    fn restore_nft_owners(
+        conn: &mut SqliteConnection,
+        moved: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> anyhow::Result<()> {
+        use schema::nft_transfers::dsl;
 
-                diesel::insert_into(schema::log_topics::table)
-                    .values(&new_log_topics)
+        for (collection, token_id) in moved {
+            let last: Option<DbNftTransfer> = dsl::nft_transfers
+                .filter(dsl::collection.eq(&collection))
+                .filter(dsl::token_id.eq(&token_id))
+                .order((dsl::block_number.desc(), dsl::log_index.desc()))
+                .select(DbNftTransfer::as_select())
+                .first(conn)
+                .optional()?;
+            if let Some(last) = last {
+                let transfer = NftTransfer::try_from(last)?;
+                diesel::insert_into(schema::nft_owners::table)
+                    .values(&NewNftOwner::from(&transfer))
                     .execute(conn)?;
             }
+        }
+        Ok(())
+    }
 
-            if !info.balances.is_empty() {
-                let new_balances: Vec<NewBalance> = info
-                    .balances
-                    .iter()
-                    .map(NewBalance::from)
-                    .collect();
+    /// Returns the ERC-1155 balances of `holder` that are not zero or are incomplete, ordered by
+    /// contract and id, starting after the token `after`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_erc1155_balances(
+        &mut self,
+        holder: &[u8; 20],
+        after: Option<([u8; 20], [u8; 32])>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<Erc1155Balance>> {
+        use schema::erc1155_balances::dsl;
 
-                diesel::insert_into(schema::balances::table)
-                    .values(&new_balances)
-                    .execute(conn)?;
+        let conn = &mut self.conn;
+        let mut query = dsl::erc1155_balances
+            .filter(dsl::holder.eq(holder.as_slice()))
+            .filter(dsl::received.ne(dsl::sent))
+            .into_boxed();
+        if let Some((contract, token_id)) = after {
+            query = query.filter(
+                dsl::contract.gt(contract.to_vec()).or(dsl::contract
+                    .eq(contract.to_vec())
+                    .and(dsl::token_id.gt(token_id.to_vec()))),
+            );
+        }
+        query
+            .order((dsl::contract, dsl::token_id))
+            .limit(limit)
+            .select(DbErc1155Balance::as_select())
+            .load::<DbErc1155Balance>(conn)?
+            .into_iter()
+            .map(Erc1155Balance::try_from)
+            .collect()
+    }
+
+    /// Adds the amounts moved by `transfers` to the amounts received by their recipients and
+    /// sent by their senders, or subtracts them when `undo` is set. As only sums are kept, blocks
+    /// can be indexed in any order.
+    fn apply_erc1155_transfers(
+        conn: &mut SqliteConnection,
+        transfers: &[Erc1155Transfer],
+        undo: bool,
+    ) -> anyhow::Result<()> {
+        use alloy::primitives::U256;
+        use schema::erc1155_balances::dsl;
+
+        let overflow = || anyhow::anyhow!("ERC-1155 balance overflow");
+        // Amounts received and sent by each holder of an id.
+        let mut moved = HashMap::<_, (U256, U256)>::new();
+        for transfer in transfers {
+            let value = U256::from_be_bytes(transfer.value);
+            // The zero address stands for mints and burns, it holds nothing.
+            if transfer.to != [0; 20] {
+                let (received, _) = moved
+                    .entry((transfer.contract, transfer.token_id, transfer.to))
+                    .or_default();
+                *received = received.checked_add(value).ok_or_else(overflow)?;
             }
+            if transfer.from != [0; 20] {
+                let (_, sent) = moved
+                    .entry((transfer.contract, transfer.token_id, transfer.from))
+                    .or_default();
+                *sent = sent.checked_add(value).ok_or_else(overflow)?;
+            }
+        }
 
-            if !info.receipts.is_empty() {
-                let new_receipts: Vec<NewReceipt> = info
-                    .receipts
-                    .iter()
-                    .map(NewReceipt::from)
-                    .collect();
+        for ((contract, token_id, holder), (received, sent)) in moved {
+            let key = dsl::contract
+                .eq(contract.to_vec())
+                .and(dsl::token_id.eq(token_id.to_vec()))
+                .and(dsl::holder.eq(holder.to_vec()));
+            let current: Option<(Vec<u8>, Vec<u8>)> = dsl::erc1155_balances
+                .filter(key.clone())
+                .select((dsl::received, dsl::sent))
+                .first(conn)
+                .optional()?;
+            let amount = |bytes: Vec<u8>| {
+                <[u8; 32]>::try_from(bytes)
+                    .map(U256::from_be_bytes)
+                    .map_err(|_| anyhow::anyhow!("Invalid ERC-1155 balance"))
+            };
+            let (current_received, current_sent) = match current {
+                Some((received, sent)) => (amount(received)?, amount(sent)?),
+                None => (U256::ZERO, U256::ZERO),
+            };
 
-                diesel::insert_into(schema::receipts::table)
-                    .values(&new_receipts)
+            let (received, sent) = if undo {
+                let underflow = || anyhow::anyhow!("ERC-1155 transfers undone twice");
+                (
+                    current_received
+                        .checked_sub(received)
+                        .ok_or_else(underflow)?,
+                    current_sent.checked_sub(sent).ok_or_else(underflow)?,
+                )
+            } else {
+                (
+                    current_received
+                        .checked_add(received)
+                        .ok_or_else(overflow)?,
+                    current_sent.checked_add(sent).ok_or_else(overflow)?,
+                )
+            };
+            if received.is_zero() && sent.is_zero() {
+                diesel::delete(dsl::erc1155_balances.filter(key)).execute(conn)?;
+            } else {
+                diesel::replace_into(dsl::erc1155_balances)
+                    .values(&DbErc1155Balance {
+                        contract: contract.to_vec(),
+                        token_id: token_id.to_vec(),
+                        holder: holder.to_vec(),
+                        received: received.to_be_bytes::<32>().to_vec(),
+                        sent: sent.to_be_bytes::<32>().to_vec(),
+                    })
                     .execute(conn)?;
             }
+        }
+        Ok(())
+    }
 
-            Ok(())
-        })?;
+    /// Returns the header of the block stored at `number`, if any.
+    #[tracing::instrument(skip(self))]
+    pub fn query_block_header(&mut self, number: u64) -> anyhow::Result<Option<Block>> {
+        let conn = &mut self.conn;
+        let db_block: Option<DbBlock> = schema::blocks::table
+            .filter(schema::blocks::number.eq(number as i64))
+            .select(DbBlock::as_select())
+            .first(conn)
+            .optional()?;
 
-        Ok(())
+        db_block.map(Block::try_from).transpose()
     }
 
-    #[cfg(test)]
-    fn get_conn(&mut self) -> &mut SqliteConnection {
-        &mut self.conn
+    /// Returns the number of the highest stored block.
+    #[tracing::instrument(skip(self))]
+    pub fn query_latest_block_number(&mut self) -> anyhow::Result<Option<u64>> {
+        let conn = &mut self.conn;
+        let number: Option<i64> = schema::blocks::table
+            .select(diesel::dsl::max(schema::blocks::number))
+            .first(conn)?;
+        Ok(number.map(|n| n as u64))
     }
 
-    #[cfg(test)]
-    pub fn connect_test() -> Self {
-        use diesel_migrations::MigrationHarness;
+    /// Returns the number of the highest stored block that is at least as settled as `finality`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_highest_block_number(
+        &mut self,
+        finality: Finality,
+    ) -> anyhow::Result<Option<u64>> {
+        let conn = &mut self.conn;
+        let number: Option<i64> = schema::blocks::table
+            .filter(schema::blocks::finality.eq_any(settled_as(finality)))
+            .select(diesel::dsl::max(schema::blocks::number))
+            .first(conn)?;
+        Ok(number.map(|n| n as u64))
+    }
 
-        let database_url = ":memory:";
-        let mut db = Database::connect(database_url).expect("Failed to connect to database");
-        db.get_conn()
-            .run_pending_migrations(MIGRATIONS)
-            .expect("Failed to run migrations");
-        db
+    /// Marks the stored blocks numbered up to `up_to` as `finality`, unless they are already more
+    /// settled. Returns how many blocks were promoted.
+    #[tracing::instrument(skip(self))]
+    pub fn promote_blocks(&mut self, finality: Finality, up_to: u64) -> anyhow::Result<usize> {
+        let conn = &mut self.conn;
+        let less_settled: Vec<&str> = Finality::ALL
+            .into_iter()
+            .filter(|f| *f < finality)
+            .map(|f| f.as_str())
+            .collect();
+        let promoted = diesel::update(schema::blocks::table)
+            .filter(schema::blocks::number.le(up_to as i64))
+            .filter(schema::blocks::finality.eq_any(less_settled))
+            .set(schema::blocks::finality.eq(finality.as_str()))
+            .execute(conn)?;
+        Ok(promoted)
     }
 
-    #[cfg(test)]
-    pub fn data_setup() -> BlockSummary {
-        use crate::types::{Balance, Receipt};
+    /// Returns the ranges of block numbers missing between the stored blocks numbered `since` or
+    /// above.
+    #[tracing::instrument(skip(self))]
+    pub fn query_block_gaps(&mut self, since: u64) -> anyhow::Result<Vec<RangeInclusive<u64>>> {
+        let conn = &mut self.conn;
+        let gaps: Vec<BlockGap> = diesel::sql_query(
+            "SELECT b.number + 1 AS gap_start, \
+                 (SELECT MIN(n.number) FROM blocks n WHERE n.number > b.number) - 1 AS gap_end \
+             FROM blocks b \
+             WHERE b.number >= ? \
+                 AND b.number < (SELECT MAX(number) FROM blocks) \
+                 AND NOT EXISTS (SELECT 1 FROM blocks n WHERE n.number = b.number + 1) \
+             ORDER BY b.number",
+        )
+        .bind::<diesel::sql_types::BigInt, _>(since as i64)
+        .load(conn)?;
+        Ok(gaps
+            .into_iter()
+            .map(|gap| gap.gap_start as u64..=gap.gap_end as u64)
+            .collect())
+    }
 
-        let block = Block {
-            number: 1,
-            hash: [1; 32],
-            parent_hash: [0; 32],
-            timestamp: 1234567890,
-            gas_limit: 1000000,
-            gas_used: 500000,
-            base_fee_per_gas: Some(100),
-        };
+    /// Returns the value stored under `key` in `indexer_state`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_state(&mut self, key: &str) -> anyhow::Result<Option<u64>> {
+        let conn = &mut self.conn;
+        let value: Option<i64> = schema::indexer_state::table
+            .filter(schema::indexer_state::key.eq(key))
+            .select(schema::indexer_state::value)
+            .first(conn)
+            .optional()?;
+        Ok(value.map(|v| v as u64))
+    }
 
-        let tx1 = Transaction { hash: [2; 32] };
-        let tx2 = Transaction { hash: [3; 32] };
+    /// Stores `value` under `key` in `indexer_state`, unless the key is already set.
+    #[tracing::instrument(skip(self))]
+    pub fn init_state(&mut self, key: &str, value: u64) -> anyhow::Result<()> {
+        let conn = &mut self.conn;
+        diesel::insert_or_ignore_into(schema::indexer_state::table)
+            .values((
+                schema::indexer_state::key.eq(key),
+                schema::indexer_state::value.eq(value as i64),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
 
-        let log1 = Log {
-            transaction_hash: Some([2; 32]),
-            log_index: Some(0),
-            address: [4; 20],
-            topics: vec![[5; 32], [6; 32]],
-            data: vec![7; 32],
-            block_number: 1,
-        };
-        let log2 = Log {
-            transaction_hash: Some([2; 32]),
-            log_index: Some(1),
-            address: [8; 20],
-            topics: vec![[9; 32]],
-            data: vec![10; 32],
-            block_number: 1,
-        };
+    /// Removes every block above `ancestor`, together with its transactions, logs, balances,
+    /// receipts and token transfers, and records the removed headers in `orphaned_blocks`. The
+    /// changes those blocks made to derived balances are undone, total supplies read at them
+    /// are forgotten, and their pending webhook deliveries are cancelled.
+    #[tracing::instrument(skip(self))]
+    pub fn rollback_to(&mut self, ancestor: u64) -> anyhow::Result<Vec<OrphanedBlock>> {
+        let orphaned_at = unix_now()?;
+        let conn = &mut self.conn;
+        conn.transaction(|conn| -> anyhow::Result<Vec<OrphanedBlock>> {
+            let orphaned = schema::blocks::table
+                .filter(schema::blocks::number.gt(ancestor as i64))
+                .order(schema::blocks::number.asc())
+                .select(DbBlock::as_select())
+                .load::<DbBlock>(conn)?
+                .into_iter()
+                .map(|db_block| {
+                    let block = Block::try_from(db_block)?;
+                    Ok(OrphanedBlock {
+                        number: block.number,
+                        hash: block.hash,
+                        parent_hash: block.parent_hash,
+                        timestamp: block.timestamp,
+                        orphaned_at,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            if orphaned.is_empty() {
+                return Ok(orphaned);
+            }
+
+            let new_orphaned: Vec<NewOrphanedBlock> =
+                orphaned.iter().map(NewOrphanedBlock::from).collect();
+            diesel::replace_into(schema::orphaned_blocks::table)
+                .values(&new_orphaned)
+                .execute(conn)?;
+
+            // Webhooks are told of the removal of the blocks they were sent, and of no other.
+            let hashes: Vec<&[u8]> = orphaned.iter().map(|block| block.hash.as_slice()).collect();
+            diesel::update(
+                schema::webhook_deliveries::table
+                    .filter(schema::webhook_deliveries::block_hash.eq_any(&hashes))
+                    .filter(
+                        schema::webhook_deliveries::status.eq(DeliveryStatus::Pending.as_str()),
+                    ),
+            )
+            .set(schema::webhook_deliveries::status.eq(DeliveryStatus::Cancelled.as_str()))
+            .execute(conn)?;
+            diesel::delete(
+                schema::webhook_events::table
+                    .filter(schema::webhook_events::block_hash.eq_any(&hashes))
+                    .filter(schema::webhook_events::removed.eq(false)),
+            )
+            .execute(conn)?;
+            for block in &orphaned {
+                Self::record_webhook_event(conn, block.number, &block.hash, true)?;
+            }
+
+            let moved_accounts = schema::accounts::table
+                .filter(schema::accounts::block_id.gt(ancestor as i64))
+                .select((
+                    schema::accounts::account,
+                    schema::accounts::token,
+                    schema::accounts::balance,
+                ))
+                .load::<(Vec<u8>, Vec<u8>, Vec<u8>)>(conn)?;
+            // The accounts are removed with their blocks, and counted again once restored.
+            for (_, token, balance) in &moved_accounts {
+                if is_nonzero(balance) {
+                    Self::count_holders(conn, token, -1)?;
+                }
+            }
+
+            let moved_nfts = schema::nft_owners::table
+                .filter(schema::nft_owners::last_transfer_block.gt(ancestor as i64))
+                .select((schema::nft_owners::collection, schema::nft_owners::token_id))
+                .load::<(Vec<u8>, Vec<u8>)>(conn)?;
+            diesel::delete(
+                schema::nft_owners::table
+                    .filter(schema::nft_owners::last_transfer_block.gt(ancestor as i64)),
+            )
+            .execute(conn)?;
+
+            let erc1155_transfers = schema::erc1155_transfers::table
+                .filter(schema::erc1155_transfers::block_number.gt(ancestor as i64))
+                .select(DbErc1155Transfer::as_select())
+                .load::<DbErc1155Transfer>(conn)?
+                .into_iter()
+                .map(Erc1155Transfer::try_from)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            Self::apply_erc1155_transfers(conn, &erc1155_transfers, true)?;
+
+            let applied_deltas = schema::balance_deltas::table
+                .filter(schema::balance_deltas::block_id.gt(ancestor as i64))
+                .filter(schema::balance_deltas::applied.eq(true))
+                .order(schema::balance_deltas::block_id.desc())
+                .select(DbBalanceDelta::as_select())
+                .load::<DbBalanceDelta>(conn)?
+                .into_iter()
+                .map(BalanceDelta::try_from)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            for delta in &applied_deltas {
+                Self::add_to_derived_balance(conn, delta, true)?;
+            }
+            // Balances seeded at an orphaned block are seeded again once they move.
+            diesel::delete(
+                schema::derived_balances::table
+                    .filter(schema::derived_balances::seeded_block.gt(ancestor as i64)),
+            )
+            .execute(conn)?;
+
+            diesel::delete(
+                schema::blocks::table.filter(schema::blocks::number.gt(ancestor as i64)),
+            )
+            .execute(conn)?;
+
+            Self::restore_nft_owners(conn, moved_nfts)?;
+            Self::restore_accounts(conn, moved_accounts)?;
+
+            diesel::update(
+                schema::token_stats::table
+                    .filter(schema::token_stats::supply_block.gt(ancestor as i64)),
+            )
+            .set((
+                schema::token_stats::total_supply.eq(None::<Vec<u8>>),
+                schema::token_stats::supply_updated_at.eq(None::<i64>),
+                schema::token_stats::supply_block.eq(None::<i64>),
+            ))
+            .execute(conn)?;
+
+            Ok(orphaned)
+        })
+    }
+
+    /// Returns the most recently orphaned blocks, highest block number first, starting after the
+    /// one orphaned at `after`, given as its orphaning time, number and hash.
+    #[tracing::instrument(skip(self))]
+    pub fn query_orphaned_blocks(
+        &mut self,
+        after: Option<(u64, u64, [u8; 32])>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<OrphanedBlock>> {
+        use schema::orphaned_blocks::dsl;
+
+        let conn = &mut self.conn;
+        let mut query = dsl::orphaned_blocks.into_boxed();
+        if let Some((orphaned_at, number, hash)) = after {
+            let (orphaned_at, number) = (orphaned_at as i64, number as i64);
+            query = query.filter(
+                dsl::orphaned_at
+                    .lt(orphaned_at)
+                    .or(dsl::orphaned_at.eq(orphaned_at).and(
+                        dsl::number
+                            .lt(number)
+                            .or(dsl::number.eq(number).and(dsl::hash.gt(hash.to_vec()))),
+                    )),
+            );
+        }
+        query
+            .order((dsl::orphaned_at.desc(), dsl::number.desc(), dsl::hash.asc()))
+            .limit(limit)
+            .select(DbOrphanedBlock::as_select())
+            .load::<DbOrphanedBlock>(conn)?
+            .into_iter()
+            .map(OrphanedBlock::try_from)
+            .collect()
+    }
+
+    /// Registers a backfill of `from..=to`, or returns the unfinished job already covering
+    /// exactly that range.
+    #[tracing::instrument(skip(self))]
+    pub fn create_backfill_job(&mut self, from: u64, to: u64) -> anyhow::Result<BackfillJob> {
+        let conn = &mut self.conn;
+        conn.transaction(|conn| -> anyhow::Result<BackfillJob> {
+            let existing: Option<DbBackfillJob> = schema::backfill_jobs::table
+                .filter(schema::backfill_jobs::from_block.eq(from as i64))
+                .filter(schema::backfill_jobs::to_block.eq(to as i64))
+                .filter(schema::backfill_jobs::next_block.le(schema::backfill_jobs::to_block))
+                .select(DbBackfillJob::as_select())
+                .first(conn)
+                .optional()?;
+            if let Some(job) = existing {
+                return job.try_into();
+            }
+
+            diesel::insert_into(schema::backfill_jobs::table)
+                .values(&NewBackfillJob {
+                    from_block: from as i64,
+                    to_block: to as i64,
+                    next_block: from as i64,
+                })
+                .execute(conn)?;
+            let id: i64 = diesel::select(last_insert_rowid()).get_result(conn)?;
+
+            Ok(BackfillJob {
+                id: id as i32,
+                from_block: from,
+                to_block: to,
+                next_block: from,
+            })
+        })
+    }
+
+    /// Returns the backfill jobs that still have blocks left to process.
+    #[tracing::instrument(skip(self))]
+    pub fn query_pending_backfill_jobs(&mut self) -> anyhow::Result<Vec<BackfillJob>> {
+        let conn = &mut self.conn;
+        schema::backfill_jobs::table
+            .filter(schema::backfill_jobs::next_block.le(schema::backfill_jobs::to_block))
+            .order(schema::backfill_jobs::id.asc())
+            .select(DbBackfillJob::as_select())
+            .load::<DbBackfillJob>(conn)?
+            .into_iter()
+            .map(BackfillJob::try_from)
+            .collect()
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn update_backfill_progress(&mut self, id: i32, next_block: u64) -> anyhow::Result<()> {
+        let conn = &mut self.conn;
+        diesel::update(schema::backfill_jobs::table.filter(schema::backfill_jobs::id.eq(id)))
+            .set(schema::backfill_jobs::next_block.eq(next_block as i64))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Records a failed attempt to fetch the block at `number`.
+    #[tracing::instrument(skip(self, hash, error))]
+    pub fn record_dead_letter(
+        &mut self,
+        number: u64,
+        hash: &[u8; 32],
+        error: &str,
+    ) -> anyhow::Result<()> {
+        use schema::dead_letter_blocks::dsl;
+
+        let last_attempt_at = unix_now()? as i64;
+        let conn = &mut self.conn;
+        diesel::insert_into(dsl::dead_letter_blocks)
+            .values(&NewDeadLetterBlock {
+                number: number as i64,
+                hash,
+                error,
+                attempts: 1,
+                last_attempt_at,
+            })
+            .on_conflict(dsl::number)
+            .do_update()
+            .set((
+                dsl::hash.eq(hash.as_slice()),
+                dsl::error.eq(error),
+                dsl::attempts.eq(dsl::attempts + 1),
+                dsl::last_attempt_at.eq(last_attempt_at),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Returns the dead-lettered blocks, lowest number first, starting above `after`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_dead_letters(
+        &mut self,
+        after: Option<u64>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<DeadLetterBlock>> {
+        let conn = &mut self.conn;
+        schema::dead_letter_blocks::table
+            .filter(schema::dead_letter_blocks::number.gt(after.map_or(-1, |number| number as i64)))
+            .order(schema::dead_letter_blocks::number.asc())
+            .limit(limit)
+            .select(DbDeadLetterBlock::as_select())
+            .load::<DbDeadLetterBlock>(conn)?
+            .into_iter()
+            .map(DeadLetterBlock::try_from)
+            .collect()
+    }
+
+    /// Returns the dead-lettered blocks attempted fewer than `max_attempts` times.
+    #[tracing::instrument(skip(self))]
+    pub fn query_retryable_dead_letters(
+        &mut self,
+        max_attempts: u32,
+    ) -> anyhow::Result<Vec<DeadLetterBlock>> {
+        let conn = &mut self.conn;
+        schema::dead_letter_blocks::table
+            .filter(schema::dead_letter_blocks::attempts.lt(max_attempts as i32))
+            .order(schema::dead_letter_blocks::number.asc())
+            .select(DbDeadLetterBlock::as_select())
+            .load::<DbDeadLetterBlock>(conn)?
+            .into_iter()
+            .map(DeadLetterBlock::try_from)
+            .collect()
+    }
+
+    /// Makes a dead-lettered block retryable again. Returns whether it was found.
+    #[tracing::instrument(skip(self))]
+    pub fn reset_dead_letter(&mut self, number: u64) -> anyhow::Result<bool> {
+        let conn = &mut self.conn;
+        let updated = diesel::update(
+            schema::dead_letter_blocks::table
+                .filter(schema::dead_letter_blocks::number.eq(number as i64)),
+        )
+        .set(schema::dead_letter_blocks::attempts.eq(0))
+        .execute(conn)?;
+        Ok(updated > 0)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn remove_dead_letter(&mut self, number: u64) -> anyhow::Result<()> {
+        let conn = &mut self.conn;
+        diesel::delete(
+            schema::dead_letter_blocks::table
+                .filter(schema::dead_letter_blocks::number.eq(number as i64)),
+        )
+        .execute(conn)?;
+        Ok(())
+    }
+
+    /// Returns every token of the registry, enabled or not.
+    #[tracing::instrument(skip(self))]
+    pub fn query_watched_tokens(&mut self) -> anyhow::Result<Vec<WatchedToken>> {
+        let conn = &mut self.conn;
+        schema::watched_tokens::table
+            .order(schema::watched_tokens::address)
+            .select(DbWatchedToken::as_select())
+            .load::<DbWatchedToken>(conn)?
+            .into_iter()
+            .map(WatchedToken::try_from)
+            .collect()
+    }
+
+    /// Returns the addresses of the enabled tokens, whose balances are fetched.
+    #[tracing::instrument(skip(self))]
+    pub fn query_enabled_tokens(&mut self) -> anyhow::Result<Vec<[u8; 20]>> {
+        Ok(self
+            .query_watched_tokens()?
+            .into_iter()
+            .filter(|token| token.enabled)
+            .map(|token| token.address)
+            .collect())
+    }
+
+    /// Adds an enabled token to the registry, flagging its past holders' balances to be fetched
+    /// when `backfill` is set. Returns whether the token was new; a token already registered is
+    /// enabled again.
+    #[tracing::instrument(skip(self))]
+    pub fn add_watched_token(
+        &mut self,
+        address: &[u8; 20],
+        backfill: bool,
+    ) -> anyhow::Result<bool> {
+        let added_at = unix_now()? as i64;
+        let conn = &mut self.conn;
+        conn.transaction(|conn| -> anyhow::Result<bool> {
+            let inserted = diesel::insert_or_ignore_into(schema::watched_tokens::table)
+                .values(&NewWatchedToken {
+                    address,
+                    enabled: true,
+                    backfill_pending: backfill,
+                    added_at,
+                })
+                .execute(conn)?;
+            if inserted > 0 {
+                return Ok(true);
+            }
+
+            let token = schema::watched_tokens::table
+                .filter(schema::watched_tokens::address.eq(address.as_slice()));
+            diesel::update(token)
+                .set(schema::watched_tokens::enabled.eq(true))
+                .execute(conn)?;
+            if backfill {
+                diesel::update(token)
+                    .set(schema::watched_tokens::backfill_pending.eq(true))
+                    .execute(conn)?;
+            }
+            Ok(false)
+        })
+    }
+
+    /// Adds the tokens that are not registered yet, leaving the others as they are.
+    #[tracing::instrument(skip(self))]
+    pub fn register_tokens(&mut self, addresses: &[[u8; 20]]) -> anyhow::Result<()> {
+        let added_at = unix_now()? as i64;
+        let conn = &mut self.conn;
+        let new_tokens: Vec<NewWatchedToken> = addresses
+            .iter()
+            .map(|address| NewWatchedToken {
+                address,
+                enabled: true,
+                backfill_pending: false,
+                added_at,
+            })
+            .collect();
+        diesel::insert_or_ignore_into(schema::watched_tokens::table)
+            .values(&new_tokens)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Removes a token from the registry. Its stored balances are kept. Returns whether it was
+    /// found.
+    #[tracing::instrument(skip(self))]
+    pub fn remove_watched_token(&mut self, address: &[u8; 20]) -> anyhow::Result<bool> {
+        let conn = &mut self.conn;
+        let deleted = diesel::delete(
+            schema::watched_tokens::table
+                .filter(schema::watched_tokens::address.eq(address.as_slice())),
+        )
+        .execute(conn)?;
+        Ok(deleted > 0)
+    }
+
+    /// Enables or disables a registered token. Returns whether it was found.
+    #[tracing::instrument(skip(self))]
+    pub fn set_token_enabled(&mut self, address: &[u8; 20], enabled: bool) -> anyhow::Result<bool> {
+        let conn = &mut self.conn;
+        let updated = diesel::update(
+            schema::watched_tokens::table
+                .filter(schema::watched_tokens::address.eq(address.as_slice())),
+        )
+        .set(schema::watched_tokens::enabled.eq(enabled))
+        .execute(conn)?;
+        Ok(updated > 0)
+    }
+
+    /// Returns the enabled tokens whose past holders' balances are still to be fetched.
+    #[tracing::instrument(skip(self))]
+    pub fn query_pending_token_backfills(&mut self) -> anyhow::Result<Vec<[u8; 20]>> {
+        Ok(self
+            .query_watched_tokens()?
+            .into_iter()
+            .filter(|token| token.enabled && token.backfill_pending)
+            .map(|token| token.address)
+            .collect())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn finish_token_backfill(&mut self, address: &[u8; 20]) -> anyhow::Result<()> {
+        let conn = &mut self.conn;
+        diesel::update(
+            schema::watched_tokens::table
+                .filter(schema::watched_tokens::address.eq(address.as_slice())),
+        )
+        .set(schema::watched_tokens::backfill_pending.eq(false))
+        .execute(conn)?;
+        Ok(())
+    }
+
+    /// Returns every account that sent or received `token` in the stored transfers, except the
+    /// zero address, along with each block where it did so and its balance was not stored yet.
+    /// Ordered by block, then account.
+    #[tracing::instrument(skip(self))]
+    pub fn query_unfetched_token_balances(
+        &mut self,
+        token: &[u8; 20],
+    ) -> anyhow::Result<Vec<([u8; 20], u64)>> {
+        let conn = &mut self.conn;
+        let unfetched: Vec<UnfetchedBalance> = diesel::sql_query(
+            "SELECT DISTINCT t.account, t.block_number FROM ( \
+                 SELECT from_address AS account, block_number FROM token_transfers WHERE token = ? \
+                 UNION SELECT to_address, block_number FROM token_transfers WHERE token = ? \
+             ) t \
+             WHERE t.account != zeroblob(20) \
+                 AND NOT EXISTS (SELECT 1 FROM balances b \
+                     WHERE b.account = t.account AND b.token = ? \
+                         AND b.block_id = t.block_number) \
+             ORDER BY t.block_number, t.account",
+        )
+        .bind::<diesel::sql_types::Binary, _>(token.as_slice())
+        .bind::<diesel::sql_types::Binary, _>(token.as_slice())
+        .bind::<diesel::sql_types::Binary, _>(token.as_slice())
+        .load(conn)?;
+        unfetched
+            .into_iter()
+            .map(|balance| {
+                Ok((
+                    balance
+                        .account
+                        .try_into()
+                        .map_err(|_| anyhow::anyhow!("Invalid account"))?,
+                    balance.block_number as u64,
+                ))
+            })
+            .collect()
+    }
+
+    /// Stores balances outside of a block's insertion, replacing the ones already stored for the
+    /// same account, token and block. They are no longer missing, if they were.
+    #[tracing::instrument(skip(self, balances))]
+    pub fn insert_balances(&mut self, balances: &[types::Balance]) -> anyhow::Result<()> {
+        use schema::missing_balances::dsl;
+
+        let conn = &mut self.conn;
+        conn.transaction(|conn| -> anyhow::Result<()> {
+            let new_balances: Vec<NewBalance> = balances.iter().map(NewBalance::from).collect();
+            diesel::replace_into(schema::balances::table)
+                .values(&new_balances)
+                .execute(conn)?;
+            Self::update_accounts(conn, balances)?;
+            for balance in balances {
+                diesel::delete(
+                    dsl::missing_balances
+                        .filter(dsl::account.eq(balance.account.as_slice()))
+                        .filter(dsl::token.eq(balance.token.as_slice()))
+                        .filter(dsl::block_id.eq(balance.block_id as i64)),
+                )
+                .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Records each balance as the latest of its account and token, unless one fetched at a later
+    /// block is already recorded, since blocks can be indexed out of order. The token's holder
+    /// count follows the accounts whose balance becomes, or stops being, zero.
+    fn update_accounts(
+        conn: &mut SqliteConnection,
+        balances: &[types::Balance],
+    ) -> QueryResult<()> {
+        use schema::accounts::dsl;
+
+        for balance in balances {
+            let current = dsl::accounts
+                .filter(dsl::account.eq(balance.account.as_slice()))
+                .filter(dsl::token.eq(balance.token.as_slice()))
+                .select((dsl::balance, dsl::block_id))
+                .first::<(Vec<u8>, i64)>(conn)
+                .optional()?;
+            if current
+                .as_ref()
+                .is_some_and(|(_, block_id)| *block_id > balance.block_id as i64)
+            {
+                continue;
+            }
+
+            diesel::replace_into(dsl::accounts)
+                .values((
+                    dsl::account.eq(balance.account.as_slice()),
+                    dsl::token.eq(balance.token.as_slice()),
+                    dsl::balance.eq(balance.balance.as_slice()),
+                    dsl::block_id.eq(balance.block_id as i64),
+                ))
+                .execute(conn)?;
+
+            let was_holder = current.is_some_and(|(held, _)| is_nonzero(&held));
+            let is_holder = is_nonzero(&balance.balance);
+            Self::count_holders(conn, &balance.token, is_holder as i64 - was_holder as i64)?;
+        }
+        Ok(())
+    }
+
+    /// Adds `change` to the holder count of `token`.
+    fn count_holders(conn: &mut SqliteConnection, token: &[u8], change: i64) -> QueryResult<()> {
+        use schema::token_stats::dsl;
+
+        if change == 0 {
+            return Ok(());
+        }
+        diesel::insert_into(dsl::token_stats)
+            .values((dsl::token.eq(token), dsl::holders.eq(change)))
+            .on_conflict(dsl::token)
+            .do_update()
+            .set(dsl::holders.eq(dsl::holders + change))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Records the derived balance of each of the `moved` accounts and tokens, or else the last
+    /// balance still stored for them, as their latest one, once the blocks rolled back are
+    /// removed and their changes undone.
+    fn restore_accounts(
+        conn: &mut SqliteConnection,
+        moved: Vec<(Vec<u8>, Vec<u8>, Vec<u8>)>,
+    ) -> anyhow::Result<()> {
+        use schema::balances::dsl;
+
+        for (account, token, _) in moved {
+            if Self::sync_derived_account(conn, &account, &token)? {
+                continue;
+            }
+            let last: Option<DbBalance> = dsl::balances
+                .filter(dsl::account.eq(&account))
+                .filter(dsl::token.eq(&token))
+                .order(dsl::block_id.desc())
+                .select(DbBalance::as_select())
+                .first(conn)
+                .optional()?;
+            if let Some(last) = last {
+                Self::update_accounts(conn, &[types::Balance::try_from(last)?])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the latest balance of `account` in each token it was looked up in, the zero token
+    /// standing for the native balance, ordered by token.
+    #[tracing::instrument(skip(self))]
+    pub fn query_account_balances(
+        &mut self,
+        account: &[u8; 20],
+    ) -> anyhow::Result<Vec<types::Balance>> {
+        use schema::accounts::dsl;
+
+        let conn = &mut self.conn;
+        dsl::accounts
+            .filter(dsl::account.eq(account.as_slice()))
+            .order(dsl::token)
+            .select((dsl::account, dsl::token, dsl::balance, dsl::block_id))
+            .load::<DbBalance>(conn)?
+            .into_iter()
+            .map(types::Balance::try_from)
+            .collect()
+    }
+
+    /// Returns the last balance stored for `account` in `token` as of `block` or an earlier block,
+    /// the zero token standing for the native balance.
+    #[tracing::instrument(skip(self))]
+    pub fn query_balance_at(
+        &mut self,
+        account: &[u8; 20],
+        token: &[u8; 20],
+        block: u64,
+    ) -> anyhow::Result<Option<types::Balance>> {
+        use schema::balances::dsl;
+
+        let conn = &mut self.conn;
+        dsl::balances
+            .filter(dsl::account.eq(account.as_slice()))
+            .filter(dsl::token.eq(token.as_slice()))
+            .filter(dsl::block_id.le(block as i64))
+            .order(dsl::block_id.desc())
+            .select(DbBalance::as_select())
+            .first::<DbBalance>(conn)
+            .optional()?
+            .map(types::Balance::try_from)
+            .transpose()
+    }
+
+    /// Returns the balances stored for an account and token matching `filter`, oldest first,
+    /// starting above the block `after`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_balance_history(
+        &mut self,
+        filter: BalanceHistoryFilter,
+        after: Option<u64>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<BalanceSnapshot>> {
+        use schema::balances::dsl;
+
+        let conn = &mut self.conn;
+        let mut query = dsl::balances
+            .inner_join(schema::blocks::table)
+            .filter(dsl::account.eq(filter.account.to_vec()))
+            .filter(dsl::token.eq(filter.token.to_vec()))
+            .filter(schema::blocks::finality.eq_any(settled_as(filter.finality)))
+            .into_boxed();
+        if let Some(from_block) = filter.from_block {
+            query = query.filter(dsl::block_id.ge(from_block as i64));
+        }
+        if let Some(to_block) = filter.to_block {
+            query = query.filter(dsl::block_id.le(to_block as i64));
+        }
+        if let Some(after) = after {
+            query = query.filter(dsl::block_id.gt(after as i64));
+        }
+
+        query
+            .order(dsl::block_id.asc())
+            .limit(limit)
+            .select((dsl::block_id, schema::blocks::timestamp, dsl::balance))
+            .load::<(i64, i64, Vec<u8>)>(conn)?
+            .into_iter()
+            .map(|(block_id, timestamp, balance)| {
+                Ok(BalanceSnapshot {
+                    block_id: block_id as u64,
+                    timestamp: timestamp as u64,
+                    balance: balance
+                        .try_into()
+                        .map_err(|_| anyhow::anyhow!("Invalid balance"))?,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the logs matching `filter` in chain order, each with its row id, starting after the
+    /// log at `after`, given as a block number and row id. Logs are paged by row id rather than
+    /// log index, which some logs are stored without.
+    #[tracing::instrument(skip(self))]
+    pub fn query_logs(
+        &mut self,
+        filter: &LogFilter,
+        after: Option<(u64, u64)>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<(u64, Log)>> {
+        use schema::logs::dsl;
+
+        let conn = &mut self.conn;
+        let mut query = dsl::logs
+            .inner_join(schema::blocks::table)
+            .filter(schema::blocks::finality.eq_any(settled_as(filter.finality)))
+            .select(models::Log::as_select())
+            .into_boxed();
+        if let Some(from_block) = filter.from_block {
+            query = query.filter(dsl::block_number.ge(from_block as i64));
+        }
+        if let Some(to_block) = filter.to_block {
+            query = query.filter(dsl::block_number.le(to_block as i64));
+        }
+        if !filter.addresses.is_empty() {
+            let addresses: Vec<Vec<u8>> = filter.addresses.iter().map(|a| a.to_vec()).collect();
+            query = query.filter(dsl::address.eq_any(addresses));
+        }
+        for (index, topics) in filter.topics.iter().enumerate() {
+            if topics.is_empty() {
+                continue;
+            }
+            let topics: Vec<Vec<u8>> = topics.iter().map(|t| t.to_vec()).collect();
+            query = query.filter(diesel::dsl::exists(
+                schema::log_topics::table
+                    .filter(schema::log_topics::log_id.nullable().eq(dsl::id))
+                    .filter(schema::log_topics::topic_index.eq(index as i32))
+                    .filter(schema::log_topics::topic.eq_any(topics)),
+            ));
+        }
+        if let Some((block_number, id)) = after {
+            query = query.filter(
+                dsl::block_number
+                    .gt(block_number as i64)
+                    .or(dsl::block_number
+                        .eq(block_number as i64)
+                        .and(dsl::id.gt(id as i32))),
+            );
+        }
+
+        // Logs are stored in the order of the block, so their row ids follow it.
+        let db_logs = query
+            .order((dsl::block_number.asc(), dsl::id.asc()))
+            .limit(limit)
+            .load::<models::Log>(conn)?;
+        let ids: Vec<u64> = db_logs
+            .iter()
+            .map(|log| log.id.unwrap_or_default() as u64)
+            .collect();
+        Ok(ids
+            .into_iter()
+            .zip(Self::load_log_topics(conn, db_logs)?)
+            .collect())
+    }
+
+    /// Returns the position in its block of each of the transactions with the given `hashes` that
+    /// is stored.
+    #[tracing::instrument(skip(self, hashes))]
+    pub fn query_transaction_indexes(
+        &mut self,
+        hashes: &[[u8; 32]],
+    ) -> anyhow::Result<HashMap<[u8; 32], u64>> {
+        use schema::transactions::dsl;
+
+        let conn = &mut self.conn;
+        let hashes: Vec<&[u8]> = hashes.iter().map(|hash| hash.as_slice()).collect();
+        dsl::transactions
+            .filter(dsl::hash.eq_any(hashes))
+            .select((dsl::hash, dsl::transaction_index))
+            .load::<(Option<Vec<u8>>, i64)>(conn)?
+            .into_iter()
+            .map(|(hash, index)| {
+                let hash = hash
+                    .and_then(|hash| hash.try_into().ok())
+                    .ok_or_else(|| anyhow::anyhow!("Invalid transaction hash"))?;
+                Ok((hash, index as u64))
+            })
+            .collect()
+    }
+
+    /// Returns the number of the block with `hash`, if it is at least as settled as `finality`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_block_number_by_hash(
+        &mut self,
+        hash: &[u8; 32],
+        finality: Finality,
+    ) -> anyhow::Result<Option<u64>> {
+        let conn = &mut self.conn;
+        let number = schema::blocks::table
+            .filter(schema::blocks::hash.eq(hash.as_slice()))
+            .filter(schema::blocks::finality.eq_any(settled_as(finality)))
+            .select(schema::blocks::number)
+            .first::<Option<i64>>(conn)
+            .optional()?;
+        Ok(number.flatten().map(|n| n as u64))
+    }
+
+    /// Returns the accounts holding `token`, largest balance first, starting after the one at
+    /// `after`, given as its balance and account.
+    #[tracing::instrument(skip(self))]
+    pub fn query_top_holders(
+        &mut self,
+        token: &[u8; 20],
+        after: Option<([u8; 32], [u8; 20])>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<types::Balance>> {
+        use schema::accounts::dsl;
+
+        let conn = &mut self.conn;
+        // Balances are big-endian, so comparing their bytes compares their values.
+        let mut query = dsl::accounts
+            .filter(dsl::token.eq(token.as_slice()))
+            .filter(dsl::balance.ne([0u8; 32].as_slice()))
+            .into_boxed();
+        if let Some((balance, account)) = after {
+            query = query.filter(
+                dsl::balance.lt(balance.to_vec()).or(dsl::balance
+                    .eq(balance.to_vec())
+                    .and(dsl::account.gt(account.to_vec()))),
+            );
+        }
+        query
+            .order((dsl::balance.desc(), dsl::account.asc()))
+            .limit(limit)
+            .select((dsl::account, dsl::token, dsl::balance, dsl::block_id))
+            .load::<DbBalance>(conn)?
+            .into_iter()
+            .map(types::Balance::try_from)
+            .collect()
+    }
+
+    /// Returns the statistics of `token`, if any of its balances were stored or its total supply
+    /// was fetched.
+    #[tracing::instrument(skip(self))]
+    pub fn query_token_stats(&mut self, token: &[u8; 20]) -> anyhow::Result<Option<TokenStats>> {
+        use schema::token_stats::dsl;
+
+        let conn = &mut self.conn;
+        dsl::token_stats
+            .filter(dsl::token.eq(token.as_slice()))
+            .select(DbTokenStats::as_select())
+            .first::<DbTokenStats>(conn)
+            .optional()?
+            .map(TokenStats::try_from)
+            .transpose()
+    }
+
+    /// Records the total supply of `token` as of the stored block `block`, fetched at
+    /// `updated_at`. It is forgotten if that block is rolled back.
+    #[tracing::instrument(skip(self))]
+    pub fn update_total_supply(
+        &mut self,
+        token: &[u8; 20],
+        total_supply: &[u8; 32],
+        block: u64,
+        updated_at: u64,
+    ) -> anyhow::Result<()> {
+        use schema::token_stats::dsl;
+
+        let conn = &mut self.conn;
+        diesel::insert_into(dsl::token_stats)
+            .values((
+                dsl::token.eq(token.as_slice()),
+                dsl::total_supply.eq(total_supply.as_slice()),
+                dsl::supply_updated_at.eq(updated_at as i64),
+                dsl::supply_block.eq(block as i64),
+            ))
+            .on_conflict(dsl::token)
+            .do_update()
+            .set((
+                dsl::total_supply.eq(total_supply.as_slice()),
+                dsl::supply_updated_at.eq(updated_at as i64),
+                dsl::supply_block.eq(block as i64),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Records balances that could not be fetched, counting one more attempt for the ones
+    /// already missing.
+    #[tracing::instrument(skip(self, balances))]
+    pub fn record_missing_balances(&mut self, balances: &[MissingBalance]) -> anyhow::Result<()> {
+        use schema::missing_balances::dsl;
+
+        let conn = &mut self.conn;
+        conn.transaction(|conn| -> anyhow::Result<()> {
+            for balance in balances {
+                diesel::insert_into(dsl::missing_balances)
+                    .values(&NewMissingBalance::from(balance))
+                    .on_conflict((dsl::account, dsl::token, dsl::block_id))
+                    .do_update()
+                    .set((
+                        dsl::error.eq(&balance.error),
+                        dsl::attempts.eq(dsl::attempts + 1),
+                    ))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the balances that could not be fetched, most recent block first, starting after
+    /// the one at `after`, given as its block, account and token.
+    #[tracing::instrument(skip(self))]
+    pub fn query_missing_balances(
+        &mut self,
+        after: Option<(u64, [u8; 20], [u8; 20])>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<MissingBalance>> {
+        self.load_missing_balances(None, after, limit)
+    }
+
+    /// Returns the missing balances attempted fewer than `max_attempts` times.
+    #[tracing::instrument(skip(self))]
+    pub fn query_retryable_missing_balances(
+        &mut self,
+        max_attempts: u32,
+        limit: i64,
+    ) -> anyhow::Result<Vec<MissingBalance>> {
+        self.load_missing_balances(Some(max_attempts), None, limit)
+    }
+
+    fn load_missing_balances(
+        &mut self,
+        max_attempts: Option<u32>,
+        after: Option<(u64, [u8; 20], [u8; 20])>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<MissingBalance>> {
+        use schema::missing_balances::dsl;
+
+        let conn = &mut self.conn;
+        let mut query = dsl::missing_balances.into_boxed();
+        if let Some(max_attempts) = max_attempts {
+            query = query.filter(dsl::attempts.lt(max_attempts as i32));
+        }
+        if let Some((block_id, account, token)) = after {
+            let block_id = block_id as i64;
+            query = query.filter(
+                dsl::block_id
+                    .lt(block_id)
+                    .or(dsl::block_id.eq(block_id).and(
+                        dsl::account.gt(account.to_vec()).or(dsl::account
+                            .eq(account.to_vec())
+                            .and(dsl::token.gt(token.to_vec()))),
+                    )),
+            );
+        }
+        query
+            .order((dsl::block_id.desc(), dsl::account, dsl::token))
+            .limit(limit)
+            .select(DbMissingBalance::as_select())
+            .load::<DbMissingBalance>(conn)?
+            .into_iter()
+            .map(MissingBalance::try_from)
+            .collect()
+    }
+
+    /// Stores the changes a block makes to the balances of watched tokens, and applies them to the
+    /// derived balances seeded before that block. The others are applied once their balance is
+    /// seeded, if it is seeded before their block.
+    fn insert_balance_deltas(
+        conn: &mut SqliteConnection,
+        deltas: &[BalanceDelta],
+    ) -> QueryResult<()> {
+        use schema::derived_balances::dsl;
+
+        for delta in deltas {
+            let seeded_block: Option<i64> = dsl::derived_balances
+                .filter(dsl::account.eq(delta.account.as_slice()))
+                .filter(dsl::token.eq(delta.token.as_slice()))
+                .select(dsl::seeded_block)
+                .first(conn)
+                .optional()?;
+            // A balance seeded at this block or a later one already includes the change.
+            let applied = seeded_block.is_some_and(|seeded| seeded < delta.block_id as i64);
+            diesel::insert_into(schema::balance_deltas::table)
+                .values(&DbBalanceDelta::new(delta, applied))
+                .execute(conn)?;
+            if applied {
+                Self::add_to_derived_balance(conn, delta, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds `delta` to its derived balance, or subtracts it when `undo` is set. Undoing the
+    /// changes of several blocks has to go from the highest block down, for the balance to end up
+    /// updated at the last block still applied.
+    fn add_to_derived_balance(
+        conn: &mut SqliteConnection,
+        delta: &BalanceDelta,
+        undo: bool,
+    ) -> QueryResult<()> {
+        use alloy::primitives::U256;
+        use schema::derived_balances::dsl;
+
+        let key = dsl::account
+            .eq(delta.account.to_vec())
+            .and(dsl::token.eq(delta.token.to_vec()));
+        let current: Option<(Vec<u8>, i64, i64)> = dsl::derived_balances
+            .filter(key.clone())
+            .select((dsl::balance, dsl::seeded_block, dsl::updated_block))
+            .first(conn)
+            .optional()?;
+        let Some((balance, seeded_block, updated_block)) = current else {
+            return Ok(());
+        };
+        let balance = <[u8; 32]>::try_from(balance)
+            .map(U256::from_be_bytes)
+            .unwrap_or_default();
+        let change = U256::from_be_bytes(delta.delta);
+
+        let (balance, updated_block) = if undo {
+            let previous: Option<i64> = schema::balance_deltas::table
+                .filter(schema::balance_deltas::account.eq(delta.account.as_slice()))
+                .filter(schema::balance_deltas::token.eq(delta.token.as_slice()))
+                .filter(schema::balance_deltas::applied.eq(true))
+                .filter(schema::balance_deltas::block_id.lt(delta.block_id as i64))
+                .select(diesel::dsl::max(schema::balance_deltas::block_id))
+                .first(conn)?;
+            (
+                balance.wrapping_sub(change),
+                previous.unwrap_or(seeded_block),
+            )
+        } else {
+            (
+                balance.wrapping_add(change),
+                updated_block.max(delta.block_id as i64),
+            )
+        };
+        diesel::update(dsl::derived_balances.filter(key))
+            .set((
+                dsl::balance.eq(balance.to_be_bytes::<32>().to_vec()),
+                dsl::updated_block.eq(updated_block),
+            ))
+            .execute(conn)?;
+        // Undone changes are followed by a rollback, which restores the accounts on its own.
+        if !undo {
+            Self::update_accounts(
+                conn,
+                &[types::Balance {
+                    account: delta.account,
+                    token: delta.token,
+                    balance: balance.to_be_bytes(),
+                    block_id: updated_block as u64,
+                }],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Records the derived balance of `account` in `token` as its latest one, so that derived
+    /// balances count towards the holders of their token. Returns whether there was one.
+    fn sync_derived_account(
+        conn: &mut SqliteConnection,
+        account: &[u8],
+        token: &[u8],
+    ) -> anyhow::Result<bool> {
+        use schema::derived_balances::dsl;
+
+        let derived: Option<DbDerivedBalance> = dsl::derived_balances
+            .filter(dsl::account.eq(account))
+            .filter(dsl::token.eq(token))
+            .select(DbDerivedBalance::as_select())
+            .first(conn)
+            .optional()?;
+        let Some(derived) = derived else {
+            return Ok(false);
+        };
+        let derived = DerivedBalance::try_from(derived)?;
+        Self::update_accounts(
+            conn,
+            &[types::Balance {
+                account: derived.account,
+                token: derived.token,
+                balance: derived.balance,
+                block_id: derived.updated_block,
+            }],
+        )?;
+        Ok(true)
+    }
+
+    /// Returns up to `limit` accounts and enabled watched tokens whose balance changed in a stored
+    /// block, but was never seeded.
+    #[tracing::instrument(skip(self))]
+    pub fn query_unseeded_balances(
+        &mut self,
+        limit: i64,
+    ) -> anyhow::Result<Vec<([u8; 20], [u8; 20])>> {
+        let conn = &mut self.conn;
+        let unseeded: Vec<UnseededBalance> = diesel::sql_query(
+            "SELECT DISTINCT d.account, d.token FROM balance_deltas d \
+             WHERE d.token IN (SELECT address FROM watched_tokens WHERE enabled) \
+                 AND NOT EXISTS (SELECT 1 FROM derived_balances b \
+                     WHERE b.account = d.account AND b.token = d.token) \
+             ORDER BY d.token, d.account \
+             LIMIT ?",
+        )
+        .bind::<diesel::sql_types::BigInt, _>(limit)
+        .load(conn)?;
+        unseeded
+            .into_iter()
+            .map(|pair| {
+                Ok((
+                    pair.account
+                        .try_into()
+                        .map_err(|_| anyhow::anyhow!("Invalid account"))?,
+                    pair.token
+                        .try_into()
+                        .map_err(|_| anyhow::anyhow!("Invalid token"))?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Seeds the derived balances that are not stored yet with `balances`, fetched as of their
+    /// block, then applies the changes already stored for the blocks after it. Returns how many
+    /// were seeded.
+    #[tracing::instrument(skip(self, balances))]
+    pub fn seed_derived_balances(&mut self, balances: &[types::Balance]) -> anyhow::Result<usize> {
+        use schema::balance_deltas::dsl;
+
+        let conn = &mut self.conn;
+        conn.transaction(|conn| -> anyhow::Result<usize> {
+            let mut seeded = 0;
+            for balance in balances {
+                let inserted = diesel::insert_or_ignore_into(schema::derived_balances::table)
+                    .values(&DbDerivedBalance::from(&DerivedBalance {
+                        account: balance.account,
+                        token: balance.token,
+                        balance: balance.balance,
+                        seeded_block: balance.block_id,
+                        updated_block: balance.block_id,
+                    }))
+                    .execute(conn)?;
+                if inserted == 0 {
+                    continue;
+                }
+                seeded += 1;
+
+                let pair = dsl::account
+                    .eq(balance.account.to_vec())
+                    .and(dsl::token.eq(balance.token.to_vec()));
+                let later = dsl::balance_deltas
+                    .filter(pair.clone())
+                    .filter(dsl::block_id.gt(balance.block_id as i64))
+                    .order(dsl::block_id.asc())
+                    .select(DbBalanceDelta::as_select())
+                    .load::<DbBalanceDelta>(conn)?;
+                for delta in later {
+                    Self::add_to_derived_balance(conn, &BalanceDelta::try_from(delta)?, false)?;
+                }
+                diesel::update(
+                    dsl::balance_deltas
+                        .filter(pair)
+                        .filter(dsl::block_id.gt(balance.block_id as i64)),
+                )
+                .set(dsl::applied.eq(true))
+                .execute(conn)?;
+                Self::sync_derived_account(conn, &balance.account, &balance.token)?;
+            }
+            Ok(seeded)
+        })
+    }
+
+    /// Returns the derived balances of `account`, ordered by token.
+    #[tracing::instrument(skip(self))]
+    pub fn query_derived_balances(
+        &mut self,
+        account: &[u8; 20],
+    ) -> anyhow::Result<Vec<DerivedBalance>> {
+        use schema::derived_balances::dsl;
+
+        let conn = &mut self.conn;
+        dsl::derived_balances
+            .filter(dsl::account.eq(account.as_slice()))
+            .order(dsl::token)
+            .select(DbDerivedBalance::as_select())
+            .load::<DbDerivedBalance>(conn)?
+            .into_iter()
+            .map(DerivedBalance::try_from)
+            .collect()
+    }
+
+    /// Returns up to `limit` derived balances, starting with the ones compared with their token
+    /// contract the longest ago.
+    #[tracing::instrument(skip(self))]
+    pub fn query_derived_balances_to_check(
+        &mut self,
+        limit: i64,
+    ) -> anyhow::Result<Vec<DerivedBalance>> {
+        use schema::derived_balances::dsl;
+
+        let conn = &mut self.conn;
+        dsl::derived_balances
+            .order((dsl::checked_at, dsl::token, dsl::account))
+            .limit(limit)
+            .select(DbDerivedBalance::as_select())
+            .load::<DbDerivedBalance>(conn)?
+            .into_iter()
+            .map(DerivedBalance::try_from)
+            .collect()
+    }
+
+    /// Records that the `checked` balances were compared with their token contract, replacing the
+    /// drift previously found for them with the one in `drifts`, if any.
+    #[tracing::instrument(skip(self, checked, drifts))]
+    pub fn record_balance_checks(
+        &mut self,
+        checked: &[([u8; 20], [u8; 20])],
+        drifts: &[BalanceDrift],
+        checked_at: u64,
+    ) -> anyhow::Result<()> {
+        let conn = &mut self.conn;
+        conn.transaction(|conn| -> anyhow::Result<()> {
+            for (account, token) in checked {
+                diesel::update(
+                    schema::derived_balances::table
+                        .filter(schema::derived_balances::account.eq(account.as_slice()))
+                        .filter(schema::derived_balances::token.eq(token.as_slice())),
+                )
+                .set(schema::derived_balances::checked_at.eq(checked_at as i64))
+                .execute(conn)?;
+                diesel::delete(
+                    schema::balance_drifts::table
+                        .filter(schema::balance_drifts::account.eq(account.as_slice()))
+                        .filter(schema::balance_drifts::token.eq(token.as_slice())),
+                )
+                .execute(conn)?;
+            }
+            let new_drifts: Vec<DbBalanceDrift> = drifts.iter().map(DbBalanceDrift::from).collect();
+            diesel::insert_into(schema::balance_drifts::table)
+                .values(&new_drifts)
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    /// Returns the derived balances found to differ from their token contract, most recently
+    /// detected first, starting after the one at `after`, given as its detection time, token and
+    /// account.
+    #[tracing::instrument(skip(self))]
+    pub fn query_balance_drifts(
+        &mut self,
+        after: Option<(u64, [u8; 20], [u8; 20])>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<BalanceDrift>> {
+        use schema::balance_drifts::dsl;
+
+        let conn = &mut self.conn;
+        let mut query = dsl::balance_drifts.into_boxed();
+        if let Some((detected_at, token, account)) = after {
+            let detected_at = detected_at as i64;
+            query = query.filter(
+                dsl::detected_at
+                    .lt(detected_at)
+                    .or(dsl::detected_at.eq(detected_at).and(
+                        dsl::token.gt(token.to_vec()).or(dsl::token
+                            .eq(token.to_vec())
+                            .and(dsl::account.gt(account.to_vec()))),
+                    )),
+            );
+        }
+        query
+            .order((dsl::detected_at.desc(), dsl::token, dsl::account))
+            .limit(limit)
+            .select(DbBalanceDrift::as_select())
+            .load::<DbBalanceDrift>(conn)?
+            .into_iter()
+            .map(BalanceDrift::try_from)
+            .collect()
+    }
+
+    /// Returns up to `limit` tokens that were transferred or added to the watchlist, but whose
+    /// metadata was never fetched. Tokens whose last attempt failed are left out until their
+    /// retry is due.
+    #[tracing::instrument(skip(self))]
+    pub fn query_unknown_tokens(&mut self, limit: i64) -> anyhow::Result<Vec<[u8; 20]>> {
+        let now = unix_now()? as i64;
+        let conn = &mut self.conn;
+        let known = schema::tokens::table
+            .filter(
+                schema::tokens::retry_at
+                    .is_null()
+                    .or(schema::tokens::retry_at.gt(now)),
+            )
+            .select(schema::tokens::address.assume_not_null());
+        let transferred: Vec<Vec<u8>> = schema::token_transfers::table
+            .select(schema::token_transfers::token)
+            .filter(schema::token_transfers::token.ne_all(known))
+            .distinct()
+            .limit(limit)
+            .load(conn)?;
+        let watched: Vec<Vec<u8>> = schema::watched_tokens::table
+            .select(schema::watched_tokens::address.assume_not_null())
+            .filter(
+                schema::watched_tokens::address
+                    .assume_not_null()
+                    .ne_all(known),
+            )
+            .load(conn)?;
+
+        let mut tokens = watched
+            .into_iter()
+            .chain(transferred)
+            .map(|token| {
+                <[u8; 20]>::try_from(token).map_err(|_| anyhow::anyhow!("Invalid address"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        tokens.sort();
+        tokens.dedup();
+        tokens.truncate(limit as usize);
+        Ok(tokens)
+    }
+
+    /// Stores the metadata of tokens, replacing what was stored for them.
+    #[tracing::instrument(skip(self, tokens))]
+    pub fn insert_token_metadata(&mut self, tokens: &[TokenMetadata]) -> anyhow::Result<()> {
+        let discovered_at = unix_now()? as i64;
+        let conn = &mut self.conn;
+        let new_tokens: Vec<NewToken> = tokens
+            .iter()
+            .map(|token| NewToken::new(token, discovered_at))
+            .collect();
+        diesel::replace_into(schema::tokens::table)
+            .values(&new_tokens)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Records a failed metadata lookup of each token, which is attempted again `base_delay`
+    /// seconds later, the delay doubling with each failure up to `max_delay`.
+    #[tracing::instrument(skip(self))]
+    pub fn record_token_metadata_failures(
+        &mut self,
+        tokens: &[[u8; 20]],
+        base_delay: u64,
+        max_delay: u64,
+    ) -> anyhow::Result<()> {
+        use diesel::sql_types::{BigInt, Binary};
+
+        let now = unix_now()? as i64;
+        let conn = &mut self.conn;
+        conn.transaction(|conn| -> anyhow::Result<()> {
+            for token in tokens {
+                diesel::sql_query(
+                    "INSERT INTO tokens (address, discovered_at, metadata_attempts, retry_at) \
+                     VALUES (?1, ?2, 1, ?2 + ?3) \
+                     ON CONFLICT (address) DO UPDATE \
+                     SET metadata_attempts = metadata_attempts + 1, \
+                         retry_at = ?2 + min(?3 << min(metadata_attempts, 32), ?4)",
+                )
+                .bind::<Binary, _>(token.as_slice())
+                .bind::<BigInt, _>(now)
+                .bind::<BigInt, _>(base_delay as i64)
+                .bind::<BigInt, _>(max_delay as i64)
+                .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the stored metadata of the given tokens. Unknown tokens, and the ones whose
+    /// metadata could not be fetched, are left out.
+    #[tracing::instrument(skip(self))]
+    pub fn query_token_metadata(
+        &mut self,
+        addresses: &[[u8; 20]],
+    ) -> anyhow::Result<Vec<TokenMetadata>> {
+        let conn = &mut self.conn;
+        let addresses: Vec<&[u8]> = addresses.iter().map(|a| a.as_slice()).collect();
+        schema::tokens::table
+            .filter(schema::tokens::address.assume_not_null().eq_any(addresses))
+            .filter(schema::tokens::metadata_attempts.eq(0))
+            .select(DbToken::as_select())
+            .load::<DbToken>(conn)?
+            .into_iter()
+            .map(TokenMetadata::try_from)
+            .collect()
+    }
+
+    /// Registers a webhook.
+    #[tracing::instrument(skip(self, secret))]
+    pub fn create_webhook(
+        &mut self,
+        url: &str,
+        secret: &str,
+        filter: &WebhookFilter,
+    ) -> anyhow::Result<Webhook> {
+        let created_at = unix_now()?;
+        let conn = &mut self.conn;
+        conn.transaction(|conn| -> anyhow::Result<Webhook> {
+            diesel::insert_into(schema::webhooks::table)
+                .values(&NewWebhook {
+                    url,
+                    secret,
+                    address: filter.address.as_ref().map(|a| a.as_slice()),
+                    token: filter.token.as_ref().map(|t| t.as_slice()),
+                    topic: filter.topic.as_ref().map(|t| t.as_slice()),
+                    min_value: filter.min_value.as_ref().map(|v| v.as_slice()),
+                    created_at: created_at as i64,
+                })
+                .execute(conn)?;
+            let id: i64 = diesel::select(last_insert_rowid()).get_result(conn)?;
+
+            Ok(Webhook {
+                id: id as i32,
+                url: url.to_string(),
+                secret: secret.to_string(),
+                filter: filter.clone(),
+                created_at,
+            })
+        })
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn query_webhooks(&mut self) -> anyhow::Result<Vec<Webhook>> {
+        let conn = &mut self.conn;
+        schema::webhooks::table
+            .order(schema::webhooks::id.asc())
+            .select(DbWebhook::as_select())
+            .load::<DbWebhook>(conn)?
+            .into_iter()
+            .map(Webhook::try_from)
+            .collect()
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn query_webhook(&mut self, id: i32) -> anyhow::Result<Option<Webhook>> {
+        let conn = &mut self.conn;
+        schema::webhooks::table
+            .filter(schema::webhooks::id.eq(id))
+            .select(DbWebhook::as_select())
+            .first::<DbWebhook>(conn)
+            .optional()?
+            .map(Webhook::try_from)
+            .transpose()
+    }
+
+    /// Removes a webhook along with its deliveries. Returns whether it was found.
+    #[tracing::instrument(skip(self))]
+    pub fn remove_webhook(&mut self, id: i32) -> anyhow::Result<bool> {
+        let conn = &mut self.conn;
+        let deleted = diesel::delete(schema::webhooks::table.filter(schema::webhooks::id.eq(id)))
+            .execute(conn)?;
+        Ok(deleted > 0)
+    }
+
+    /// Records that a block was stored, or rolled back, for the webhooks to be told of it. Nothing
+    /// is recorded while there are no webhooks.
+    fn record_webhook_event(
+        conn: &mut SqliteConnection,
+        number: u64,
+        hash: &[u8],
+        removed: bool,
+    ) -> QueryResult<usize> {
+        diesel::sql_query(
+            "INSERT INTO webhook_events (block_number, block_hash, removed) \
+             SELECT ?, ?, ? WHERE EXISTS (SELECT 1 FROM webhooks)",
+        )
+        .bind::<diesel::sql_types::BigInt, _>(number as i64)
+        .bind::<diesel::sql_types::Binary, _>(hash)
+        .bind::<diesel::sql_types::Bool, _>(removed)
+        .execute(conn)
+    }
+
+    /// Returns up to `limit` blocks whose webhook deliveries are still to be queued, in the order
+    /// they were stored or rolled back.
+    #[tracing::instrument(skip(self))]
+    pub fn query_webhook_events(&mut self, limit: i64) -> anyhow::Result<Vec<WebhookEvent>> {
+        let conn = &mut self.conn;
+        schema::webhook_events::table
+            .order(schema::webhook_events::id.asc())
+            .limit(limit)
+            .select(DbWebhookEvent::as_select())
+            .load::<DbWebhookEvent>(conn)?
+            .into_iter()
+            .map(WebhookEvent::try_from)
+            .collect()
+    }
+
+    /// Queues each `(webhook_id, payload)` for delivery, due right away, and marks `event` as
+    /// handled, both at once.
+    #[tracing::instrument(skip(self, payloads))]
+    pub fn queue_webhook_deliveries(
+        &mut self,
+        event: &WebhookEvent,
+        payloads: &[(i32, String)],
+    ) -> anyhow::Result<()> {
+        let created_at = unix_now()? as i64;
+        let conn = &mut self.conn;
+        conn.transaction(|conn| -> anyhow::Result<()> {
+            let deliveries: Vec<NewWebhookDelivery> = payloads
+                .iter()
+                .map(|(webhook_id, payload)| NewWebhookDelivery {
+                    webhook_id: *webhook_id,
+                    block_number: event.block_number as i64,
+                    block_hash: &event.block_hash,
+                    payload,
+                    status: DeliveryStatus::Pending.as_str(),
+                    next_attempt_at: created_at,
+                    created_at,
+                })
+                .collect();
+            if !deliveries.is_empty() {
+                diesel::insert_into(schema::webhook_deliveries::table)
+                    .values(&deliveries)
+                    .execute(conn)?;
+            }
+            diesel::delete(
+                schema::webhook_events::table.filter(schema::webhook_events::id.eq(event.id)),
+            )
+            .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    /// Returns the deliveries of the block with `hash` that have `status`, to any webhook.
+    #[tracing::instrument(skip(self))]
+    pub fn query_block_webhook_deliveries(
+        &mut self,
+        hash: &[u8; 32],
+        status: DeliveryStatus,
+    ) -> anyhow::Result<Vec<WebhookDelivery>> {
+        use schema::webhook_deliveries::dsl;
+
+        let conn = &mut self.conn;
+        dsl::webhook_deliveries
+            .filter(dsl::block_hash.eq(hash.as_slice()))
+            .filter(dsl::status.eq(status.as_str()))
+            .order(dsl::id.asc())
+            .select(DbWebhookDelivery::as_select())
+            .load::<DbWebhookDelivery>(conn)?
+            .into_iter()
+            .map(WebhookDelivery::try_from)
+            .collect()
+    }
+
+    /// Returns up to `limit` pending deliveries whose next attempt is due, oldest first.
+    #[tracing::instrument(skip(self))]
+    pub fn query_due_webhook_deliveries(
+        &mut self,
+        limit: i64,
+    ) -> anyhow::Result<Vec<WebhookDelivery>> {
+        use schema::webhook_deliveries::dsl;
+
+        let now = unix_now()? as i64;
+        let conn = &mut self.conn;
+        dsl::webhook_deliveries
+            .filter(dsl::status.eq(DeliveryStatus::Pending.as_str()))
+            .filter(dsl::next_attempt_at.le(now))
+            .order(dsl::id.asc())
+            .limit(limit)
+            .select(DbWebhookDelivery::as_select())
+            .load::<DbWebhookDelivery>(conn)?
+            .into_iter()
+            .map(WebhookDelivery::try_from)
+            .collect()
+    }
+
+    /// Records an attempt to deliver a payload, which leaves it with `status`. A pending delivery
+    /// is attempted again from `next_attempt_at`. Deliveries cancelled during the attempt are
+    /// left alone.
+    #[tracing::instrument(skip(self, error))]
+    pub fn record_webhook_attempt(
+        &mut self,
+        id: i32,
+        status: DeliveryStatus,
+        status_code: Option<u16>,
+        error: Option<&str>,
+        next_attempt_at: u64,
+    ) -> anyhow::Result<()> {
+        use schema::webhook_deliveries::dsl;
+
+        let now = unix_now()? as i64;
+        let conn = &mut self.conn;
+        diesel::update(
+            dsl::webhook_deliveries
+                .filter(dsl::id.eq(id))
+                .filter(dsl::status.eq(DeliveryStatus::Pending.as_str())),
+        )
+        .set((
+            dsl::status.eq(status.as_str()),
+            dsl::attempts.eq(dsl::attempts + 1),
+            dsl::next_attempt_at.eq(next_attempt_at as i64),
+            dsl::last_status_code.eq(status_code.map(i32::from)),
+            dsl::last_error.eq(error),
+            dsl::delivered_at.eq((status == DeliveryStatus::Delivered).then_some(now)),
+        ))
+        .execute(conn)?;
+        Ok(())
+    }
+
+    /// Returns the deliveries of a webhook, most recent first, starting below the delivery `before`.
+    #[tracing::instrument(skip(self))]
+    pub fn query_webhook_deliveries(
+        &mut self,
+        webhook_id: i32,
+        before: Option<i32>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<WebhookDelivery>> {
+        use schema::webhook_deliveries::dsl;
+
+        let conn = &mut self.conn;
+        dsl::webhook_deliveries
+            .filter(dsl::webhook_id.eq(webhook_id))
+            .filter(dsl::id.lt(before.unwrap_or(i32::MAX)))
+            .order(dsl::id.desc())
+            .limit(limit)
+            .select(DbWebhookDelivery::as_select())
+            .load::<DbWebhookDelivery>(conn)?
+            .into_iter()
+            .map(WebhookDelivery::try_from)
+            .collect()
+    }
+
+    #[tracing::instrument(skip(self, info))]
+    pub fn insert_block(&mut self, info: &BlockSummary) -> anyhow::Result<()> {
+        let conn = &mut self.conn;
+        conn.transaction(|conn| -> anyhow::Result<()> {
+            let new_block = NewBlock::from(&info.block);
+            diesel::insert_into(schema::blocks::table)
+                .values(&new_block)
+                .execute(conn)?;
+
+            if !info.transactions.is_empty() {
+                let new_txs: Vec<NewTransaction> = info
+                    .transactions
+                    .iter()
+                    .map(|tx| NewTransaction {
+                        block_number: info.block.number as i64,
+                        ..NewTransaction::from(tx)
+                    })
+                    .collect();
+
+                diesel::insert_into(schema::transactions::table)
+                    .values(&new_txs)
+                    .execute(conn)?;
+
+                let new_access_list_items: Vec<NewAccessListItem> =
+                    info.transactions
+                        .iter()
+                        .flat_map(|tx| {
+                            tx.access_list.iter().enumerate().map(|(index, item)| {
+                                NewAccessListItem {
+                                    transaction_hash: &tx.hash,
+                                    item_index: index as i32,
+                                    address: &item.address,
+                                    storage_keys: item.storage_keys.concat(),
+                                }
+                            })
+                        })
+                        .collect();
+                if !new_access_list_items.is_empty() {
+                    diesel::insert_into(schema::access_list_items::table)
+                        .values(&new_access_list_items)
+                        .execute(conn)?;
+                }
+            }
+
+            if !info.logs.is_empty() {
+                let mut new_log_topics: Vec<NewLogTopic> = Vec::new();
+
+                for log in &info.logs {
+                    let new_log = NewLog {
+                        transaction_hash: log.transaction_hash.as_ref().map(|h| h.as_slice()),
+                        log_index: log.log_index.map(|i| i as i64),
+                        address: &log.address,
+                        data: &log.data,
+                        block_number: info.block.number as i64,
+                    };
+
+                    diesel::insert_into(schema::logs::table)
+                        .values(&new_log)
+                        .execute(conn)?;
+
+                    let last_id: i64 = diesel::select(last_insert_rowid()).get_result(conn)?;
+
+                    let topics: Vec<NewLogTopic> = log
+                        .topics
+                        .iter()
+                        .enumerate()
+                        .map(|(i, topic)| NewLogTopic {
+                            log_id: last_id as i32,
+                            topic_index: i as i32,
+                            topic,
+                        })
+                        .collect();
+
+                    new_log_topics.extend(topics);
+                }
+
+                diesel::insert_into(schema::log_topics::table)
+                    .values(&new_log_topics)
+                    .execute(conn)?;
+            }
+
+            if !info.balances.is_empty() {
+                let new_balances: Vec<NewBalance> =
+                    info.balances.iter().map(NewBalance::from).collect();
+
+                diesel::insert_into(schema::balances::table)
+                    .values(&new_balances)
+                    .execute(conn)?;
+                Self::update_accounts(conn, &info.balances)?;
+            }
+
+            if !info.missing_balances.is_empty() {
+                let new_missing: Vec<NewMissingBalance> = info
+                    .missing_balances
+                    .iter()
+                    .map(NewMissingBalance::from)
+                    .collect();
+
+                diesel::insert_into(schema::missing_balances::table)
+                    .values(&new_missing)
+                    .execute(conn)?;
+            }
+
+            if !info.balance_deltas.is_empty() {
+                Self::insert_balance_deltas(conn, &info.balance_deltas)?;
+            }
+
+            if !info.receipts.is_empty() {
+                let new_receipts: Vec<NewReceipt> =
+                    info.receipts.iter().map(NewReceipt::from).collect();
+
+                diesel::insert_into(schema::receipts::table)
+                    .values(&new_receipts)
+                    .execute(conn)?;
+            }
+
+            if !info.token_transfers.is_empty() {
+                let new_transfers: Vec<NewTokenTransfer> = info
+                    .token_transfers
+                    .iter()
+                    .map(NewTokenTransfer::from)
+                    .collect();
+
+                diesel::insert_into(schema::token_transfers::table)
+                    .values(&new_transfers)
+                    .execute(conn)?;
+            }
+
+            if !info.nft_transfers.is_empty() {
+                let new_transfers: Vec<NewNftTransfer> = info
+                    .nft_transfers
+                    .iter()
+                    .map(NewNftTransfer::from)
+                    .collect();
+
+                diesel::insert_into(schema::nft_transfers::table)
+                    .values(&new_transfers)
+                    .execute(conn)?;
+                Self::update_nft_owners(conn, &info.nft_transfers)?;
+            }
+
+            if !info.erc1155_transfers.is_empty() {
+                let new_transfers: Vec<NewErc1155Transfer> = info
+                    .erc1155_transfers
+                    .iter()
+                    .map(NewErc1155Transfer::from)
+                    .collect();
+
+                diesel::insert_into(schema::erc1155_transfers::table)
+                    .values(&new_transfers)
+                    .execute(conn)?;
+                Self::apply_erc1155_transfers(conn, &info.erc1155_transfers, false)?;
+            }
+
+            Self::record_webhook_event(conn, info.block.number, &info.block.hash, false)?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn get_conn(&mut self) -> &mut SqliteConnection {
+        &mut self.conn
+    }
+
+    #[cfg(test)]
+    pub fn connect_test() -> Self {
+        use diesel_migrations::MigrationHarness;
+
+        let database_url = ":memory:";
+        let mut db = Database::connect(database_url).expect("Failed to connect to database");
+        db.get_conn()
+            .run_pending_migrations(MIGRATIONS)
+            .expect("Failed to run migrations");
+        db
+    }
+
+    #[cfg(test)]
+    pub fn data_setup() -> BlockSummary {
+        use crate::types::{Balance, Erc1155Transfer, NftTransfer, Receipt, TokenTransfer};
+
+        let block = Block {
+            number: 1,
+            hash: [1; 32],
+            parent_hash: [0; 32],
+            timestamp: 1234567890,
+            gas_limit: 1000000,
+            gas_used: 500000,
+            base_fee_per_gas: Some(100),
+            finality: Finality::Latest,
+        };
+
+        let tx1 = Transaction {
+            hash: [2; 32],
+            block_number: 1,
+            transaction_index: 0,
+            tx_type: 2,
+            chain_id: Some(1),
+            from: [7; 20],
+            to: Some([8; 20]),
+            value: [1; 32],
+            nonce: 5,
+            input: vec![0xa9, 0x05, 0x9c, 0xbb],
+            gas_limit: 21000,
+            gas_price: None,
+            max_fee_per_gas: Some(30_000_000_000),
+            max_priority_fee_per_gas: Some(1_000_000_000),
+            access_list: vec![types::AccessListItem {
+                address: [9; 20],
+                storage_keys: vec![[10; 32], [11; 32]],
+            }],
+        };
+        let tx2 = Transaction {
+            hash: [3; 32],
+            block_number: 1,
+            transaction_index: 1,
+            from: [8; 20],
+            gas_limit: 100000,
+            gas_price: Some(20_000_000_000),
+            ..Default::default()
+        };
+
+        let log1 = Log {
+            transaction_hash: Some([2; 32]),
+            log_index: Some(0),
+            address: [4; 20],
+            topics: vec![[5; 32], [6; 32]],
+            data: vec![7; 32],
+            block_number: 1,
+        };
+        let log2 = Log {
+            transaction_hash: Some([2; 32]),
+            log_index: Some(1),
+            address: [8; 20],
+            topics: vec![[9; 32]],
+            data: vec![10; 32],
+            block_number: 1,
+        };
         let log3 = Log {
             transaction_hash: Some([2; 32]),
             log_index: Some(2),
@@ -383,13 +2774,71 @@ impl Database {
             block_id: 1,
         };
 
-        let receipt1 = Receipt {
+        let receipt1 = Receipt {
+            transaction_hash: [2; 32],
+            gas_used: 21000,
+            transaction_index: 0,
+            success: true,
+            cumulative_gas_used: 21000,
+            effective_gas_price: 25_000_000_000,
+            contract_address: None,
+            logs_bloom: vec![0; 256],
+        };
+        let receipt2 = Receipt {
+            transaction_hash: [3; 32],
+            gas_used: 30000,
+            transaction_index: 1,
+            success: false,
+            cumulative_gas_used: 51000,
+            effective_gas_price: 20_000_000_000,
+            contract_address: Some([9; 20]),
+            logs_bloom: vec![1; 256],
+        };
+
+        let transfer1 = TokenTransfer {
+            token: [8; 20],
+            from: [7; 20],
+            to: [4; 20],
+            value: [1; 32],
+            transaction_hash: [2; 32],
+            log_index: 0,
+            block_number: 1,
+        };
+        let transfer2 = TokenTransfer {
+            token: [5; 20],
+            from: [4; 20],
+            to: [7; 20],
+            value: [6; 32],
+            transaction_hash: [3; 32],
+            log_index: 1,
+            block_number: 1,
+        };
+
+        let mut token_id = [0; 32];
+        token_id[31] = 42;
+        let mint = NftTransfer {
+            collection: [10; 20],
+            token_id,
+            from: [0; 20],
+            to: [7; 20],
             transaction_hash: [2; 32],
-            gas_used: 21000,
+            log_index: 2,
+            block_number: 1,
         };
-        let receipt2 = Receipt {
+
+        let mut amount = [0; 32];
+        amount[31] = 5;
+        let erc1155_mint = Erc1155Transfer {
+            contract: [11; 20],
+            operator: [7; 20],
+            from: [0; 20],
+            to: [4; 20],
+            token_id,
+            value: amount,
             transaction_hash: [3; 32],
-            gas_used: 30000,
+            log_index: 3,
+            batch_index: 0,
+            block_number: 1,
         };
 
         let mut block_summary = BlockSummary {
@@ -405,7 +2854,18 @@ impl Database {
                 log7.clone(),
             ],
             balances: vec![balance1.clone(), balance2.clone()],
+            missing_balances: vec![MissingBalance {
+                account: [9; 20],
+                token: [0; 20],
+                block_id: 1,
+                error: "missing trie node".to_string(),
+                attempts: 1,
+            }],
+            balance_deltas: Vec::new(),
             receipts: vec![receipt1.clone(), receipt2.clone()],
+            token_transfers: vec![transfer1, transfer2],
+            nft_transfers: vec![mint],
+            erc1155_transfers: vec![erc1155_mint],
         };
         block_summary
             .logs
@@ -426,7 +2886,7 @@ mod tests {
         let info = Database::data_setup();
         db.insert_block(&info).expect("Insertion failed.");
         let mut queried_info = db
-            .query_block_by_hash(&info.block.hash)
+            .query_block_by_hash(&info.block.hash, Finality::Latest)
             .expect("Query failed.");
         queried_info
             .logs
@@ -436,4 +2896,1207 @@ mod tests {
         assert_eq!(info.transactions, queried_info.transactions);
         assert_eq!(info.logs, queried_info.logs);
     }
+
+    #[test]
+    fn test_query_logs() {
+        let mut db = Database::connect_test();
+        let info = Database::data_setup();
+        db.insert_block(&info).expect("Insertion failed.");
+        let mut query = |filter: LogFilter| {
+            db.query_logs(&filter, None, 10)
+                .expect("Query failed.")
+                .into_iter()
+                .map(|(_, log)| log.address)
+                .collect::<Vec<_>>()
+        };
+
+        // Topics are matched by position.
+        let topic0 = |topics: Vec<[u8; 32]>| LogFilter {
+            topics: vec![topics],
+            ..Default::default()
+        };
+        assert_eq!(query(topic0(vec![[5; 32]])), vec![[4; 20]]);
+        assert!(query(topic0(vec![[6; 32]])).is_empty());
+        assert_eq!(
+            query(LogFilter {
+                topics: vec![vec![], vec![[6; 32]]],
+                ..Default::default()
+            }),
+            vec![[4; 20]]
+        );
+        let mut any_of = query(topic0(vec![[5; 32], [13; 32]]));
+        any_of.sort();
+        assert_eq!(any_of, vec![[4; 20], [12; 20]]);
+
+        let mut addresses = query(LogFilter {
+            addresses: vec![[8; 20], [12; 20]],
+            ..Default::default()
+        });
+        addresses.sort();
+        assert_eq!(addresses, vec![[8; 20], [12; 20]]);
+        assert!(
+            query(LogFilter {
+                addresses: vec![[4; 20]],
+                topics: vec![vec![[13; 32]]],
+                ..Default::default()
+            })
+            .is_empty()
+        );
+        assert!(
+            query(LogFilter {
+                from_block: Some(2),
+                ..Default::default()
+            })
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_query_logs_after_cursor() {
+        let mut db = Database::connect_test();
+        let logs: Vec<Log> = (0..3)
+            .map(|log_index| Log {
+                transaction_hash: None,
+                log_index: Some(log_index),
+                address: [4; 20],
+                topics: vec![[5; 32]],
+                data: Vec::new(),
+                block_number: 2,
+            })
+            .collect();
+        db.insert_block(&BlockSummary {
+            block: Block {
+                number: 2,
+                hash: [30; 32],
+                ..Default::default()
+            },
+            logs: logs.clone(),
+            ..Default::default()
+        })
+        .expect("Insertion failed.");
+
+        let filter = LogFilter {
+            addresses: vec![[4; 20]],
+            ..Default::default()
+        };
+        let mut query = |after| {
+            db.query_logs(&filter, after, 2)
+                .expect("Query failed.")
+                .into_iter()
+                .unzip::<_, _, Vec<_>, Vec<_>>()
+        };
+        let (ids, first) = query(None);
+        assert_eq!(first, logs[..2]);
+        let (ids, second) = query(Some((2, ids[1])));
+        assert_eq!(second, logs[2..]);
+        assert!(query(Some((2, ids[0]))).1.is_empty());
+    }
+
+    #[test]
+    fn test_query_logs_without_index() {
+        let mut db = Database::connect_test();
+        // Logs of a pending block have no index yet.
+        let logs: Vec<Log> = (0..3)
+            .map(|data| Log {
+                transaction_hash: None,
+                log_index: None,
+                address: [4; 20],
+                topics: Vec::new(),
+                data: vec![data],
+                block_number: 2,
+            })
+            .collect();
+        db.insert_block(&BlockSummary {
+            block: Block {
+                number: 2,
+                hash: [30; 32],
+                ..Default::default()
+            },
+            logs: logs.clone(),
+            ..Default::default()
+        })
+        .expect("Insertion failed.");
+
+        let filter = LogFilter::default();
+        let mut paged = Vec::new();
+        let mut after = None;
+        loop {
+            let page = db.query_logs(&filter, after, 2).expect("Query failed.");
+            after = page.last().map(|(id, log)| (log.block_number, *id));
+            paged.extend(page.into_iter().map(|(_, log)| log));
+            if after.is_none() {
+                break;
+            }
+        }
+        assert_eq!(paged, logs);
+    }
+
+    #[test]
+    fn test_query_receipts() {
+        let mut db = Database::connect_test();
+
+        let info = Database::data_setup();
+        db.insert_block(&info).expect("Insertion failed.");
+        for receipt in &info.receipts {
+            let queried = db
+                .query_receipt_by_transaction_hash(&receipt.transaction_hash, Finality::Latest)
+                .expect("Query failed.");
+            assert_eq!(receipt, &queried);
+        }
+
+        let failed = db
+            .query_failed_transactions(1, Finality::Latest)
+            .expect("Query failed.");
+        assert_eq!(failed, vec![info.transactions[1].clone()]);
+        assert!(db.query_failed_transactions(2, Finality::Latest).is_err());
+    }
+
+    #[test]
+    fn test_pages_survive_new_blocks() {
+        let mut db = Database::connect_test();
+        let info = Database::data_setup();
+        db.insert_block(&info).expect("Insertion failed.");
+        let filter = TransferFilter {
+            party: TransferParty::Account([4; 20]),
+            from_block: None,
+            to_block: None,
+            finality: Finality::Latest,
+        };
+        let history = BalanceHistoryFilter {
+            account: info.balances[0].account,
+            token: info.balances[0].token,
+            from_block: None,
+            to_block: None,
+            finality: Finality::Latest,
+        };
+        let first_transfers = db
+            .query_token_transfers(filter, None, 1)
+            .expect("Query failed.");
+        let first_balances = db
+            .query_balance_history(history, None, 1)
+            .expect("Query failed.");
+
+        // A block stored between two pages neither shifts the rest of a list nor is skipped by it.
+        let transfer = TokenTransfer {
+            transaction_hash: [9; 32],
+            block_number: 2,
+            ..info.token_transfers[1].clone()
+        };
+        let balance = types::Balance {
+            block_id: 2,
+            ..info.balances[0].clone()
+        };
+        db.insert_block(&BlockSummary {
+            block: Block {
+                number: 2,
+                hash: [2; 32],
+                parent_hash: info.block.hash,
+                ..info.block.clone()
+            },
+            balances: vec![balance.clone()],
+            token_transfers: vec![transfer.clone()],
+            ..Default::default()
+        })
+        .expect("Insertion failed.");
+
+        let last = &first_transfers[0];
+        let rest = db
+            .query_token_transfers(filter, Some((last.block_number, last.log_index)), 10)
+            .expect("Query failed.");
+        assert_eq!(
+            [first_transfers, rest].concat(),
+            vec![
+                info.token_transfers[1].clone(),
+                info.token_transfers[0].clone()
+            ]
+        );
+        let rest = db
+            .query_balance_history(history, Some(first_balances[0].block_id), 10)
+            .expect("Query failed.");
+        assert_eq!(
+            [first_balances, rest]
+                .concat()
+                .iter()
+                .map(|snapshot| (snapshot.block_id, snapshot.balance))
+                .collect::<Vec<_>>(),
+            vec![(1, balance.balance), (2, balance.balance)]
+        );
+    }
+
+    #[test]
+    fn test_query_token_transfers() {
+        let mut db = Database::connect_test();
+
+        let info = Database::data_setup();
+        db.insert_block(&info).expect("Insertion failed.");
+        let filter = |party, from_block| TransferFilter {
+            party,
+            from_block,
+            to_block: None,
+            finality: Finality::Latest,
+        };
+
+        let by_token = db
+            .query_token_transfers(filter(TransferParty::Token([8; 20]), None), None, 10)
+            .expect("Query failed.");
+        assert_eq!(by_token, vec![info.token_transfers[0].clone()]);
+
+        // Both the sender and the recipient see the transfer, most recent first.
+        let by_account = db
+            .query_token_transfers(filter(TransferParty::Account([4; 20]), None), None, 10)
+            .expect("Query failed.");
+        assert_eq!(
+            by_account,
+            vec![
+                info.token_transfers[1].clone(),
+                info.token_transfers[0].clone()
+            ]
+        );
+        let page = db
+            .query_token_transfers(
+                filter(TransferParty::Account([4; 20]), None),
+                Some((1, info.token_transfers[1].log_index)),
+                1,
+            )
+            .expect("Query failed.");
+        assert_eq!(page, vec![info.token_transfers[0].clone()]);
+
+        let later = db
+            .query_token_transfers(filter(TransferParty::Account([4; 20]), Some(2)), None, 10)
+            .expect("Query failed.");
+        assert!(later.is_empty());
+
+        db.rollback_to(0).expect("Rollback failed.");
+        let remaining_transfers: i64 = schema::token_transfers::table
+            .count()
+            .get_result(db.get_conn())
+            .expect("Count failed.");
+        assert_eq!(remaining_transfers, 0);
+    }
+
+    #[test]
+    fn test_nft_ownership() {
+        let mut db = Database::connect_test();
+
+        let info = Database::data_setup();
+        db.insert_block(&info).expect("Insertion failed.");
+        let mint = info.nft_transfers[0].clone();
+        let owned_by = |db: &mut Database, owner: [u8; 20]| {
+            db.query_nfts_owned(&owner, None, 10)
+                .expect("Query failed.")
+                .into_iter()
+                .map(|nft| (nft.token_id, nft.last_transfer_block))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(owned_by(&mut db, [7; 20]), vec![(mint.token_id, 1)]);
+
+        let sale = NftTransfer {
+            from: [7; 20],
+            to: [4; 20],
+            transaction_hash: [30; 32],
+            log_index: 0,
+            block_number: 3,
+            ..mint.clone()
+        };
+        let block = |number: u64, parent: u8, nft_transfers| BlockSummary {
+            block: Block {
+                number,
+                hash: [number as u8 * 10; 32],
+                parent_hash: [parent; 32],
+                ..Default::default()
+            },
+            nft_transfers,
+            ..Default::default()
+        };
+        db.insert_block(&block(3, 20, vec![sale.clone()]))
+            .expect("Insertion failed.");
+        assert!(owned_by(&mut db, [7; 20]).is_empty());
+        assert_eq!(owned_by(&mut db, [4; 20]), vec![(mint.token_id, 3)]);
+
+        // A backfilled older transfer does not override the current owner.
+        let older = NftTransfer {
+            from: [7; 20],
+            to: [5; 20],
+            transaction_hash: [20; 32],
+            block_number: 2,
+            ..sale.clone()
+        };
+        db.insert_block(&block(2, 1, vec![older.clone()]))
+            .expect("Insertion failed.");
+        assert_eq!(owned_by(&mut db, [4; 20]), vec![(mint.token_id, 3)]);
+
+        let history = db
+            .query_nft_transfers(
+                TransferFilter {
+                    party: TransferParty::Token(mint.collection),
+                    from_block: None,
+                    to_block: None,
+                    finality: Finality::Latest,
+                },
+                Some(mint.token_id),
+                None,
+                10,
+            )
+            .expect("Query failed.");
+        assert_eq!(history, vec![sale, older.clone(), mint.clone()]);
+
+        // Rolling back the sale gives the token back to the previous recipient.
+        db.rollback_to(2).expect("Rollback failed.");
+        assert!(owned_by(&mut db, [4; 20]).is_empty());
+        assert_eq!(owned_by(&mut db, [5; 20]), vec![(mint.token_id, 2)]);
+    }
+
+    #[test]
+    fn test_erc1155_balances() {
+        use alloy::primitives::U256;
+
+        let mut db = Database::connect_test();
+
+        let info = Database::data_setup();
+        db.insert_block(&info).expect("Insertion failed.");
+        let mint = info.erc1155_transfers[0].clone();
+        let balances = |db: &mut Database, holder: [u8; 20]| {
+            db.query_erc1155_balances(&holder, None, 10)
+                .expect("Query failed.")
+                .into_iter()
+                .map(|b| (b.token_id, U256::from_be_bytes(b.balance), b.complete))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            balances(&mut db, [4; 20]),
+            vec![(mint.token_id, U256::from(5), true)]
+        );
+
+        // The whole balance is sent on, along with an id received before the first block.
+        let other_id = [1; 32];
+        let sent = |batch_index, token_id, value: u64| Erc1155Transfer {
+            from: [4; 20],
+            to: [6; 20],
+            token_id,
+            value: U256::from(value).to_be_bytes(),
+            transaction_hash: [20; 32],
+            log_index: 0,
+            batch_index,
+            block_number: 2,
+            ..mint.clone()
+        };
+        let block = BlockSummary {
+            block: Block {
+                number: 2,
+                hash: [20; 32],
+                parent_hash: info.block.hash,
+                ..Default::default()
+            },
+            erc1155_transfers: vec![sent(0, mint.token_id, 5), sent(1, other_id, 1)],
+            ..Default::default()
+        };
+        db.insert_block(&block).expect("Insertion failed.");
+        // The id received before the first block leaves an unknown balance, not a negative one.
+        assert_eq!(
+            balances(&mut db, [4; 20]),
+            vec![(other_id, U256::ZERO, false)]
+        );
+        assert_eq!(
+            balances(&mut db, [6; 20]),
+            vec![
+                (mint.token_id, U256::from(5), true),
+                (other_id, U256::from(1), true)
+            ]
+        );
+
+        db.rollback_to(1).expect("Rollback failed.");
+        assert_eq!(
+            balances(&mut db, [4; 20]),
+            vec![(mint.token_id, U256::from(5), true)]
+        );
+        assert!(balances(&mut db, [6; 20]).is_empty());
+    }
+
+    #[test]
+    fn test_rollback_to_ancestor() {
+        let mut db = Database::connect_test();
+
+        let info = Database::data_setup();
+        db.insert_block(&info).expect("Insertion failed.");
+        let child = BlockSummary {
+            block: Block {
+                number: 2,
+                hash: [30; 32],
+                parent_hash: info.block.hash,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        db.insert_block(&child).expect("Insertion failed.");
+
+        let orphaned = db.rollback_to(0).expect("Rollback failed.");
+        assert_eq!(
+            orphaned.iter().map(|b| b.number).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert!(db.query_block_header(1).expect("Query failed.").is_none());
+        assert!(
+            db.query_transaction_by_hash(&[2; 32], Finality::Latest)
+                .is_err()
+        );
+
+        let remaining_logs: i64 = schema::logs::table
+            .count()
+            .get_result(db.get_conn())
+            .expect("Count failed.");
+        let remaining_balances: i64 = schema::balances::table
+            .count()
+            .get_result(db.get_conn())
+            .expect("Count failed.");
+        assert_eq!(remaining_logs, 0);
+        assert_eq!(remaining_balances, 0);
+        assert!(
+            db.query_missing_balances(None, 10)
+                .expect("Query failed.")
+                .is_empty()
+        );
+
+        let recorded = db.query_orphaned_blocks(None, 10).expect("Query failed.");
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].hash, [30; 32]);
+
+        // The replaced block can be indexed again at the same height.
+        db.insert_block(&info).expect("Re-insertion failed.");
+    }
+
+    #[test]
+    fn test_query_block_gaps() {
+        let mut db = Database::connect_test();
+        assert_eq!(db.query_latest_block_number().expect("Query failed."), None);
+
+        for (number, hash) in [(3, 3), (4, 4), (7, 7), (10, 10)] {
+            let block = BlockSummary {
+                block: Block {
+                    number,
+                    hash: [hash; 32],
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            db.insert_block(&block).expect("Insertion failed.");
+        }
+
+        assert_eq!(
+            db.query_latest_block_number().expect("Query failed."),
+            Some(10)
+        );
+        assert_eq!(
+            db.query_block_gaps(0).expect("Query failed."),
+            vec![5..=6, 8..=9]
+        );
+        assert_eq!(db.query_block_gaps(7).expect("Query failed."), vec![8..=9]);
+    }
+
+    #[test]
+    fn test_query_blocks() {
+        let mut db = Database::connect_test();
+        for number in [3, 4, 7, 10] {
+            let block = BlockSummary {
+                block: Block {
+                    number,
+                    hash: [number as u8; 32],
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            db.insert_block(&block).expect("Insertion failed.");
+        }
+        db.promote_blocks(Finality::Finalized, 4)
+            .expect("Promotion failed.");
+        let mut numbers = |from, to, after, finality| {
+            db.query_blocks(from, to, after, 2, finality)
+                .expect("Query failed.")
+                .into_iter()
+                .map(|block| block.number)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(numbers(None, None, None, Finality::Latest), vec![3, 4]);
+        assert_eq!(numbers(None, None, Some(4), Finality::Latest), vec![7, 10]);
+        assert_eq!(
+            numbers(Some(4), Some(7), None, Finality::Latest),
+            vec![4, 7]
+        );
+        assert_eq!(numbers(None, None, Some(3), Finality::Finalized), vec![4]);
+        assert!(numbers(None, Some(9), Some(7), Finality::Latest).is_empty());
+
+        assert_eq!(
+            db.query_block_header_by_hash(&[7; 32], Finality::Latest)
+                .expect("Query failed.")
+                .map(|block| block.number),
+            Some(7)
+        );
+        assert!(
+            db.query_block_header_by_number(7, Finality::Finalized)
+                .expect("Query failed.")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_query_block_contents() {
+        let mut db = Database::connect_test();
+        let info = Database::data_setup();
+        db.insert_block(&info).expect("Insertion failed.");
+
+        assert_eq!(
+            db.query_block_transactions(1, 1, 1).expect("Query failed."),
+            info.transactions[1..2]
+        );
+        assert!(
+            db.query_block_transactions(1, 10, 2)
+                .expect("Query failed.")
+                .is_empty()
+        );
+
+        // Pages of logs cover them all, once.
+        let mut logs = db.query_block_logs(1, 1, 0).expect("Query failed.");
+        logs.extend(db.query_block_logs(1, 10, 1).expect("Query failed."));
+        logs.sort_by_key(|log| (log.transaction_hash, log.log_index));
+        assert_eq!(logs, info.logs);
+    }
+
+    #[test]
+    fn test_dead_letter_attempts() {
+        let mut db = Database::connect_test();
+
+        db.record_dead_letter(5, &[5; 32], "timeout")
+            .expect("Record failed.");
+        db.record_dead_letter(5, &[6; 32], "rate limited")
+            .expect("Record failed.");
+        db.record_dead_letter(8, &[8; 32], "timeout")
+            .expect("Record failed.");
+
+        let dead_letters = db.query_dead_letters(None, 10).expect("Query failed.");
+        assert_eq!(dead_letters.len(), 2);
+        assert_eq!(dead_letters[0].number, 5);
+        assert_eq!(dead_letters[0].hash, [6; 32]);
+        assert_eq!(dead_letters[0].error, "rate limited");
+        assert_eq!(dead_letters[0].attempts, 2);
+
+        let retryable = db.query_retryable_dead_letters(2).expect("Query failed.");
+        assert_eq!(
+            retryable.iter().map(|b| b.number).collect::<Vec<_>>(),
+            vec![8]
+        );
+
+        assert!(db.reset_dead_letter(5).expect("Reset failed."));
+        assert!(!db.reset_dead_letter(6).expect("Reset failed."));
+        assert_eq!(
+            db.query_retryable_dead_letters(2)
+                .expect("Query failed.")
+                .len(),
+            2
+        );
+
+        db.remove_dead_letter(8).expect("Removal failed.");
+        assert_eq!(
+            db.query_dead_letters(None, 10)
+                .expect("Query failed.")
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_webhook_deliveries() {
+        let mut db = Database::connect_test();
+        let filter = WebhookFilter {
+            address: Some([7; 20]),
+            ..Default::default()
+        };
+        let webhook = db
+            .create_webhook("http://127.0.0.1:9000/hook", "secret", &filter)
+            .expect("Insertion failed.");
+        assert_eq!(
+            db.query_webhook(webhook.id).expect("Query failed."),
+            Some(webhook.clone())
+        );
+
+        // Blocks stored while webhooks exist wait for their deliveries to be queued.
+        db.insert_block(&Database::data_setup())
+            .expect("Insertion failed.");
+        let events = db.query_webhook_events(10).expect("Query failed.");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].block_hash, [1; 32]);
+        assert!(!events[0].removed);
+        let payloads = [
+            (webhook.id, "{}".to_string()),
+            (webhook.id, "{}".to_string()),
+        ];
+        db.queue_webhook_deliveries(&events[0], &payloads)
+            .expect("Insertion failed.");
+        assert!(
+            db.query_webhook_events(10)
+                .expect("Query failed.")
+                .is_empty()
+        );
+        let due = db.query_due_webhook_deliveries(10).expect("Query failed.");
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[0].block_hash, [1; 32]);
+        let (first, second) = (due[0].id, due[1].id);
+        assert!(first < second);
+
+        // A failed attempt waits for its backoff, a successful one is done.
+        db.record_webhook_attempt(
+            first,
+            DeliveryStatus::Pending,
+            Some(500),
+            Some("HTTP 500"),
+            u64::MAX >> 1,
+        )
+        .expect("Update failed.");
+        db.record_webhook_attempt(second, DeliveryStatus::Delivered, Some(200), None, 0)
+            .expect("Update failed.");
+        assert!(
+            db.query_due_webhook_deliveries(10)
+                .expect("Query failed.")
+                .is_empty()
+        );
+
+        let log = db
+            .query_webhook_deliveries(webhook.id, None, 10)
+            .expect("Query failed.");
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].status, DeliveryStatus::Delivered);
+        assert!(log[0].delivered_at.is_some());
+        assert_eq!(log[1].attempts, 1);
+        assert_eq!(log[1].last_status_code, Some(500));
+        assert_eq!(log[1].last_error.as_deref(), Some("HTTP 500"));
+        assert_eq!(
+            db.query_webhook_deliveries(webhook.id, Some(second), 10)
+                .expect("Query failed.")
+                .iter()
+                .map(|d| d.id)
+                .collect::<Vec<_>>(),
+            [first]
+        );
+
+        assert!(db.remove_webhook(webhook.id).expect("Removal failed."));
+        assert!(!db.remove_webhook(webhook.id).expect("Removal failed."));
+        assert!(
+            db.query_webhook_deliveries(webhook.id, None, 10)
+                .expect("Query failed.")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_token_registry() {
+        let mut db = Database::connect_test();
+        // The migrations seed the tokens that used to be built in.
+        assert_eq!(db.query_enabled_tokens().expect("Query failed.").len(), 3);
+
+        assert!(db.add_watched_token(&[8; 20], false).expect("Add failed."));
+        db.register_tokens(&[[8; 20], [5; 20]])
+            .expect("Registration failed.");
+        assert!(
+            db.set_token_enabled(&[5; 20], false)
+                .expect("Update failed.")
+        );
+        assert!(
+            !db.set_token_enabled(&[6; 20], false)
+                .expect("Update failed.")
+        );
+
+        let enabled = db.query_enabled_tokens().expect("Query failed.");
+        assert!(enabled.contains(&[8; 20]));
+        assert!(!enabled.contains(&[5; 20]));
+        assert!(
+            db.query_pending_token_backfills()
+                .expect("Query failed.")
+                .is_empty()
+        );
+
+        // Adding a disabled token again enables it, with the requested backfill.
+        assert!(!db.add_watched_token(&[5; 20], true).expect("Add failed."));
+        assert_eq!(
+            db.query_pending_token_backfills().expect("Query failed."),
+            vec![[5; 20]]
+        );
+        db.finish_token_backfill(&[5; 20]).expect("Update failed.");
+        assert!(
+            db.query_pending_token_backfills()
+                .expect("Query failed.")
+                .is_empty()
+        );
+
+        assert!(db.remove_watched_token(&[8; 20]).expect("Removal failed."));
+        assert!(!db.remove_watched_token(&[8; 20]).expect("Removal failed."));
+        assert_eq!(db.query_watched_tokens().expect("Query failed.").len(), 4);
+
+        db.insert_block(&Database::data_setup())
+            .expect("Insertion failed.");
+        assert_eq!(
+            db.query_unfetched_token_balances(&[8; 20])
+                .expect("Query failed."),
+            vec![([4; 20], 1), ([7; 20], 1)]
+        );
+        db.insert_balances(&[types::Balance {
+            account: [7; 20],
+            token: [8; 20],
+            balance: [1; 32],
+            block_id: 1,
+        }])
+        .expect("Insertion failed.");
+        assert_eq!(
+            db.query_unfetched_token_balances(&[8; 20])
+                .expect("Query failed."),
+            vec![([4; 20], 1)]
+        );
+    }
+
+    #[test]
+    fn test_token_metadata() {
+        let mut db = Database::connect_test();
+        db.insert_block(&Database::data_setup())
+            .expect("Insertion failed.");
+
+        // Transferred tokens come along with the tokens seeded in the watchlist.
+        let unknown = db.query_unknown_tokens(10).expect("Query failed.");
+        assert_eq!(unknown.len(), 5);
+        assert!(unknown.contains(&[5; 20]) && unknown.contains(&[8; 20]));
+
+        let token = TokenMetadata {
+            address: [8; 20],
+            name: Some("Eight".to_string()),
+            symbol: None,
+            decimals: Some(2),
+        };
+        db.insert_token_metadata(std::slice::from_ref(&token))
+            .expect("Insertion failed.");
+        let unknown = db.query_unknown_tokens(10).expect("Query failed.");
+        assert_eq!(unknown.len(), 4);
+        assert!(!unknown.contains(&[8; 20]));
+
+        assert_eq!(
+            db.query_token_metadata(&[[8; 20], [5; 20]])
+                .expect("Query failed."),
+            vec![token.clone()]
+        );
+
+        // A failed lookup is put off, instead of being attempted again in every round.
+        db.record_token_metadata_failures(&[[5; 20]], 60, 3600)
+            .expect("Record failed.");
+        let unknown = db.query_unknown_tokens(10).expect("Query failed.");
+        assert_eq!(unknown.len(), 3);
+        assert!(!unknown.contains(&[5; 20]));
+        assert_eq!(
+            db.query_token_metadata(&[[8; 20], [5; 20]])
+                .expect("Query failed."),
+            vec![token]
+        );
+        db.record_token_metadata_failures(&[[5; 20]], 0, 0)
+            .expect("Record failed.");
+        let unknown = db.query_unknown_tokens(10).expect("Query failed.");
+        assert!(unknown.contains(&[5; 20]));
+    }
+
+    #[test]
+    fn test_missing_balances() {
+        let mut db = Database::connect_test();
+        db.insert_block(&Database::data_setup())
+            .expect("Insertion failed.");
+
+        let missing = db.query_missing_balances(None, 10).expect("Query failed.");
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].account, [9; 20]);
+
+        let mut failed_again = missing[0].clone();
+        failed_again.error = "header not found".to_string();
+        db.record_missing_balances(&[failed_again])
+            .expect("Record failed.");
+        let missing = db.query_missing_balances(None, 10).expect("Query failed.");
+        assert_eq!(missing[0].attempts, 2);
+        assert_eq!(missing[0].error, "header not found");
+        assert!(
+            db.query_retryable_missing_balances(2, 10)
+                .expect("Query failed.")
+                .is_empty()
+        );
+        assert_eq!(
+            db.query_retryable_missing_balances(3, 10)
+                .expect("Query failed.")
+                .len(),
+            1
+        );
+
+        db.insert_balances(&[types::Balance {
+            account: [9; 20],
+            token: [0; 20],
+            balance: [1; 32],
+            block_id: 1,
+        }])
+        .expect("Insertion failed.");
+        assert!(
+            db.query_missing_balances(None, 10)
+                .expect("Query failed.")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_account_balances() {
+        let mut db = Database::connect_test();
+        let info = Database::data_setup();
+        db.insert_block(&info).expect("Insertion failed.");
+        let newer = types::Balance {
+            balance: [7; 32],
+            block_id: 2,
+            ..info.balances[0].clone()
+        };
+        db.insert_block(&BlockSummary {
+            block: Block {
+                number: 2,
+                hash: [30; 32],
+                parent_hash: info.block.hash,
+                timestamp: 1234567900,
+                ..Default::default()
+            },
+            balances: vec![newer.clone()],
+            ..Default::default()
+        })
+        .expect("Insertion failed.");
+
+        let latest = db.query_account_balances(&[1; 20]).expect("Query failed.");
+        assert_eq!(latest, vec![newer.clone()]);
+
+        // A balance fetched later for an older block does not replace the latest one.
+        db.insert_balances(&[info.balances[0].clone()])
+            .expect("Insertion failed.");
+        assert_eq!(
+            db.query_account_balances(&[1; 20]).expect("Query failed.")[0].block_id,
+            2
+        );
+
+        let filter = BalanceHistoryFilter {
+            account: [1; 20],
+            token: [2; 20],
+            from_block: None,
+            to_block: None,
+            finality: Finality::Latest,
+        };
+        let history = db
+            .query_balance_history(filter, None, 10)
+            .expect("Query failed.");
+        assert_eq!(
+            history
+                .iter()
+                .map(|s| (s.block_id, s.timestamp, s.balance))
+                .collect::<Vec<_>>(),
+            vec![(1, 1234567890, [3; 32]), (2, 1234567900, [7; 32])]
+        );
+        let filter = BalanceHistoryFilter {
+            from_block: Some(2),
+            ..filter
+        };
+        assert_eq!(
+            db.query_balance_history(filter, None, 10)
+                .expect("Query failed.")
+                .len(),
+            1
+        );
+
+        // Rolling back a block gives the latest balance back to the block before.
+        db.rollback_to(1).expect("Rollback failed.");
+        assert_eq!(
+            db.query_account_balances(&[1; 20]).expect("Query failed."),
+            vec![info.balances[0].clone()]
+        );
+        db.rollback_to(0).expect("Rollback failed.");
+        assert!(
+            db.query_account_balances(&[1; 20])
+                .expect("Query failed.")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_token_holders() {
+        let mut db = Database::connect_test();
+        let info = Database::data_setup();
+        db.insert_block(&info).expect("Insertion failed.");
+        let holders = |db: &mut Database| {
+            db.query_token_stats(&[2; 20])
+                .expect("Query failed.")
+                .map_or(0, |stats| stats.holders)
+        };
+        assert_eq!(holders(&mut db), 1);
+
+        let block = |number: u64, balances: Vec<types::Balance>| BlockSummary {
+            block: Block {
+                number,
+                hash: [30 + number as u8; 32],
+                parent_hash: [29 + number as u8; 32],
+                ..Default::default()
+            },
+            balances,
+            ..Default::default()
+        };
+        let larger = types::Balance {
+            account: [4; 20],
+            balance: [9; 32],
+            block_id: 2,
+            ..info.balances[0].clone()
+        };
+        let empty = types::Balance {
+            account: [5; 20],
+            balance: [0; 32],
+            block_id: 2,
+            ..info.balances[0].clone()
+        };
+        db.insert_block(&block(2, vec![larger.clone(), empty]))
+            .expect("Insertion failed.");
+        assert_eq!(holders(&mut db), 2);
+        assert_eq!(
+            db.query_top_holders(&[2; 20], None, 10)
+                .expect("Query failed."),
+            vec![larger.clone(), info.balances[0].clone()]
+        );
+        assert_eq!(
+            db.query_top_holders(&[2; 20], Some((larger.balance, larger.account)), 10)
+                .expect("Query failed."),
+            vec![info.balances[0].clone()]
+        );
+
+        // An account whose balance drops to zero is no longer a holder.
+        let emptied = types::Balance {
+            balance: [0; 32],
+            block_id: 3,
+            ..info.balances[0].clone()
+        };
+        db.insert_block(&block(3, vec![emptied]))
+            .expect("Insertion failed.");
+        assert_eq!(holders(&mut db), 1);
+        assert_eq!(
+            db.query_top_holders(&[2; 20], None, 10)
+                .expect("Query failed."),
+            vec![larger]
+        );
+
+        db.rollback_to(1).expect("Rollback failed.");
+        assert_eq!(holders(&mut db), 1);
+        assert_eq!(
+            db.query_top_holders(&[2; 20], None, 10)
+                .expect("Query failed."),
+            vec![info.balances[0].clone()]
+        );
+
+        db.update_total_supply(&[2; 20], &[8; 32], 1, 1234567999)
+            .expect("Update failed.");
+        let stats = db
+            .query_token_stats(&[2; 20])
+            .expect("Query failed.")
+            .expect("Stats not found.");
+        assert_eq!(stats.holders, 1);
+        assert_eq!(stats.total_supply, Some([8; 32]));
+        assert_eq!(stats.supply_updated_at, Some(1234567999));
+        assert_eq!(stats.supply_block, Some(1));
+
+        // A supply read at an orphaned block is forgotten.
+        db.rollback_to(0).expect("Rollback failed.");
+        let stats = db
+            .query_token_stats(&[2; 20])
+            .expect("Query failed.")
+            .expect("Stats not found.");
+        assert_eq!(stats.total_supply, None);
+        assert_eq!(stats.supply_block, None);
+    }
+
+    #[test]
+    fn test_derived_balances() {
+        use alloy::primitives::{I256, U256};
+
+        let mut db = Database::connect_test();
+        let (token, a, b) = ([6; 20], [7; 20], [8; 20]);
+        db.register_tokens(&[token]).expect("Registration failed.");
+        let block = |number: u64, deltas: &[([u8; 20], i64)]| BlockSummary {
+            block: Block {
+                number,
+                hash: [40 + number as u8; 32],
+                ..Default::default()
+            },
+            balance_deltas: deltas
+                .iter()
+                .map(|(account, amount)| BalanceDelta {
+                    account: *account,
+                    token,
+                    block_id: number,
+                    delta: I256::try_from(*amount).unwrap().into_raw().to_be_bytes(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let seed = |account: [u8; 20], balance: u64, block_id: u64| types::Balance {
+            account,
+            token,
+            balance: U256::from(balance).to_be_bytes(),
+            block_id,
+        };
+        let derived = |db: &mut Database, account: &[u8; 20]| {
+            db.query_derived_balances(account)
+                .expect("Query failed.")
+                .first()
+                .map(|balance| {
+                    (
+                        U256::from_be_bytes(balance.balance).to::<u64>(),
+                        balance.updated_block,
+                    )
+                })
+        };
+
+        // Changes are kept aside until the balance is seeded.
+        db.insert_block(&block(1, &[(a, 100)]))
+            .expect("Insertion failed.");
+        assert_eq!(derived(&mut db, &a), None);
+        assert_eq!(
+            db.query_unseeded_balances(10).expect("Query failed."),
+            vec![(a, token)]
+        );
+
+        // A balance seeded at a block already includes its changes.
+        assert_eq!(db.seed_derived_balances(&[seed(a, 100, 1)]).unwrap(), 1);
+        assert_eq!(db.seed_derived_balances(&[seed(a, 0, 1)]).unwrap(), 0);
+        assert!(
+            db.query_unseeded_balances(10)
+                .expect("Query failed.")
+                .is_empty()
+        );
+        assert_eq!(derived(&mut db, &a), Some((100, 1)));
+
+        db.insert_block(&block(2, &[(a, -30)]))
+            .expect("Insertion failed.");
+        db.insert_block(&block(3, &[(a, 5), (b, 50)]))
+            .expect("Insertion failed.");
+        assert_eq!(derived(&mut db, &a), Some((75, 3)));
+
+        // Changes stored after the seeded block are applied when seeding.
+        db.seed_derived_balances(&[seed(b, 10, 2)]).unwrap();
+        assert_eq!(derived(&mut db, &b), Some((60, 3)));
+
+        // Derived balances make up the holder index, as fetched ones do.
+        let holders = |db: &mut Database| {
+            db.query_token_stats(&token)
+                .expect("Query failed.")
+                .map_or(0, |stats| stats.holders)
+        };
+        assert_eq!(holders(&mut db), 2);
+        assert_eq!(
+            db.query_top_holders(&token, None, 10)
+                .expect("Query failed."),
+            vec![seed(a, 75, 3), seed(b, 60, 3)]
+        );
+
+        db.rollback_to(2).expect("Rollback failed.");
+        assert_eq!(derived(&mut db, &a), Some((70, 2)));
+        assert_eq!(derived(&mut db, &b), Some((10, 2)));
+        assert_eq!(
+            db.query_top_holders(&token, None, 10)
+                .expect("Query failed."),
+            vec![seed(a, 70, 2), seed(b, 10, 2)]
+        );
+
+        // Balances seeded at an orphaned block are forgotten.
+        db.rollback_to(1).expect("Rollback failed.");
+        assert_eq!(derived(&mut db, &a), Some((100, 1)));
+        assert_eq!(derived(&mut db, &b), None);
+        assert_eq!(holders(&mut db), 1);
+        assert_eq!(
+            db.query_account_balances(&a).expect("Query failed."),
+            vec![seed(a, 100, 1)]
+        );
+
+        let drift = BalanceDrift {
+            account: a,
+            token,
+            block_id: 1,
+            derived: U256::from(100).to_be_bytes(),
+            onchain: U256::from(90).to_be_bytes(),
+            detected_at: 7,
+        };
+        db.record_balance_checks(&[(a, token)], std::slice::from_ref(&drift), 7)
+            .expect("Record failed.");
+        assert_eq!(
+            db.query_balance_drifts(None, 10).expect("Query failed."),
+            vec![drift]
+        );
+        db.record_balance_checks(&[(a, token)], &[], 8)
+            .expect("Record failed.");
+        assert!(
+            db.query_balance_drifts(None, 10)
+                .expect("Query failed.")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_backfill_job_progress() {
+        let mut db = Database::connect_test();
+
+        let job = db.create_backfill_job(10, 20).expect("Creation failed.");
+        assert_eq!(job.next_block, 10);
+        assert_eq!(
+            db.create_backfill_job(10, 20).expect("Creation failed."),
+            job
+        );
+
+        db.update_backfill_progress(job.id, 15)
+            .expect("Update failed.");
+        let pending = db.query_pending_backfill_jobs().expect("Query failed.");
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].next_block, 15);
+
+        db.update_backfill_progress(job.id, 21)
+            .expect("Update failed.");
+        assert!(
+            db.query_pending_backfill_jobs()
+                .expect("Query failed.")
+                .is_empty()
+        );
+        assert_ne!(
+            db.create_backfill_job(10, 20).expect("Creation failed.").id,
+            job.id
+        );
+    }
+
+    #[test]
+    fn test_promote_blocks() {
+        let mut db = Database::connect_test();
+        for number in 1..=3 {
+            let block = BlockSummary {
+                block: Block {
+                    number,
+                    hash: [number as u8; 32],
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            db.insert_block(&block).expect("Insertion failed.");
+        }
+
+        assert_eq!(db.promote_blocks(Finality::Safe, 2).unwrap(), 2);
+        assert_eq!(db.promote_blocks(Finality::Finalized, 1).unwrap(), 1);
+        // A finalized block is never demoted back to safe.
+        assert_eq!(db.promote_blocks(Finality::Safe, 2).unwrap(), 0);
+
+        let finality = |db: &mut Database, number| {
+            db.query_block_header(number)
+                .unwrap()
+                .map(|block| block.finality)
+        };
+        assert_eq!(finality(&mut db, 1), Some(Finality::Finalized));
+        assert_eq!(finality(&mut db, 2), Some(Finality::Safe));
+        assert_eq!(finality(&mut db, 3), Some(Finality::Latest));
+        assert_eq!(
+            db.query_highest_block_number(Finality::Safe).unwrap(),
+            Some(2)
+        );
+        assert!(db.query_block_by_number(3, Finality::Safe).is_err());
+        assert!(db.query_block_by_number(2, Finality::Safe).is_ok());
+    }
 }
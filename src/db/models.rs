@@ -1,7 +1,15 @@
-use crate::db::schema::{balances, blocks, log_topics, logs, receipts, transactions};
+use crate::db::schema::{
+    access_list_items, backfill_jobs, balance_deltas, balance_drifts, balances, blocks,
+    dead_letter_blocks, derived_balances, erc1155_balances, erc1155_transfers, log_topics, logs,
+    missing_balances, nft_owners, nft_transfers, orphaned_blocks, receipts, token_stats,
+    token_transfers, tokens, transactions, watched_tokens, webhook_deliveries, webhook_events,
+    webhooks,
+};
 use crate::types;
+use alloy::primitives::U256;
 
 use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Binary};
 
 #[derive(Insertable, AsChangeset)]
 #[diesel(table_name = blocks)]
@@ -13,6 +21,7 @@ pub struct NewBlock<'a> {
     pub gas_limit: i64,
     pub gas_used: i64,
     pub base_fee_per_gas: Option<i64>,
+    pub finality: &'a str,
 }
 
 impl<'a> From<&'a types::Block> for NewBlock<'a> {
@@ -25,6 +34,7 @@ impl<'a> From<&'a types::Block> for NewBlock<'a> {
             gas_limit: block.gas_limit as i64,
             gas_used: block.gas_used as i64,
             base_fee_per_gas: block.base_fee_per_gas.map(|val| val as i64),
+            finality: block.finality.as_str(),
         }
     }
 }
@@ -34,6 +44,76 @@ impl<'a> From<&'a types::Block> for NewBlock<'a> {
 pub struct NewTransaction<'a> {
     pub hash: &'a [u8],
     pub block_number: i64,
+    pub transaction_index: i64,
+    pub tx_type: i32,
+    pub chain_id: Option<i64>,
+    pub from_address: &'a [u8],
+    pub to_address: Option<&'a [u8]>,
+    pub value: &'a [u8],
+    pub nonce: i64,
+    pub input: &'a [u8],
+    pub gas_limit: i64,
+    pub gas_price: Option<Vec<u8>>,
+    pub max_fee_per_gas: Option<Vec<u8>>,
+    pub max_priority_fee_per_gas: Option<Vec<u8>>,
+}
+
+impl<'a> From<&'a types::Transaction> for NewTransaction<'a> {
+    fn from(tx: &'a types::Transaction) -> Self {
+        let fee = |fee: Option<u128>| fee.map(|fee| fee.to_be_bytes().to_vec());
+        NewTransaction {
+            hash: &tx.hash,
+            block_number: tx.block_number as i64,
+            transaction_index: tx.transaction_index as i64,
+            tx_type: tx.tx_type as i32,
+            chain_id: tx.chain_id.map(|id| id as i64),
+            from_address: &tx.from,
+            to_address: tx.to.as_ref().map(|to| to.as_slice()),
+            value: &tx.value,
+            nonce: tx.nonce as i64,
+            input: &tx.input,
+            gas_limit: tx.gas_limit as i64,
+            gas_price: fee(tx.gas_price),
+            max_fee_per_gas: fee(tx.max_fee_per_gas),
+            max_priority_fee_per_gas: fee(tx.max_priority_fee_per_gas),
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = access_list_items)]
+pub struct NewAccessListItem<'a> {
+    pub transaction_hash: &'a [u8],
+    pub item_index: i32,
+    pub address: &'a [u8],
+    pub storage_keys: Vec<u8>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = access_list_items)]
+pub struct DbAccessListItem {
+    pub transaction_hash: Vec<u8>,
+    pub item_index: i32,
+    pub address: Vec<u8>,
+    pub storage_keys: Vec<u8>,
+}
+
+impl TryFrom<DbAccessListItem> for types::AccessListItem {
+    type Error = anyhow::Error;
+
+    fn try_from(item: DbAccessListItem) -> Result<Self, Self::Error> {
+        Ok(types::AccessListItem {
+            address: item
+                .address
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid address"))?,
+            storage_keys: item
+                .storage_keys
+                .chunks_exact(32)
+                .map(|key| key.try_into().expect("chunks are 32 bytes long"))
+                .collect(),
+        })
+    }
 }
 
 #[derive(Queryable, AsChangeset, Selectable)]
@@ -52,9 +132,7 @@ impl TryFrom<Log> for types::Log {
 
     fn try_from(log: Log) -> Result<Self, Self::Error> {
         Ok(types::Log {
-            transaction_hash: log
-                .transaction_hash
-                .and_then(|hash| hash.try_into().ok()),
+            transaction_hash: log.transaction_hash.and_then(|hash| hash.try_into().ok()),
             log_index: log.log_index.map(|index| index as u64),
             address: log
                 .address
@@ -103,6 +181,7 @@ pub struct DbBlock {
     pub gas_limit: i64,
     pub gas_used: i64,
     pub base_fee_per_gas: Option<i64>,
+    pub finality: String,
 }
 
 impl TryFrom<DbBlock> for types::Block {
@@ -120,6 +199,7 @@ impl TryFrom<DbBlock> for types::Block {
             gas_limit: block.gas_limit as u64,
             gas_used: block.gas_used as u64,
             base_fee_per_gas: block.base_fee_per_gas.map(|val| val as u64),
+            finality: block.finality.parse()?,
         })
     }
 }
@@ -129,6 +209,27 @@ impl TryFrom<DbBlock> for types::Block {
 pub struct DbTransaction {
     pub hash: Option<Vec<u8>>,
     pub block_number: i64,
+    pub transaction_index: i64,
+    pub tx_type: i32,
+    pub chain_id: Option<i64>,
+    pub from_address: Vec<u8>,
+    pub to_address: Option<Vec<u8>>,
+    pub value: Vec<u8>,
+    pub nonce: i64,
+    pub input: Vec<u8>,
+    pub gas_limit: i64,
+    pub gas_price: Option<Vec<u8>>,
+    pub max_fee_per_gas: Option<Vec<u8>>,
+    pub max_priority_fee_per_gas: Option<Vec<u8>>,
+}
+
+fn parse_fee(fee: Option<Vec<u8>>) -> anyhow::Result<Option<u128>> {
+    fee.map(|fee| {
+        fee.try_into()
+            .map(u128::from_be_bytes)
+            .map_err(|_| anyhow::anyhow!("Invalid fee"))
+    })
+    .transpose()
 }
 
 impl TryFrom<DbTransaction> for types::Transaction {
@@ -141,6 +242,31 @@ impl TryFrom<DbTransaction> for types::Transaction {
                 .ok_or_else(|| anyhow::anyhow!("Missing hash"))?
                 .try_into()
                 .map_err(|_| anyhow::anyhow!("Invalid hash"))?,
+            block_number: tx.block_number as u64,
+            transaction_index: tx.transaction_index as u64,
+            tx_type: tx.tx_type as u8,
+            chain_id: tx.chain_id.map(|id| id as u64),
+            from: tx
+                .from_address
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid address"))?,
+            to: tx
+                .to_address
+                .map(|to| to.try_into())
+                .transpose()
+                .map_err(|_| anyhow::anyhow!("Invalid address"))?,
+            value: tx
+                .value
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid value"))?,
+            nonce: tx.nonce as u64,
+            input: tx.input,
+            gas_limit: tx.gas_limit as u64,
+            gas_price: parse_fee(tx.gas_price)?,
+            max_fee_per_gas: parse_fee(tx.max_fee_per_gas)?,
+            max_priority_fee_per_gas: parse_fee(tx.max_priority_fee_per_gas)?,
+            // Stored in `access_list_items`.
+            access_list: Vec::new(),
         })
     }
 }
@@ -165,11 +291,243 @@ impl<'a> From<&'a types::Balance> for NewBalance<'a> {
     }
 }
 
+/// A row of `balances`, or of `accounts` which has the same columns.
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = balances)]
+pub struct DbBalance {
+    pub account: Vec<u8>,
+    pub token: Vec<u8>,
+    pub balance: Vec<u8>,
+    pub block_id: i64,
+}
+
+impl TryFrom<DbBalance> for types::Balance {
+    type Error = anyhow::Error;
+
+    fn try_from(balance: DbBalance) -> Result<Self, Self::Error> {
+        Ok(types::Balance {
+            account: balance
+                .account
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid account"))?,
+            token: balance
+                .token
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid token"))?,
+            balance: balance
+                .balance
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid balance"))?,
+            block_id: balance.block_id as u64,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = missing_balances)]
+pub struct NewMissingBalance<'a> {
+    pub account: &'a [u8],
+    pub token: &'a [u8],
+    pub block_id: i64,
+    pub error: &'a str,
+    pub attempts: i32,
+}
+
+impl<'a> From<&'a types::MissingBalance> for NewMissingBalance<'a> {
+    fn from(balance: &'a types::MissingBalance) -> Self {
+        NewMissingBalance {
+            account: &balance.account,
+            token: &balance.token,
+            block_id: balance.block_id as i64,
+            error: &balance.error,
+            attempts: balance.attempts as i32,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = missing_balances)]
+pub struct DbMissingBalance {
+    pub account: Vec<u8>,
+    pub token: Vec<u8>,
+    pub block_id: i64,
+    pub error: String,
+    pub attempts: i32,
+}
+
+impl TryFrom<DbMissingBalance> for types::MissingBalance {
+    type Error = anyhow::Error;
+
+    fn try_from(balance: DbMissingBalance) -> Result<Self, Self::Error> {
+        Ok(types::MissingBalance {
+            account: balance
+                .account
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid account"))?,
+            token: balance
+                .token
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid token"))?,
+            block_id: balance.block_id as u64,
+            error: balance.error,
+            attempts: balance.attempts as u32,
+        })
+    }
+}
+
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = balance_deltas)]
+pub struct DbBalanceDelta {
+    pub account: Vec<u8>,
+    pub token: Vec<u8>,
+    pub block_id: i64,
+    pub delta: Vec<u8>,
+    pub applied: bool,
+}
+
+impl DbBalanceDelta {
+    pub fn new(delta: &types::BalanceDelta, applied: bool) -> Self {
+        DbBalanceDelta {
+            account: delta.account.to_vec(),
+            token: delta.token.to_vec(),
+            block_id: delta.block_id as i64,
+            delta: delta.delta.to_vec(),
+            applied,
+        }
+    }
+}
+
+impl TryFrom<DbBalanceDelta> for types::BalanceDelta {
+    type Error = anyhow::Error;
+
+    fn try_from(delta: DbBalanceDelta) -> Result<Self, Self::Error> {
+        Ok(types::BalanceDelta {
+            account: delta
+                .account
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid account"))?,
+            token: delta
+                .token
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid token"))?,
+            block_id: delta.block_id as u64,
+            delta: delta
+                .delta
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid delta"))?,
+        })
+    }
+}
+
+/// Every column of `derived_balances` but `checked_at`, which is left alone when a balance is
+/// stored.
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = derived_balances)]
+pub struct DbDerivedBalance {
+    pub account: Vec<u8>,
+    pub token: Vec<u8>,
+    pub balance: Vec<u8>,
+    pub seeded_block: i64,
+    pub updated_block: i64,
+}
+
+impl From<&types::DerivedBalance> for DbDerivedBalance {
+    fn from(balance: &types::DerivedBalance) -> Self {
+        DbDerivedBalance {
+            account: balance.account.to_vec(),
+            token: balance.token.to_vec(),
+            balance: balance.balance.to_vec(),
+            seeded_block: balance.seeded_block as i64,
+            updated_block: balance.updated_block as i64,
+        }
+    }
+}
+
+impl TryFrom<DbDerivedBalance> for types::DerivedBalance {
+    type Error = anyhow::Error;
+
+    fn try_from(balance: DbDerivedBalance) -> Result<Self, Self::Error> {
+        Ok(types::DerivedBalance {
+            account: balance
+                .account
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid account"))?,
+            token: balance
+                .token
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid token"))?,
+            balance: balance
+                .balance
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid balance"))?,
+            seeded_block: balance.seeded_block as u64,
+            updated_block: balance.updated_block as u64,
+        })
+    }
+}
+
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = balance_drifts)]
+pub struct DbBalanceDrift {
+    pub account: Vec<u8>,
+    pub token: Vec<u8>,
+    pub block_id: i64,
+    pub derived: Vec<u8>,
+    pub onchain: Vec<u8>,
+    pub detected_at: i64,
+}
+
+impl From<&types::BalanceDrift> for DbBalanceDrift {
+    fn from(drift: &types::BalanceDrift) -> Self {
+        DbBalanceDrift {
+            account: drift.account.to_vec(),
+            token: drift.token.to_vec(),
+            block_id: drift.block_id as i64,
+            derived: drift.derived.to_vec(),
+            onchain: drift.onchain.to_vec(),
+            detected_at: drift.detected_at as i64,
+        }
+    }
+}
+
+impl TryFrom<DbBalanceDrift> for types::BalanceDrift {
+    type Error = anyhow::Error;
+
+    fn try_from(drift: DbBalanceDrift) -> Result<Self, Self::Error> {
+        Ok(types::BalanceDrift {
+            account: drift
+                .account
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid account"))?,
+            token: drift
+                .token
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid token"))?,
+            block_id: drift.block_id as u64,
+            derived: drift
+                .derived
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid derived balance"))?,
+            onchain: drift
+                .onchain
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid on-chain balance"))?,
+            detected_at: drift.detected_at as u64,
+        })
+    }
+}
+
 #[derive(Insertable, AsChangeset)]
 #[diesel(table_name = receipts)]
 pub struct NewReceipt<'a> {
     pub transaction_hash: &'a [u8],
     pub gas_used: i64,
+    pub transaction_index: i64,
+    pub success: bool,
+    pub cumulative_gas_used: i64,
+    pub effective_gas_price: Vec<u8>,
+    pub contract_address: Option<&'a [u8]>,
+    pub logs_bloom: &'a [u8],
 }
 
 impl<'a> From<&'a types::Receipt> for NewReceipt<'a> {
@@ -177,6 +535,778 @@ impl<'a> From<&'a types::Receipt> for NewReceipt<'a> {
         NewReceipt {
             transaction_hash: &receipt.transaction_hash,
             gas_used: receipt.gas_used as i64,
+            transaction_index: receipt.transaction_index as i64,
+            success: receipt.success,
+            cumulative_gas_used: receipt.cumulative_gas_used as i64,
+            effective_gas_price: receipt.effective_gas_price.to_be_bytes().to_vec(),
+            contract_address: receipt.contract_address.as_ref().map(|a| a.as_slice()),
+            logs_bloom: &receipt.logs_bloom,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = receipts)]
+pub struct DbReceipt {
+    pub transaction_hash: Option<Vec<u8>>,
+    pub gas_used: i64,
+    pub transaction_index: i64,
+    pub success: bool,
+    pub cumulative_gas_used: i64,
+    pub effective_gas_price: Vec<u8>,
+    pub contract_address: Option<Vec<u8>>,
+    pub logs_bloom: Vec<u8>,
+}
+
+impl TryFrom<DbReceipt> for types::Receipt {
+    type Error = anyhow::Error;
+
+    fn try_from(receipt: DbReceipt) -> Result<Self, Self::Error> {
+        Ok(types::Receipt {
+            transaction_hash: receipt
+                .transaction_hash
+                .ok_or_else(|| anyhow::anyhow!("Missing hash"))?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid hash"))?,
+            gas_used: receipt.gas_used as u64,
+            transaction_index: receipt.transaction_index as u64,
+            success: receipt.success,
+            cumulative_gas_used: receipt.cumulative_gas_used as u64,
+            effective_gas_price: parse_fee(Some(receipt.effective_gas_price))?.unwrap_or_default(),
+            contract_address: receipt
+                .contract_address
+                .map(|address| address.try_into())
+                .transpose()
+                .map_err(|_| anyhow::anyhow!("Invalid address"))?,
+            logs_bloom: receipt.logs_bloom,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = token_transfers)]
+pub struct NewTokenTransfer<'a> {
+    pub transaction_hash: &'a [u8],
+    pub log_index: i64,
+    pub token: &'a [u8],
+    pub from_address: &'a [u8],
+    pub to_address: &'a [u8],
+    pub value: &'a [u8],
+    pub block_number: i64,
+}
+
+impl<'a> From<&'a types::TokenTransfer> for NewTokenTransfer<'a> {
+    fn from(transfer: &'a types::TokenTransfer) -> Self {
+        NewTokenTransfer {
+            transaction_hash: &transfer.transaction_hash,
+            log_index: transfer.log_index as i64,
+            token: &transfer.token,
+            from_address: &transfer.from,
+            to_address: &transfer.to,
+            value: &transfer.value,
+            block_number: transfer.block_number as i64,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = token_transfers)]
+pub struct DbTokenTransfer {
+    pub transaction_hash: Vec<u8>,
+    pub log_index: i64,
+    pub token: Vec<u8>,
+    pub from_address: Vec<u8>,
+    pub to_address: Vec<u8>,
+    pub value: Vec<u8>,
+    pub block_number: i64,
+}
+
+impl TryFrom<DbTokenTransfer> for types::TokenTransfer {
+    type Error = anyhow::Error;
+
+    fn try_from(transfer: DbTokenTransfer) -> Result<Self, Self::Error> {
+        Ok(types::TokenTransfer {
+            token: transfer
+                .token
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid token address"))?,
+            from: transfer
+                .from_address
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid from address"))?,
+            to: transfer
+                .to_address
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid to address"))?,
+            value: transfer
+                .value
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid value"))?,
+            transaction_hash: transfer
+                .transaction_hash
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid hash"))?,
+            log_index: transfer.log_index as u64,
+            block_number: transfer.block_number as u64,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = nft_transfers)]
+pub struct NewNftTransfer<'a> {
+    pub transaction_hash: &'a [u8],
+    pub log_index: i64,
+    pub collection: &'a [u8],
+    pub token_id: &'a [u8],
+    pub from_address: &'a [u8],
+    pub to_address: &'a [u8],
+    pub block_number: i64,
+}
+
+impl<'a> From<&'a types::NftTransfer> for NewNftTransfer<'a> {
+    fn from(transfer: &'a types::NftTransfer) -> Self {
+        NewNftTransfer {
+            transaction_hash: &transfer.transaction_hash,
+            log_index: transfer.log_index as i64,
+            collection: &transfer.collection,
+            token_id: &transfer.token_id,
+            from_address: &transfer.from,
+            to_address: &transfer.to,
+            block_number: transfer.block_number as i64,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = nft_transfers)]
+pub struct DbNftTransfer {
+    pub transaction_hash: Vec<u8>,
+    pub log_index: i64,
+    pub collection: Vec<u8>,
+    pub token_id: Vec<u8>,
+    pub from_address: Vec<u8>,
+    pub to_address: Vec<u8>,
+    pub block_number: i64,
+}
+
+impl TryFrom<DbNftTransfer> for types::NftTransfer {
+    type Error = anyhow::Error;
+
+    fn try_from(transfer: DbNftTransfer) -> Result<Self, Self::Error> {
+        Ok(types::NftTransfer {
+            collection: transfer
+                .collection
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid collection address"))?,
+            token_id: transfer
+                .token_id
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid token id"))?,
+            from: transfer
+                .from_address
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid from address"))?,
+            to: transfer
+                .to_address
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid to address"))?,
+            transaction_hash: transfer
+                .transaction_hash
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid hash"))?,
+            log_index: transfer.log_index as u64,
+            block_number: transfer.block_number as u64,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = nft_owners)]
+pub struct NewNftOwner<'a> {
+    pub collection: &'a [u8],
+    pub token_id: &'a [u8],
+    pub owner: &'a [u8],
+    pub last_transfer_block: i64,
+}
+
+impl<'a> From<&'a types::NftTransfer> for NewNftOwner<'a> {
+    fn from(transfer: &'a types::NftTransfer) -> Self {
+        NewNftOwner {
+            collection: &transfer.collection,
+            token_id: &transfer.token_id,
+            owner: &transfer.to,
+            last_transfer_block: transfer.block_number as i64,
         }
     }
 }
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = nft_owners)]
+pub struct DbNftOwner {
+    pub collection: Vec<u8>,
+    pub token_id: Vec<u8>,
+    pub owner: Vec<u8>,
+    pub last_transfer_block: i64,
+}
+
+impl TryFrom<DbNftOwner> for types::NftOwnership {
+    type Error = anyhow::Error;
+
+    fn try_from(owner: DbNftOwner) -> Result<Self, Self::Error> {
+        Ok(types::NftOwnership {
+            collection: owner
+                .collection
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid collection address"))?,
+            token_id: owner
+                .token_id
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid token id"))?,
+            owner: owner
+                .owner
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid owner address"))?,
+            last_transfer_block: owner.last_transfer_block as u64,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = erc1155_transfers)]
+pub struct NewErc1155Transfer<'a> {
+    pub transaction_hash: &'a [u8],
+    pub log_index: i64,
+    pub batch_index: i64,
+    pub contract: &'a [u8],
+    pub operator: &'a [u8],
+    pub from_address: &'a [u8],
+    pub to_address: &'a [u8],
+    pub token_id: &'a [u8],
+    pub value: &'a [u8],
+    pub block_number: i64,
+}
+
+impl<'a> From<&'a types::Erc1155Transfer> for NewErc1155Transfer<'a> {
+    fn from(transfer: &'a types::Erc1155Transfer) -> Self {
+        NewErc1155Transfer {
+            transaction_hash: &transfer.transaction_hash,
+            log_index: transfer.log_index as i64,
+            batch_index: transfer.batch_index as i64,
+            contract: &transfer.contract,
+            operator: &transfer.operator,
+            from_address: &transfer.from,
+            to_address: &transfer.to,
+            token_id: &transfer.token_id,
+            value: &transfer.value,
+            block_number: transfer.block_number as i64,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = erc1155_transfers)]
+pub struct DbErc1155Transfer {
+    pub transaction_hash: Vec<u8>,
+    pub log_index: i64,
+    pub batch_index: i64,
+    pub contract: Vec<u8>,
+    pub operator: Vec<u8>,
+    pub from_address: Vec<u8>,
+    pub to_address: Vec<u8>,
+    pub token_id: Vec<u8>,
+    pub value: Vec<u8>,
+    pub block_number: i64,
+}
+
+impl TryFrom<DbErc1155Transfer> for types::Erc1155Transfer {
+    type Error = anyhow::Error;
+
+    fn try_from(transfer: DbErc1155Transfer) -> Result<Self, Self::Error> {
+        Ok(types::Erc1155Transfer {
+            contract: transfer
+                .contract
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid contract address"))?,
+            operator: transfer
+                .operator
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid operator address"))?,
+            from: transfer
+                .from_address
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid from address"))?,
+            to: transfer
+                .to_address
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid to address"))?,
+            token_id: transfer
+                .token_id
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid token id"))?,
+            value: transfer
+                .value
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid value"))?,
+            transaction_hash: transfer
+                .transaction_hash
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid hash"))?,
+            log_index: transfer.log_index as u64,
+            batch_index: transfer.batch_index as u64,
+            block_number: transfer.block_number as u64,
+        })
+    }
+}
+
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = erc1155_balances)]
+pub struct DbErc1155Balance {
+    pub contract: Vec<u8>,
+    pub token_id: Vec<u8>,
+    pub holder: Vec<u8>,
+    pub received: Vec<u8>,
+    pub sent: Vec<u8>,
+}
+
+impl TryFrom<DbErc1155Balance> for types::Erc1155Balance {
+    type Error = anyhow::Error;
+
+    fn try_from(balance: DbErc1155Balance) -> Result<Self, Self::Error> {
+        let amount = |bytes: Vec<u8>| {
+            <[u8; 32]>::try_from(bytes)
+                .map(U256::from_be_bytes)
+                .map_err(|_| anyhow::anyhow!("Invalid balance"))
+        };
+        let received = amount(balance.received)?;
+        let sent = amount(balance.sent)?;
+        // Tokens received before the first indexed block were sent since, the balance is unknown.
+        let (balance_amount, complete) = match received.checked_sub(sent) {
+            Some(amount) => (amount, true),
+            None => (U256::ZERO, false),
+        };
+        Ok(types::Erc1155Balance {
+            contract: balance
+                .contract
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid contract address"))?,
+            token_id: balance
+                .token_id
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid token id"))?,
+            holder: balance
+                .holder
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid holder address"))?,
+            balance: balance_amount.to_be_bytes(),
+            complete,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = orphaned_blocks)]
+pub struct NewOrphanedBlock<'a> {
+    pub hash: &'a [u8],
+    pub number: i64,
+    pub parent_hash: &'a [u8],
+    pub timestamp: i64,
+    pub orphaned_at: i64,
+}
+
+impl<'a> From<&'a types::OrphanedBlock> for NewOrphanedBlock<'a> {
+    fn from(block: &'a types::OrphanedBlock) -> Self {
+        NewOrphanedBlock {
+            hash: &block.hash,
+            number: block.number as i64,
+            parent_hash: &block.parent_hash,
+            timestamp: block.timestamp as i64,
+            orphaned_at: block.orphaned_at as i64,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = orphaned_blocks)]
+pub struct DbOrphanedBlock {
+    pub hash: Option<Vec<u8>>,
+    pub number: i64,
+    pub parent_hash: Vec<u8>,
+    pub timestamp: i64,
+    pub orphaned_at: i64,
+}
+
+impl TryFrom<DbOrphanedBlock> for types::OrphanedBlock {
+    type Error = anyhow::Error;
+
+    fn try_from(block: DbOrphanedBlock) -> Result<Self, Self::Error> {
+        Ok(types::OrphanedBlock {
+            number: block.number as u64,
+            hash: block
+                .hash
+                .ok_or_else(|| anyhow::anyhow!("Missing hash"))?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid hash"))?,
+            parent_hash: block
+                .parent_hash
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid hash"))?,
+            timestamp: block.timestamp as u64,
+            orphaned_at: block.orphaned_at as u64,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = backfill_jobs)]
+pub struct NewBackfillJob {
+    pub from_block: i64,
+    pub to_block: i64,
+    pub next_block: i64,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = backfill_jobs)]
+pub struct DbBackfillJob {
+    pub id: Option<i32>,
+    pub from_block: i64,
+    pub to_block: i64,
+    pub next_block: i64,
+}
+
+impl TryFrom<DbBackfillJob> for types::BackfillJob {
+    type Error = anyhow::Error;
+
+    fn try_from(job: DbBackfillJob) -> Result<Self, Self::Error> {
+        Ok(types::BackfillJob {
+            id: job.id.ok_or_else(|| anyhow::anyhow!("Missing id"))?,
+            from_block: job.from_block as u64,
+            to_block: job.to_block as u64,
+            next_block: job.next_block as u64,
+        })
+    }
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = dead_letter_blocks)]
+pub struct NewDeadLetterBlock<'a> {
+    pub number: i64,
+    pub hash: &'a [u8],
+    pub error: &'a str,
+    pub attempts: i32,
+    pub last_attempt_at: i64,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = dead_letter_blocks)]
+pub struct DbDeadLetterBlock {
+    pub number: Option<i64>,
+    pub hash: Vec<u8>,
+    pub error: String,
+    pub attempts: i32,
+    pub last_attempt_at: i64,
+}
+
+impl TryFrom<DbDeadLetterBlock> for types::DeadLetterBlock {
+    type Error = anyhow::Error;
+
+    fn try_from(block: DbDeadLetterBlock) -> Result<Self, Self::Error> {
+        Ok(types::DeadLetterBlock {
+            number: block
+                .number
+                .ok_or_else(|| anyhow::anyhow!("Missing block number"))? as u64,
+            hash: block
+                .hash
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid hash"))?,
+            error: block.error,
+            attempts: block.attempts as u32,
+            last_attempt_at: block.last_attempt_at as u64,
+        })
+    }
+}
+
+/// A run of missing block numbers between two stored blocks.
+#[derive(QueryableByName)]
+pub struct BlockGap {
+    #[diesel(sql_type = BigInt)]
+    pub gap_start: i64,
+    #[diesel(sql_type = BigInt)]
+    pub gap_end: i64,
+}
+
+/// An account and token whose balance changed, but was never seeded.
+#[derive(QueryableByName)]
+pub struct UnseededBalance {
+    #[diesel(sql_type = Binary)]
+    pub account: Vec<u8>,
+    #[diesel(sql_type = Binary)]
+    pub token: Vec<u8>,
+}
+
+/// An account that moved a token in a block, where its balance was not fetched.
+#[derive(QueryableByName)]
+pub struct UnfetchedBalance {
+    #[diesel(sql_type = Binary)]
+    pub account: Vec<u8>,
+    #[diesel(sql_type = BigInt)]
+    pub block_number: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = watched_tokens)]
+pub struct NewWatchedToken<'a> {
+    pub address: &'a [u8],
+    pub enabled: bool,
+    pub backfill_pending: bool,
+    pub added_at: i64,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = watched_tokens)]
+pub struct DbWatchedToken {
+    pub address: Option<Vec<u8>>,
+    pub enabled: bool,
+    pub backfill_pending: bool,
+    pub added_at: i64,
+}
+
+impl TryFrom<DbWatchedToken> for types::WatchedToken {
+    type Error = anyhow::Error;
+
+    fn try_from(token: DbWatchedToken) -> Result<Self, Self::Error> {
+        Ok(types::WatchedToken {
+            address: token
+                .address
+                .ok_or_else(|| anyhow::anyhow!("Missing address"))?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid address"))?,
+            enabled: token.enabled,
+            backfill_pending: token.backfill_pending,
+            added_at: token.added_at as u64,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = tokens)]
+pub struct NewToken<'a> {
+    pub address: &'a [u8],
+    pub name: Option<&'a str>,
+    pub symbol: Option<&'a str>,
+    pub decimals: Option<i32>,
+    pub discovered_at: i64,
+}
+
+impl<'a> NewToken<'a> {
+    pub fn new(metadata: &'a types::TokenMetadata, discovered_at: i64) -> Self {
+        NewToken {
+            address: &metadata.address,
+            name: metadata.name.as_deref(),
+            symbol: metadata.symbol.as_deref(),
+            decimals: metadata.decimals.map(i32::from),
+            discovered_at,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = tokens)]
+pub struct DbToken {
+    pub address: Option<Vec<u8>>,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<i32>,
+}
+
+impl TryFrom<DbToken> for types::TokenMetadata {
+    type Error = anyhow::Error;
+
+    fn try_from(token: DbToken) -> Result<Self, Self::Error> {
+        Ok(types::TokenMetadata {
+            address: token
+                .address
+                .ok_or_else(|| anyhow::anyhow!("Missing address"))?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid address"))?,
+            name: token.name,
+            symbol: token.symbol,
+            decimals: token
+                .decimals
+                .map(u8::try_from)
+                .transpose()
+                .map_err(|_| anyhow::anyhow!("Invalid decimals"))?,
+        })
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = token_stats)]
+pub struct DbTokenStats {
+    pub token: Option<Vec<u8>>,
+    pub holders: i64,
+    pub total_supply: Option<Vec<u8>>,
+    pub supply_updated_at: Option<i64>,
+    pub supply_block: Option<i64>,
+}
+
+impl TryFrom<DbTokenStats> for types::TokenStats {
+    type Error = anyhow::Error;
+
+    fn try_from(stats: DbTokenStats) -> Result<Self, Self::Error> {
+        Ok(types::TokenStats {
+            token: stats
+                .token
+                .ok_or_else(|| anyhow::anyhow!("Missing token"))?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid token"))?,
+            holders: stats.holders as u64,
+            total_supply: stats
+                .total_supply
+                .map(|supply| {
+                    supply
+                        .try_into()
+                        .map_err(|_| anyhow::anyhow!("Invalid total supply"))
+                })
+                .transpose()?,
+            supply_updated_at: stats.supply_updated_at.map(|at| at as u64),
+            supply_block: stats.supply_block.map(|block| block as u64),
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webhooks)]
+pub struct NewWebhook<'a> {
+    pub url: &'a str,
+    pub secret: &'a str,
+    pub address: Option<&'a [u8]>,
+    pub token: Option<&'a [u8]>,
+    pub topic: Option<&'a [u8]>,
+    pub min_value: Option<&'a [u8]>,
+    pub created_at: i64,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = webhooks)]
+pub struct DbWebhook {
+    pub id: Option<i32>,
+    pub url: String,
+    pub secret: String,
+    pub address: Option<Vec<u8>>,
+    pub token: Option<Vec<u8>>,
+    pub topic: Option<Vec<u8>>,
+    pub min_value: Option<Vec<u8>>,
+    pub created_at: i64,
+}
+
+/// Converts an optional BLOB column to a fixed-size array.
+fn optional_array<const N: usize>(
+    value: Option<Vec<u8>>,
+    name: &str,
+) -> anyhow::Result<Option<[u8; N]>> {
+    value
+        .map(|value| {
+            value
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid {name}"))
+        })
+        .transpose()
+}
+
+impl TryFrom<DbWebhook> for types::Webhook {
+    type Error = anyhow::Error;
+
+    fn try_from(webhook: DbWebhook) -> Result<Self, Self::Error> {
+        Ok(types::Webhook {
+            id: webhook.id.ok_or_else(|| anyhow::anyhow!("Missing id"))?,
+            url: webhook.url,
+            secret: webhook.secret,
+            filter: types::WebhookFilter {
+                address: optional_array(webhook.address, "address")?,
+                token: optional_array(webhook.token, "token")?,
+                topic: optional_array(webhook.topic, "topic")?,
+                min_value: optional_array(webhook.min_value, "minimum value")?,
+            },
+            created_at: webhook.created_at as u64,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct NewWebhookDelivery<'a> {
+    pub webhook_id: i32,
+    pub block_number: i64,
+    pub block_hash: &'a [u8],
+    pub payload: &'a str,
+    pub status: &'a str,
+    pub next_attempt_at: i64,
+    pub created_at: i64,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct DbWebhookDelivery {
+    pub id: Option<i32>,
+    pub webhook_id: i32,
+    pub block_number: i64,
+    pub block_hash: Vec<u8>,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: i64,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub delivered_at: Option<i64>,
+}
+
+impl TryFrom<DbWebhookDelivery> for types::WebhookDelivery {
+    type Error = anyhow::Error;
+
+    fn try_from(delivery: DbWebhookDelivery) -> Result<Self, Self::Error> {
+        Ok(types::WebhookDelivery {
+            id: delivery.id.ok_or_else(|| anyhow::anyhow!("Missing id"))?,
+            webhook_id: delivery.webhook_id,
+            block_number: delivery.block_number as u64,
+            block_hash: delivery
+                .block_hash
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid block hash"))?,
+            payload: delivery.payload,
+            status: delivery.status.parse()?,
+            attempts: delivery.attempts as u32,
+            next_attempt_at: delivery.next_attempt_at as u64,
+            last_status_code: delivery.last_status_code.map(|code| code as u16),
+            last_error: delivery.last_error,
+            created_at: delivery.created_at as u64,
+            delivered_at: delivery.delivered_at.map(|at| at as u64),
+        })
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = webhook_events)]
+pub struct DbWebhookEvent {
+    pub id: Option<i32>,
+    pub block_number: i64,
+    pub block_hash: Vec<u8>,
+    pub removed: bool,
+}
+
+impl TryFrom<DbWebhookEvent> for types::WebhookEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(event: DbWebhookEvent) -> Result<Self, Self::Error> {
+        Ok(types::WebhookEvent {
+            id: event.id.ok_or_else(|| anyhow::anyhow!("Missing id"))?,
+            block_number: event.block_number as u64,
+            block_hash: event
+                .block_hash
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid block hash"))?,
+            removed: event.removed,
+        })
+    }
+}
@@ -1,5 +1,53 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    access_list_items (transaction_hash, item_index) {
+        transaction_hash -> Binary,
+        item_index -> Integer,
+        address -> Binary,
+        storage_keys -> Binary,
+    }
+}
+
+diesel::table! {
+    accounts (account, token) {
+        account -> Binary,
+        token -> Binary,
+        balance -> Binary,
+        block_id -> BigInt,
+    }
+}
+
+diesel::table! {
+    backfill_jobs (id) {
+        id -> Nullable<Integer>,
+        from_block -> BigInt,
+        to_block -> BigInt,
+        next_block -> BigInt,
+    }
+}
+
+diesel::table! {
+    balance_deltas (account, token, block_id) {
+        account -> Binary,
+        token -> Binary,
+        block_id -> BigInt,
+        delta -> Binary,
+        applied -> Bool,
+    }
+}
+
+diesel::table! {
+    balance_drifts (account, token) {
+        account -> Binary,
+        token -> Binary,
+        block_id -> BigInt,
+        derived -> Binary,
+        onchain -> Binary,
+        detected_at -> BigInt,
+    }
+}
+
 diesel::table! {
     balances (account, token, block_id) {
         account -> Binary,
@@ -18,6 +66,60 @@ diesel::table! {
         gas_limit -> BigInt,
         gas_used -> BigInt,
         base_fee_per_gas -> Nullable<BigInt>,
+        finality -> Text,
+    }
+}
+
+diesel::table! {
+    dead_letter_blocks (number) {
+        number -> Nullable<BigInt>,
+        hash -> Binary,
+        error -> Text,
+        attempts -> Integer,
+        last_attempt_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    derived_balances (account, token) {
+        account -> Binary,
+        token -> Binary,
+        balance -> Binary,
+        seeded_block -> BigInt,
+        updated_block -> BigInt,
+        checked_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    erc1155_balances (contract, token_id, holder) {
+        contract -> Binary,
+        token_id -> Binary,
+        holder -> Binary,
+        received -> Binary,
+        sent -> Binary,
+    }
+}
+
+diesel::table! {
+    erc1155_transfers (transaction_hash, log_index, batch_index) {
+        transaction_hash -> Binary,
+        log_index -> BigInt,
+        batch_index -> BigInt,
+        contract -> Binary,
+        operator -> Binary,
+        from_address -> Binary,
+        to_address -> Binary,
+        token_id -> Binary,
+        value -> Binary,
+        block_number -> BigInt,
+    }
+}
+
+diesel::table! {
+    indexer_state (key) {
+        key -> Text,
+        value -> BigInt,
     }
 }
 
@@ -40,10 +142,91 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    missing_balances (account, token, block_id) {
+        account -> Binary,
+        token -> Binary,
+        block_id -> BigInt,
+        error -> Text,
+        attempts -> Integer,
+    }
+}
+
+diesel::table! {
+    nft_owners (collection, token_id) {
+        collection -> Binary,
+        token_id -> Binary,
+        owner -> Binary,
+        last_transfer_block -> BigInt,
+    }
+}
+
+diesel::table! {
+    nft_transfers (transaction_hash, log_index) {
+        transaction_hash -> Binary,
+        log_index -> BigInt,
+        collection -> Binary,
+        token_id -> Binary,
+        from_address -> Binary,
+        to_address -> Binary,
+        block_number -> BigInt,
+    }
+}
+
+diesel::table! {
+    orphaned_blocks (hash) {
+        hash -> Nullable<Binary>,
+        number -> BigInt,
+        parent_hash -> Binary,
+        timestamp -> BigInt,
+        orphaned_at -> BigInt,
+    }
+}
+
 diesel::table! {
     receipts (transaction_hash) {
         transaction_hash -> Nullable<Binary>,
         gas_used -> BigInt,
+        transaction_index -> BigInt,
+        success -> Bool,
+        cumulative_gas_used -> BigInt,
+        effective_gas_price -> Binary,
+        contract_address -> Nullable<Binary>,
+        logs_bloom -> Binary,
+    }
+}
+
+diesel::table! {
+    token_stats (token) {
+        token -> Nullable<Binary>,
+        holders -> BigInt,
+        total_supply -> Nullable<Binary>,
+        supply_updated_at -> Nullable<BigInt>,
+        supply_block -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    token_transfers (transaction_hash, log_index) {
+        transaction_hash -> Binary,
+        log_index -> BigInt,
+        token -> Binary,
+        from_address -> Binary,
+        to_address -> Binary,
+        value -> Binary,
+        block_number -> BigInt,
+    }
+}
+
+diesel::table! {
+    tokens (address) {
+        address -> Nullable<Binary>,
+        name -> Nullable<Text>,
+        symbol -> Nullable<Text>,
+        decimals -> Nullable<Integer>,
+        discovered_at -> BigInt,
+        metadata_attempts -> Integer,
+        retry_at -> Nullable<BigInt>,
     }
 }
 
@@ -51,21 +234,111 @@ diesel::table! {
     transactions (hash) {
         hash -> Nullable<Binary>,
         block_number -> BigInt,
+        transaction_index -> BigInt,
+        tx_type -> Integer,
+        chain_id -> Nullable<BigInt>,
+        from_address -> Binary,
+        to_address -> Nullable<Binary>,
+        value -> Binary,
+        nonce -> BigInt,
+        input -> Binary,
+        gas_limit -> BigInt,
+        gas_price -> Nullable<Binary>,
+        max_fee_per_gas -> Nullable<Binary>,
+        max_priority_fee_per_gas -> Nullable<Binary>,
+    }
+}
+
+diesel::table! {
+    watched_tokens (address) {
+        address -> Nullable<Binary>,
+        enabled -> Bool,
+        backfill_pending -> Bool,
+        added_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Nullable<Integer>,
+        webhook_id -> Integer,
+        block_number -> BigInt,
+        block_hash -> Binary,
+        payload -> Text,
+        status -> Text,
+        attempts -> Integer,
+        next_attempt_at -> BigInt,
+        last_status_code -> Nullable<Integer>,
+        last_error -> Nullable<Text>,
+        created_at -> BigInt,
+        delivered_at -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    webhook_events (id) {
+        id -> Nullable<Integer>,
+        block_number -> BigInt,
+        block_hash -> Binary,
+        removed -> Bool,
+    }
+}
+
+diesel::table! {
+    webhooks (id) {
+        id -> Nullable<Integer>,
+        url -> Text,
+        secret -> Text,
+        address -> Nullable<Binary>,
+        token -> Nullable<Binary>,
+        topic -> Nullable<Binary>,
+        min_value -> Nullable<Binary>,
+        created_at -> BigInt,
     }
 }
 
+diesel::joinable!(access_list_items -> transactions (transaction_hash));
+diesel::joinable!(accounts -> blocks (block_id));
+diesel::joinable!(balance_deltas -> blocks (block_id));
+diesel::joinable!(balance_drifts -> blocks (block_id));
 diesel::joinable!(balances -> blocks (block_id));
+diesel::joinable!(erc1155_transfers -> blocks (block_number));
 diesel::joinable!(log_topics -> logs (log_id));
 diesel::joinable!(logs -> blocks (block_number));
 diesel::joinable!(logs -> transactions (transaction_hash));
+diesel::joinable!(missing_balances -> blocks (block_id));
+diesel::joinable!(nft_transfers -> blocks (block_number));
 diesel::joinable!(receipts -> transactions (transaction_hash));
+diesel::joinable!(token_transfers -> blocks (block_number));
 diesel::joinable!(transactions -> blocks (block_number));
+diesel::joinable!(webhook_deliveries -> webhooks (webhook_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    access_list_items,
+    accounts,
+    backfill_jobs,
+    balance_deltas,
+    balance_drifts,
     balances,
     blocks,
+    dead_letter_blocks,
+    derived_balances,
+    erc1155_balances,
+    erc1155_transfers,
+    indexer_state,
     log_topics,
     logs,
+    missing_balances,
+    nft_owners,
+    nft_transfers,
+    orphaned_blocks,
     receipts,
+    token_stats,
+    token_transfers,
+    tokens,
     transactions,
+    watched_tokens,
+    webhook_deliveries,
+    webhook_events,
+    webhooks,
 );
@@ -0,0 +1,379 @@
+use std::{
+    ops::RangeInclusive,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use alloy_provider::{DynProvider, Provider, ProviderBuilder, WsConnect};
+use alloy_rpc_types_eth::{BlockNumberOrTag, Header};
+use futures_util::StreamExt;
+use tokio::sync::{mpsc, watch};
+
+use crate::{
+    eth_client::retry::RetryPolicy,
+    types::{ConnectionState, ConnectionStatus},
+};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How long the chain head may stay the same before the endpoint is considered stuck, and the
+/// next one connected. Blocks come every 12 seconds on mainnet.
+pub(super) const HEAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How often the first endpoint, the preferred one, is checked while following another one.
+pub(super) const PRIMARY_PROBE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Most recent blocks fetched right after reconnecting. Anything older that was missed during the
+/// outage is left to the indexer's gap healing.
+const MAX_CATCH_UP: u64 = 128;
+
+/// How new blocks are learned about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestionMode {
+    /// `eth_subscribe` to new heads. Needs a WebSocket endpoint.
+    Subscribe,
+    /// `eth_blockNumber` and `eth_getBlockByNumber` at a fixed interval. Works on any endpoint.
+    Poll,
+}
+
+impl IngestionMode {
+    /// The mode an endpoint is used with when none is configured: polling for HTTP endpoints and
+    /// subscribing for the others.
+    pub fn for_url(url: &str) -> Self {
+        if is_http(url) {
+            IngestionMode::Poll
+        } else {
+            IngestionMode::Subscribe
+        }
+    }
+}
+
+fn is_http(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+pub(super) async fn connect_endpoint(url: &str) -> anyhow::Result<DynProvider> {
+    if is_http(url) {
+        return Ok(ProviderBuilder::new().connect_http(url.parse()?).erased());
+    }
+
+    // Reconnection is handled by the `Supervisor`, which can also move to another endpoint.
+    let provider = ProviderBuilder::new()
+        .connect_ws(WsConnect::new(url).with_max_retries(1))
+        .await?
+        .erased();
+    Ok(provider)
+}
+
+/// Keeps following new blocks, forwarding the header of each one.
+///
+/// When the subscription ends, polling fails or no new block comes within `head_timeout`, the
+/// next endpoint is connected, with an exponential backoff between attempts, and the blocks
+/// produced in the meantime are forwarded before the new ones. While following any endpoint but
+/// the first, the first one is checked every `primary_probe_interval`, and moved back to as soon as
+/// it answers.
+pub(super) struct Supervisor {
+    pub urls: Vec<String>,
+    pub endpoint: usize,
+    /// Overrides the mode picked from each endpoint's URL.
+    pub mode: Option<IngestionMode>,
+    pub poll_interval: Duration,
+    pub retry: RetryPolicy,
+    pub head_timeout: Duration,
+    pub primary_probe_interval: Duration,
+    pub provider: watch::Sender<DynProvider>,
+    pub status: watch::Sender<ConnectionStatus>,
+    pub headers: mpsc::Sender<Header>,
+}
+
+impl Supervisor {
+    pub async fn run(mut self) {
+        let mut last_block = None;
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let provider = self.provider.borrow().clone();
+            let previous = last_block;
+            let mode = self
+                .mode
+                .unwrap_or_else(|| IngestionMode::for_url(&self.urls[self.endpoint]));
+            let follow = async {
+                match mode {
+                    IngestionMode::Subscribe => self.subscribe(&provider, &mut last_block).await,
+                    IngestionMode::Poll => self.poll(&provider, &mut last_block).await,
+                }
+            };
+            let outcome = tokio::select! {
+                result = follow => Outcome::Ended(result),
+                primary = self.probe_primary(), if self.endpoint != 0 => {
+                    Outcome::PrimaryBack(primary)
+                }
+            };
+            let result = match outcome {
+                Outcome::Ended(result) => result,
+                Outcome::PrimaryBack(primary) => {
+                    tracing::info!("Endpoint 0 is back, leaving endpoint {}", self.endpoint);
+                    self.endpoint = 0;
+                    self.provider.send_replace(primary);
+                    self.status.send_modify(|status| status.reconnects += 1);
+                    continue;
+                }
+            };
+            match result {
+                Ok(()) => tracing::warn!("Following endpoint {} ended", self.endpoint),
+                Err(e) => tracing::warn!("Following endpoint {} failed: {e}", self.endpoint),
+            }
+            if self.headers.is_closed() {
+                return;
+            }
+            self.set_state(ConnectionState::Disconnected);
+            if last_block != previous {
+                backoff = INITIAL_BACKOFF;
+            }
+
+            loop {
+                tracing::info!("Reconnecting in {backoff:?}");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+
+                self.endpoint = (self.endpoint + 1) % self.urls.len();
+                self.set_state(ConnectionState::Connecting);
+                match connect_endpoint(&self.urls[self.endpoint]).await {
+                    Ok(provider) => {
+                        self.provider.send_replace(provider);
+                        self.status.send_modify(|status| status.reconnects += 1);
+                        break;
+                    }
+                    Err(e) => {
+                        tracing::warn!("Connection to endpoint {} failed: {e}", self.endpoint);
+                        self.set_state(ConnectionState::Disconnected);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits until the first endpoint answers again, and returns a connection to it.
+    async fn probe_primary(&self) -> DynProvider {
+        loop {
+            tokio::time::sleep(self.primary_probe_interval).await;
+            let probe = async {
+                let provider = connect_endpoint(&self.urls[0]).await?;
+                provider.get_block_number().await?;
+                anyhow::Ok(provider)
+            };
+            match probe.await {
+                Ok(provider) => return provider,
+                Err(e) => tracing::debug!("Endpoint 0 is still unavailable: {e}"),
+            }
+        }
+    }
+
+    async fn subscribe(
+        &self,
+        provider: &DynProvider,
+        last_block: &mut Option<u64>,
+    ) -> anyhow::Result<()> {
+        // Subscribing first ensures nothing is lost while the missed blocks are fetched.
+        let sub = provider.subscribe_blocks().await?;
+        self.set_state(ConnectionState::Connected);
+
+        if let Some(last) = *last_block {
+            let head = provider.get_block_number().await?;
+            if !self
+                .forward(provider, missed(last, head), last_block)
+                .await?
+            {
+                return Ok(());
+            }
+        }
+
+        let mut stream = sub.into_stream();
+        loop {
+            // A subscription can stay open without delivering anything.
+            let header = match tokio::time::timeout(self.head_timeout, stream.next()).await {
+                Ok(Some(header)) => header,
+                Ok(None) => break,
+                Err(_) => anyhow::bail!("No new head for {:?}", self.head_timeout),
+            };
+            let number = header.number;
+            if self.headers.send(header).await.is_err() {
+                tracing::error!("Failed to send header to processing channel");
+                break;
+            }
+            *last_block = Some(number);
+        }
+
+        Ok(())
+    }
+
+    async fn poll(
+        &self,
+        provider: &DynProvider,
+        last_block: &mut Option<u64>,
+    ) -> anyhow::Result<()> {
+        let mut interval = tokio::time::interval(self.poll_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut connected = false;
+        let mut advanced_at = Instant::now();
+        loop {
+            interval.tick().await;
+            let head = self
+                .retry
+                .retry("eth_blockNumber", || provider.get_block_number())
+                .await?;
+            if !connected {
+                self.set_state(ConnectionState::Connected);
+                connected = true;
+            }
+            if last_block.is_none_or(|last| head > last) {
+                advanced_at = Instant::now();
+            } else if advanced_at.elapsed() >= self.head_timeout {
+                anyhow::bail!("Head stuck at block {head} for {:?}", self.head_timeout);
+            }
+
+            let range = match *last_block {
+                Some(last) => missed(last, head),
+                None => head..=head,
+            };
+            if !self.forward(provider, range, last_block).await? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Fetches and forwards the headers of `range`. Returns `false` once nobody is listening.
+    async fn forward(
+        &self,
+        provider: &DynProvider,
+        range: RangeInclusive<u64>,
+        last_block: &mut Option<u64>,
+    ) -> anyhow::Result<bool> {
+        for number in range {
+            let header = self
+                .retry
+                .retry("eth_getBlockByNumber", || async {
+                    provider
+                        .get_block_by_number(BlockNumberOrTag::Number(number))
+                        .await?
+                        .ok_or_else(|| anyhow::anyhow!("Block {number} not found"))
+                })
+                .await?
+                .header;
+            if self.headers.send(header).await.is_err() {
+                return Ok(false);
+            }
+            *last_block = Some(number);
+        }
+        Ok(true)
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        tracing::info!("Endpoint {} is {state:?}", self.endpoint);
+        self.status.send_modify(|status| {
+            status.state = state;
+            status.endpoint = self.endpoint;
+            status.since = now();
+        });
+    }
+}
+
+/// How following an endpoint stopped.
+enum Outcome {
+    Ended(anyhow::Result<()>),
+    /// The first endpoint answers again, through this connection.
+    PrimaryBack(DynProvider),
+}
+
+/// The blocks after `last` up to `head`, limited to the most recent `MAX_CATCH_UP`.
+fn missed(last: u64, head: u64) -> RangeInclusive<u64> {
+    (last + 1).max(head.saturating_sub(MAX_CATCH_UP - 1))..=head
+}
+
+pub(super) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, routing::post};
+
+    use super::*;
+
+    /// Serves a node whose head stays at block 16.
+    async fn serve_stuck_node() -> String {
+        let app = Router::new().route(
+            "/",
+            post(|Json(request): Json<serde_json::Value>| async move {
+                Json(serde_json::json!({"jsonrpc": "2.0", "id": request["id"], "result": "0x10"}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{address}")
+    }
+
+    async fn supervisor(urls: Vec<String>, endpoint: usize) -> Supervisor {
+        let provider = connect_endpoint(&urls[0]).await.unwrap();
+        Supervisor {
+            urls,
+            endpoint,
+            mode: None,
+            poll_interval: Duration::from_millis(10),
+            retry: RetryPolicy::default(),
+            head_timeout: Duration::from_millis(100),
+            primary_probe_interval: Duration::from_millis(10),
+            provider: watch::channel(provider).0,
+            status: watch::channel(ConnectionStatus::default()).0,
+            headers: mpsc::channel(1).0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stuck_head_fails_polling() {
+        let url = serve_stuck_node().await;
+        let supervisor = supervisor(vec![url], 0).await;
+        let provider = supervisor.provider.borrow().clone();
+
+        let mut last_block = Some(16);
+        let result = supervisor.poll(&provider, &mut last_block).await;
+        assert!(result.unwrap_err().to_string().contains("stuck"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_primary() {
+        let url = serve_stuck_node().await;
+        let supervisor = supervisor(vec![url, "http://127.0.0.1:1".to_string()], 1).await;
+
+        let primary = tokio::time::timeout(Duration::from_secs(5), supervisor.probe_primary())
+            .await
+            .unwrap();
+        assert_eq!(primary.get_block_number().await.unwrap(), 16);
+    }
+
+    #[test]
+    fn test_ingestion_mode_for_url() {
+        assert_eq!(
+            IngestionMode::for_url("https://node.example"),
+            IngestionMode::Poll
+        );
+        assert_eq!(
+            IngestionMode::for_url("http://127.0.0.1:8545"),
+            IngestionMode::Poll
+        );
+        assert_eq!(
+            IngestionMode::for_url("wss://node.example"),
+            IngestionMode::Subscribe
+        );
+    }
+
+    #[test]
+    fn test_missed_blocks_are_capped() {
+        assert_eq!(missed(10, 12), 11..=12);
+        assert!(missed(12, 12).is_empty());
+        assert_eq!(missed(10, 1000), 1000 - MAX_CATCH_UP + 1..=1000);
+    }
+}
@@ -0,0 +1,18 @@
+use alloy_sol_types::sol;
+
+sol! {
+    /// The ERC-1155 multi token standard interface.
+    #[sol(rpc)]
+    interface IERC1155 {
+        // Events
+        event TransferSingle(address indexed operator, address indexed from, address indexed to, uint256 id, uint256 value);
+        event TransferBatch(address indexed operator, address indexed from, address indexed to, uint256[] ids, uint256[] values);
+        event ApprovalForAll(address indexed account, address indexed operator, bool approved);
+        event URI(string value, uint256 indexed id);
+
+        // Functions
+        function balanceOf(address account, uint256 id) external view returns (uint256);
+        function balanceOfBatch(address[] calldata accounts, uint256[] calldata ids) external view returns (uint256[] memory);
+        function uri(uint256 id) external view returns (string memory);
+    }
+}
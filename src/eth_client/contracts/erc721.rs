@@ -0,0 +1,19 @@
+use alloy_sol_types::sol;
+
+sol! {
+    /// The ERC-721 non-fungible token standard interface.
+    #[sol(rpc)]
+    interface IERC721 {
+        // Events
+        event Transfer(address indexed from, address indexed to, uint256 indexed tokenId);
+        event Approval(address indexed owner, address indexed approved, uint256 indexed tokenId);
+        event ApprovalForAll(address indexed owner, address indexed operator, bool approved);
+
+        // Functions
+        function name() external view returns (string memory);
+        function symbol() external view returns (string memory);
+        function tokenURI(uint256 tokenId) external view returns (string memory);
+        function balanceOf(address owner) external view returns (uint256);
+        function ownerOf(uint256 tokenId) external view returns (address);
+    }
+}
@@ -0,0 +1,25 @@
+use alloy::primitives::{Address, address};
+use alloy_sol_types::sol;
+
+/// Where Multicall3 is deployed, at the same address on nearly every EVM chain.
+pub const MULTICALL3: Address = address!("0xcA11bde05977b3631167028862bE2a173976CA11");
+
+sol! {
+    /// The part of Multicall3 used to aggregate balance lookups.
+    #[sol(rpc)]
+    interface IMulticall3 {
+        struct Call3 {
+            address target;
+            bool allowFailure;
+            bytes callData;
+        }
+
+        struct Result {
+            bool success;
+            bytes returnData;
+        }
+
+        function aggregate3(Call3[] calldata calls) external payable returns (Result[] memory returnData);
+        function getEthBalance(address addr) external view returns (uint256 balance);
+    }
+}
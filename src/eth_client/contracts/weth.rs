@@ -0,0 +1,10 @@
+use alloy_sol_types::sol;
+
+sol! {
+    /// The events WETH9 emits when ether is wrapped and unwrapped, minting and burning tokens
+    /// without a `Transfer`.
+    interface IWETH {
+        event Deposit(address indexed dst, uint256 wad);
+        event Withdrawal(address indexed src, uint256 wad);
+    }
+}
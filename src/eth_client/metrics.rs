@@ -0,0 +1,47 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::types::RpcStats;
+
+/// Counts the calls made to the node, overall and for each fetched block.
+#[derive(Debug, Default)]
+pub struct RpcMetrics {
+    blocks: AtomicU64,
+    calls: AtomicU64,
+    block_calls: AtomicU64,
+    last_block: AtomicU64,
+    last_block_calls: AtomicU64,
+    max_block_calls: AtomicU64,
+}
+
+impl RpcMetrics {
+    /// Records the calls made to fetch block `number`.
+    pub fn record_block(&self, number: u64, calls: u64) {
+        self.blocks.fetch_add(1, Ordering::Relaxed);
+        self.block_calls.fetch_add(calls, Ordering::Relaxed);
+        self.last_block.store(number, Ordering::Relaxed);
+        self.last_block_calls.store(calls, Ordering::Relaxed);
+        self.max_block_calls.fetch_max(calls, Ordering::Relaxed);
+        self.calls.fetch_add(calls, Ordering::Relaxed);
+        tracing::debug!("Block {number} fetched with {calls} RPC call(s)");
+    }
+
+    /// Records calls made outside of fetching a block, such as balances fetched again.
+    pub fn record_calls(&self, calls: u64) {
+        self.calls.fetch_add(calls, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> RpcStats {
+        let blocks = self.blocks.load(Ordering::Relaxed);
+        RpcStats {
+            calls: self.calls.load(Ordering::Relaxed),
+            blocks,
+            last_block: (blocks > 0).then(|| self.last_block.load(Ordering::Relaxed)),
+            last_block_calls: self.last_block_calls.load(Ordering::Relaxed),
+            max_block_calls: self.max_block_calls.load(Ordering::Relaxed),
+            average_block_calls: match blocks {
+                0 => 0.0,
+                blocks => self.block_calls.load(Ordering::Relaxed) as f64 / blocks as f64,
+            },
+        }
+    }
+}
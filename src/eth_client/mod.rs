@@ -1,60 +1,316 @@
+pub mod connection;
 mod contracts;
+pub mod metrics;
 mod parser_log;
 mod parser_receipt;
+pub mod retry;
+pub mod source;
+mod token_metadata;
 mod types;
 pub mod update_balances;
 
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
-use alloy_provider::{DynProvider, Provider, ProviderBuilder, WsConnect};
-use alloy_rpc_types_eth::{BlockId, BlockNumberOrTag, BlockTransactions, Filter, Header};
-use futures_util::StreamExt;
-use tokio::sync::mpsc::{self, Receiver};
+use alloy::primitives::Address;
+use alloy_rpc_types_eth::{BlockTransactions, Header};
+use tokio::sync::{
+    mpsc::{self, Receiver},
+    watch,
+};
 use tracing::Instrument;
 
-use crate::{eth_client::update_balances::get_balances, types::BlockSummary};
+use crate::{
+    eth_client::{
+        connection::{
+            HEAD_TIMEOUT, IngestionMode, PRIMARY_PROBE_INTERVAL, Supervisor, connect_endpoint, now,
+        },
+        metrics::RpcMetrics,
+        retry::{BlockFetchError, RetryPolicy},
+        source::{BlockSource, CountingSource, RecordingSource, ReplaySource, RpcSource},
+        types::ParsedData,
+        update_balances::{BalanceMode, BatchPolicy, get_balances},
+    },
+    types::{
+        Balance, BalanceDelta, Block, BlockSummary, ConnectionState, ConnectionStatus, Finality,
+        MissingBalance, TokenMetadata,
+    },
+};
 
-#[tracing::instrument(skip(rpc))]
-pub async fn connect(
-    rpc: impl Into<String>,
-) -> anyhow::Result<Receiver<anyhow::Result<BlockSummary>>> {
-    let provider = ProviderBuilder::new()
-        .connect_ws(WsConnect::new(rpc))
-        .await?
-        .erased();
+/// Tokens whose balances are fetched for the accounts moving them, as currently enabled in the
+/// registry.
+pub type Watchlist = watch::Receiver<HashSet<Address>>;
 
-    let (sender, receiver) = mpsc::channel(100);
+/// Handle used to request specific blocks from the node, alongside the subscription stream.
+///
+/// It always talks to the endpoint the subscription is currently connected to.
+#[derive(Clone)]
+pub struct EthClient {
+    source: Arc<dyn BlockSource>,
+    status: watch::Receiver<ConnectionStatus>,
+    retry: RetryPolicy,
+    batch: BatchPolicy,
+    balance_mode: BalanceMode,
+    tokens: Watchlist,
+    metrics: Arc<RpcMetrics>,
+}
 
-    let provider = Arc::new(provider);
+impl EthClient {
+    /// Returns a receiver that tracks the state of the connection to the node.
+    pub fn status(&self) -> watch::Receiver<ConnectionStatus> {
+        self.status.clone()
+    }
 
-    let (header_sender, mut header_receiver) = mpsc::channel(100);
+    /// Returns the counts of the calls made to the node.
+    pub fn metrics(&self) -> Arc<RpcMetrics> {
+        Arc::clone(&self.metrics)
+    }
 
-    let provider_clone_1 = Arc::clone(&provider);
-    tokio::spawn(
-        async move {
-            let sub = match provider_clone_1.subscribe_blocks().await {
-                Ok(sub) => sub,
-                Err(e) => {
-                    tracing::error!("Failed to subscribe to blocks: {}", e);
-                    return;
-                }
-            };
-            let mut stream = sub.into_stream();
-            while let Some(header) = stream.next().await {
-                if header_sender.send(header).await.is_err() {
-                    tracing::error!("Failed to send header to processing channel");
-                    break;
-                }
+    /// Fetches and parses the block with the given hash.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_block_by_hash(&self, hash: [u8; 32]) -> anyhow::Result<BlockSummary> {
+        let header = self
+            .source
+            .header_by_hash(hash.into())
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Block not found"))?;
+        get_block_info(self, header).await
+    }
+
+    /// Returns the number of the most recent block known by the node.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_block_number(&self) -> anyhow::Result<u64> {
+        self.source.block_number().await
+    }
+
+    /// Returns the header of the block the chain currently tags as `finality`, if any.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_tagged_block(&self, finality: Finality) -> anyhow::Result<Option<Block>> {
+        let header = self.source.header_by_tag(finality).await?;
+        Ok(header.map(Block::from))
+    }
+
+    /// Fetches and parses the canonical block at the given height.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_block_by_number(&self, number: u64) -> anyhow::Result<BlockSummary> {
+        let header = self
+            .source
+            .header_by_number(number)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Block {number} not found"))?;
+        get_block_info(self, header).await
+    }
+
+    /// Fetches the name, symbol and decimals of an ERC-20 token, as of `block`.
+    #[tracing::instrument(skip(self, block), fields(number = block.number))]
+    pub async fn get_token_metadata(
+        &self,
+        token: [u8; 20],
+        block: &Block,
+    ) -> anyhow::Result<TokenMetadata> {
+        let source = Arc::clone(&self.source);
+        token_metadata::fetch_metadata(source, token.into(), block.hash.into()).await
+    }
+
+    /// Fetches the total supply of an ERC-20 token as of `block`.
+    #[tracing::instrument(skip(self, block), fields(number = block.number))]
+    pub async fn get_total_supply(
+        &self,
+        token: [u8; 20],
+        block: &Block,
+    ) -> anyhow::Result<[u8; 32]> {
+        let source = Arc::clone(&self.source);
+        let supply = token_metadata::fetch_total_supply(source, token.into(), block.hash.into());
+        Ok(supply.await?.to_be_bytes())
+    }
+
+    /// Fetches the balances of `(account, token)` pairs as of `block`, the zero token standing
+    /// for the native balance. The ones the node cannot serve are returned as missing.
+    #[tracing::instrument(skip(self, block, requests), fields(number = block.number))]
+    pub async fn get_balances_at(
+        &self,
+        block: &Block,
+        requests: &[([u8; 20], [u8; 20])],
+    ) -> (Vec<Balance>, Vec<MissingBalance>) {
+        let mut interactions = HashMap::<_, HashSet<_>>::new();
+        for (account, token) in requests {
+            interactions
+                .entry(Address::from(*account))
+                .or_default()
+                .insert(Address::from(*token));
+        }
+        let source = Arc::new(CountingSource::new(Arc::clone(&self.source)));
+        let (balances, missing) = get_balances(
+            source.clone(),
+            ParsedData {
+                block_id: block.number,
+                interactions,
+                deltas: HashMap::new(),
+            },
+            block.hash.into(),
+            self.retry,
+            self.batch,
+        )
+        .await;
+        self.metrics.record_calls(source.calls());
+        (balances.into_iter().map(|b| b.into()).collect(), missing)
+    }
+}
+
+/// How `connect` follows the chain.
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    pub retry: RetryPolicy,
+    /// Forces an ingestion mode instead of picking it from each endpoint's URL scheme.
+    pub mode: Option<IngestionMode>,
+    /// Wait between two checks for new blocks when polling.
+    pub poll_interval: Duration,
+    /// Saves every response received from the node in this directory, to be replayed later.
+    pub record_dir: Option<PathBuf>,
+    /// Tokens whose balances are fetched, read again for each block.
+    pub tokens: Watchlist,
+    /// How balance lookups are grouped into calls.
+    pub batch: BatchPolicy,
+    /// Whether the balances of watched tokens are fetched in every block, or derived.
+    pub balance_mode: BalanceMode,
+}
+
+/// Connects to the first reachable endpoint of `urls` and starts following new blocks, either
+/// through a subscription or by polling.
+///
+/// If the connection is lost later on, the following endpoints are tried in turn until one
+/// works again. Blocks that cannot be fetched, even after retrying as `options.retry` allows, are
+/// sent as a [`BlockFetchError`].
+#[tracing::instrument(skip(urls))]
+pub async fn connect(
+    urls: Vec<String>,
+    options: ConnectOptions,
+) -> anyhow::Result<(EthClient, Receiver<anyhow::Result<BlockSummary>>)> {
+    let retry = options.retry;
+    let mut connected = Err(anyhow::anyhow!("No RPC endpoint configured"));
+    for (endpoint, url) in urls.iter().enumerate() {
+        match connect_endpoint(url).await {
+            Ok(provider) => {
+                connected = Ok((endpoint, provider));
+                break;
+            }
+            Err(e) => {
+                tracing::warn!("Connection to endpoint {endpoint} failed: {e}");
+                connected = Err(e);
             }
         }
-        .instrument(tracing::info_span!("block_subscription_listener")),
+    }
+    let (endpoint, provider) = connected?;
+
+    let (provider_sender, provider_receiver) = watch::channel(provider);
+    let (status_sender, status_receiver) = watch::channel(ConnectionStatus {
+        state: ConnectionState::Connecting,
+        endpoint,
+        since: now(),
+        reconnects: 0,
+    });
+
+    let (header_sender, header_receiver) = mpsc::channel(100);
+
+    let supervisor = Supervisor {
+        urls,
+        endpoint,
+        mode: options.mode,
+        poll_interval: options.poll_interval,
+        retry,
+        head_timeout: HEAD_TIMEOUT,
+        primary_probe_interval: PRIMARY_PROBE_INTERVAL,
+        provider: provider_sender,
+        status: status_sender,
+        headers: header_sender,
+    };
+    tokio::spawn(
+        supervisor
+            .run()
+            .instrument(tracing::info_span!("block_subscription_listener")),
     );
 
-    let provider_clone_2 = Arc::clone(&provider);
+    let mut source: Arc<dyn BlockSource> = Arc::new(RpcSource::new(provider_receiver));
+    if let Some(dir) = options.record_dir {
+        source = Arc::new(RecordingSource::new(source, dir));
+    }
+    let client = EthClient {
+        source,
+        status: status_receiver,
+        retry,
+        batch: options.batch,
+        balance_mode: options.balance_mode,
+        tokens: options.tokens,
+        metrics: Arc::default(),
+    };
+    let receiver = process_headers(client.clone(), header_receiver);
+
+    Ok((client, receiver))
+}
+
+/// Replays the blocks recorded in `dir`, as if they had been received from a node, in the order
+/// they were produced. The stream ends after the last one.
+#[tracing::instrument(skip(tokens))]
+pub async fn replay(
+    dir: PathBuf,
+    retry: RetryPolicy,
+    tokens: Watchlist,
+    balance_mode: BalanceMode,
+) -> anyhow::Result<(EthClient, Receiver<anyhow::Result<BlockSummary>>)> {
+    let source = ReplaySource::open(dir).await?;
+    let headers = source.headers().to_vec();
+
+    let (_, status_receiver) = watch::channel(ConnectionStatus {
+        state: ConnectionState::Connected,
+        endpoint: 0,
+        since: now(),
+        reconnects: 0,
+    });
+    let client = EthClient {
+        source: Arc::new(source),
+        status: status_receiver,
+        retry,
+        batch: BatchPolicy::default(),
+        balance_mode,
+        tokens,
+        metrics: Arc::default(),
+    };
+
+    let (header_sender, header_receiver) = mpsc::channel(100);
+    tokio::spawn(async move {
+        for header in headers {
+            if header_sender.send(header).await.is_err() {
+                break;
+            }
+        }
+    });
+    let receiver = process_headers(client.clone(), header_receiver);
+
+    Ok((client, receiver))
+}
+
+/// Fetches the content of each block whose header is received, in the same order.
+fn process_headers(
+    client: EthClient,
+    mut header_receiver: Receiver<Header>,
+) -> Receiver<anyhow::Result<BlockSummary>> {
+    let (sender, receiver) = mpsc::channel(100);
     tokio::spawn(
         async move {
             while let Some(header) = header_receiver.recv().await {
-                let info = get_block_info(Arc::clone(&provider_clone_2), header).await;
+                let (number, hash) = (header.number, header.hash.into());
+                let info = get_block_info(&client, header).await.map_err(|source| {
+                    BlockFetchError {
+                        number,
+                        hash,
+                        source,
+                    }
+                    .into()
+                });
                 if sender.send(info).await.is_err() {
                     eprintln!("Receiver dropped. Stopping block listener.");
                     break;
@@ -63,41 +319,65 @@ pub async fn connect(
         }
         .instrument(tracing::info_span!("block_processing_thread")),
     );
+    receiver
+}
 
-    Ok(receiver)
+/// Fetches the content of the block with the given header through `client`, recording how many
+/// calls it took.
+#[tracing::instrument(skip(client, header), fields(number = header.number))]
+async fn get_block_info(client: &EthClient, header: Header) -> anyhow::Result<BlockSummary> {
+    let counter = Arc::new(CountingSource::new(Arc::clone(&client.source)));
+    let info = fetch_block(counter.clone(), client, header).await;
+    if let Ok(info) = &info {
+        client
+            .metrics
+            .record_block(info.block.number, counter.calls());
+    } else {
+        client.metrics.record_calls(counter.calls());
+    }
+    info
 }
 
-#[tracing::instrument(skip(provider))]
-async fn get_block_info(
-    provider: Arc<DynProvider>,
+async fn fetch_block(
+    source: Arc<dyn BlockSource>,
+    client: &EthClient,
     header: Header,
 ) -> anyhow::Result<BlockSummary> {
-    let filter = Filter::new().at_block_hash(header.hash);
-    let block_id = BlockId::Number(BlockNumberOrTag::Number(header.number));
+    let retry = client.retry;
+    let tokens = client.tokens.borrow().clone();
     let (block_result, logs_result, receipts_result) = tokio::join!(
-        provider.get_block_by_hash(header.hash).full(),
-        provider.get_logs(&filter),
-        provider.get_block_receipts(block_id),
+        retry.retry("eth_getBlockByHash", || async {
+            source
+                .block_by_hash(header.hash)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Block not found"))
+        }),
+        retry.retry("eth_getLogs", || source.logs(header.hash)),
+        retry.retry("eth_getBlockReceipts", || async {
+            source
+                .block_receipts(header.hash)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Receipts not found"))
+        }),
     );
 
-    let (block, logs, receipts) = (
-        block_result?.ok_or_else(|| anyhow::anyhow!("Block not found"))?,
-        logs_result?,
-        receipts_result?,
-    );
+    let (block, logs, receipts) = (block_result?, logs_result?, receipts_result?);
 
     let transactions = match block.transactions {
         BlockTransactions::Full(transactions) => Ok(transactions),
         _ => Err(anyhow::anyhow!("Block transactions are not full")),
     }?;
 
-    let receipts = receipts.ok_or_else(|| anyhow::anyhow!("Receipts not found"))?;
-
-    let (mut logs_accounts, (transactions_accounts, receipts)) = tokio::join!(
-        parser_log::parse_logs(&logs),
+    let ((mut logs_accounts, transfers), (transactions_accounts, receipts)) = tokio::join!(
+        parser_log::parse_logs(&logs, &tokens),
         parser_receipt::parse_receipts(&receipts, &transactions),
     );
 
+    let balance_deltas = match client.balance_mode {
+        BalanceMode::Rpc => Vec::new(),
+        BalanceMode::Derived => derive_deltas(&mut logs_accounts, header.number),
+    };
+
     for (account, contracts) in transactions_accounts.interactions {
         logs_accounts
             .interactions
@@ -106,30 +386,61 @@ async fn get_block_info(
             .extend(contracts);
     }
 
-    let balances = get_balances(provider, logs_accounts).await;
+    // Blocks without logs would otherwise report their balances at block 0.
+    logs_accounts.block_id = header.number;
+    let (balances, missing_balances) =
+        get_balances(source, logs_accounts, header.hash, retry, client.batch).await;
 
     Ok(BlockSummary {
         block: header.into(),
         logs: logs.iter().map(|log| log.clone().into()).collect(),
-        transactions: transactions
-            .iter()
-            .map(|tx| {
-                // tx.
-                (*tx.inner.hash()).into()
-            })
-            .collect(),
+        transactions: transactions.iter().map(Into::into).collect(),
         balances: balances.into_iter().map(|b| b.into()).collect(),
+        missing_balances,
+        balance_deltas,
         receipts,
+        token_transfers: transfers.tokens,
+        nft_transfers: transfers.nfts,
+        erc1155_transfers: transfers.erc1155,
     })
 }
 
+/// Takes the watched tokens out of the balances to fetch, returning how much each account's
+/// balance moved in them instead.
+fn derive_deltas(parsed: &mut ParsedData, block_id: u64) -> Vec<BalanceDelta> {
+    let mut deltas: Vec<BalanceDelta> = parsed
+        .deltas
+        .drain()
+        .filter(|(_, delta)| !delta.is_zero())
+        .map(|((account, token), delta)| BalanceDelta {
+            account: account.into(),
+            token: token.into(),
+            block_id,
+            delta: delta.to_be_bytes(),
+        })
+        .collect();
+    deltas.sort_by_key(|delta| (delta.account, delta.token));
+
+    for contracts in parsed.interactions.values_mut() {
+        contracts.retain(|token| *token == Address::ZERO);
+    }
+    parsed
+        .interactions
+        .retain(|_, contracts| !contracts.is_empty());
+    deltas
+}
+
 #[cfg(test)]
 mod tests {
     use std::env;
 
-    use alloy::primitives::b256;
+    use alloy::primitives::{U256, address, b256};
+
+    use alloy_provider::{DynProvider, Provider, ProviderBuilder, WsConnect};
+
+    use super::{source::FIXTURES_DIR, *};
 
-    use super::*;
+    const USDC: Address = address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
 
     async fn provider() -> DynProvider {
         dotenvy::dotenv().ok();
@@ -142,19 +453,139 @@ mod tests {
             .erased()
     }
 
+    fn client(source: Arc<dyn BlockSource>, tokens: Watchlist, batch: BatchPolicy) -> EthClient {
+        EthClient {
+            source,
+            status: watch::channel(ConnectionStatus::default()).1,
+            retry: RetryPolicy::default(),
+            batch,
+            balance_mode: BalanceMode::Rpc,
+            tokens,
+            metrics: Arc::default(),
+        }
+    }
+
     #[tokio::test]
     async fn test_get_block_data() {
-        let provider = Arc::new(provider().await);
+        let (_sender, provider) = watch::channel(provider().await);
+        let source = Arc::new(RpcSource::new(provider));
         let hash = b256!("0xe3d57e27e5300f22504990aec927d0cd055313adca707092ea34a557a0c501c7");
         let header = Header::<alloy::consensus::Header> {
             hash,
             ..Default::default()
         };
-        let info = get_block_info(Arc::clone(&provider), header)
+        let client = client(
+            source,
+            watch::channel(HashSet::new()).1,
+            BatchPolicy::default(),
+        );
+        let info = get_block_info(&client, header)
             .await
             .expect("Block info retrieval failed");
         assert_eq!(info.block.hash, hash);
         assert_eq!(info.logs.len(), 816);
         assert_eq!(info.transactions.len(), 311);
     }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let dir = env::temp_dir().join(format!("indexer-record-{}", std::process::id()));
+        let fixtures = Arc::new(ReplaySource::open(FIXTURES_DIR).await.unwrap());
+        let headers = fixtures.headers().to_vec();
+        let recording: Arc<dyn BlockSource> = Arc::new(RecordingSource::new(fixtures, &dir));
+        let (_watchlist, tokens) = watch::channel(HashSet::from([USDC]));
+        // Balances are recorded one lookup per call, and replayed in a single batch.
+        let batch = BatchPolicy {
+            batch_size: 1,
+            concurrency: 2,
+        };
+        let recorder = client(recording, tokens.clone(), batch);
+
+        let mut recorded = Vec::new();
+        for header in &headers {
+            let info = get_block_info(&recorder, header.clone()).await.unwrap();
+            recorded.push(info);
+        }
+
+        let (client, mut receiver) = replay(
+            dir.clone(),
+            RetryPolicy::default(),
+            tokens,
+            BalanceMode::Rpc,
+        )
+        .await
+        .unwrap();
+        let mut replayed = Vec::new();
+        while let Some(info) = receiver.recv().await {
+            replayed.push(info.unwrap());
+        }
+        // Balances are fetched concurrently, in no particular order.
+        for info in recorded.iter_mut().chain(replayed.iter_mut()) {
+            info.balances.sort_by_key(|b| (b.account, b.token));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(replayed, recorded);
+        assert_eq!(replayed.len(), headers.len());
+        assert_eq!(client.status().borrow().state, ConnectionState::Connected);
+
+        // Each balance is the one at its own block: B receives 1 ETH in block 100, then sends
+        // 0.5 ETH and pays for gas in block 101.
+        let b = address!("0x0376aac07ad725e01357b1725b5cec61ae10473c");
+        let native_balance_of_b = |info: &BlockSummary| {
+            let balance = info
+                .balances
+                .iter()
+                .find(|balance| balance.account == b.0 && balance.token == [0; 20])
+                .unwrap();
+            (balance.block_id, U256::from_be_bytes(balance.balance))
+        };
+        assert_eq!(
+            native_balance_of_b(&replayed[0]),
+            (100, U256::from(1_000_000_000_000_000_000u64))
+        );
+        assert_eq!(
+            native_balance_of_b(&replayed[1]),
+            (101, U256::from(499_958_000_000_000_000u64))
+        );
+        assert!(replayed.iter().all(|info| info.missing_balances.is_empty()));
+
+        // Block 101 takes its block, logs and receipts, then one call for its four balances.
+        let stats = client.metrics().stats();
+        assert_eq!(stats.blocks, 2);
+        assert_eq!(stats.last_block, Some(101));
+        assert_eq!(stats.last_block_calls, 4);
+        assert_eq!(recorder.metrics().stats().last_block_calls, 7);
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_balances_are_missing() {
+        let source = Arc::new(ReplaySource::open(FIXTURES_DIR).await.unwrap());
+        let header = source.headers()[0].clone();
+        // C's balance was only recorded at block 101.
+        let c = address!("0x00000000000000000000000000000000000c0c00");
+        let retry = RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        };
+
+        let (balances, missing) = get_balances(
+            source,
+            ParsedData {
+                block_id: header.number,
+                interactions: HashMap::from([(c, HashSet::from([Address::ZERO]))]),
+                deltas: HashMap::new(),
+            },
+            header.hash,
+            retry,
+            BatchPolicy::default(),
+        )
+        .await;
+
+        assert!(balances.is_empty());
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].account, c.0);
+        assert_eq!(missing[0].block_id, 100);
+        assert_eq!(missing[0].attempts, 1);
+    }
 }
@@ -1,64 +1,381 @@
 use std::collections::{HashMap, HashSet};
 
+use alloy::primitives::{Address, U256};
 use alloy_rpc_types_eth::Log;
 use alloy_sol_types::SolEvent;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use crate::eth_client::{
-    contracts::erc20::IERC20,
-    types::{KNOWN_TOKENS, ParsedData},
+use crate::{
+    eth_client::{
+        contracts::{erc20::IERC20, erc721::IERC721, erc1155::IERC1155, weth::IWETH},
+        types::{ParsedData, Transfers},
+    },
+    types::{Erc1155Transfer, NftTransfer, TokenTransfer},
 };
 
-#[tracing::instrument(skip(logs))]
-pub async fn parse_logs(logs: &Vec<Log>) -> ParsedData {
+/// How a log moves the ERC-20 balances of the token emitting it: the accounts involved, each with
+/// the amount it gains as a two's complement integer.
+struct BalanceChange {
+    token: Address,
+    deltas: Vec<(Address, U256)>,
+}
+
+/// Decodes `log` into `transfers` if it is an ERC-20, ERC-721 or ERC-1155 transfer. ERC-20
+/// transfers, and WETH deposits and withdrawals, are also returned as the balances they change,
+/// since they are the only ones whose balances are tracked.
+fn decode_transfer(log: &Log, block_id: u64, transfers: &mut Transfers) -> Option<BalanceChange> {
+    // Logs of pending blocks cannot be pointed at, so their transfers are not kept.
+    let location = log.transaction_hash.zip(log.log_index);
+    let block_number = log.block_number.unwrap_or(block_id);
+
+    match log.topic0() {
+        Some(&IERC1155::TransferSingle::SIGNATURE_HASH) => {
+            let event = IERC1155::TransferSingle::decode_log(&log.inner).ok()?;
+            let (transaction_hash, log_index) = location?;
+            transfers.erc1155.push(Erc1155Transfer {
+                contract: event.address.into(),
+                operator: event.operator.into(),
+                from: event.from.into(),
+                to: event.to.into(),
+                token_id: event.id.to_be_bytes(),
+                value: event.value.to_be_bytes(),
+                transaction_hash: transaction_hash.into(),
+                log_index,
+                batch_index: 0,
+                block_number,
+            });
+            None
+        }
+        Some(&IERC1155::TransferBatch::SIGNATURE_HASH) => {
+            let event = IERC1155::TransferBatch::decode_log(&log.inner).ok()?;
+            let (transaction_hash, log_index) = location?;
+            if event.ids.len() != event.values.len() {
+                return None;
+            }
+            let moved = event.ids.iter().zip(&event.values).enumerate();
+            transfers
+                .erc1155
+                .extend(moved.map(|(batch_index, (id, value))| Erc1155Transfer {
+                    contract: event.address.into(),
+                    operator: event.operator.into(),
+                    from: event.from.into(),
+                    to: event.to.into(),
+                    token_id: id.to_be_bytes(),
+                    value: value.to_be_bytes(),
+                    transaction_hash: transaction_hash.into(),
+                    log_index,
+                    batch_index: batch_index as u64,
+                    block_number,
+                }));
+            None
+        }
+        // ERC-721 transfers share the ERC-20 signature, but also index the token id.
+        Some(&IERC721::Transfer::SIGNATURE_HASH) if log.topics().len() == 4 => {
+            let event = IERC721::Transfer::decode_log(&log.inner).ok()?;
+            let (transaction_hash, log_index) = location?;
+            transfers.nfts.push(NftTransfer {
+                collection: event.address.into(),
+                token_id: event.tokenId.to_be_bytes(),
+                from: event.from.into(),
+                to: event.to.into(),
+                transaction_hash: transaction_hash.into(),
+                log_index,
+                block_number,
+            });
+            None
+        }
+        // Wrapping ether mints WETH, and unwrapping it burns some, without a `Transfer`.
+        Some(&IWETH::Deposit::SIGNATURE_HASH) => {
+            let event = IWETH::Deposit::decode_log(&log.inner).ok()?;
+            Some(BalanceChange {
+                token: event.address,
+                deltas: vec![(event.dst, event.wad)],
+            })
+        }
+        Some(&IWETH::Withdrawal::SIGNATURE_HASH) => {
+            let event = IWETH::Withdrawal::decode_log(&log.inner).ok()?;
+            Some(BalanceChange {
+                token: event.address,
+                deltas: vec![(event.src, event.wad.wrapping_neg())],
+            })
+        }
+        _ => {
+            let event = IERC20::Transfer::decode_log(&log.inner).ok()?;
+            if let Some((transaction_hash, log_index)) = location {
+                transfers.tokens.push(TokenTransfer {
+                    token: event.address.into(),
+                    from: event.from.into(),
+                    to: event.to.into(),
+                    value: event.value.to_be_bytes(),
+                    transaction_hash: transaction_hash.into(),
+                    log_index,
+                    block_number,
+                });
+            }
+            Some(BalanceChange {
+                token: event.address,
+                deltas: vec![
+                    (event.from, event.value.wrapping_neg()),
+                    (event.to, event.value),
+                ],
+            })
+        }
+    }
+}
+
+/// Decodes the ERC-20, ERC-721 and ERC-1155 transfer events of a block's logs.
+/// Every transfer is returned, while only the accounts moving one of `tokens` are kept as
+/// interactions, for their balances to be fetched, along with how much their balances moved.
+#[tracing::instrument(skip(logs, tokens))]
+pub async fn parse_logs(logs: &Vec<Log>, tokens: &HashSet<Address>) -> (ParsedData, Transfers) {
     let block_id = logs
         .first()
         .and_then(|l| l.block_number)
         .unwrap_or_default();
-    let interactions = logs
+    let (interactions, deltas, mut transfers) = logs
         .into_par_iter()
         .fold(
-            HashMap::<_, HashSet<_>>::new,
+            || {
+                (
+                    HashMap::<_, HashSet<_>>::new(),
+                    HashMap::<_, U256>::new(),
+                    Transfers::default(),
+                )
+            },
             |mut acc, log| {
-                let interactions = &mut acc;
+                let (ref mut interactions, ref mut deltas, ref mut transfers) = acc;
 
-                let Ok(event) = IERC20::Transfer::decode_log(&log.inner) else {
+                let Some(change) = decode_transfer(log, block_id, transfers) else {
                     return acc;
                 };
 
-                // If the transfer is made with an unknown token, it's not tracked.
-                if !KNOWN_TOKENS.contains(&event.address) {
+                // If the transfer is made with a token that is not watched, it's not tracked.
+                if !tokens.contains(&change.token) {
                     return acc;
                 }
 
-                interactions
-                    .entry(event.from)
-                    .or_default()
-                    .insert(event.address);
-                interactions
-                    .entry(event.to)
-                    .or_default()
-                    .insert(event.address);
+                for (account, delta) in change.deltas {
+                    interactions
+                        .entry(account)
+                        .or_default()
+                        .insert(change.token);
+                    if account != Address::ZERO {
+                        let total = deltas.entry((account, change.token)).or_default();
+                        *total = total.wrapping_add(delta);
+                    }
+                }
 
                 acc
             },
         )
         .reduce(
-            HashMap::new,
+            || (HashMap::new(), HashMap::new(), Transfers::default()),
             |mut total, partial| {
-                let t_interactions = &mut total;
-                let p_interactions = partial;
+                let (ref mut t_interactions, ref mut t_deltas, ref mut t_transfers) = total;
+                let (p_interactions, p_deltas, p_transfers) = partial;
 
                 for (account, contracts) in p_interactions {
                     t_interactions.entry(account).or_default().extend(contracts);
                 }
+                for (key, delta) in p_deltas {
+                    let total = t_deltas.entry(key).or_default();
+                    *total = total.wrapping_add(delta);
+                }
+                t_transfers.extend(p_transfers);
 
                 total
             },
         );
+    transfers.tokens.sort_by_key(|transfer| transfer.log_index);
+    transfers.nfts.sort_by_key(|transfer| transfer.log_index);
+    transfers
+        .erc1155
+        .sort_by_key(|transfer| (transfer.log_index, transfer.batch_index));
+
+    (
+        ParsedData {
+            block_id,
+            interactions,
+            deltas,
+        },
+        transfers,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{Address, B256, LogData, U256};
+
+    use super::*;
+
+    fn log(address: Address, data: LogData, log_index: u64) -> Log {
+        Log {
+            inner: alloy::primitives::Log { address, data },
+            block_number: Some(7),
+            transaction_hash: Some(B256::repeat_byte(1)),
+            log_index: Some(log_index),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_logs_splits_erc20_and_erc721() {
+        let (from, to) = (Address::repeat_byte(2), Address::repeat_byte(3));
+        let (token, collection) = (Address::repeat_byte(6), Address::repeat_byte(4));
+        let logs = vec![
+            log(
+                token,
+                IERC20::Transfer {
+                    from,
+                    to,
+                    value: U256::from(500),
+                }
+                .encode_log_data(),
+                0,
+            ),
+            log(
+                collection,
+                IERC721::Transfer {
+                    from,
+                    to,
+                    tokenId: U256::from(42),
+                }
+                .encode_log_data(),
+                1,
+            ),
+        ];
+
+        let (parsed, transfers) = parse_logs(&logs, &HashSet::from([token])).await;
+
+        assert_eq!(transfers.tokens.len(), 1);
+        assert_eq!(transfers.tokens[0].value, U256::from(500).to_be_bytes());
+        assert_eq!(transfers.nfts.len(), 1);
+        assert_eq!(transfers.nfts[0].collection, <[u8; 20]>::from(collection));
+        assert_eq!(transfers.nfts[0].token_id, U256::from(42).to_be_bytes());
+        assert_eq!(transfers.nfts[0].block_number, 7);
+        assert!(parsed.interactions[&from].contains(&token));
+        // NFTs have no balance to look up.
+        assert!(!parsed.interactions[&from].contains(&collection));
+
+        // Transfers of tokens that are not watched are kept, without their balances.
+        let (parsed, transfers) = parse_logs(&logs, &HashSet::new()).await;
+        assert_eq!(transfers.tokens.len(), 1);
+        assert!(parsed.interactions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_parse_logs_sums_balance_deltas() {
+        let (from, to) = (Address::repeat_byte(2), Address::repeat_byte(3));
+        let (weth, other) = (Address::repeat_byte(6), Address::repeat_byte(7));
+        let logs = vec![
+            log(
+                weth,
+                IWETH::Deposit {
+                    dst: from,
+                    wad: U256::from(1000),
+                }
+                .encode_log_data(),
+                0,
+            ),
+            log(
+                weth,
+                IERC20::Transfer {
+                    from,
+                    to,
+                    value: U256::from(400),
+                }
+                .encode_log_data(),
+                1,
+            ),
+            log(
+                weth,
+                IWETH::Withdrawal {
+                    src: to,
+                    wad: U256::from(100),
+                }
+                .encode_log_data(),
+                2,
+            ),
+            log(
+                other,
+                IERC20::Transfer {
+                    from: Address::ZERO,
+                    to,
+                    value: U256::from(5),
+                }
+                .encode_log_data(),
+                3,
+            ),
+        ];
+
+        let (parsed, transfers) = parse_logs(&logs, &HashSet::from([weth])).await;
+
+        // Deposits and withdrawals are not transfers, but move balances all the same.
+        assert_eq!(transfers.tokens.len(), 2);
+        assert_eq!(parsed.deltas.len(), 2);
+        assert_eq!(parsed.deltas[&(from, weth)], U256::from(600));
+        assert_eq!(parsed.deltas[&(to, weth)], U256::from(300));
+        assert!(parsed.interactions[&to].contains(&weth));
+        assert!(!parsed.interactions[&to].contains(&other));
+    }
+
+    #[tokio::test]
+    async fn test_parse_logs_expands_erc1155_batches() {
+        let (operator, from, to) = (
+            Address::repeat_byte(1),
+            Address::ZERO,
+            Address::repeat_byte(3),
+        );
+        let contract = Address::repeat_byte(5);
+        let logs = vec![
+            log(
+                contract,
+                IERC1155::TransferSingle {
+                    operator,
+                    from,
+                    to,
+                    id: U256::from(1),
+                    value: U256::from(10),
+                }
+                .encode_log_data(),
+                0,
+            ),
+            log(
+                contract,
+                IERC1155::TransferBatch {
+                    operator,
+                    from,
+                    to,
+                    ids: vec![U256::from(2), U256::from(3)],
+                    values: vec![U256::from(20), U256::from(30)],
+                }
+                .encode_log_data(),
+                1,
+            ),
+        ];
 
-    ParsedData {
-        block_id,
-        interactions,
+        let (_, transfers) = parse_logs(&logs, &HashSet::new()).await;
+
+        assert!(transfers.tokens.is_empty());
+        let moved: Vec<_> = transfers
+            .erc1155
+            .iter()
+            .map(|t| {
+                (
+                    t.log_index,
+                    t.batch_index,
+                    U256::from_be_bytes(t.token_id),
+                    U256::from_be_bytes(t.value),
+                )
+            })
+            .collect();
+        assert_eq!(
+            moved,
+            vec![
+                (0, 0, U256::from(1), U256::from(10)),
+                (1, 0, U256::from(2), U256::from(20)),
+                (1, 1, U256::from(3), U256::from(30)),
+            ]
+        );
     }
 }
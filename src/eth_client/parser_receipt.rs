@@ -8,7 +8,7 @@ use crate::{eth_client::types::ParsedData, types::Receipt};
 
 /// Parses a vector of transaction receipts in parallel to extract key information.
 /// This functions gets all the Transfer events which is used for get token transactions.
-/// It does a summary of the receipt to store it in the DB.
+/// It does a summary of every receipt to store it in the DB.
 #[tracing::instrument(skip(receipts, transactions))]
 pub async fn parse_receipts(
     receipts: &Vec<TransactionReceipt>,
@@ -26,6 +26,8 @@ pub async fn parse_receipts(
             |mut acc, (tx, receipt)| {
                 let (ref mut receipts_summary, ref mut interactions) = acc;
 
+                receipts_summary.push(Receipt::from(receipt));
+
                 // Just native transfer will be tracked here
                 if tx.value() == U256::ZERO {
                     return acc;
@@ -41,11 +43,6 @@ pub async fn parse_receipts(
                         .insert(Address::ZERO);
                 }
 
-                receipts_summary.push(Receipt {
-                    transaction_hash: (*tx.inner.hash()).into(),
-                    gas_used: receipt.gas_used,
-                });
-
                 acc
             },
         )
@@ -68,6 +65,7 @@ pub async fn parse_receipts(
         ParsedData {
             block_id,
             interactions,
+            deltas: HashMap::new(),
         },
         receipts_summary,
     )
@@ -0,0 +1,104 @@
+use std::{future::Future, time::Duration};
+
+use thiserror::Error;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// How RPC calls made while fetching a block are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts per call, the first one included.
+    pub max_attempts: u32,
+    /// Wait before the second attempt. It doubles after each failure.
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Runs `call` until it succeeds or the attempts are exhausted, returning the last error.
+    pub async fn retry<T, E, F, Fut>(&self, name: &str, mut call: F) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Into<anyhow::Error>,
+    {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt >= self.max_attempts => {
+                    return Err(e
+                        .into()
+                        .context(format!("{name} failed after {attempt} attempts")));
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "{name} failed (attempt {attempt}), retrying in {backoff:?}: {}",
+                        e.into()
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// A block that could not be fetched, even after retrying.
+#[derive(Debug, Error)]
+#[error("Failed to fetch block {number}: {source:#}")]
+pub struct BlockFetchError {
+    pub number: u64,
+    pub hash: [u8; 32],
+    #[source]
+    pub source: anyhow::Error,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_retry_until_success() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+        };
+        let calls = AtomicU32::new(0);
+
+        let result = policy
+            .retry("call", || async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err(anyhow::anyhow!("unavailable")),
+                    _ => Ok(42),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let result = policy
+            .retry("call", || async {
+                Err::<(), _>(anyhow::anyhow!("unavailable"))
+            })
+            .await;
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("failed after 3 attempts")
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use alloy::primitives::{Address, B256, Bytes, U256};
+use alloy_rpc_types_eth::{Block, Header, Log, TransactionReceipt};
+
+use crate::{
+    eth_client::source::{BlockSource, SourceFuture},
+    types::Finality,
+};
+
+/// Forwards every call to another source, counting them.
+pub struct CountingSource {
+    inner: Arc<dyn BlockSource>,
+    calls: AtomicU64,
+}
+
+impl CountingSource {
+    pub fn new(inner: Arc<dyn BlockSource>) -> Self {
+        CountingSource {
+            inner,
+            calls: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of calls forwarded so far, retries included.
+    pub fn calls(&self) -> u64 {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    fn count(&self) -> &dyn BlockSource {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.inner.as_ref()
+    }
+}
+
+impl BlockSource for CountingSource {
+    fn block_number(&self) -> SourceFuture<'_, u64> {
+        self.count().block_number()
+    }
+
+    fn header_by_number(&self, number: u64) -> SourceFuture<'_, Option<Header>> {
+        self.count().header_by_number(number)
+    }
+
+    fn header_by_hash(&self, hash: B256) -> SourceFuture<'_, Option<Header>> {
+        self.count().header_by_hash(hash)
+    }
+
+    fn header_by_tag(&self, finality: Finality) -> SourceFuture<'_, Option<Header>> {
+        self.count().header_by_tag(finality)
+    }
+
+    fn block_by_hash(&self, hash: B256) -> SourceFuture<'_, Option<Block>> {
+        self.count().block_by_hash(hash)
+    }
+
+    fn logs(&self, block_hash: B256) -> SourceFuture<'_, Vec<Log>> {
+        self.count().logs(block_hash)
+    }
+
+    fn block_receipts(
+        &self,
+        block_hash: B256,
+    ) -> SourceFuture<'_, Option<Vec<TransactionReceipt>>> {
+        self.count().block_receipts(block_hash)
+    }
+
+    fn balance(&self, account: Address, block_hash: B256) -> SourceFuture<'_, U256> {
+        self.count().balance(account, block_hash)
+    }
+
+    fn token_balance(
+        &self,
+        token: Address,
+        account: Address,
+        block_hash: B256,
+    ) -> SourceFuture<'_, U256> {
+        self.count().token_balance(token, account, block_hash)
+    }
+
+    fn balances(
+        &self,
+        requests: Vec<(Address, Address)>,
+        block_hash: B256,
+    ) -> SourceFuture<'_, Vec<anyhow::Result<U256>>> {
+        self.count().balances(requests, block_hash)
+    }
+
+    fn call(&self, to: Address, data: Bytes, block_hash: B256) -> SourceFuture<'_, Bytes> {
+        self.count().call(to, data, block_hash)
+    }
+}
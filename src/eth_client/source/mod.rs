@@ -0,0 +1,110 @@
+mod count;
+mod record;
+mod replay;
+mod rpc;
+
+pub use count::CountingSource;
+pub use record::RecordingSource;
+pub use replay::ReplaySource;
+pub use rpc::RpcSource;
+
+use alloy::primitives::{Address, B256, Bytes, U256};
+use alloy_rpc_types_eth::{Block, Header, Log, TransactionReceipt};
+use futures::{StreamExt, future::BoxFuture, stream};
+
+use crate::types::Finality;
+
+/// Blocks recorded for the offline tests.
+#[cfg(test)]
+pub const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/replay");
+
+/// Calls in flight at once when balances are looked up one by one, per batch of lookups.
+const ONE_BY_ONE_CONCURRENCY: usize = 16;
+
+pub type SourceFuture<'a, T> = BoxFuture<'a, anyhow::Result<T>>;
+
+/// Where the data of a block comes from.
+///
+/// Each method matches one RPC call made while processing a block, so a source can be backed by a
+/// live node, or by responses previously recorded from one.
+pub trait BlockSource: Send + Sync {
+    /// Number of the most recent block available.
+    fn block_number(&self) -> SourceFuture<'_, u64>;
+
+    fn header_by_number(&self, number: u64) -> SourceFuture<'_, Option<Header>>;
+
+    fn header_by_hash(&self, hash: B256) -> SourceFuture<'_, Option<Header>>;
+
+    /// Header of the block the chain currently tags as `finality`, if it has one yet.
+    fn header_by_tag(&self, finality: Finality) -> SourceFuture<'_, Option<Header>>;
+
+    /// The block with the given hash, including its full transactions.
+    fn block_by_hash(&self, hash: B256) -> SourceFuture<'_, Option<Block>>;
+
+    /// Every log emitted in the block with the given hash.
+    fn logs(&self, block_hash: B256) -> SourceFuture<'_, Vec<Log>>;
+
+    fn block_receipts(&self, block_hash: B256)
+    -> SourceFuture<'_, Option<Vec<TransactionReceipt>>>;
+
+    /// Native balance of `account` as of the block with the given hash.
+    fn balance(&self, account: Address, block_hash: B256) -> SourceFuture<'_, U256>;
+
+    /// ERC-20 balance of `account` in `token`, as of the block with the given hash.
+    fn token_balance(
+        &self,
+        token: Address,
+        account: Address,
+        block_hash: B256,
+    ) -> SourceFuture<'_, U256>;
+
+    /// Balances of `(account, token)` pairs as of the block with the given hash, the zero token
+    /// standing for the native balance. Each lookup can fail on its own.
+    ///
+    /// Sources able to answer them all in one call should; this one makes a call per pair.
+    fn balances(
+        &self,
+        requests: Vec<(Address, Address)>,
+        block_hash: B256,
+    ) -> SourceFuture<'_, Vec<anyhow::Result<U256>>> {
+        Box::pin(async move { Ok(balances_one_by_one(self, requests, block_hash).await) })
+    }
+
+    /// Result of calling the contract at `to` with `data`, as of the block with the given hash.
+    fn call(&self, to: Address, data: Bytes, block_hash: B256) -> SourceFuture<'_, Bytes>;
+}
+
+/// Looks each balance up with a call of its own, through `balance` or `token_balance`, at most
+/// [`ONE_BY_ONE_CONCURRENCY`] at a time. Results are in the order of `requests`.
+async fn balances_one_by_one<S: BlockSource + ?Sized>(
+    source: &S,
+    requests: Vec<(Address, Address)>,
+    block_hash: B256,
+) -> Vec<anyhow::Result<U256>> {
+    let lookups = requests
+        .into_iter()
+        .enumerate()
+        .map(|(index, (account, token))| async move {
+            let balance = if token == Address::ZERO {
+                source.balance(account, block_hash).await
+            } else {
+                source.token_balance(token, account, block_hash).await
+            };
+            (index, balance)
+        });
+    let mut balances: Vec<_> = stream::iter(lookups)
+        .buffer_unordered(ONE_BY_ONE_CONCURRENCY)
+        .collect()
+        .await;
+    balances.sort_by_key(|(index, _)| *index);
+    balances.into_iter().map(|(_, balance)| balance).collect()
+}
+
+/// Name of the file holding a recorded response, relative to the recording directory.
+fn fixture_path(method: &str, key: &str) -> String {
+    format!("{method}/{key}.json")
+}
+
+fn hex_key(bytes: impl AsRef<[u8]>) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
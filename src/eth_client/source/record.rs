@@ -0,0 +1,184 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use alloy::primitives::{Address, B256, Bytes, U256};
+use alloy_rpc_types_eth::{Block, Header, Log, TransactionReceipt};
+use serde::Serialize;
+
+use crate::{
+    eth_client::source::{BlockSource, SourceFuture, fixture_path, hex_key},
+    types::Finality,
+};
+
+/// Forwards every call to another source and saves each response as a JSON file, in the layout
+/// [`ReplaySource`](super::ReplaySource) reads.
+pub struct RecordingSource {
+    inner: Arc<dyn BlockSource>,
+    dir: PathBuf,
+}
+
+impl RecordingSource {
+    pub fn new(inner: Arc<dyn BlockSource>, dir: impl Into<PathBuf>) -> Self {
+        RecordingSource {
+            inner,
+            dir: dir.into(),
+        }
+    }
+
+    /// Saves `response` under `method/key.json`. Failing to save only loses the recording, so
+    /// it is logged rather than returned.
+    async fn record<T: Serialize>(&self, method: &str, key: &str, response: &T) {
+        let path = self.dir.join(fixture_path(method, key));
+        if let Err(e) = write_json(&path, response).await {
+            tracing::warn!("Failed to record {}: {e}", path.display());
+        }
+    }
+}
+
+async fn write_json<T: Serialize>(path: &Path, value: &T) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, serde_json::to_vec_pretty(value)?).await?;
+    Ok(())
+}
+
+impl BlockSource for RecordingSource {
+    fn block_number(&self) -> SourceFuture<'_, u64> {
+        // Replays use the highest recorded block instead.
+        self.inner.block_number()
+    }
+
+    fn header_by_number(&self, number: u64) -> SourceFuture<'_, Option<Header>> {
+        Box::pin(async move {
+            let header = self.inner.header_by_number(number).await?;
+            if let Some(header) = &header {
+                self.record("header_by_number", &number.to_string(), header)
+                    .await;
+            }
+            Ok(header)
+        })
+    }
+
+    fn header_by_hash(&self, hash: B256) -> SourceFuture<'_, Option<Header>> {
+        Box::pin(async move {
+            let header = self.inner.header_by_hash(hash).await?;
+            if let Some(header) = &header {
+                self.record("header_by_hash", &hex_key(hash), header).await;
+            }
+            Ok(header)
+        })
+    }
+
+    fn header_by_tag(&self, finality: Finality) -> SourceFuture<'_, Option<Header>> {
+        Box::pin(async move {
+            let header = self.inner.header_by_tag(finality).await?;
+            if let Some(header) = &header {
+                self.record("header_by_tag", finality.as_str(), header)
+                    .await;
+            }
+            Ok(header)
+        })
+    }
+
+    fn block_by_hash(&self, hash: B256) -> SourceFuture<'_, Option<Block>> {
+        Box::pin(async move {
+            let block = self.inner.block_by_hash(hash).await?;
+            if let Some(block) = &block {
+                self.record("block_by_hash", &hex_key(hash), block).await;
+            }
+            Ok(block)
+        })
+    }
+
+    fn logs(&self, block_hash: B256) -> SourceFuture<'_, Vec<Log>> {
+        Box::pin(async move {
+            let logs = self.inner.logs(block_hash).await?;
+            self.record("logs", &hex_key(block_hash), &logs).await;
+            Ok(logs)
+        })
+    }
+
+    fn block_receipts(
+        &self,
+        block_hash: B256,
+    ) -> SourceFuture<'_, Option<Vec<TransactionReceipt>>> {
+        Box::pin(async move {
+            let receipts = self.inner.block_receipts(block_hash).await?;
+            if let Some(receipts) = &receipts {
+                self.record("block_receipts", &hex_key(block_hash), receipts)
+                    .await;
+            }
+            Ok(receipts)
+        })
+    }
+
+    fn balance(&self, account: Address, block_hash: B256) -> SourceFuture<'_, U256> {
+        Box::pin(async move {
+            let balance = self.inner.balance(account, block_hash).await?;
+            let key = format!("{}-{}", hex_key(block_hash), hex_key(account));
+            self.record("balance", &key, &balance).await;
+            Ok(balance)
+        })
+    }
+
+    fn token_balance(
+        &self,
+        token: Address,
+        account: Address,
+        block_hash: B256,
+    ) -> SourceFuture<'_, U256> {
+        Box::pin(async move {
+            let balance = self.inner.token_balance(token, account, block_hash).await?;
+            let key = format!(
+                "{}-{}-{}",
+                hex_key(block_hash),
+                hex_key(token),
+                hex_key(account)
+            );
+            self.record("token_balance", &key, &balance).await;
+            Ok(balance)
+        })
+    }
+
+    fn balances(
+        &self,
+        requests: Vec<(Address, Address)>,
+        block_hash: B256,
+    ) -> SourceFuture<'_, Vec<anyhow::Result<U256>>> {
+        Box::pin(async move {
+            let balances = self.inner.balances(requests.clone(), block_hash).await?;
+            // Each balance is saved on its own, so a replay does not depend on how they were
+            // grouped.
+            for ((account, token), balance) in requests.iter().zip(&balances) {
+                let Ok(balance) = balance else {
+                    continue;
+                };
+                if *token == Address::ZERO {
+                    let key = format!("{}-{}", hex_key(block_hash), hex_key(account));
+                    self.record("balance", &key, balance).await;
+                } else {
+                    let key = format!(
+                        "{}-{}-{}",
+                        hex_key(block_hash),
+                        hex_key(token),
+                        hex_key(account)
+                    );
+                    self.record("token_balance", &key, balance).await;
+                }
+            }
+            Ok(balances)
+        })
+    }
+
+    fn call(&self, to: Address, data: Bytes, block_hash: B256) -> SourceFuture<'_, Bytes> {
+        Box::pin(async move {
+            let key = format!("{}-{}-{}", hex_key(block_hash), hex_key(to), hex_key(&data));
+            let result = self.inner.call(to, data, block_hash).await?;
+            self.record("call", &key, &result).await;
+            Ok(result)
+        })
+    }
+}
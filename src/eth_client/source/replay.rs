@@ -0,0 +1,168 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use alloy::primitives::{Address, B256, Bytes, U256};
+use alloy_rpc_types_eth::{Block, Header, Log, TransactionReceipt};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    eth_client::source::{BlockSource, SourceFuture, fixture_path, hex_key},
+    types::Finality,
+};
+
+/// Answers calls with the responses saved by a [`RecordingSource`](super::RecordingSource).
+///
+/// Calls that were never recorded fail, so a replay never reaches the network.
+pub struct ReplaySource {
+    dir: PathBuf,
+    /// Headers of the recorded blocks, ordered by number then timestamp.
+    headers: Vec<Header>,
+    /// Canonical hash at each height: the last recorded block of that height.
+    canonical: BTreeMap<u64, B256>,
+}
+
+impl ReplaySource {
+    /// Loads the index of the blocks recorded in `dir`.
+    pub async fn open(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        let mut headers = Vec::new();
+        let mut entries = tokio::fs::read_dir(dir.join("block_by_hash"))
+            .await
+            .map_err(|e| anyhow::anyhow!("No recorded blocks in {}: {e}", dir.display()))?;
+        while let Some(entry) = entries.next_entry().await? {
+            let block: Block = read_json(&entry.path()).await?;
+            headers.push(block.header);
+        }
+        headers.sort_by_key(|header| (header.number, header.timestamp));
+
+        let canonical = headers
+            .iter()
+            .map(|header| (header.number, header.hash))
+            .collect();
+        Ok(ReplaySource {
+            dir,
+            headers,
+            canonical,
+        })
+    }
+
+    /// Headers of every recorded block, in the order they are replayed.
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    async fn load<T: DeserializeOwned>(&self, method: &str, key: &str) -> anyhow::Result<T> {
+        read_json(&self.dir.join(fixture_path(method, key))).await
+    }
+
+    /// Like `load`, but a response that was not recorded is `None`.
+    async fn load_optional<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        key: &str,
+    ) -> anyhow::Result<Option<T>> {
+        let path = self.dir.join(fixture_path(method, key));
+        if !tokio::fs::try_exists(&path).await? {
+            return Ok(None);
+        }
+        read_json(&path).await.map(Some)
+    }
+}
+
+async fn read_json<T: DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let content = tokio::fs::read(path)
+        .await
+        .map_err(|e| anyhow::anyhow!("No recorded response at {}: {e}", path.display()))?;
+    Ok(serde_json::from_slice(&content)?)
+}
+
+impl BlockSource for ReplaySource {
+    fn block_number(&self) -> SourceFuture<'_, u64> {
+        Box::pin(async move {
+            self.canonical
+                .last_key_value()
+                .map(|(number, _)| *number)
+                .ok_or_else(|| anyhow::anyhow!("No recorded blocks"))
+        })
+    }
+
+    fn header_by_number(&self, number: u64) -> SourceFuture<'_, Option<Header>> {
+        Box::pin(async move {
+            if let Some(header) = self
+                .load_optional("header_by_number", &number.to_string())
+                .await?
+            {
+                return Ok(Some(header));
+            }
+            match self.canonical.get(&number) {
+                Some(hash) => self.header_by_hash(*hash).await,
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn header_by_hash(&self, hash: B256) -> SourceFuture<'_, Option<Header>> {
+        Box::pin(async move {
+            if let Some(header) = self.load_optional("header_by_hash", &hex_key(hash)).await? {
+                return Ok(Some(header));
+            }
+            let block = self.block_by_hash(hash).await?;
+            Ok(block.map(|block| block.header))
+        })
+    }
+
+    fn header_by_tag(&self, finality: Finality) -> SourceFuture<'_, Option<Header>> {
+        Box::pin(async move { self.load_optional("header_by_tag", finality.as_str()).await })
+    }
+
+    fn block_by_hash(&self, hash: B256) -> SourceFuture<'_, Option<Block>> {
+        Box::pin(async move { self.load_optional("block_by_hash", &hex_key(hash)).await })
+    }
+
+    fn logs(&self, block_hash: B256) -> SourceFuture<'_, Vec<Log>> {
+        Box::pin(async move { self.load("logs", &hex_key(block_hash)).await })
+    }
+
+    fn block_receipts(
+        &self,
+        block_hash: B256,
+    ) -> SourceFuture<'_, Option<Vec<TransactionReceipt>>> {
+        Box::pin(async move {
+            self.load_optional("block_receipts", &hex_key(block_hash))
+                .await
+        })
+    }
+
+    fn balance(&self, account: Address, block_hash: B256) -> SourceFuture<'_, U256> {
+        Box::pin(async move {
+            let key = format!("{}-{}", hex_key(block_hash), hex_key(account));
+            self.load("balance", &key).await
+        })
+    }
+
+    fn token_balance(
+        &self,
+        token: Address,
+        account: Address,
+        block_hash: B256,
+    ) -> SourceFuture<'_, U256> {
+        Box::pin(async move {
+            let key = format!(
+                "{}-{}-{}",
+                hex_key(block_hash),
+                hex_key(token),
+                hex_key(account)
+            );
+            self.load("token_balance", &key).await
+        })
+    }
+
+    fn call(&self, to: Address, data: Bytes, block_hash: B256) -> SourceFuture<'_, Bytes> {
+        Box::pin(async move {
+            let key = format!("{}-{}-{}", hex_key(block_hash), hex_key(to), hex_key(&data));
+            self.load("call", &key).await
+        })
+    }
+}
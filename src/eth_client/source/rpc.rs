@@ -0,0 +1,265 @@
+use std::sync::Arc;
+
+use alloy::primitives::{Address, B256, Bytes, U256};
+use alloy_provider::{DynProvider, Provider};
+use alloy_rpc_types_eth::{
+    Block, BlockId, BlockNumberOrTag, Filter, Header, Log, TransactionReceipt, TransactionRequest,
+};
+use alloy_sol_types::{SolCall, SolValue};
+use tokio::sync::watch;
+
+use crate::{
+    eth_client::{
+        contracts::{
+            erc20::IERC20,
+            multicall3::{IMulticall3, MULTICALL3},
+        },
+        source::{BlockSource, SourceFuture, balances_one_by_one},
+    },
+    types::Finality,
+};
+
+/// Reads blocks from a node, through the provider the connection supervisor currently uses.
+pub struct RpcSource {
+    provider: watch::Receiver<DynProvider>,
+}
+
+impl RpcSource {
+    pub fn new(provider: watch::Receiver<DynProvider>) -> Self {
+        RpcSource { provider }
+    }
+
+    fn provider(&self) -> DynProvider {
+        self.provider.borrow().clone()
+    }
+
+    /// Looks the balances up through Multicall3's `aggregate3`, or returns `None` if the node
+    /// answered that the call cannot be made at that block.
+    async fn multicall_balances(
+        &self,
+        requests: &[(Address, Address)],
+        block_hash: B256,
+    ) -> anyhow::Result<Option<Vec<anyhow::Result<U256>>>> {
+        let calls = requests
+            .iter()
+            .map(|(account, token)| {
+                let (target, call_data) = if *token == Address::ZERO {
+                    let call = IMulticall3::getEthBalanceCall { addr: *account };
+                    (MULTICALL3, call.abi_encode())
+                } else {
+                    let call = IERC20::balanceOfCall { account: *account };
+                    (*token, call.abi_encode())
+                };
+                IMulticall3::Call3 {
+                    target,
+                    allowFailure: true,
+                    callData: call_data.into(),
+                }
+            })
+            .collect();
+
+        let multicall = IMulticall3::new(MULTICALL3, Arc::new(self.provider()));
+        let results = match multicall
+            .aggregate3(calls)
+            .block(BlockId::hash(block_hash))
+            .call()
+            .await
+        {
+            Ok(results) => results,
+            Err(e) if multicall_unavailable(&e) => {
+                tracing::debug!("Multicall3 unavailable, balances looked up one by one: {e}");
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        anyhow::ensure!(
+            results.len() == requests.len(),
+            "Multicall3 returned {} results for {} calls",
+            results.len(),
+            requests.len()
+        );
+
+        Ok(Some(
+            results
+                .into_iter()
+                .map(|result| {
+                    anyhow::ensure!(result.success, "Balance lookup reverted");
+                    Ok(U256::abi_decode(&result.returnData)?)
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Whether the node answered a call to Multicall3, rather than failing to be reached: the call
+/// reverted, or returned nothing because the contract is not deployed at that block.
+fn multicall_unavailable(error: &alloy_contract::Error) -> bool {
+    match error {
+        alloy_contract::Error::ZeroData(..) => true,
+        alloy_contract::Error::TransportError(e) => e
+            .as_error_resp()
+            .is_some_and(|resp| resp.as_revert_data().is_some() || resp.message.contains("revert")),
+        _ => false,
+    }
+}
+
+impl BlockSource for RpcSource {
+    fn block_number(&self) -> SourceFuture<'_, u64> {
+        Box::pin(async move { Ok(self.provider().get_block_number().await?) })
+    }
+
+    fn header_by_number(&self, number: u64) -> SourceFuture<'_, Option<Header>> {
+        Box::pin(async move {
+            let block = self
+                .provider()
+                .get_block_by_number(BlockNumberOrTag::Number(number))
+                .await?;
+            Ok(block.map(|block| block.header))
+        })
+    }
+
+    fn header_by_hash(&self, hash: B256) -> SourceFuture<'_, Option<Header>> {
+        Box::pin(async move {
+            let block = self.provider().get_block_by_hash(hash).await?;
+            Ok(block.map(|block| block.header))
+        })
+    }
+
+    fn header_by_tag(&self, finality: Finality) -> SourceFuture<'_, Option<Header>> {
+        let tag = match finality {
+            Finality::Latest => BlockNumberOrTag::Latest,
+            Finality::Safe => BlockNumberOrTag::Safe,
+            Finality::Finalized => BlockNumberOrTag::Finalized,
+        };
+        Box::pin(async move {
+            let block = self.provider().get_block_by_number(tag).await?;
+            Ok(block.map(|block| block.header))
+        })
+    }
+
+    fn block_by_hash(&self, hash: B256) -> SourceFuture<'_, Option<Block>> {
+        Box::pin(async move { Ok(self.provider().get_block_by_hash(hash).full().await?) })
+    }
+
+    fn logs(&self, block_hash: B256) -> SourceFuture<'_, Vec<Log>> {
+        Box::pin(async move {
+            let filter = Filter::new().at_block_hash(block_hash);
+            Ok(self.provider().get_logs(&filter).await?)
+        })
+    }
+
+    fn block_receipts(
+        &self,
+        block_hash: B256,
+    ) -> SourceFuture<'_, Option<Vec<TransactionReceipt>>> {
+        Box::pin(async move {
+            Ok(self
+                .provider()
+                .get_block_receipts(BlockId::hash(block_hash))
+                .await?)
+        })
+    }
+
+    fn balance(&self, account: Address, block_hash: B256) -> SourceFuture<'_, U256> {
+        Box::pin(async move {
+            Ok(self
+                .provider()
+                .get_balance(account)
+                .block_id(BlockId::hash(block_hash))
+                .await?)
+        })
+    }
+
+    fn token_balance(
+        &self,
+        token: Address,
+        account: Address,
+        block_hash: B256,
+    ) -> SourceFuture<'_, U256> {
+        Box::pin(async move {
+            let contract = IERC20::new(token, Arc::new(self.provider()));
+            Ok(contract
+                .balanceOf(account)
+                .block(BlockId::hash(block_hash))
+                .call()
+                .await?)
+        })
+    }
+
+    /// Looks every balance up in a single call to Multicall3, native balances through its
+    /// `getEthBalance`. When Multicall3 cannot serve them, because the whole call reverts or the
+    /// contract is not deployed yet at that block (14,353,601 on mainnet), each balance is looked
+    /// up with a call of its own.
+    fn balances(
+        &self,
+        requests: Vec<(Address, Address)>,
+        block_hash: B256,
+    ) -> SourceFuture<'_, Vec<anyhow::Result<U256>>> {
+        Box::pin(async move {
+            match self.multicall_balances(&requests, block_hash).await? {
+                Some(balances) => Ok(balances),
+                None => Ok(balances_one_by_one(self, requests, block_hash).await),
+            }
+        })
+    }
+
+    fn call(&self, to: Address, data: Bytes, block_hash: B256) -> SourceFuture<'_, Bytes> {
+        Box::pin(async move {
+            let request = TransactionRequest::default().to(to).input(data.into());
+            Ok(self
+                .provider()
+                .call(request)
+                .block(BlockId::hash(block_hash))
+                .await?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_provider::ProviderBuilder;
+    use axum::{Json, Router, routing::post};
+
+    use super::*;
+
+    /// Serves a node on which Multicall3 is not deployed, every token balance being 7 and every
+    /// native balance 5.
+    async fn serve_node_without_multicall() -> String {
+        let app = Router::new().route(
+            "/",
+            post(|Json(request): Json<serde_json::Value>| async move {
+                let result = match request["method"].as_str() {
+                    Some("eth_getBalance") => "0x5".to_string(),
+                    Some("eth_call")
+                        if request["params"][0]["to"] == MULTICALL3.to_string().to_lowercase() =>
+                    {
+                        "0x".to_string()
+                    }
+                    _ => format!("0x{}", hex::encode(U256::from(7).to_be_bytes::<32>())),
+                };
+                Json(serde_json::json!({"jsonrpc": "2.0", "id": request["id"], "result": result}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{address}")
+    }
+
+    #[tokio::test]
+    async fn test_balances_without_multicall() {
+        let url = serve_node_without_multicall().await;
+        let provider = ProviderBuilder::new()
+            .connect_http(url.parse().unwrap())
+            .erased();
+        let source = RpcSource::new(watch::channel(provider).1);
+
+        let requests = vec![
+            (Address::repeat_byte(1), Address::ZERO),
+            (Address::repeat_byte(1), Address::repeat_byte(2)),
+        ];
+        let balances = source.balances(requests, B256::ZERO).await.unwrap();
+        let balances: Vec<_> = balances.into_iter().map(Result::unwrap).collect();
+        assert_eq!(balances, vec![U256::from(5), U256::from(7)]);
+    }
+}
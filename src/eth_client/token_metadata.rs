@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use alloy::primitives::{Address, B256, U256};
+use alloy_sol_types::SolCall;
+
+use crate::{
+    eth_client::{contracts::erc20::IERC20, source::BlockSource},
+    types::TokenMetadata,
+};
+
+/// Calls `name`, `symbol` and `decimals` on `token`, as of the block with the given hash.
+///
+/// A call that fails or returns something that cannot be decoded leaves its field empty, since
+/// the three functions are optional in the ERC-20 standard. It is an error only if every call
+/// failed, which is more likely a problem with the node than with the token.
+#[tracing::instrument(skip(source))]
+pub async fn fetch_metadata(
+    source: Arc<dyn BlockSource>,
+    token: Address,
+    block_hash: B256,
+) -> anyhow::Result<TokenMetadata> {
+    let call = |data: Vec<u8>| source.call(token, data.into(), block_hash);
+    let (name, symbol, decimals) = tokio::join!(
+        call(IERC20::nameCall {}.abi_encode()),
+        call(IERC20::symbolCall {}.abi_encode()),
+        call(IERC20::decimalsCall {}.abi_encode()),
+    );
+    if let (Err(e), Err(_), Err(_)) = (&name, &symbol, &decimals) {
+        anyhow::bail!("Metadata of token {token} unavailable: {e}");
+    }
+
+    Ok(TokenMetadata {
+        address: token.into(),
+        name: name.ok().and_then(|data| decode_text(&data)),
+        symbol: symbol.ok().and_then(|data| decode_text(&data)),
+        decimals: decimals
+            .ok()
+            .and_then(|data| IERC20::decimalsCall::abi_decode_returns(&data).ok()),
+    })
+}
+
+/// Calls `totalSupply` on `token`, as of the block with the given hash.
+#[tracing::instrument(skip(source))]
+pub async fn fetch_total_supply(
+    source: Arc<dyn BlockSource>,
+    token: Address,
+    block_hash: B256,
+) -> anyhow::Result<U256> {
+    let data = source
+        .call(
+            token,
+            IERC20::totalSupplyCall {}.abi_encode().into(),
+            block_hash,
+        )
+        .await?;
+    IERC20::totalSupplyCall::abi_decode_returns(&data)
+        .map_err(|e| anyhow::anyhow!("Total supply of token {token} cannot be decoded: {e}"))
+}
+
+/// Decodes a `string` return value, or the `bytes32` some older tokens (such as MKR) return
+/// instead, padded with zeros.
+fn decode_text(data: &[u8]) -> Option<String> {
+    if let Ok(text) = IERC20::nameCall::abi_decode_returns(data) {
+        return Some(text);
+    }
+    if data.len() != 32 {
+        return None;
+    }
+    let end = data.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    String::from_utf8(data[..end].to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::B256;
+    use alloy_sol_types::SolValue;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_text() {
+        let encoded = "USD Coin".to_string().abi_encode();
+        assert_eq!(decode_text(&encoded).as_deref(), Some("USD Coin"));
+
+        let mut padded = [0u8; 32];
+        padded[..3].copy_from_slice(b"MKR");
+        assert_eq!(
+            decode_text(B256::from(padded).as_slice()).as_deref(),
+            Some("MKR")
+        );
+
+        assert_eq!(decode_text(&[]), None);
+        assert_eq!(decode_text(&[0xff; 32]), None);
+    }
+}
@@ -1,17 +1,35 @@
 use std::collections::{HashMap, HashSet};
 
-use alloy::primitives::{Address, U256, address};
+use alloy::primitives::{Address, U256};
 
-pub const USDC: Address = address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
-pub const WETH: Address = address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
-pub const WBTC: Address = address!("0x2260fac5e5542a773aa44fbcfedf7c193bc2c599");
-
-pub const KNOWN_TOKENS: &[Address] = &[USDC, WETH, WBTC];
+use crate::types::{Erc1155Transfer, NftTransfer, TokenTransfer};
 
 pub struct ParsedData {
     pub block_id: u64,
     /// A map of which account interacted with which contracts.
     pub interactions: HashMap<Address, HashSet<Address>>,
+    /// Net change of the balance of each account in each watched token, as a two's complement
+    /// integer. The zero address, which mints and burns, is left out.
+    pub deltas: HashMap<(Address, Address), U256>,
+}
+
+/// Transfers decoded from the logs of a block.
+#[derive(Default)]
+pub struct Transfers {
+    /// ERC-20 transfers.
+    pub tokens: Vec<TokenTransfer>,
+    /// ERC-721 transfers.
+    pub nfts: Vec<NftTransfer>,
+    /// ERC-1155 transfers, one per id moved.
+    pub erc1155: Vec<Erc1155Transfer>,
+}
+
+impl Transfers {
+    pub fn extend(&mut self, other: Transfers) {
+        self.tokens.extend(other.tokens);
+        self.nfts.extend(other.nfts);
+        self.erc1155.extend(other.erc1155);
+    }
 }
 
 pub struct Balance {
@@ -1,59 +1,115 @@
-use std::{pin::Pin, sync::Arc};
+use std::sync::Arc;
 
-use alloy::primitives::Address;
-use alloy_provider::{DynProvider, Provider};
-use futures::{future::join_all, Future};
+use alloy::primitives::{Address, B256};
+use futures::{StreamExt, stream};
 
-use crate::eth_client::{
-    contracts::erc20::IERC20,
-    types::{Balance, ParsedData},
+use crate::{
+    eth_client::{
+        retry::RetryPolicy,
+        source::BlockSource,
+        types::{Balance, ParsedData},
+    },
+    types::MissingBalance,
 };
 
-#[tracing::instrument(skip(provider, interaction))]
-pub async fn get_balances(provider: Arc<DynProvider>, interaction: ParsedData) -> Vec<Balance> {
-    let mut balance_futures: Vec<Pin<Box<dyn Future<Output = Option<Balance>> + Send>>> =
-        Vec::new();
-    let block_id = interaction.block_id;
+/// How balance lookups are grouped into calls to the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchPolicy {
+    /// Balances looked up in a single call.
+    pub batch_size: usize,
+    /// Calls in flight at once, per block.
+    pub concurrency: usize,
+}
 
-    for (account, contracts) in interaction.interactions {
-        for token_address in contracts {
-            if token_address != Address::ZERO {
-                let contract = IERC20::new(token_address, Arc::clone(&provider));
-                let future = async move {
-                    if let Ok(balance) = contract.balanceOf(account).call().await {
-                        Some(Balance {
-                            account,
-                            balance,
-                            token: token_address,
-                            block_id,
-                        })
-                    } else {
-                        None
-                    }
-                };
-                balance_futures.push(Box::pin(future));
-            } else {
-                let provider = Arc::clone(&provider);
-                let future = async move {
-                    if let Ok(balance) = provider.get_balance(account).await {
-                        Some(Balance {
-                            account,
-                            balance,
-                            token: Address::ZERO,
-                            block_id,
-                        })
-                    } else {
-                        None
-                    }
-                };
-                balance_futures.push(Box::pin(future));
-            }
+impl Default for BatchPolicy {
+    fn default() -> Self {
+        BatchPolicy {
+            batch_size: 500,
+            concurrency: 4,
         }
     }
+}
 
-    join_all(balance_futures)
-        .await
+/// Where the balances of watched tokens come from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceMode {
+    /// Fetched from the token contract for each account moving the token, in every block.
+    #[default]
+    Rpc,
+    /// Fetched once per account, then kept up to date with the transfers of the following blocks.
+    /// Native balances are still fetched.
+    Derived,
+}
+
+/// Fetches the balance of each account in each contract it interacted with, as of the block with
+/// hash `block_hash`, rather than whatever block the node is at when the call is made.
+///
+/// Lookups are sent in chunks of `batch.batch_size`, at most `batch.concurrency` at a time.
+/// Balances the node cannot serve at that block, even after retrying, are returned as missing
+/// with the error, to be fetched again later.
+#[tracing::instrument(skip(source, interaction, retry))]
+pub async fn get_balances(
+    source: Arc<dyn BlockSource>,
+    interaction: ParsedData,
+    block_hash: B256,
+    retry: RetryPolicy,
+    batch: BatchPolicy,
+) -> (Vec<Balance>, Vec<MissingBalance>) {
+    let block_id = interaction.block_id;
+    let requests: Vec<(Address, Address)> = interaction
+        .interactions
         .into_iter()
-        .flatten()
+        .flat_map(|(account, contracts)| contracts.into_iter().map(move |token| (account, token)))
+        .collect();
+
+    let chunks: Vec<Vec<_>> = requests
+        .chunks(batch.batch_size.max(1))
+        .map(<[_]>::to_vec)
+        .collect();
+    let results: Vec<_> = stream::iter(chunks)
+        .map(|chunk| {
+            let source = Arc::clone(&source);
+            async move {
+                let balances = retry
+                    .retry("balances", || source.balances(chunk.clone(), block_hash))
+                    .await;
+                (chunk, balances)
+            }
+        })
+        .buffer_unordered(batch.concurrency.max(1))
         .collect()
+        .await;
+
+    let mut balances = Vec::new();
+    let mut missing = Vec::new();
+    for (chunk, result) in results {
+        let lookups: Vec<anyhow::Result<_>> = match result {
+            Ok(lookups) => lookups,
+            Err(e) => {
+                let error = format!("{e:#}");
+                chunk
+                    .iter()
+                    .map(|_| Err(anyhow::anyhow!(error.clone())))
+                    .collect()
+            }
+        };
+        for ((account, token), lookup) in chunk.into_iter().zip(lookups) {
+            match lookup {
+                Ok(balance) => balances.push(Balance {
+                    account,
+                    balance,
+                    token,
+                    block_id,
+                }),
+                Err(e) => missing.push(MissingBalance {
+                    account: account.into(),
+                    token: token.into(),
+                    block_id,
+                    error: format!("{e:#}"),
+                    attempts: 1,
+                }),
+            }
+        }
+    }
+    (balances, missing)
 }
@@ -0,0 +1,222 @@
+use std::{ops::RangeInclusive, sync::Arc, time::Duration};
+
+use futures::{StreamExt, stream};
+use tokio::sync::Mutex;
+
+use super::{LIVE_START_KEY, links_with_stored};
+use crate::{
+    api::stream::ChainEvents,
+    db::Database,
+    eth_client::{EthClient, retry::BlockFetchError},
+    types::BackfillJob,
+};
+
+/// How many blocks are fetched from the node at the same time while backfilling.
+const BACKFILL_CONCURRENCY: usize = 8;
+
+/// How often the stored range is checked for blocks that failed to be indexed.
+const GAP_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Keeps the stored chain complete.
+///
+/// On start, the requested range (if any) and the blocks produced since the last indexed one are
+/// registered as backfill jobs, leaving out the `confirmations` most recent ones. Then,
+/// periodically, every gap between the blocks stored since live following first started is
+/// registered too, and all unfinished jobs, including the ones left over by a previous run, are
+/// processed. Gaps below that point are left alone, since they are only the space between
+/// requested historical ranges. Stored blocks are published to `events`.
+#[tracing::instrument(skip(database, client, events))]
+pub async fn run(
+    database: Arc<Mutex<Database>>,
+    client: EthClient,
+    events: ChainEvents,
+    requested: Option<RangeInclusive<u64>>,
+    confirmations: u64,
+) -> anyhow::Result<()> {
+    if let Some(range) = requested {
+        register(&database, range).await?;
+    }
+
+    let latest = database.lock().await.query_latest_block_number()?;
+    if let Some(latest) = latest {
+        let head = client
+            .get_block_number()
+            .await?
+            .saturating_sub(confirmations);
+        if head > latest {
+            register(&database, latest + 1..=head).await?;
+        }
+    }
+
+    loop {
+        let since = database.lock().await.query_state(LIVE_START_KEY)?;
+        if let Some(since) = since {
+            let gaps = database.lock().await.query_block_gaps(since)?;
+            for gap in gaps {
+                register(&database, gap).await?;
+            }
+        }
+
+        let jobs = database.lock().await.query_pending_backfill_jobs()?;
+        for job in jobs {
+            // A failed job keeps its progress and is attempted again on the next round.
+            if let Err(e) = run_job(&database, &client, &events, &job).await {
+                tracing::error!("Backfill job {} failed: {e}", job.id);
+            }
+        }
+
+        tokio::time::sleep(GAP_CHECK_INTERVAL).await;
+    }
+}
+
+async fn register(database: &Mutex<Database>, range: RangeInclusive<u64>) -> anyhow::Result<()> {
+    let job = database
+        .lock()
+        .await
+        .create_backfill_job(*range.start(), *range.end())?;
+    tracing::info!(
+        "Backfill job {} registered for blocks {} to {}",
+        job.id,
+        job.from_block,
+        job.to_block
+    );
+    Ok(())
+}
+
+/// Indexes the remaining blocks of `job` in ascending order, skipping the ones already stored.
+///
+/// Blocks are fetched concurrently, but stored one after the other so the saved progress never
+/// moves past a block that is not in the database. A block that fails to be fetched is
+/// dead-lettered, as the live follower does, and the job carries on past it. A block that does
+/// not link up with its stored neighbours, fetched during a reorganization, stops the job, which
+/// starts again from that block on the next round.
+#[tracing::instrument(skip(database, client, events))]
+async fn run_job(
+    database: &Mutex<Database>,
+    client: &EthClient,
+    events: &ChainEvents,
+    job: &BackfillJob,
+) -> anyhow::Result<()> {
+    tracing::info!(
+        "Backfilling blocks {} to {} (job {})",
+        job.next_block,
+        job.to_block,
+        job.id
+    );
+
+    let mut blocks = stream::iter(job.next_block..=job.to_block)
+        .map(|number| async move {
+            if database.lock().await.query_block_header(number)?.is_some() {
+                return Ok((number, None));
+            }
+            let block = client.get_block_by_number(number).await;
+            anyhow::Ok((number, Some(block)))
+        })
+        .buffered(BACKFILL_CONCURRENCY);
+
+    while let Some(result) = blocks.next().await {
+        let (number, block) = result?;
+        let mut database = database.lock().await;
+        match block {
+            // The live follower may have stored it in the meantime.
+            Some(Ok(block)) if database.query_block_header(number)?.is_none() => {
+                if !links_with_stored(&mut database, &block.block)? {
+                    anyhow::bail!(
+                        "Backfilled block {number} does not link up with the stored blocks"
+                    );
+                }
+                database.insert_block(&block)?;
+                events.publish_stored(block);
+            }
+            Some(Err(e)) => {
+                tracing::warn!("Backfilled block {number} failed: {e:#}");
+                // The hash is only known when the header could be fetched.
+                let hash = e
+                    .downcast_ref::<BlockFetchError>()
+                    .map_or([0; 32], |failed| failed.hash);
+                database.record_dead_letter(number, &hash, &format!("{e:#}"))?;
+            }
+            _ => {}
+        }
+        database.update_backfill_progress(job.id, number + 1)?;
+    }
+
+    tracing::info!("Backfill job {} finished", job.id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        eth_client::{
+            self, retry::RetryPolicy, source::FIXTURES_DIR, update_balances::BalanceMode,
+        },
+        types::{Block, BlockSummary},
+    };
+
+    #[tokio::test]
+    async fn test_dead_letter_failed_blocks() {
+        let database = Mutex::new(Database::connect_test());
+        let (client, _) = eth_client::replay(
+            PathBuf::from(FIXTURES_DIR),
+            RetryPolicy::default(),
+            tokio::sync::watch::channel(Default::default()).1,
+            BalanceMode::Rpc,
+        )
+        .await
+        .unwrap();
+        // Block 102 was never recorded, so it cannot be fetched.
+        let job = database.lock().await.create_backfill_job(100, 102).unwrap();
+
+        run_job(&database, &client, &ChainEvents::default(), &job)
+            .await
+            .unwrap();
+
+        let mut database = database.lock().await;
+        assert!(database.query_block_header(101).unwrap().is_some());
+        let dead_letters = database.query_dead_letters(None, 10).unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].number, 102);
+        assert!(database.query_pending_backfill_jobs().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stop_at_blocks_off_the_stored_chain() {
+        let database = Mutex::new(Database::connect_test());
+        let (client, _) = eth_client::replay(
+            PathBuf::from(FIXTURES_DIR),
+            RetryPolicy::default(),
+            tokio::sync::watch::channel(Default::default()).1,
+            BalanceMode::Rpc,
+        )
+        .await
+        .unwrap();
+        // Stored from another branch, which block 101 is not the parent of.
+        let child = BlockSummary {
+            block: Block {
+                number: 102,
+                hash: [9; 32],
+                parent_hash: [7; 32],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        database.lock().await.insert_block(&child).unwrap();
+        let job = database.lock().await.create_backfill_job(100, 101).unwrap();
+
+        assert!(
+            run_job(&database, &client, &ChainEvents::default(), &job)
+                .await
+                .is_err()
+        );
+
+        let mut database = database.lock().await;
+        assert!(database.query_block_header(100).unwrap().is_some());
+        assert!(database.query_block_header(101).unwrap().is_none());
+        let pending = database.query_pending_backfill_jobs().unwrap();
+        assert_eq!(pending[0].next_block, 101);
+    }
+}
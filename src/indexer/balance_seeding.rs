@@ -0,0 +1,60 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::Mutex;
+
+use crate::{db::Database, eth_client::EthClient};
+
+/// How often the balances that started moving are seeded.
+const SEEDING_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Balances seeded in one round.
+const SEEDING_BATCH: i64 = 500;
+
+/// Seeds the derived balances of the accounts that moved a watched token for the first time,
+/// for the following blocks to be applied to them.
+#[tracing::instrument(skip(database, client))]
+pub async fn run(database: Arc<Mutex<Database>>, client: EthClient) {
+    loop {
+        match seed_balances(&database, &client).await {
+            Ok(0) => {}
+            Ok(seeded) => tracing::info!("{seeded} derived balance(s) seeded"),
+            Err(e) => tracing::error!("Failed to seed derived balances: {e}"),
+        }
+        tokio::time::sleep(SEEDING_INTERVAL).await;
+    }
+}
+
+/// Fetches a batch of unseeded balances as of the latest stored block, the changes of the blocks
+/// up to it being included, and returns how many were seeded.
+///
+/// Balances the node cannot serve are left unseeded, to be attempted again in the next round.
+pub async fn seed_balances(
+    database: &Mutex<Database>,
+    client: &EthClient,
+) -> anyhow::Result<usize> {
+    let (unseeded, latest) = {
+        let mut database = database.lock().await;
+        let latest = match database.query_latest_block_number()? {
+            Some(number) => database.query_block_header(number)?,
+            None => None,
+        };
+        (database.query_unseeded_balances(SEEDING_BATCH)?, latest)
+    };
+    let Some(block) = latest else {
+        return Ok(0);
+    };
+    if unseeded.is_empty() {
+        return Ok(0);
+    }
+
+    let (balances, missing) = client.get_balances_at(&block, &unseeded).await;
+    for balance in &missing {
+        tracing::warn!(
+            "Cannot seed the balance of {} in {}: {}",
+            hex::encode(balance.account),
+            hex::encode(balance.token),
+            balance.error
+        );
+    }
+    database.lock().await.seed_derived_balances(&balances)
+}
@@ -0,0 +1,156 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::{Mutex, Notify};
+
+use super::links_with_stored;
+use crate::{
+    api::stream::ChainEvents, db::Database, eth_client::EthClient, types::DeadLetterBlock,
+};
+
+/// How often dead-lettered blocks are attempted again, unless woken up earlier.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Attempts after which a block is only retried on request, through the admin API.
+const MAX_DEAD_LETTER_ATTEMPTS: u32 = 10;
+
+/// Periodically fetches the dead-lettered blocks again, storing the ones that succeed and
+/// publishing them to `events`.
+///
+/// `wake` triggers a round right away, which is how the admin API retries a block on demand.
+#[tracing::instrument(skip(database, client, events, wake))]
+pub async fn run(
+    database: Arc<Mutex<Database>>,
+    client: EthClient,
+    events: ChainEvents,
+    wake: Arc<Notify>,
+) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(RETRY_INTERVAL) => {}
+            _ = wake.notified() => {}
+        }
+
+        let due = database
+            .lock()
+            .await
+            .query_retryable_dead_letters(MAX_DEAD_LETTER_ATTEMPTS);
+        let due = match due {
+            Ok(due) => due,
+            Err(e) => {
+                tracing::error!("Failed to load dead-lettered blocks: {e}");
+                continue;
+            }
+        };
+
+        for dead_letter in due {
+            if let Err(e) = retry_block(&database, &client, &events, &dead_letter).await {
+                tracing::error!("Failed to retry block {}: {e}", dead_letter.number);
+            }
+        }
+    }
+}
+
+/// Fetches the canonical block at the dead letter's height, as the one that failed may have been
+/// reorganized away since.
+///
+/// A block that does not link up with its stored neighbours, fetched during a reorganization, is
+/// not stored: it counts as another failed attempt and is fetched again on a later round.
+async fn retry_block(
+    database: &Mutex<Database>,
+    client: &EthClient,
+    events: &ChainEvents,
+    dead_letter: &DeadLetterBlock,
+) -> anyhow::Result<()> {
+    let number = dead_letter.number;
+    if database.lock().await.query_block_header(number)?.is_some() {
+        // Already stored by the backfill in the meantime.
+        return database.lock().await.remove_dead_letter(number);
+    }
+
+    match client.get_block_by_number(number).await {
+        Ok(block) => {
+            let mut database = database.lock().await;
+            if database.query_block_header(number)?.is_none() {
+                if !links_with_stored(&mut database, &block.block)? {
+                    tracing::warn!(
+                        "Dead-lettered block {number} does not link up with the stored blocks"
+                    );
+                    return database.record_dead_letter(
+                        number,
+                        &block.block.hash,
+                        "Block does not link up with the stored blocks",
+                    );
+                }
+                database.insert_block(&block)?;
+                events.publish_stored(block);
+            }
+            database.remove_dead_letter(number)?;
+            tracing::info!("Dead-lettered block {number} indexed");
+        }
+        Err(e) => {
+            tracing::warn!("Dead-lettered block {number} failed again: {e:#}");
+            database.lock().await.record_dead_letter(
+                number,
+                &dead_letter.hash,
+                &format!("{e:#}"),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        eth_client::{
+            self, retry::RetryPolicy, source::FIXTURES_DIR, update_balances::BalanceMode,
+        },
+        types::{Block, BlockSummary},
+    };
+
+    #[tokio::test]
+    async fn test_keep_blocks_off_the_stored_chain() {
+        let database = Mutex::new(Database::connect_test());
+        let (client, _) = eth_client::replay(
+            PathBuf::from(FIXTURES_DIR),
+            RetryPolicy::default(),
+            tokio::sync::watch::channel(Default::default()).1,
+            BalanceMode::Rpc,
+        )
+        .await
+        .unwrap();
+        // Stored from another branch, which block 101 is not the parent of.
+        let child = BlockSummary {
+            block: Block {
+                number: 102,
+                hash: [9; 32],
+                parent_hash: [7; 32],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let dead_letter = {
+            let mut database = database.lock().await;
+            database.insert_block(&child).unwrap();
+            database
+                .record_dead_letter(101, &[0; 32], "timeout")
+                .unwrap();
+            database.query_retryable_dead_letters(10).unwrap().remove(0)
+        };
+
+        retry_block(&database, &client, &ChainEvents::default(), &dead_letter)
+            .await
+            .unwrap();
+
+        let mut database = database.lock().await;
+        assert!(database.query_block_header(101).unwrap().is_none());
+        let dead_letters = database.query_retryable_dead_letters(10).unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].attempts, 2);
+        assert_ne!(dead_letters[0].hash, [0; 32]);
+    }
+}
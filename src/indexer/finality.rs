@@ -0,0 +1,140 @@
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use tokio::sync::Mutex;
+
+use crate::{
+    db::Database,
+    eth_client::EthClient,
+    types::{BlockSummary, Finality},
+};
+
+/// How often the chain's `safe` and `finalized` tags are checked, about once per slot.
+const FINALITY_CHECK_INTERVAL: Duration = Duration::from_secs(12);
+
+/// Keeps the finality of the stored blocks up to date with the chain's `safe` and `finalized`
+/// tags.
+#[tracing::instrument(skip(database, client))]
+pub async fn run(database: Arc<Mutex<Database>>, client: EthClient) {
+    loop {
+        for finality in [Finality::Safe, Finality::Finalized] {
+            if let Err(e) = promote(&database, &client, finality).await {
+                tracing::warn!("Failed to update {} blocks: {e}", finality.as_str());
+            }
+        }
+        tokio::time::sleep(FINALITY_CHECK_INTERVAL).await;
+    }
+}
+
+/// Marks the stored blocks up to the one tagged `finality` as such.
+async fn promote(
+    database: &Mutex<Database>,
+    client: &EthClient,
+    finality: Finality,
+) -> anyhow::Result<()> {
+    let Some(tagged) = client.get_tagged_block(finality).await? else {
+        return Ok(());
+    };
+    let mut database = database.lock().await;
+    let stored = database.query_block_header(tagged.number)?;
+    if stored.is_some_and(|stored| stored.hash != tagged.hash) {
+        // The reorganization has not been processed yet; the next check will catch up.
+        anyhow::bail!(
+            "Stored block {} is not the one tagged {}",
+            tagged.number,
+            finality.as_str()
+        );
+    }
+    let promoted = database.promote_blocks(finality, tagged.number)?;
+    if promoted > 0 {
+        tracing::info!(
+            "{promoted} block(s) up to {} are now {}",
+            tagged.number,
+            finality.as_str()
+        );
+    }
+    Ok(())
+}
+
+/// Blocks held back until enough blocks are built on top of them, so that shallow
+/// reorganizations happen before anything is stored.
+pub struct PendingBlocks {
+    confirmations: u64,
+    /// Oldest first.
+    blocks: VecDeque<BlockSummary>,
+}
+
+impl PendingBlocks {
+    pub fn new(confirmations: u64) -> Self {
+        PendingBlocks {
+            confirmations,
+            blocks: VecDeque::new(),
+        }
+    }
+
+    /// Adds a new head and returns the blocks that now have enough confirmations, oldest first.
+    ///
+    /// Held blocks the new head replaces are dropped. Deeper reorganizations are handled once
+    /// the blocks are stored.
+    pub fn push(&mut self, block: BlockSummary) -> Vec<BlockSummary> {
+        let head = block.block.number;
+        while self.blocks.back().is_some_and(|held| {
+            held.block.number >= head
+                || (held.block.number + 1 == head && held.block.hash != block.block.parent_hash)
+        }) {
+            self.blocks.pop_back();
+        }
+        self.blocks.push_back(block);
+
+        let mut confirmed = Vec::new();
+        while self
+            .blocks
+            .front()
+            .is_some_and(|held| held.block.number + self.confirmations <= head)
+        {
+            confirmed.extend(self.blocks.pop_front());
+        }
+        confirmed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Block;
+
+    fn block(number: u64, hash: u8, parent: u8) -> BlockSummary {
+        BlockSummary {
+            block: Block {
+                number,
+                hash: [hash; 32],
+                parent_hash: [parent; 32],
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn numbers(blocks: Vec<BlockSummary>) -> Vec<(u64, u8)> {
+        blocks
+            .into_iter()
+            .map(|b| (b.block.number, b.block.hash[0]))
+            .collect()
+    }
+
+    #[test]
+    fn test_pending_blocks() {
+        let mut pending = PendingBlocks::new(2);
+        assert!(pending.push(block(10, 10, 9)).is_empty());
+        assert!(pending.push(block(11, 11, 10)).is_empty());
+        assert_eq!(numbers(pending.push(block(12, 12, 11))), vec![(10, 10)]);
+
+        // Block 12 is replaced by a sibling, then by the unseen parent of the new block 13.
+        assert!(pending.push(block(12, 22, 11)).is_empty());
+        assert_eq!(numbers(pending.push(block(13, 33, 23))), vec![(11, 11)]);
+        assert!(pending.push(block(14, 34, 33)).is_empty());
+        assert_eq!(numbers(pending.push(block(15, 35, 34))), vec![(13, 33)]);
+
+        let mut pending = PendingBlocks::new(0);
+        assert_eq!(numbers(pending.push(block(10, 10, 9))), vec![(10, 10)]);
+    }
+}
@@ -0,0 +1,64 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use tokio::sync::Mutex;
+
+use crate::{db::Database, eth_client::EthClient};
+
+/// How often the balances the node could not serve are fetched again.
+const REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Attempts after which a balance stays flagged as missing, listed by the admin API.
+const MAX_BALANCE_ATTEMPTS: u32 = 5;
+
+/// Missing balances fetched again in one round.
+const REFETCH_BATCH: i64 = 500;
+
+/// Periodically fetches the missing balances again, at the block they belong to.
+#[tracing::instrument(skip(database, client))]
+pub async fn run(database: Arc<Mutex<Database>>, client: EthClient) {
+    loop {
+        tokio::time::sleep(REFETCH_INTERVAL).await;
+        if let Err(e) = refetch(&database, &client).await {
+            tracing::error!("Failed to fetch missing balances again: {e}");
+        }
+    }
+}
+
+async fn refetch(database: &Mutex<Database>, client: &EthClient) -> anyhow::Result<()> {
+    let due = database
+        .lock()
+        .await
+        .query_retryable_missing_balances(MAX_BALANCE_ATTEMPTS, REFETCH_BATCH)?;
+
+    let mut by_block = BTreeMap::<_, Vec<_>>::new();
+    for balance in due {
+        by_block
+            .entry(balance.block_id)
+            .or_default()
+            .push((balance.account, balance.token));
+    }
+
+    for (number, requests) in by_block {
+        // Blocks rolled back in the meantime took their missing balances with them.
+        let Some(block) = database.lock().await.query_block_header(number)? else {
+            continue;
+        };
+        let (balances, missing) = client.get_balances_at(&block, &requests).await;
+        let mut database = database.lock().await;
+        // The block may have been replaced by another branch while its balances were fetched.
+        let stored = database.query_block_header(number)?;
+        if stored.is_none_or(|stored| stored.hash != block.hash) {
+            continue;
+        }
+        database.insert_balances(&balances)?;
+        database.record_missing_balances(&missing)?;
+        if !balances.is_empty() {
+            tracing::info!(
+                "{} missing balance(s) of block {number} fetched",
+                balances.len()
+            );
+        }
+    }
+
+    Ok(())
+}
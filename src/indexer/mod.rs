@@ -1,21 +1,149 @@
+mod backfill;
+mod balance_seeding;
+mod dead_letter;
+mod finality;
+mod missing_balances;
+mod reconciliation;
+mod token_backfill;
+mod token_discovery;
+mod token_supply;
+mod webhooks;
+
 use std::sync::Arc;
 
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify, mpsc::Receiver};
+
+use crate::{
+    api::{self, stream::ChainEvents},
+    config::Config,
+    db::Database,
+    eth_client::{
+        self, ConnectOptions, EthClient, retry::BlockFetchError, update_balances::BalanceMode,
+    },
+    indexer::finality::PendingBlocks,
+    types::{Block, BlockSummary, Finality},
+};
+
+/// How many blocks the indexer is willing to walk back looking for a common ancestor.
+pub const MAX_REORG_DEPTH: usize = 64;
+
+/// `indexer_state` key holding the first block ever stored by the live follower.
+const LIVE_START_KEY: &str = "live_start";
+
+#[tracing::instrument(skip(config))]
+pub async fn start(config: Config) -> anyhow::Result<()> {
+    let mut db = Database::connect(&config.database_url)?;
+    db.register_tokens(&config.tokens)?;
+    let tokens = api::TokenRegistry::default();
+    tokens.reload(&mut db)?;
+    let database = Arc::new(Mutex::new(db));
+
+    let (client, rx) = match &config.replay_dir {
+        Some(dir) => {
+            let watchlist = tokens.watchlist.subscribe();
+            eth_client::replay(dir.clone(), config.retry, watchlist, config.balance_mode).await?
+        }
+        None => {
+            let options = ConnectOptions {
+                retry: config.retry,
+                mode: config.ingestion,
+                poll_interval: config.poll_interval,
+                record_dir: config.record_dir,
+                tokens: tokens.watchlist.subscribe(),
+                batch: config.batch,
+                balance_mode: config.balance_mode,
+            };
+            eth_client::connect(config.rpc_urls, options).await?
+        }
+    };
+    println!("Connection established. Background task is listening for new blocks...");
+
+    let dead_letters = Arc::new(Notify::new());
+    let events = ChainEvents::default();
+    let state = api::AppState {
+        db: Arc::clone(&database),
+        connection: client.status(),
+        dead_letters: Arc::clone(&dead_letters),
+        tokens: tokens.clone(),
+        metrics: client.metrics(),
+        events: events.clone(),
+        admin_token: config.admin_token.as_deref().map(Arc::from),
+    };
+    tokio::spawn(async move {
+        api::run_api(state).await;
+    });
 
-use crate::{api, db::Database, eth_client};
+    // Metadata calls are recorded along with the blocks, so they can be replayed as well.
+    tokio::spawn(token_discovery::run(Arc::clone(&database), client.clone()));
 
-#[tracing::instrument(skip(rpc, database_url))]
-pub async fn start(rpc: impl Into<String>, database_url: &str) -> anyhow::Result<()> {
-    let database = Arc::new(Mutex::new(Database::connect(database_url)?));
+    if config.replay_dir.is_some() {
+        // Backfilling and retries would ask for blocks that were not recorded.
+        follow(&database, &client, &events, rx, config.confirmations).await;
+        tracing::info!("Replay finished. The API keeps serving until interrupted.");
+        tokio::signal::ctrl_c().await?;
+        return Ok(());
+    }
 
     let db = Arc::clone(&database);
+    let backfill_client = client.clone();
+    let backfill_events = events.clone();
     tokio::spawn(async move {
-        api::run_api(db).await;
+        let result = backfill::run(
+            db,
+            backfill_client,
+            backfill_events,
+            config.backfill,
+            config.confirmations,
+        );
+        if let Err(e) = result.await {
+            tracing::error!("Backfill stopped: {e}");
+        }
     });
 
-    let mut rx = eth_client::connect(rpc).await?;
-    println!("Connection established. Background task is listening for new blocks...");
+    tokio::spawn(dead_letter::run(
+        Arc::clone(&database),
+        client.clone(),
+        events.clone(),
+        dead_letters,
+    ));
+
+    tokio::spawn(finality::run(Arc::clone(&database), client.clone()));
+
+    tokio::spawn(missing_balances::run(Arc::clone(&database), client.clone()));
+
+    tokio::spawn(token_backfill::run(
+        Arc::clone(&database),
+        client.clone(),
+        tokens.backfills,
+    ));
 
+    tokio::spawn(token_supply::run(Arc::clone(&database), client.clone()));
+
+    tokio::spawn(webhooks::run(Arc::clone(&database), events.subscribe()));
+
+    if config.balance_mode == BalanceMode::Derived {
+        tokio::spawn(balance_seeding::run(Arc::clone(&database), client.clone()));
+        tokio::spawn(reconciliation::run(Arc::clone(&database), client.clone()));
+    }
+
+    follow(&database, &client, &events, rx, config.confirmations).await;
+
+    Ok(())
+}
+
+/// Stores the blocks received from `rx` until the stream ends, recording those that could not be
+/// fetched for a later retry.
+///
+/// Each block is only stored once `confirmations` blocks are built on top of it, and is then
+/// published to `events`.
+async fn follow(
+    database: &Mutex<Database>,
+    client: &EthClient,
+    events: &ChainEvents,
+    mut rx: Receiver<anyhow::Result<BlockSummary>>,
+    confirmations: u64,
+) {
+    let mut pending = PendingBlocks::new(confirmations);
     while let Some(block) = rx.recv().await {
         match block {
             Ok(block) => {
@@ -28,14 +156,339 @@ pub async fn start(rpc: impl Into<String>, database_url: &str) -> anyhow::Result
                         .map(|x| format!("{x:02x}"))
                         .collect::<String>()
                 );
-                let result = database.lock().await.insert_block(&block);
-                if let Err(e) = result {
-                    eprintln!("Error inserting block into database: {e}");
+                for block in pending.push(block) {
+                    let number = block.block.number;
+                    let result = match process_block(database, client, events, block).await {
+                        Ok(()) => database.lock().await.init_state(LIVE_START_KEY, number),
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        eprintln!("Error inserting block into database: {e}");
+                    }
+                }
+            }
+            Err(e) => {
+                println!("Error receiving block: {e}");
+                if let Some(failed) = e.downcast_ref::<BlockFetchError>() {
+                    let result = database.lock().await.record_dead_letter(
+                        failed.number,
+                        &failed.hash,
+                        &format!("{:#}", failed.source),
+                    );
+                    if let Err(e) = result {
+                        tracing::error!("Error recording dead-lettered block: {e}");
+                    }
                 }
             }
-            Err(e) => println!("Error receiving block: {e}"),
         }
     }
+}
+
+/// Stores a new head block, rolling back any stored blocks it does not build upon.
+///
+/// The block's ancestry is followed backwards, fetching each missing canonical parent, until a
+/// stored block with the expected hash (or no stored block at all) is found. Everything stored
+/// above that common ancestor is orphaned and the canonical branch is indexed in its place. Both
+/// are published to `events` as they are committed.
+#[tracing::instrument(skip(database, client, events, block), fields(number = block.block.number))]
+async fn process_block(
+    database: &Mutex<Database>,
+    client: &EthClient,
+    events: &ChainEvents,
+    block: BlockSummary,
+) -> anyhow::Result<()> {
+    let number = block.block.number;
+    let stored = database.lock().await.query_block_header(number)?;
+    if stored.as_ref().is_some_and(|b| b.hash == block.block.hash) {
+        tracing::debug!("Block {number} is already indexed");
+        return Ok(());
+    }
+    if stored
+        .as_ref()
+        .is_some_and(|b| b.finality == Finality::Finalized)
+    {
+        anyhow::bail!("Block {number} conflicts with a finalized block");
+    }
+    let mut reorg = stored.is_some();
+
+    // Newest first; the last entry is the oldest block of the canonical branch.
+    let mut canonical = vec![block];
+    loop {
+        let oldest = &canonical[canonical.len() - 1].block;
+        if oldest.number == 0 {
+            break;
+        }
+        let parent = database
+            .lock()
+            .await
+            .query_block_header(oldest.number - 1)?;
+        match parent {
+            Some(parent) if parent.hash != oldest.parent_hash => {
+                if parent.finality == Finality::Finalized {
+                    anyhow::bail!(
+                        "Reorganization at block {number} reaches finalized block {}",
+                        parent.number
+                    );
+                }
+                if canonical.len() > MAX_REORG_DEPTH {
+                    anyhow::bail!(
+                        "Reorganization deeper than {MAX_REORG_DEPTH} blocks at block {number}"
+                    );
+                }
+                let parent = client.get_block_by_hash(oldest.parent_hash).await?;
+                canonical.push(parent);
+                reorg = true;
+            }
+            _ => break,
+        }
+    }
+
+    let mut database = database.lock().await;
+    if reorg {
+        let ancestor = canonical[canonical.len() - 1]
+            .block
+            .number
+            .saturating_sub(1);
+        let orphaned = database.rollback_to(ancestor)?;
+        tracing::warn!(
+            "Chain reorganization at block {number}: {} block(s) orphaned above {ancestor}",
+            orphaned.len()
+        );
+        events.publish_removed(orphaned);
+    }
+    for block in canonical.into_iter().rev() {
+        database.insert_block(&block)?;
+        events.publish_followed(block);
+    }
 
     Ok(())
 }
+
+/// Whether `block` links up with the blocks stored right below and above it, if any. A block
+/// fetched by number during a reorganization may belong to a branch abandoned since, which
+/// [`process_block`] would never notice once stored.
+fn links_with_stored(database: &mut Database, block: &Block) -> anyhow::Result<bool> {
+    let parent = match block.number.checked_sub(1) {
+        Some(number) => database.query_block_header(number)?,
+        None => None,
+    };
+    let child = database.query_block_header(block.number + 1)?;
+    Ok(parent.is_none_or(|parent| parent.hash == block.parent_hash)
+        && child.is_none_or(|child| child.parent_hash == block.hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::{
+        eth_client::{retry::RetryPolicy, source::FIXTURES_DIR},
+        types::{ConnectionState, ConnectionStatus},
+    };
+
+    #[tokio::test]
+    async fn test_replay_to_api() {
+        let database = Arc::new(Mutex::new(Database::connect_test()));
+        // The registry starts with the tokens seeded by the migrations, USDC among them.
+        let tokens = api::TokenRegistry::default();
+        tokens.reload(&mut *database.lock().await).unwrap();
+        let (client, rx) = eth_client::replay(
+            PathBuf::from(FIXTURES_DIR),
+            RetryPolicy::default(),
+            tokens.watchlist.subscribe(),
+            BalanceMode::Rpc,
+        )
+        .await
+        .unwrap();
+        follow(&database, &client, &ChainEvents::default(), rx, 0).await;
+        // Only the metadata of USDC was recorded; the other watched tokens stay unknown.
+        let discovered = token_discovery::discover_tokens(&database, &client)
+            .await
+            .unwrap();
+        assert_eq!(discovered, 1);
+
+        let state = api::AppState {
+            db: Arc::clone(&database),
+            connection: client.status(),
+            dead_letters: Arc::new(Notify::new()),
+            tokens,
+            metrics: client.metrics(),
+            events: ChainEvents::default(),
+            admin_token: None,
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, api::router(state)).await });
+
+        let response = reqwest::get(format!("http://{address}/blocks/101"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let block: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(block["transactions"]["items"].as_array().unwrap().len(), 2);
+        assert_eq!(block["logs"]["items"].as_array().unwrap().len(), 1);
+
+        let response = reqwest::get(format!(
+            "http://{address}/transactions/29f5511befad0fdedad0163483dbe88fe8d66435ceeb63bdc587e9180de38897"
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let transaction: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            transaction["from"],
+            "563bd9e11d18b6ea60c2f159f8d3062d30e8039e"
+        );
+        assert_eq!(
+            transaction["to"],
+            "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+        );
+        assert_eq!(transaction["tx_type"], 2);
+        assert_eq!(transaction["nonce"], 1);
+        assert_eq!(transaction["value"], "0");
+
+        let response = reqwest::get(format!(
+            "http://{address}/transactions/29f5511befad0fdedad0163483dbe88fe8d66435ceeb63bdc587e9180de38897/receipt"
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let receipt: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(receipt["status"], "success");
+        assert_eq!(receipt["transaction_index"], 0);
+        assert_eq!(receipt["effective_gas_price"], "2000000000");
+        assert_eq!(receipt["contract_address"], serde_json::Value::Null);
+
+        let response = reqwest::get(format!(
+            "http://{address}/tokens/a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48/transfers"
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let transfers: serde_json::Value = response.json().await.unwrap();
+        let transfers = transfers["items"].as_array().unwrap();
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(
+            transfers[0]["from"],
+            "563bd9e11d18b6ea60c2f159f8d3062d30e8039e"
+        );
+        assert_eq!(transfers[0]["value"], "500");
+        assert_eq!(transfers[0]["formatted_value"], "0.0005");
+        assert_eq!(transfers[0]["block_number"], 101);
+
+        let response = reqwest::get(format!(
+            "http://{address}/tokens/a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let token: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(token["name"], "USD Coin");
+        assert_eq!(token["symbol"], "USDC");
+        assert_eq!(token["decimals"], 6);
+
+        let response = reqwest::get(format!(
+            "http://{address}/accounts/00000000000000000000000000000000000c0c00/transfers"
+        ))
+        .await
+        .unwrap();
+        let transfers: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(transfers["items"].as_array().unwrap().len(), 1);
+
+        let response = reqwest::get(format!(
+            "http://{address}/accounts/00000000000000000000000000000000000c0c00/transfers?to_block=100"
+        ))
+        .await
+        .unwrap();
+        let transfers: serde_json::Value = response.json().await.unwrap();
+        assert!(transfers["items"].as_array().unwrap().is_empty());
+
+        // B received 1 ETH in block 100, then sent 0.5 ETH and paid for gas in block 101.
+        let response = reqwest::get(format!(
+            "http://{address}/accounts/0376aac07ad725e01357b1725b5cec61ae10473c/balances"
+        ))
+        .await
+        .unwrap();
+        let balances: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            balances["items"][0]["token"],
+            "0000000000000000000000000000000000000000"
+        );
+        assert_eq!(balances["items"][0]["formatted_balance"], "0.499958");
+        assert_eq!(balances["items"][0]["block_number"], 101);
+
+        let response = reqwest::get(format!(
+            "http://{address}/accounts/0376aac07ad725e01357b1725b5cec61ae10473c/balances/0000000000000000000000000000000000000000/history"
+        ))
+        .await
+        .unwrap();
+        let history: serde_json::Value = response.json().await.unwrap();
+        let history = history["items"].as_array().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0]["formatted_balance"], "1");
+
+        let response = reqwest::get(format!(
+            "http://{address}/blocks/hash/0c77e5294229610a30581cbf1d7e7159900afed2ce2883ad5399d1eaea8209c1"
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = reqwest::get(format!("http://{address}/status"))
+            .await
+            .unwrap();
+        let status: ConnectionStatus = response.json().await.unwrap();
+        assert_eq!(status.state, ConnectionState::Connected);
+    }
+
+    #[tokio::test]
+    async fn test_replay_derived_balances() {
+        use alloy::primitives::{U256, address};
+
+        let database = Arc::new(Mutex::new(Database::connect_test()));
+        let tokens = api::TokenRegistry::default();
+        tokens.reload(&mut *database.lock().await).unwrap();
+        let (client, rx) = eth_client::replay(
+            PathBuf::from(FIXTURES_DIR),
+            RetryPolicy::default(),
+            tokens.watchlist.subscribe(),
+            BalanceMode::Derived,
+        )
+        .await
+        .unwrap();
+        follow(&database, &client, &ChainEvents::default(), rx, 0).await;
+
+        let a: [u8; 20] = address!("563bd9e11d18b6ea60c2f159f8d3062d30e8039e").into();
+        let c: [u8; 20] = address!("00000000000000000000000000000000000c0c00").into();
+        let usdc: [u8; 20] = address!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").into();
+        // The USDC transfer of block 101 is kept as changes instead of being fetched.
+        assert_eq!(
+            database.lock().await.query_unseeded_balances(10).unwrap(),
+            vec![(c, usdc), (a, usdc)]
+        );
+
+        let seeded = balance_seeding::seed_balances(&database, &client)
+            .await
+            .unwrap();
+        assert_eq!(seeded, 2);
+        for (account, expected) in [(a, 1500), (c, 500)] {
+            let derived = database
+                .lock()
+                .await
+                .query_derived_balances(&account)
+                .unwrap();
+            assert_eq!(derived.len(), 1);
+            assert_eq!(
+                U256::from_be_bytes(derived[0].balance),
+                U256::from(expected)
+            );
+            assert_eq!(derived[0].seeded_block, 101);
+        }
+
+        let drifts = reconciliation::reconcile(&database, &client).await.unwrap();
+        assert!(drifts.is_empty());
+    }
+}
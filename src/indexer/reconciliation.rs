@@ -0,0 +1,98 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use alloy::primitives::U256;
+use tokio::sync::Mutex;
+
+use crate::{
+    db::{Database, unix_now},
+    eth_client::EthClient,
+    types::BalanceDrift,
+};
+
+/// How often derived balances are compared with their token contract.
+const RECONCILIATION_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Derived balances compared in one round.
+const RECONCILIATION_BATCH: i64 = 500;
+
+/// Periodically compares the derived balances with the balances returned by their token contract,
+/// recording the ones that drifted.
+#[tracing::instrument(skip(database, client))]
+pub async fn run(database: Arc<Mutex<Database>>, client: EthClient) {
+    loop {
+        tokio::time::sleep(RECONCILIATION_INTERVAL).await;
+        if let Err(e) = reconcile(&database, &client).await {
+            tracing::error!("Failed to reconcile derived balances: {e}");
+        }
+    }
+}
+
+/// Compares the batch of derived balances checked the longest ago with their token contract, as
+/// of the latest stored block, and returns the drifts found.
+///
+/// Balances the node cannot serve are left to the next round.
+pub async fn reconcile(
+    database: &Mutex<Database>,
+    client: &EthClient,
+) -> anyhow::Result<Vec<BalanceDrift>> {
+    // Both are read together, so the derived balances are the ones as of that block.
+    let (derived, latest) = {
+        let mut database = database.lock().await;
+        let latest = match database.query_latest_block_number()? {
+            Some(number) => database.query_block_header(number)?,
+            None => None,
+        };
+        (
+            database.query_derived_balances_to_check(RECONCILIATION_BATCH)?,
+            latest,
+        )
+    };
+    let Some(block) = latest else {
+        return Ok(Vec::new());
+    };
+    if derived.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let derived: HashMap<_, _> = derived
+        .into_iter()
+        .map(|balance| ((balance.account, balance.token), balance.balance))
+        .collect();
+    let requests: Vec<_> = derived.keys().copied().collect();
+    let (onchain, _) = client.get_balances_at(&block, &requests).await;
+
+    let detected_at = unix_now()?;
+    let mut checked = Vec::new();
+    let mut drifts = Vec::new();
+    for balance in onchain {
+        let key = (balance.account, balance.token);
+        checked.push(key);
+        let Some(&derived) = derived.get(&key) else {
+            continue;
+        };
+        if derived != balance.balance {
+            tracing::warn!(
+                "Derived balance of {} in {} is {} at block {}, the token contract returns {}",
+                hex::encode(balance.account),
+                hex::encode(balance.token),
+                U256::from_be_bytes(derived),
+                block.number,
+                U256::from_be_bytes(balance.balance)
+            );
+            drifts.push(BalanceDrift {
+                account: balance.account,
+                token: balance.token,
+                block_id: block.number,
+                derived,
+                onchain: balance.balance,
+                detected_at,
+            });
+        }
+    }
+
+    database
+        .lock()
+        .await
+        .record_balance_checks(&checked, &drifts, detected_at)?;
+    Ok(drifts)
+}
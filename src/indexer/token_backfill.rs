@@ -0,0 +1,91 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use tokio::sync::{Mutex, Notify};
+
+use crate::{db::Database, eth_client::EthClient};
+
+/// How often the backfills left unfinished by balances the node could not serve are resumed.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Fetches the balances of the tokens added to the watchlist with a backfill, for every account
+/// that moved them in the blocks already stored.
+///
+/// `wake` is notified by the admin API when such a token is added. Pending backfills left over by
+/// a previous run are handled right away, and unfinished ones are resumed every minute.
+#[tracing::instrument(skip(database, client, wake))]
+pub async fn run(database: Arc<Mutex<Database>>, client: EthClient, wake: Arc<Notify>) {
+    loop {
+        let mut unfinished = false;
+        let pending = database.lock().await.query_pending_token_backfills();
+        match pending {
+            Ok(pending) => {
+                for token in pending {
+                    match backfill_token(&database, &client, token).await {
+                        Ok(finished) => unfinished |= !finished,
+                        Err(e) => {
+                            unfinished = true;
+                            tracing::error!("Failed to backfill token {}: {e}", hex::encode(token));
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                unfinished = true;
+                tracing::error!("Failed to load pending token backfills: {e}");
+            }
+        }
+
+        if unfinished {
+            tokio::select! {
+                _ = wake.notified() => {}
+                _ = tokio::time::sleep(RETRY_INTERVAL) => {}
+            }
+        } else {
+            wake.notified().await;
+        }
+    }
+}
+
+/// Stores the balances of the token's past holders at each stored block where they moved it,
+/// skipping the ones stored by a previous attempt. The backfill is finished, and `true` returned,
+/// once none is left.
+async fn backfill_token(
+    database: &Mutex<Database>,
+    client: &EthClient,
+    token: [u8; 20],
+) -> anyhow::Result<bool> {
+    let unfetched = database
+        .lock()
+        .await
+        .query_unfetched_token_balances(&token)?;
+
+    let mut by_block = BTreeMap::<_, Vec<_>>::new();
+    for (account, number) in unfetched {
+        by_block.entry(number).or_default().push((account, token));
+    }
+
+    let mut fetched = 0;
+    let mut unserved = 0;
+    for (number, requests) in by_block {
+        // Blocks rolled back in the meantime took their transfers with them.
+        let Some(block) = database.lock().await.query_block_header(number)? else {
+            continue;
+        };
+        let (balances, missing) = client.get_balances_at(&block, &requests).await;
+        let mut database = database.lock().await;
+        database.insert_balances(&balances)?;
+        database.record_missing_balances(&missing)?;
+        fetched += balances.len();
+        unserved += missing.len();
+    }
+
+    tracing::info!(
+        "Backfilled {fetched} balances of token {}, {unserved} left to fetch",
+        hex::encode(token)
+    );
+    if unserved > 0 {
+        return Ok(false);
+    }
+    database.lock().await.finish_token_backfill(&token)?;
+    Ok(true)
+}
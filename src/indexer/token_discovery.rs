@@ -0,0 +1,84 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::future::join_all;
+use tokio::sync::Mutex;
+
+use crate::{db::Database, eth_client::EthClient, types::ConnectionState};
+
+/// How often newly transferred tokens are looked for.
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Tokens whose metadata is fetched in one round.
+const DISCOVERY_BATCH: i64 = 50;
+
+/// Wait before looking a token up again after its metadata calls all failed, doubled with each
+/// failure up to `MAX_RETRY_DELAY`.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Fetches and stores the metadata of the tokens seen in transfers or added to the watchlist, the
+/// first time they are found.
+#[tracing::instrument(skip(database, client))]
+pub async fn run(database: Arc<Mutex<Database>>, client: EthClient) {
+    loop {
+        match discover_tokens(&database, &client).await {
+            Ok(0) => {}
+            Ok(discovered) => tracing::info!("Metadata of {discovered} token(s) stored"),
+            Err(e) => tracing::warn!("Token discovery failed: {e}"),
+        }
+        tokio::time::sleep(DISCOVERY_INTERVAL).await;
+    }
+}
+
+/// Stores the metadata of a batch of unknown tokens as of the latest stored block, returning how
+/// many were stored. The tokens that could not be looked up are attempted again later.
+///
+/// Nothing is fetched while the node is unreachable, since every token would then be stored
+/// without metadata.
+pub async fn discover_tokens(
+    database: &Mutex<Database>,
+    client: &EthClient,
+) -> anyhow::Result<usize> {
+    if client.status().borrow().state != ConnectionState::Connected {
+        return Ok(0);
+    }
+    let (unknown, latest) = {
+        let mut database = database.lock().await;
+        let latest = match database.query_latest_block_number()? {
+            Some(number) => database.query_block_header(number)?,
+            None => None,
+        };
+        (database.query_unknown_tokens(DISCOVERY_BATCH)?, latest)
+    };
+    let Some(block) = latest else {
+        return Ok(0);
+    };
+
+    let fetched = join_all(
+        unknown
+            .iter()
+            .map(|token| client.get_token_metadata(*token, &block)),
+    )
+    .await;
+    let mut tokens = Vec::new();
+    let mut failed = Vec::new();
+    for (token, result) in unknown.iter().zip(fetched) {
+        match result {
+            Ok(metadata) => tokens.push(metadata),
+            Err(e) => {
+                tracing::warn!("{e}");
+                failed.push(*token);
+            }
+        }
+    }
+
+    let mut database = database.lock().await;
+    database.insert_token_metadata(&tokens)?;
+    database.record_token_metadata_failures(
+        &failed,
+        RETRY_DELAY.as_secs(),
+        MAX_RETRY_DELAY.as_secs(),
+    )?;
+    Ok(tokens.len())
+}
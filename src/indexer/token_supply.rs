@@ -0,0 +1,68 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::future::join_all;
+use tokio::sync::Mutex;
+
+use crate::{
+    db::{Database, unix_now},
+    eth_client::EthClient,
+    types::ConnectionState,
+};
+
+/// How often the total supply of the watched tokens is fetched again.
+const SUPPLY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically fetches and stores the total supply of the enabled watched tokens.
+#[tracing::instrument(skip(database, client))]
+pub async fn run(database: Arc<Mutex<Database>>, client: EthClient) {
+    loop {
+        if let Err(e) = update_supplies(&database, &client).await {
+            tracing::warn!("Failed to update total supplies: {e}");
+        }
+        tokio::time::sleep(SUPPLY_INTERVAL).await;
+    }
+}
+
+/// Stores the total supply of each enabled watched token as of the latest stored block, returning
+/// how many were stored.
+///
+/// Tokens whose supply cannot be fetched keep the last one stored.
+pub async fn update_supplies(
+    database: &Mutex<Database>,
+    client: &EthClient,
+) -> anyhow::Result<usize> {
+    if client.status().borrow().state != ConnectionState::Connected {
+        return Ok(0);
+    }
+    let (tokens, latest) = {
+        let mut database = database.lock().await;
+        let latest = match database.query_latest_block_number()? {
+            Some(number) => database.query_block_header(number)?,
+            None => None,
+        };
+        (database.query_enabled_tokens()?, latest)
+    };
+    let Some(block) = latest else {
+        return Ok(0);
+    };
+
+    let fetched = join_all(
+        tokens
+            .iter()
+            .map(|token| client.get_total_supply(*token, &block)),
+    )
+    .await;
+    let updated_at = unix_now()?;
+    let mut database = database.lock().await;
+    let mut updated = 0;
+    for (token, result) in tokens.iter().zip(fetched) {
+        match result {
+            Ok(supply) => {
+                database.update_total_supply(token, &supply, block.number, updated_at)?;
+                updated += 1;
+            }
+            Err(e) => tracing::warn!("{e}"),
+        }
+    }
+    Ok(updated)
+}
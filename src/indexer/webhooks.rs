@@ -0,0 +1,575 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use alloy::primitives::U256;
+use axum::http::header::CONTENT_TYPE;
+use futures::{StreamExt, stream};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::sync::{
+    Mutex, Notify,
+    broadcast::{self, error::RecvError},
+};
+
+use crate::{
+    api::{
+        models::{TokenTransfer, WebhookEventKind, WebhookPayload},
+        stream::ChainEvent,
+    },
+    db::{Database, unix_now},
+    types::{BlockSummary, DeliveryStatus, Webhook, WebhookDelivery, WebhookEvent, WebhookFilter},
+};
+
+/// How often due deliveries, and blocks to queue deliveries of, are looked for, besides right
+/// after a block is stored, rolled back or queued.
+const DELIVERY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Stored or rolled back blocks whose deliveries are queued while holding the database.
+const EVENT_BATCH: i64 = 100;
+
+/// Attempts after which a delivery is given up on.
+const MAX_DELIVERY_ATTEMPTS: u32 = 8;
+
+/// Wait before the second attempt of a delivery. It doubles after each failure.
+const RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(3600);
+
+/// How long a webhook has to answer.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Deliveries attempted at the same time, so that a slow webhook does not hold up the others.
+const DELIVERY_CONCURRENCY: usize = 8;
+
+/// Due deliveries attempted in one round.
+const DELIVERY_BATCH: i64 = 100;
+
+/// Header holding `sha256=` and the hex HMAC-SHA256 of the timestamp, a `.` and the body, keyed
+/// with the webhook's secret.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// Header holding the Unix time of the attempt, signed along with the body so that a payload
+/// cannot be replayed later on.
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+
+/// Header holding the id of the delivery, the same for every attempt.
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+/// Queues a delivery to every matching webhook for each block stored, and to every webhook sent a
+/// block once it is rolled back, and posts the queued deliveries, attempting the failed ones again
+/// with a growing backoff. The blocks are recorded along with them in the database, `events`
+/// only waking the queueing up.
+#[tracing::instrument(skip(database, events))]
+pub async fn run(database: Arc<Mutex<Database>>, events: broadcast::Receiver<ChainEvent>) {
+    let client = match reqwest::Client::builder().timeout(DELIVERY_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to build the webhook client: {e}");
+            return;
+        }
+    };
+    let queued = Notify::new();
+    tokio::join!(
+        queue(&database, events, &queued),
+        deliver(&database, &client, &queued)
+    );
+}
+
+async fn queue(
+    database: &Mutex<Database>,
+    mut events: broadcast::Receiver<ChainEvent>,
+    queued: &Notify,
+) {
+    loop {
+        match queue_deliveries(database).await {
+            Ok(0) => {}
+            Ok(_) => queued.notify_one(),
+            Err(e) => tracing::error!("Failed to queue webhook deliveries: {e}"),
+        }
+        // Missed events are of no consequence, the blocks being read from the database.
+        tokio::select! {
+            event = events.recv() => {
+                if let Err(RecvError::Closed) = event {
+                    return;
+                }
+            }
+            _ = tokio::time::sleep(DELIVERY_INTERVAL) => {}
+        }
+    }
+}
+
+async fn deliver(database: &Mutex<Database>, client: &reqwest::Client, queued: &Notify) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(DELIVERY_INTERVAL) => {}
+            _ = queued.notified() => {}
+        }
+        if let Err(e) = deliver_due(database, client, RETRY_BACKOFF).await {
+            tracing::error!("Failed to deliver webhooks: {e}");
+        }
+    }
+}
+
+/// Queues the deliveries of the blocks stored or rolled back since the last call. Returns how many
+/// were queued.
+pub async fn queue_deliveries(database: &Mutex<Database>) -> anyhow::Result<usize> {
+    let mut queued = 0;
+    loop {
+        let mut database = database.lock().await;
+        let events = database.query_webhook_events(EVENT_BATCH)?;
+        if events.is_empty() {
+            return Ok(queued);
+        }
+        let webhooks = database.query_webhooks()?;
+        for event in events {
+            let payloads = if event.removed {
+                removed_payloads(&mut database, &event)?
+            } else {
+                stored_payloads(&mut database, &webhooks, &event)?
+            };
+            queued += payloads.len();
+            database.queue_webhook_deliveries(&event, &payloads)?;
+        }
+    }
+}
+
+/// The payloads of a stored block, for every webhook it matches.
+fn stored_payloads(
+    database: &mut Database,
+    webhooks: &[Webhook],
+    event: &WebhookEvent,
+) -> anyhow::Result<Vec<(i32, String)>> {
+    // A block rolled back before its turn is left out.
+    let Some(block) = database.query_stored_block(&event.block_hash)? else {
+        return Ok(Vec::new());
+    };
+    webhooks
+        .iter()
+        .filter_map(|webhook| payload(webhook, &block))
+        .map(|payload| Ok((payload.webhook_id, serde_json::to_string(&payload)?)))
+        .collect()
+}
+
+/// The payloads of a rolled back block, for every webhook it was delivered to: the ones they
+/// received, marked as removed.
+fn removed_payloads(
+    database: &mut Database,
+    event: &WebhookEvent,
+) -> anyhow::Result<Vec<(i32, String)>> {
+    database
+        .query_block_webhook_deliveries(&event.block_hash, DeliveryStatus::Delivered)?
+        .into_iter()
+        .map(|delivery| {
+            let mut payload: WebhookPayload = serde_json::from_str(&delivery.payload)?;
+            payload.event = WebhookEventKind::Removed;
+            Ok((delivery.webhook_id, serde_json::to_string(&payload)?))
+        })
+        .collect()
+}
+
+/// What `block` holds for `webhook`, if anything matches its filter.
+fn payload(webhook: &Webhook, block: &BlockSummary) -> Option<WebhookPayload> {
+    let filter = &webhook.filter;
+    let at_least = |value: &[u8; 32]| {
+        filter
+            .min_value
+            .is_none_or(|min| U256::from_be_bytes(*value) >= U256::from_be_bytes(min))
+    };
+    let involves = |from: &[u8; 20], to: Option<&[u8; 20]>| {
+        filter
+            .address
+            .is_none_or(|address| *from == address || to == Some(&address))
+    };
+
+    let transfers_only = filter.topic.is_some() || filter.token.is_some();
+    let transactions: Vec<_> = block
+        .transactions
+        .iter()
+        .filter(|tx| !transfers_only && involves(&tx.from, tx.to.as_ref()) && at_least(&tx.value))
+        .cloned()
+        .map(Into::into)
+        .collect();
+
+    let token_transfers: Vec<_> = block
+        .token_transfers
+        .iter()
+        .filter(|transfer| {
+            filter.topic.is_none()
+                && filter.token.is_none_or(|token| transfer.token == token)
+                && involves(&transfer.from, Some(&transfer.to))
+                && at_least(&transfer.value)
+        })
+        .cloned()
+        .map(|transfer| TokenTransfer::new(transfer, None))
+        .collect();
+
+    let logs: Vec<_> = block
+        .logs
+        .iter()
+        .filter(|log| matches_log(filter, log))
+        .cloned()
+        .map(Into::into)
+        .collect();
+
+    if transactions.is_empty() && token_transfers.is_empty() && logs.is_empty() {
+        return None;
+    }
+    Some(WebhookPayload {
+        webhook_id: webhook.id,
+        event: WebhookEventKind::Stored,
+        block: block.block.clone().into(),
+        transactions,
+        token_transfers,
+        logs,
+    })
+}
+
+fn matches_log(filter: &WebhookFilter, log: &crate::types::Log) -> bool {
+    filter.topic.is_some_and(|topic| {
+        log.topics.first() == Some(&topic) && filter.address.is_none_or(|a| log.address == a)
+    })
+}
+
+/// Posts the due deliveries. A delivery that fails is attempted again after `backoff`, doubled
+/// after each failure, until it was attempted `MAX_DELIVERY_ATTEMPTS` times. Returns how many
+/// were delivered.
+pub async fn deliver_due(
+    database: &Mutex<Database>,
+    client: &reqwest::Client,
+    backoff: Duration,
+) -> anyhow::Result<usize> {
+    let (due, webhooks) = {
+        let mut database = database.lock().await;
+        let due = database.query_due_webhook_deliveries(DELIVERY_BATCH)?;
+        let webhooks: HashMap<_, _> = database
+            .query_webhooks()?
+            .into_iter()
+            .map(|webhook| (webhook.id, webhook))
+            .collect();
+        (due, webhooks)
+    };
+
+    let attempts: Vec<_> = stream::iter(due)
+        .map(|delivery| {
+            let webhook = webhooks.get(&delivery.webhook_id);
+            async move {
+                // Deliveries go along with their webhook, which may have just been removed.
+                let webhook = webhook?;
+                let outcome = post(client, webhook, &delivery).await;
+                Some((delivery, outcome))
+            }
+        })
+        .buffer_unordered(DELIVERY_CONCURRENCY)
+        .filter_map(futures::future::ready)
+        .collect()
+        .await;
+
+    let now = unix_now()?;
+    let mut delivered = 0;
+    let mut database = database.lock().await;
+    for (delivery, outcome) in attempts {
+        match outcome {
+            Ok(code) => {
+                database.record_webhook_attempt(
+                    delivery.id,
+                    DeliveryStatus::Delivered,
+                    Some(code),
+                    None,
+                    now,
+                )?;
+                delivered += 1;
+            }
+            Err((code, error)) => {
+                let attempts = delivery.attempts + 1;
+                let wait = backoff
+                    .saturating_mul(2u32.saturating_pow(attempts - 1))
+                    .min(MAX_RETRY_BACKOFF);
+                let status = if attempts >= MAX_DELIVERY_ATTEMPTS {
+                    DeliveryStatus::Failed
+                } else {
+                    DeliveryStatus::Pending
+                };
+                tracing::warn!(
+                    "Delivery {} to webhook {} failed (attempt {attempts}): {error}",
+                    delivery.id,
+                    delivery.webhook_id
+                );
+                database.record_webhook_attempt(
+                    delivery.id,
+                    status,
+                    code,
+                    Some(&error),
+                    now + wait.as_secs(),
+                )?;
+            }
+        }
+    }
+    Ok(delivered)
+}
+
+/// Posts the payload of a delivery. Returns the response status, or the status, if a response
+/// was received, and the error of a failed attempt.
+async fn post(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    delivery: &WebhookDelivery,
+) -> Result<u16, (Option<u16>, String)> {
+    let timestamp = unix_now().map_err(|e| (None, e.to_string()))?;
+    let response = client
+        .post(&webhook.url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(DELIVERY_HEADER, delivery.id)
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(
+            SIGNATURE_HEADER,
+            sign(&webhook.secret, timestamp, delivery.payload.as_bytes()),
+        )
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16())
+    } else {
+        Err((Some(status.as_u16()), format!("HTTP {status}")))
+    }
+}
+
+/// The value of [`SIGNATURE_HEADER`] for `body`, sent at `timestamp`.
+pub fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(format!("{timestamp}.").as_bytes());
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{
+        Router,
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+    };
+
+    use super::*;
+
+    /// Stands in for the server behind a webhook. It fails the first request, then records the
+    /// signature, timestamp and body of each.
+    #[derive(Clone, Default)]
+    struct Receiver {
+        calls: Arc<AtomicUsize>,
+        received: Arc<std::sync::Mutex<Vec<(String, u64, Bytes)>>>,
+    }
+
+    async fn receive(
+        State(receiver): State<Receiver>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> StatusCode {
+        if receiver.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            return StatusCode::SERVICE_UNAVAILABLE;
+        }
+        let signature = headers[SIGNATURE_HEADER].to_str().unwrap().to_string();
+        let timestamp = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+        receiver
+            .received
+            .lock()
+            .unwrap()
+            .push((signature, timestamp, body));
+        StatusCode::OK
+    }
+
+    #[tokio::test]
+    async fn test_deliver_signed_payloads() {
+        let receiver = Receiver::default();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let app = Router::new()
+            .route("/hook", post(receive))
+            .with_state(receiver.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let database = Mutex::new(Database::connect_test());
+        let (account, token) = {
+            let mut db = database.lock().await;
+            // Sends the first transaction and transfer of the block, and receives the second.
+            let account = WebhookFilter {
+                address: Some([7; 20]),
+                ..Default::default()
+            };
+            // Only one transfer of the block is in that token, and worth that much.
+            let token = WebhookFilter {
+                token: Some([5; 20]),
+                min_value: Some([2; 32]),
+                ..Default::default()
+            };
+            let unmatched = WebhookFilter {
+                topic: Some([99; 32]),
+                ..Default::default()
+            };
+            let account = db.create_webhook(&url, "first", &account).unwrap();
+            let token = db.create_webhook(&url, "second", &token).unwrap();
+            db.create_webhook(&url, "third", &unmatched).unwrap();
+            (account, token)
+        };
+
+        database
+            .lock()
+            .await
+            .insert_block(&Database::data_setup())
+            .unwrap();
+        assert_eq!(queue_deliveries(&database).await.unwrap(), 2);
+        assert_eq!(queue_deliveries(&database).await.unwrap(), 0);
+
+        // The delivery refused at first is due again right away, without a backoff.
+        let client = reqwest::Client::new();
+        assert_eq!(
+            deliver_due(&database, &client, Duration::ZERO)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            deliver_due(&database, &client, Duration::ZERO)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            deliver_due(&database, &client, Duration::ZERO)
+                .await
+                .unwrap(),
+            0
+        );
+
+        let received = receiver.received.lock().unwrap().clone();
+        assert_eq!(received.len(), 2);
+        for (signature, timestamp, body) in received {
+            let payload: WebhookPayload = serde_json::from_slice(&body).unwrap();
+            assert_eq!(payload.block.number, 1);
+            assert_eq!(payload.event, WebhookEventKind::Stored);
+            if payload.webhook_id == account.id {
+                assert_eq!(*signature, sign(&account.secret, timestamp, &body));
+                assert!(!payload.transactions.is_empty());
+                assert_eq!(payload.token_transfers.len(), 2);
+            } else {
+                assert_eq!(payload.webhook_id, token.id);
+                assert_eq!(*signature, sign(&token.secret, timestamp, &body));
+                assert!(payload.transactions.is_empty());
+                assert_eq!(payload.token_transfers.len(), 1);
+                assert_eq!(
+                    payload.token_transfers[0].token,
+                    "0505050505050505050505050505050505050505"
+                );
+            }
+        }
+
+        let mut db = database.lock().await;
+        let log: Vec<_> = [account.id, token.id]
+            .into_iter()
+            .flat_map(|id| db.query_webhook_deliveries(id, None, 10).unwrap())
+            .collect();
+        assert!(log.iter().all(|d| d.status == DeliveryStatus::Delivered));
+        assert_eq!(log.iter().map(|d| d.attempts).sum::<u32>(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_give_up_after_max_attempts() {
+        // Nothing listens on the port once the listener is dropped.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        drop(listener);
+
+        let database = Mutex::new(Database::connect_test());
+        let filter = WebhookFilter {
+            address: Some([7; 20]),
+            ..Default::default()
+        };
+        let webhook = database
+            .lock()
+            .await
+            .create_webhook(&url, "secret", &filter)
+            .unwrap();
+        database
+            .lock()
+            .await
+            .insert_block(&Database::data_setup())
+            .unwrap();
+        queue_deliveries(&database).await.unwrap();
+
+        let client = reqwest::Client::new();
+        for _ in 0..MAX_DELIVERY_ATTEMPTS + 1 {
+            assert_eq!(
+                deliver_due(&database, &client, Duration::ZERO)
+                    .await
+                    .unwrap(),
+                0
+            );
+        }
+
+        let log = database
+            .lock()
+            .await
+            .query_webhook_deliveries(webhook.id, None, 10)
+            .unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].status, DeliveryStatus::Failed);
+        assert_eq!(log[0].attempts, MAX_DELIVERY_ATTEMPTS);
+        assert_eq!(log[0].last_status_code, None);
+        assert!(log[0].last_error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_notify_removed_blocks() {
+        let database = Mutex::new(Database::connect_test());
+        let filter = WebhookFilter {
+            address: Some([7; 20]),
+            ..Default::default()
+        };
+        let mut db = database.lock().await;
+        let delivered = db
+            .create_webhook("http://127.0.0.1:9000/a", "a", &filter)
+            .unwrap();
+        let pending = db
+            .create_webhook("http://127.0.0.1:9000/b", "b", &filter)
+            .unwrap();
+        db.insert_block(&Database::data_setup()).unwrap();
+        drop(db);
+        assert_eq!(queue_deliveries(&database).await.unwrap(), 2);
+
+        let mut db = database.lock().await;
+        let due = db.query_due_webhook_deliveries(10).unwrap();
+        let first = due.iter().find(|d| d.webhook_id == delivered.id).unwrap();
+        db.record_webhook_attempt(first.id, DeliveryStatus::Delivered, Some(200), None, 0)
+            .unwrap();
+        db.rollback_to(0).unwrap();
+        assert!(db.query_due_webhook_deliveries(10).unwrap().is_empty());
+        drop(db);
+
+        // Only the webhook that received the block is told of its removal.
+        assert_eq!(queue_deliveries(&database).await.unwrap(), 1);
+        let mut db = database.lock().await;
+        let due = db.query_due_webhook_deliveries(10).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].webhook_id, delivered.id);
+        let removed: WebhookPayload = serde_json::from_str(&due[0].payload).unwrap();
+        let stored: WebhookPayload = serde_json::from_str(&first.payload).unwrap();
+        assert_eq!(removed.event, WebhookEventKind::Removed);
+        assert_eq!(removed.block.hash, stored.block.hash);
+        assert_eq!(removed.transactions.len(), stored.transactions.len());
+
+        let log = db.query_webhook_deliveries(pending.id, None, 10).unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].status, DeliveryStatus::Cancelled);
+        // An attempt finishing after the rollback does not revive the delivery.
+        db.record_webhook_attempt(log[0].id, DeliveryStatus::Delivered, Some(200), None, 0)
+            .unwrap();
+        let log = db.query_webhook_deliveries(pending.id, None, 10).unwrap();
+        assert_eq!(log[0].status, DeliveryStatus::Cancelled);
+    }
+}
@@ -1,9 +1,9 @@
 #[cfg(feature = "profiling")]
 use chrono::Utc;
-use std::env;
 use tracing_subscriber::{EnvFilter, fmt, prelude::*};
 
 pub mod api;
+pub mod config;
 pub mod db;
 pub mod eth_client;
 pub mod indexer;
@@ -64,10 +64,7 @@ async fn main() -> anyhow::Result<()> {
     #[cfg(feature = "profiling")]
     start_profiling()?;
 
-    let rpc_url = env::var("JSON_RPC_API_KEY")
-        .expect("JSON_RPC_API_KEY must be set. You can set it in .env file");
-    let database_url =
-        env::var("DATABASE_URL").expect("DATABASE_URL must be set. You can set it in .env file");
-    indexer::start(rpc_url, &database_url).await?;
+    let config = config::Config::load()?;
+    indexer::start(config).await?;
     Ok(())
 }
@@ -6,7 +6,15 @@ pub struct BlockSummary {
     pub transactions: Vec<Transaction>,
     pub logs: Vec<Log>,
     pub balances: Vec<Balance>,
+    /// Balances the node could not serve at this block.
+    pub missing_balances: Vec<MissingBalance>,
+    /// Changes made by this block to the balances of watched tokens, when they are derived from
+    /// transfers instead of fetched.
+    pub balance_deltas: Vec<BalanceDelta>,
     pub receipts: Vec<Receipt>,
+    pub token_transfers: Vec<TokenTransfer>,
+    pub nft_transfers: Vec<NftTransfer>,
+    pub erc1155_transfers: Vec<Erc1155Transfer>,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -24,10 +32,138 @@ pub struct Balance {
     pub block_id: u64,
 }
 
+/// A balance as of one block, with the time of that block.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceSnapshot {
+    pub block_id: u64,
+    pub timestamp: u64,
+    pub balance: [u8; 32],
+}
+
+/// A balance that could not be fetched at its block, typically because the node no longer holds
+/// the state of that block. The zero token stands for the native balance.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissingBalance {
+    pub account: [u8; 20],
+    pub token: [u8; 20],
+    pub block_id: u64,
+    /// Error of the last attempt.
+    pub error: String,
+    pub attempts: u32,
+}
+
+/// Net change a block makes to the balance of an account in an ERC-20 token.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceDelta {
+    pub account: [u8; 20],
+    pub token: [u8; 20],
+    pub block_id: u64,
+    /// 32 byte big-endian two's complement integer.
+    pub delta: [u8; 32],
+}
+
+/// Balance of an ERC-20 token, fetched once as of `seeded_block` and kept up to date since with
+/// the changes made by the following blocks.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DerivedBalance {
+    pub account: [u8; 20],
+    pub token: [u8; 20],
+    /// 32 byte big-endian two's complement integer, negative only if the derivation went wrong.
+    pub balance: [u8; 32],
+    pub seeded_block: u64,
+    /// Last block whose changes were applied, `seeded_block` if none was.
+    pub updated_block: u64,
+}
+
+/// A derived balance that did not match the balance returned by the token contract, as of the
+/// same block.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceDrift {
+    pub account: [u8; 20],
+    pub token: [u8; 20],
+    pub block_id: u64,
+    /// 32 byte big-endian two's complement integer.
+    pub derived: [u8; 32],
+    pub onchain: [u8; 32],
+    /// Unix timestamp of the check.
+    pub detected_at: u64,
+}
+
+/// An ERC-20 `Transfer` event.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenTransfer {
+    pub token: [u8; 20],
+    pub from: [u8; 20],
+    pub to: [u8; 20],
+    pub value: [u8; 32],
+    pub transaction_hash: [u8; 32],
+    pub log_index: u64,
+    pub block_number: u64,
+}
+
+/// An ERC-721 `Transfer` event. Mints come from, and burns go to, the zero address.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NftTransfer {
+    pub collection: [u8; 20],
+    pub token_id: [u8; 32],
+    pub from: [u8; 20],
+    pub to: [u8; 20],
+    pub transaction_hash: [u8; 32],
+    pub log_index: u64,
+    pub block_number: u64,
+}
+
+/// The current owner of an ERC-721 token, as of the last transfer indexed.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NftOwnership {
+    pub collection: [u8; 20],
+    pub token_id: [u8; 32],
+    pub owner: [u8; 20],
+    pub last_transfer_block: u64,
+}
+
+/// One id moved by an ERC-1155 `TransferSingle` or `TransferBatch` event. A batch is split into
+/// one transfer per id, numbered by `batch_index`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Erc1155Transfer {
+    pub contract: [u8; 20],
+    pub operator: [u8; 20],
+    pub from: [u8; 20],
+    pub to: [u8; 20],
+    pub token_id: [u8; 32],
+    pub value: [u8; 32],
+    pub transaction_hash: [u8; 32],
+    pub log_index: u64,
+    pub batch_index: u64,
+    pub block_number: u64,
+}
+
+/// Balance of an ERC-1155 id, summed from the indexed transfers. It is incomplete when the holder
+/// sent tokens received before the first indexed block.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Erc1155Balance {
+    pub contract: [u8; 20],
+    pub token_id: [u8; 32],
+    pub holder: [u8; 20],
+    /// 32 byte big-endian integer, zero when incomplete.
+    pub balance: [u8; 32],
+    /// Whether every transfer making up the balance was indexed.
+    pub complete: bool,
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Receipt {
     pub transaction_hash: [u8; 32],
     pub gas_used: u64,
+    pub transaction_index: u64,
+    /// Whether the transaction succeeded, as opposed to being reverted.
+    pub success: bool,
+    pub cumulative_gas_used: u64,
+    pub effective_gas_price: u128,
+    /// The contract deployed by the transaction, if any.
+    pub contract_address: Option<[u8; 20]>,
+    /// 256 bytes.
+    pub logs_bloom: Vec<u8>,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -39,11 +175,277 @@ pub struct Block {
     pub gas_limit: u64,
     pub gas_used: u64,
     pub base_fee_per_gas: Option<u64>,
+    pub finality: Finality,
+}
+
+/// How settled a block is, from the chain's `safe` and `finalized` tags. Variants are ordered from
+/// the least to the most settled.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Finality {
+    /// Part of the canonical chain, but can still be reorganized.
+    #[default]
+    Latest,
+    /// Unlikely to be reorganized.
+    Safe,
+    /// Can no longer be reorganized.
+    Finalized,
+}
+
+impl Finality {
+    pub const ALL: [Finality; 3] = [Finality::Latest, Finality::Safe, Finality::Finalized];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Finality::Latest => "latest",
+            Finality::Safe => "safe",
+            Finality::Finalized => "finalized",
+        }
+    }
+}
+
+impl std::str::FromStr for Finality {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Finality::ALL
+            .into_iter()
+            .find(|finality| finality.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("Invalid finality {s}"))
+    }
+}
+
+/// A block that was part of the indexed chain until a reorganization replaced it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrphanedBlock {
+    pub number: u64,
+    pub hash: [u8; 32],
+    pub parent_hash: [u8; 32],
+    pub timestamp: u64,
+    /// Unix timestamp of when the block was rolled back.
+    pub orphaned_at: u64,
+}
+
+/// A historical block range being indexed, with its progress.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackfillJob {
+    pub id: i32,
+    pub from_block: u64,
+    pub to_block: u64,
+    /// First block of the range that has not been processed yet.
+    pub next_block: u64,
+}
+
+/// A block that could not be fetched and is waiting to be attempted again.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeadLetterBlock {
+    pub number: u64,
+    pub hash: [u8; 32],
+    /// Last error seen while fetching the block.
+    pub error: String,
+    pub attempts: u32,
+    /// Unix timestamp of the last failed attempt.
+    pub last_attempt_at: u64,
+}
+
+/// Metadata of an ERC-20 token, as returned by its contract. Fields it does not implement are
+/// `None`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    pub address: [u8; 20],
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+}
+
+/// Statistics of an ERC-20 token, from the latest balances stored.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenStats {
+    pub token: [u8; 20],
+    /// Accounts whose latest balance is not zero.
+    pub holders: u64,
+    /// As returned by the token contract, once fetched.
+    pub total_supply: Option<[u8; 32]>,
+    /// Unix timestamp of when `total_supply` was fetched.
+    pub supply_updated_at: Option<u64>,
+    /// Stored block `total_supply` was read at.
+    pub supply_block: Option<u64>,
+}
+
+/// An ERC-20 token in the registry of tracked tokens.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchedToken {
+    pub address: [u8; 20],
+    /// Only the balances of enabled tokens are fetched.
+    pub enabled: bool,
+    /// Whether the balances of the token's past holders are still to be fetched.
+    pub backfill_pending: bool,
+    /// Unix timestamp of when the token was added, 0 for the tokens tracked from the start.
+    pub added_at: u64,
+}
+
+/// A URL notified of the stored blocks matching its filter.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    /// Key of the HMAC-SHA256 signature of each payload.
+    pub secret: String,
+    pub filter: WebhookFilter,
+    pub created_at: u64,
+}
+
+/// What a webhook is notified of. Fields left empty match anything.
+///
+/// Without `topic`, the webhook matches the transactions and token transfers sent or received
+/// by `address`, in `token` (native transactions being left out when it is set), worth at least
+/// `min_value`. With `topic`, it matches the logs with that first topic, emitted by `address`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookFilter {
+    pub address: Option<[u8; 20]>,
+    pub token: Option<[u8; 20]>,
+    pub topic: Option<[u8; 32]>,
+    /// 32 byte big-endian integer, in wei or in the token's smallest unit.
+    pub min_value: Option<[u8; 32]>,
+}
+
+/// A payload sent, or to be sent, to a webhook.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub block_number: u64,
+    pub block_hash: [u8; 32],
+    /// The JSON body posted.
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// Unix timestamp before which a pending delivery is not attempted.
+    pub next_attempt_at: u64,
+    /// HTTP status of the last response, if one was received.
+    pub last_status_code: Option<u16>,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    pub delivered_at: Option<u64>,
+}
+
+/// A block stored or rolled back, whose webhook deliveries are still to be queued.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub id: i32,
+    pub block_number: u64,
+    pub block_hash: [u8; 32],
+    /// Whether the block was rolled back, rather than stored.
+    pub removed: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Not attempted yet, or to be attempted again.
+    #[default]
+    Pending,
+    Delivered,
+    /// Given up on after too many attempts.
+    Failed,
+    /// Called off before being delivered, its block having been rolled back.
+    Cancelled,
+}
+
+impl DeliveryStatus {
+    pub const ALL: [DeliveryStatus; 4] = [
+        DeliveryStatus::Pending,
+        DeliveryStatus::Delivered,
+        DeliveryStatus::Failed,
+        DeliveryStatus::Cancelled,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl std::str::FromStr for DeliveryStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DeliveryStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("Invalid delivery status {s}"))
+    }
+}
+
+/// Calls made to the node since the indexer started.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcStats {
+    /// Every call, retries included.
+    pub calls: u64,
+    /// Blocks fetched.
+    pub blocks: u64,
+    pub last_block: Option<u64>,
+    /// Calls made to fetch the last block.
+    pub last_block_calls: u64,
+    pub max_block_calls: u64,
+    pub average_block_calls: f64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    #[default]
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
+/// State of the connection to the RPC node.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionStatus {
+    pub state: ConnectionState,
+    /// Position, in the configured list, of the RPC endpoint in use.
+    pub endpoint: usize,
+    /// Unix timestamp of the last state change.
+    pub since: u64,
+    /// How many times the connection was established again after being lost.
+    pub reconnects: u64,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transaction {
     pub hash: [u8; 32],
+    pub block_number: u64,
+    pub transaction_index: u64,
+    /// EIP-2718 type: 0 for legacy transactions, 2 for EIP-1559 ones, and so on.
+    pub tx_type: u8,
+    /// Missing on legacy transactions signed without replay protection.
+    pub chain_id: Option<u64>,
+    pub from: [u8; 20],
+    /// Missing on contract creations.
+    pub to: Option<[u8; 20]>,
+    pub value: [u8; 32],
+    pub nonce: u64,
+    pub input: Vec<u8>,
+    pub gas_limit: u64,
+    /// Set on transactions with a fixed gas price (legacy and EIP-2930).
+    pub gas_price: Option<u128>,
+    /// Set on dynamic fee transactions (EIP-1559 and later).
+    pub max_fee_per_gas: Option<u128>,
+    pub max_priority_fee_per_gas: Option<u128>,
+    pub access_list: Vec<AccessListItem>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessListItem {
+    pub address: [u8; 20],
+    pub storage_keys: Vec<[u8; 32]>,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -66,23 +468,66 @@ impl From<alloy_rpc_types_eth::Header> for Block {
             gas_limit: header.gas_limit,
             gas_used: header.gas_used,
             base_fee_per_gas: header.base_fee_per_gas,
+            finality: Finality::Latest,
+        }
+    }
+}
+
+impl From<&alloy_rpc_types_eth::Transaction> for Transaction {
+    fn from(tx: &alloy_rpc_types_eth::Transaction) -> Self {
+        use alloy::consensus::{Transaction as _, Typed2718};
+
+        let dynamic_fee = tx.is_dynamic_fee();
+        Transaction {
+            hash: (*tx.inner.hash()).into(),
+            block_number: tx.block_number.unwrap_or_default(),
+            transaction_index: tx.transaction_index.unwrap_or_default(),
+            tx_type: tx.ty(),
+            chain_id: tx.chain_id(),
+            from: tx.inner.signer().into(),
+            to: tx.to().map(Into::into),
+            value: tx.value().to_be_bytes(),
+            nonce: tx.nonce(),
+            input: tx.input().to_vec(),
+            gas_limit: tx.gas_limit(),
+            gas_price: tx.gas_price(),
+            max_fee_per_gas: dynamic_fee.then(|| tx.max_fee_per_gas()),
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas(),
+            access_list: tx
+                .access_list()
+                .map(|list| {
+                    list.iter()
+                        .map(|item| AccessListItem {
+                            address: item.address.into(),
+                            storage_keys: item.storage_keys.iter().map(|key| key.0).collect(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
         }
     }
 }
 
-impl From<alloy::primitives::B256> for Transaction {
-    fn from(hash: alloy::primitives::B256) -> Self {
-        Transaction { hash: hash.into() }
+impl From<&alloy_rpc_types_eth::TransactionReceipt> for Receipt {
+    fn from(receipt: &alloy_rpc_types_eth::TransactionReceipt) -> Self {
+        use alloy::consensus::TxReceipt;
+
+        Receipt {
+            transaction_hash: receipt.transaction_hash.into(),
+            gas_used: receipt.gas_used,
+            transaction_index: receipt.transaction_index.unwrap_or_default(),
+            success: receipt.status(),
+            cumulative_gas_used: receipt.inner.cumulative_gas_used(),
+            effective_gas_price: receipt.effective_gas_price,
+            contract_address: receipt.contract_address.map(Into::into),
+            logs_bloom: receipt.inner.bloom().to_vec(),
+        }
     }
 }
 
 impl From<alloy_rpc_types_eth::Log> for Log {
     fn from(log: alloy_rpc_types_eth::Log) -> Self {
-        let topics = log
-            .topics()
-            .iter()
-            .map(|t| t.to_owned().into())
-            .collect();
+        let topics = log.topics().iter().map(|t| t.to_owned().into()).collect();
         Log {
             transaction_hash: log.transaction_hash.map(|h| h.into()),
             log_index: log.log_index,